@@ -1,19 +1,30 @@
+use std::cmp::Ordering;
+
+use serde::{Deserialize, Serialize};
 use unitn_market_2022::good::good_kind::GoodKind;
 use unitn_market_2022::good::good_kind::GoodKind::EUR;
 
 use doge_common::account::Account;
 
+use crate::ledger::Ledger;
+use crate::pricing_curve::PricingCurve;
+
 #[derive(Debug, PartialEq)]
 pub enum BuyPriceComputationError {
     NonPositiveQuantity,
     NegativeExchangeRateEarnPercentage,
+    /// The market cannot sell that much of its `reservable` quantity, at least not at a finite price.
     ExceedsReservableQuantity { reservable: f32 },
+    /// A good cannot be paid for with itself.
+    SameKind,
 }
 
 #[derive(Debug, PartialEq)]
 pub enum SellPriceComputationError {
     NonPositiveQuantity,
     NegativeExchangeEarnRatePercentage,
+    /// A good cannot be paid for with itself.
+    SameKind,
 }
 
 #[derive(Debug, PartialEq)]
@@ -27,20 +38,24 @@ pub enum SellExchangeRateComputationError {
     NonPositiveExchangeRateEarnPercentage,
 }
 
-#[derive(Debug, Clone)]
+/// Bookkeeping of the market. Quantities are kept as exact [Ledger]s and converted to `f32` only when read.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AccountOps {
-    pub assets: Account,
-    pub reservations: Account,
-    pub futures: Account,
+    pub assets: Ledger,
+    pub reservations: Ledger,
+    pub futures: Ledger,
+    /// Deposits backing the outstanding locks. They still belong to the traders, so they are not priced in.
+    #[serde(default = "Ledger::new_empty")]
+    pub deposits: Ledger,
 }
 
 impl AccountOps {
     pub fn new_empty() -> AccountOps {
-        AccountOps { assets: Account::new_empty(), reservations: Account::new_empty(), futures: Account::new_empty() }
+        AccountOps { assets: Ledger::new_empty(), reservations: Ledger::new_empty(), futures: Ledger::new_empty(), deposits: Ledger::new_empty() }
     }
 
     pub fn of_assets(assets: Account) -> AccountOps {
-        AccountOps { assets, reservations: Account::new_empty(), futures: Account::new_empty() }
+        AccountOps { assets: Ledger::of_account(&assets), reservations: Ledger::new_empty(), futures: Ledger::new_empty(), deposits: Ledger::new_empty() }
     }
 
     pub fn get_reservable_quantity_by_kind(&self, kind: GoodKind) -> f32 {
@@ -52,14 +67,14 @@ impl AccountOps {
     }
 
     pub fn get_total_quantity_by_kind(&self, kind: GoodKind) -> f32 {
-        self.assets.get_quantity_by_kind(kind) + self.reservations.get_quantity_by_kind(kind)
+        (self.assets.get(kind) + self.reservations.get(kind)).to_f32()
     }
 
     pub fn get_future_quantity_by_kind(&self, kind: GoodKind) -> f32 {
         self.futures.get_quantity_by_kind(kind)
     }
 
-    pub fn compute_buy_price(&self, of_kind: GoodKind, of_quantity: f32, exchange_rate_earn_percentage: f32) -> Result<f32, BuyPriceComputationError> {
+    pub fn compute_buy_price(&self, curve: &dyn PricingCurve, of_kind: GoodKind, of_quantity: f32, exchange_rate_earn_percentage: f32) -> Result<f32, BuyPriceComputationError> {
         if of_quantity <= 0. {
             return Err(BuyPriceComputationError::NonPositiveQuantity);
         }
@@ -76,17 +91,19 @@ impl AccountOps {
         let exchange_rate = if of_kind == EUR {
             1.
         } else {
-            (self.get_reservable_quantity_by_kind(EUR) + self.get_reserved_quantity_by_kind(EUR) + self.get_future_quantity_by_kind(EUR)) /
-                (self.get_reservable_quantity_by_kind(of_kind) - of_quantity)
+            curve.buy_exchange_rate(self, of_kind, of_quantity)
         };
 
+        // buying the whole stock, or a curve dividing by an empty inventory, has no price
         let buy_price = of_quantity * exchange_rate * (100. + exchange_rate_earn_percentage) / 100.;
-        assert!(buy_price >= 0.);
+        if !buy_price.is_finite() || buy_price < 0. {
+            return Err(BuyPriceComputationError::ExceedsReservableQuantity { reservable });
+        }
 
         Ok(buy_price)
     }
 
-    pub fn compute_sell_price(&self, of_kind: GoodKind, of_quantity: f32, exchange_rate_earn_percentage: f32) -> Result<f32, SellPriceComputationError> {
+    pub fn compute_sell_price(&self, curve: &dyn PricingCurve, of_kind: GoodKind, of_quantity: f32, exchange_rate_earn_percentage: f32) -> Result<f32, SellPriceComputationError> {
         if of_quantity <= 0. {
             return Err(SellPriceComputationError::NonPositiveQuantity);
         }
@@ -98,7 +115,7 @@ impl AccountOps {
         let exchange_rate = if of_kind == EUR {
             1.
         } else {
-            self.get_reservable_quantity_by_kind(EUR) / (self.get_reservable_quantity_by_kind(of_kind) + self.get_reserved_quantity_by_kind(of_kind) + self.get_future_quantity_by_kind(of_kind) + of_quantity)
+            curve.sell_exchange_rate(self, of_kind, of_quantity)
         };
 
         let sell_price = of_quantity * exchange_rate * (100. - exchange_rate_earn_percentage) / 100.;
@@ -106,32 +123,119 @@ impl AccountOps {
         Ok(sell_price)
     }
 
-    pub fn compute_buy_exchange_rate(&self, kind: GoodKind, exchange_rate_earn_percentage: f32) -> Result<f32, BuyExchangeRateComputationError> {
-        self.compute_buy_price(kind, 1., exchange_rate_earn_percentage)
+    /// Like [AccountOps::compute_buy_price], but paid in `in_kind`. The EUR price is paid with as much `in_kind` as the
+    /// market would buy for it, without a spread, so that only the spread of `of_kind` is paid. A price that no quantity
+    /// of `in_kind` is worth to the market is refused like a quantity without a finite price.
+    pub fn compute_cross_buy_price(&self, curve: &dyn PricingCurve, of_kind: GoodKind, of_quantity: f32, in_kind: GoodKind, exchange_rate_earn_percentage: f32) -> Result<f32, BuyPriceComputationError> {
+        if of_kind == in_kind {
+            return Err(BuyPriceComputationError::SameKind);
+        }
+
+        let buy_price = self.compute_buy_price(curve, of_kind, of_quantity, exchange_rate_earn_percentage)?;
+        self.get_quantity_worth(curve, in_kind, buy_price)
+            .ok_or(BuyPriceComputationError::ExceedsReservableQuantity { reservable: self.get_reservable_quantity_by_kind(of_kind) })
+    }
+
+    /// Like [AccountOps::compute_sell_price], but paid in `in_kind`. The EUR price is paid with as much `in_kind` as the
+    /// market would sell for it, without a spread, so that only the spread of `of_kind` is paid.
+    pub fn compute_cross_sell_price(&self, curve: &dyn PricingCurve, of_kind: GoodKind, of_quantity: f32, in_kind: GoodKind, exchange_rate_earn_percentage: f32) -> Result<f32, SellPriceComputationError> {
+        if of_kind == in_kind {
+            return Err(SellPriceComputationError::SameKind);
+        }
+
+        let sell_price = self.compute_sell_price(curve, of_kind, of_quantity, exchange_rate_earn_percentage)?;
+        Ok(self.get_quantity_bought_with(curve, in_kind, sell_price))
+    }
+
+    /// Smallest quantity of `kind` the market would buy for at least `price` EUR, `None` if no finite quantity is worth
+    /// that much. The rate of a sell depends on its quantity, so the quantity is searched for by halving an interval.
+    fn get_quantity_worth(&self, curve: &dyn PricingCurve, kind: GoodKind, price: f32) -> Option<f32> {
+        if kind == EUR {
+            return Some(price);
+        }
+
+        let worth = |quantity: f32| self.compute_sell_price(curve, kind, quantity, 0.).unwrap();
+
+        // doubled until it is worth the price, a NaN worth included
+        let mut high: f32 = 1.;
+        while !matches!(worth(high).partial_cmp(&price), Some(Ordering::Greater | Ordering::Equal)) {
+            high *= 2.;
+            if !high.is_finite() {
+                return None;
+            }
+        }
+
+        let mut low = 0.;
+        while let Some(middle) = middle_between(low, high) {
+            if worth(middle) >= price {
+                high = middle;
+            } else {
+                low = middle;
+            }
+        }
+
+        Some(high)
+    }
+
+    /// Largest quantity of `kind` the market would sell for at most `price` EUR.
+    fn get_quantity_bought_with(&self, curve: &dyn PricingCurve, kind: GoodKind, price: f32) -> f32 {
+        if kind == EUR {
+            return price;
+        }
+
+        let costs_at_most = |quantity: f32| matches!(self.compute_buy_price(curve, kind, quantity, 0.), Ok(cost) if cost <= price);
+
+        let mut low = 0.;
+        let mut high = self.get_reservable_quantity_by_kind(kind);
+        if costs_at_most(high) {
+            return high;
+        }
+        while let Some(middle) = middle_between(low, high) {
+            if costs_at_most(middle) {
+                low = middle;
+            } else {
+                high = middle;
+            }
+        }
+
+        low
+    }
+
+    pub fn compute_buy_exchange_rate(&self, curve: &dyn PricingCurve, kind: GoodKind, exchange_rate_earn_percentage: f32) -> Result<f32, BuyExchangeRateComputationError> {
+        self.compute_buy_price(curve, kind, 1., exchange_rate_earn_percentage)
             .map_err(|err| match err {
-                BuyPriceComputationError::NonPositiveQuantity => unreachable!(),
+                BuyPriceComputationError::NonPositiveQuantity | BuyPriceComputationError::SameKind => unreachable!(),
                 BuyPriceComputationError::NegativeExchangeRateEarnPercentage => BuyExchangeRateComputationError::NonPositiveExchangeRateEarnPercentage,
                 BuyPriceComputationError::ExceedsReservableQuantity { reservable } =>
                     BuyExchangeRateComputationError::ExceedsReservableQuantity { reservable }
             })
     }
 
-    pub fn compute_sell_exchange_rate(&self, kind: GoodKind, exchange_rate_earn_percentage: f32) -> Result<f32, SellExchangeRateComputationError> {
-        self.compute_sell_price(kind, 1., exchange_rate_earn_percentage)
+    pub fn compute_sell_exchange_rate(&self, curve: &dyn PricingCurve, kind: GoodKind, exchange_rate_earn_percentage: f32) -> Result<f32, SellExchangeRateComputationError> {
+        self.compute_sell_price(curve, kind, 1., exchange_rate_earn_percentage)
             .map_err(|err| match err {
-                SellPriceComputationError::NonPositiveQuantity => unreachable!(),
+                SellPriceComputationError::NonPositiveQuantity | SellPriceComputationError::SameKind => unreachable!(),
                 SellPriceComputationError::NegativeExchangeEarnRatePercentage =>
                     SellExchangeRateComputationError::NonPositiveExchangeRateEarnPercentage
             })
     }
 }
 
+/// Quantity halfway between `low` and `high`, `None` once no `f32` lies between them.
+fn middle_between(low: f32, high: f32) -> Option<f32> {
+    let middle = low + (high - low) / 2.;
+    (middle > low && middle < high).then_some(middle)
+}
+
 #[cfg(test)]
 mod tests {
     use assert_approx_eq::assert_approx_eq;
     use unitn_market_2022::good::good::Good;
     use unitn_market_2022::good::good_kind::GoodKind::{USD, YEN, YUAN};
 
+    use crate::pricing_curve::FutureInventoryCurve;
+    use crate::test_fixtures::uniform_account;
+
     use super::*;
 
     #[test]
@@ -146,7 +250,7 @@ mod tests {
         let ops = AccountOps::of_assets(assets);
 
 
-        let computed = ops.compute_buy_price(EUR, 77., 1.).unwrap();
+        let computed = ops.compute_buy_price(&FutureInventoryCurve, EUR, 77., 1.).unwrap();
         let expected = 77. + (77. * 0.01);
 
         assert_eq!(computed, expected);
@@ -163,7 +267,7 @@ mod tests {
 
         let ops = AccountOps::of_assets(assets);
 
-        let computed = ops.compute_sell_price(EUR, 325., 1.).unwrap();
+        let computed = ops.compute_sell_price(&FutureInventoryCurve, EUR, 325., 1.).unwrap();
         let expected = 325. - (325. * 0.01);
 
         assert_eq!(computed, expected);
@@ -184,9 +288,9 @@ mod tests {
             yuan: Good::new(YUAN, 0.),
         };
 
-        let ops = AccountOps { assets, reservations, futures: Account::new_empty() };
+        let ops = AccountOps { assets: Ledger::of_account(&assets), reservations: Ledger::of_account(&reservations), futures: Ledger::new_empty(), deposits: Ledger::new_empty() };
 
-        let computed = ops.compute_buy_price(EUR, 5_000., 1.).unwrap_err();
+        let computed = ops.compute_buy_price(&FutureInventoryCurve, EUR, 5_000., 1.).unwrap_err();
         let expected = BuyPriceComputationError::ExceedsReservableQuantity { reservable: 2_000. };
 
         assert_eq!(computed, expected)
@@ -203,7 +307,7 @@ mod tests {
 
         let ops = AccountOps::of_assets(assets);
 
-        let computed = ops.compute_buy_price(USD, 9_874., 1.).unwrap_err();
+        let computed = ops.compute_buy_price(&FutureInventoryCurve, USD, 9_874., 1.).unwrap_err();
         let expected = BuyPriceComputationError::ExceedsReservableQuantity { reservable: 4_000. };
 
         assert_eq!(computed, expected);
@@ -224,9 +328,9 @@ mod tests {
             yuan: Good::new(YUAN, 0.),
         };
 
-        let ops = AccountOps { assets, reservations, futures: Account::new_empty() };
+        let ops = AccountOps { assets: Ledger::of_account(&assets), reservations: Ledger::of_account(&reservations), futures: Ledger::new_empty(), deposits: Ledger::new_empty() };
 
-        let computed = ops.compute_buy_price(USD, 9_874., 1.).unwrap_err();
+        let computed = ops.compute_buy_price(&FutureInventoryCurve, USD, 9_874., 1.).unwrap_err();
         let expected = BuyPriceComputationError::ExceedsReservableQuantity { reservable: 4_000. };
 
         assert_eq!(computed, expected);
@@ -243,7 +347,7 @@ mod tests {
 
         let ops = AccountOps::of_assets(assets);
 
-        let computed = ops.compute_buy_price(USD, 1_800., 1.).unwrap();
+        let computed = ops.compute_buy_price(&FutureInventoryCurve, USD, 1_800., 1.).unwrap();
 
         assert_eq!(computed, 9_090.);
     }
@@ -263,9 +367,9 @@ mod tests {
             yuan: Good::new(YUAN, 0.),
         };
 
-        let ops = AccountOps { assets, reservations, futures: Account::new_empty() };
+        let ops = AccountOps { assets: Ledger::of_account(&assets), reservations: Ledger::of_account(&reservations), futures: Ledger::new_empty(), deposits: Ledger::new_empty() };
 
-        let computed = ops.compute_buy_price(USD, 1_800., 1.).unwrap();
+        let computed = ops.compute_buy_price(&FutureInventoryCurve, USD, 1_800., 1.).unwrap();
 
         assert_eq!(computed, 18_180.);
     }
@@ -291,9 +395,9 @@ mod tests {
             yuan: Good::new(YUAN, 0.),
         };
 
-        let ops = AccountOps { assets, reservations, futures };
+        let ops = AccountOps { assets: Ledger::of_account(&assets), reservations: Ledger::of_account(&reservations), futures: Ledger::of_account(&futures), deposits: Ledger::new_empty() };
 
-        let computed = ops.compute_buy_price(USD, 1_800., 1.).unwrap();
+        let computed = ops.compute_buy_price(&FutureInventoryCurve, USD, 1_800., 1.).unwrap();
 
         assert_eq!(computed, 21_816.);
     }
@@ -309,7 +413,7 @@ mod tests {
 
         let ops = AccountOps::of_assets(assets);
 
-        let computed = ops.compute_buy_price(YEN, 54_879., 1.).unwrap();
+        let computed = ops.compute_buy_price(&FutureInventoryCurve, YEN, 54_879., 1.).unwrap();
 
         assert_approx_eq!(computed, 8_512., 1.);
     }
@@ -329,9 +433,9 @@ mod tests {
             yuan: Good::new(YUAN, 0.),
         };
 
-        let ops = AccountOps { assets, reservations, futures: Account::new_empty() };
+        let ops = AccountOps { assets: Ledger::of_account(&assets), reservations: Ledger::of_account(&reservations), futures: Ledger::new_empty(), deposits: Ledger::new_empty() };
 
-        let computed = ops.compute_buy_price(YEN, 54_879., 1.).unwrap();
+        let computed = ops.compute_buy_price(&FutureInventoryCurve, YEN, 54_879., 1.).unwrap();
 
         assert_approx_eq!(computed, 10_875., 1.);
     }
@@ -357,13 +461,62 @@ mod tests {
             yuan: Good::new(YUAN, 0.),
         };
 
-        let ops = AccountOps { assets, reservations, futures };
+        let ops = AccountOps { assets: Ledger::of_account(&assets), reservations: Ledger::of_account(&reservations), futures: Ledger::of_account(&futures), deposits: Ledger::new_empty() };
 
-        let computed = ops.compute_buy_price(YEN, 54_879., 1.).unwrap();
+        let computed = ops.compute_buy_price(&FutureInventoryCurve, YEN, 54_879., 1.).unwrap();
 
         assert_approx_eq!(computed, 12_516., 1.);
     }
 
+    #[test]
+    fn test_cross_buy_computed() {
+        let assets = Account {
+            eur: Good::new(EUR, 100_000.),
+            usd: Good::new(USD, 100_000.),
+            yen: Good::new(YEN, 100_000.),
+            yuan: Good::new(YUAN, 0.),
+        };
+
+        let ops = AccountOps::of_assets(assets);
+
+        let buy_price = ops.compute_buy_price(&FutureInventoryCurve, USD, 1_000., 1.).unwrap();
+        assert_eq!(ops.compute_cross_buy_price(&FutureInventoryCurve, USD, 1_000., EUR, 1.).unwrap(), buy_price);
+
+        // the YEN the market would buy for the EUR price, which is cheaper than paying the spread of the sell as well
+        let computed = ops.compute_cross_buy_price(&FutureInventoryCurve, USD, 1_000., YEN, 1.).unwrap();
+        assert_approx_eq!(computed, buy_price * 100_000. / (100_000. - buy_price), 0.01);
+        assert!(computed < ops.compute_cross_buy_price(&FutureInventoryCurve, USD, 1_000., YEN, 2.).unwrap());
+
+        let sell_price = ops.compute_sell_price(&FutureInventoryCurve, USD, 1_000., 1.).unwrap();
+        assert_eq!(ops.compute_cross_sell_price(&FutureInventoryCurve, USD, 1_000., EUR, 1.).unwrap(), sell_price);
+        let computed = ops.compute_cross_sell_price(&FutureInventoryCurve, USD, 1_000., YEN, 1.).unwrap();
+        assert_approx_eq!(computed, sell_price * 100_000. / (100_000. + sell_price), 0.01);
+
+        assert_eq!(ops.compute_cross_buy_price(&FutureInventoryCurve, YEN, 1_000., YEN, 1.), Err(BuyPriceComputationError::SameKind));
+        assert_eq!(ops.compute_cross_sell_price(&FutureInventoryCurve, EUR, 1_000., EUR, 1.), Err(SellPriceComputationError::SameKind));
+    }
+
+    #[test]
+    fn test_large_cross_prices() {
+        let ops = AccountOps::of_assets(uniform_account(100_000.));
+
+        // the YEN paid is worth the whole EUR price to the market, which pays at most the spread of one leg less than
+        // selling the YEN and buying the USD one after the other
+        let buy_price = ops.compute_buy_price(&FutureInventoryCurve, USD, 40_000., 1.).unwrap();
+        let computed = ops.compute_cross_buy_price(&FutureInventoryCurve, USD, 40_000., YEN, 1.).unwrap();
+        assert!(ops.compute_sell_price(&FutureInventoryCurve, YEN, computed, 0.).unwrap() >= buy_price);
+
+        let sell_price = ops.compute_sell_price(&FutureInventoryCurve, USD, 40_000., 1.).unwrap();
+        let computed = ops.compute_cross_sell_price(&FutureInventoryCurve, USD, 40_000., YEN, 1.).unwrap();
+        assert!(ops.compute_buy_price(&FutureInventoryCurve, YEN, computed, 0.).unwrap() <= sell_price);
+
+        // no quantity of YEN is worth more EUR than the market has
+        let buy_price = ops.compute_buy_price(&FutureInventoryCurve, USD, 60_000., 1.).unwrap();
+        assert!(buy_price > 100_000.);
+        assert_eq!(ops.compute_cross_buy_price(&FutureInventoryCurve, USD, 60_000., YEN, 1.),
+            Err(BuyPriceComputationError::ExceedsReservableQuantity { reservable: 100_000. }));
+    }
+
     #[test]
     fn test_sell_computed_1() {
         let assets = Account {
@@ -375,7 +528,7 @@ mod tests {
 
         let ops = AccountOps::of_assets(assets);
 
-        let computed = ops.compute_sell_price(YUAN, 10_500., 1.).unwrap();
+        let computed = ops.compute_sell_price(&FutureInventoryCurve, YUAN, 10_500., 1.).unwrap();
 
         assert_approx_eq!(computed, 4_255., 1.);
     }
@@ -395,9 +548,9 @@ mod tests {
             yuan: Good::new(YUAN, 35_000.),
         };
 
-        let ops = AccountOps { assets, reservations, futures: Account::new_empty() };
+        let ops = AccountOps { assets: Ledger::of_account(&assets), reservations: Ledger::of_account(&reservations), futures: Ledger::new_empty(), deposits: Ledger::new_empty() };
 
-        let computed = ops.compute_sell_price(YUAN, 10_500., 1.).unwrap();
+        let computed = ops.compute_sell_price(&FutureInventoryCurve, YUAN, 10_500., 1.).unwrap();
 
         assert_approx_eq!(computed, 2_431., 1.);
     }
@@ -423,9 +576,9 @@ mod tests {
             yuan: Good::new(YUAN, 14_000.),
         };
 
-        let ops = AccountOps { assets, reservations, futures };
+        let ops = AccountOps { assets: Ledger::of_account(&assets), reservations: Ledger::of_account(&reservations), futures: Ledger::of_account(&futures), deposits: Ledger::new_empty() };
 
-        let computed = ops.compute_sell_price(YUAN, 10_500., 1.).unwrap();
+        let computed = ops.compute_sell_price(&FutureInventoryCurve, YUAN, 10_500., 1.).unwrap();
 
         assert_approx_eq!(computed, 2_089., 1.);
     }
@@ -441,7 +594,7 @@ mod tests {
 
         let ops = AccountOps::of_assets(assets);
 
-        let computed = ops.compute_sell_price(USD, 36_000., 1.).unwrap();
+        let computed = ops.compute_sell_price(&FutureInventoryCurve, USD, 36_000., 1.).unwrap();
 
         assert_approx_eq!(computed, 49_119., 1.);
     }
@@ -461,9 +614,9 @@ mod tests {
             yuan: Good::new(YUAN, 0.),
         };
 
-        let ops = AccountOps { assets, reservations, futures: Account::new_empty() };
+        let ops = AccountOps { assets: Ledger::of_account(&assets), reservations: Ledger::of_account(&reservations), futures: Ledger::new_empty(), deposits: Ledger::new_empty() };
 
-        let computed = ops.compute_sell_price(USD, 36_000., 1.).unwrap();
+        let computed = ops.compute_sell_price(&FutureInventoryCurve, USD, 36_000., 1.).unwrap();
 
         assert_approx_eq!(computed, 44_890., 1.);
     }
@@ -489,9 +642,9 @@ mod tests {
             yuan: Good::new(YUAN, 0.),
         };
 
-        let ops = AccountOps { assets, reservations, futures };
+        let ops = AccountOps { assets: Ledger::of_account(&assets), reservations: Ledger::of_account(&reservations), futures: Ledger::of_account(&futures), deposits: Ledger::new_empty() };
 
-        let computed = ops.compute_sell_price(USD, 36_000., 1.).unwrap();
+        let computed = ops.compute_sell_price(&FutureInventoryCurve, USD, 36_000., 1.).unwrap();
 
         assert_approx_eq!(computed, 37_101., 1.);
     }
@@ -507,7 +660,7 @@ mod tests {
 
         let ops = AccountOps::of_assets(assets);
 
-        let computed = ops.compute_sell_price(USD, 0., 1.).unwrap_err();
+        let computed = ops.compute_sell_price(&FutureInventoryCurve, USD, 0., 1.).unwrap_err();
 
         assert_eq!(computed, SellPriceComputationError::NonPositiveQuantity);
     }
@@ -523,7 +676,7 @@ mod tests {
 
         let ops = AccountOps::of_assets(assets);
 
-        let computed = ops.compute_buy_price(YEN, 29_000., 1.).unwrap_err();
+        let computed = ops.compute_buy_price(&FutureInventoryCurve, YEN, 29_000., 1.).unwrap_err();
 
         assert_eq!(computed, BuyPriceComputationError::ExceedsReservableQuantity { reservable: 0.0 });
     }
@@ -539,7 +692,7 @@ mod tests {
 
         let ops = AccountOps::of_assets(assets);
 
-        let computed = ops.compute_buy_price(YEN, 0., 1.).unwrap_err();
+        let computed = ops.compute_buy_price(&FutureInventoryCurve, YEN, 0., 1.).unwrap_err();
 
         assert_eq!(computed, BuyPriceComputationError::NonPositiveQuantity);
     }
@@ -553,9 +706,9 @@ mod tests {
             yuan: Good::new(YUAN, 0.),
         };
 
-        let ops = AccountOps { assets, reservations: Account::new_empty(), futures: Account::new_empty() };
+        let ops = AccountOps { assets: Ledger::of_account(&assets), reservations: Ledger::new_empty(), futures: Ledger::new_empty(), deposits: Ledger::new_empty() };
 
-        let computed = ops.compute_buy_price(USD, f32::MIN_POSITIVE, 1.).unwrap();
+        let computed = ops.compute_buy_price(&FutureInventoryCurve, USD, f32::MIN_POSITIVE, 1.).unwrap();
 
         assert_ne!(computed, 0.);
     }
@@ -0,0 +1,192 @@
+use std::collections::{HashMap, HashSet};
+
+use unitn_market_2022::good::good_kind::GoodKind;
+use uuid::Uuid;
+
+use crate::account_ops::AccountOps;
+use crate::basket_transaction::BasketTx;
+use crate::buy_transaction::{BuyTx, BuyTxState};
+use crate::forward_contract::{ForwardTx, ForwardTxState};
+use crate::ledger::Ledger;
+use crate::quantity::Quantity;
+use crate::sell_transaction::{SellTx, SellTxState};
+
+/// Operation of the [TxService](crate::service::TxService) after which the ledgers were audited, with the transactions
+/// it touched.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AuditedOperation {
+    /// `uuid` is `None` when the reservation was refused.
+    BuyReservation { uuid: Option<Uuid> },
+    SellReservation { uuid: Option<Uuid> },
+    BasketReservation { uuid: Option<Uuid> },
+    ForwardAgreement { uuid: Option<Uuid> },
+    Buy { uuid: Uuid },
+    Sell { uuid: Uuid },
+    BasketBuy { uuid: Uuid },
+    ForwardDelivery { uuid: Uuid },
+    ForwardClaim { uuid: Uuid },
+    CancelBuy { uuid: Uuid },
+    CancelSell { uuid: Uuid },
+    RenewBuy { uuid: Uuid },
+    RenewSell { uuid: Uuid },
+    Tick { expired: Vec<Uuid> },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Discrepancy {
+    /// The reserved goods do not match the goods of the `Reserved` transactions and of the open forwards.
+    Reservations { kind: GoodKind, ledger: Quantity, transactions: Quantity },
+    /// The future goods do not match the pending bids of buys, the goods of sells and what the open forwards bring in.
+    Futures { kind: GoodKind, ledger: Quantity, transactions: Quantity },
+    /// The deposits do not match the deposits of the `Reserved` transactions and the margins of the open forwards.
+    Deposits { kind: GoodKind, ledger: Quantity, transactions: Quantity },
+    /// A `Reserved` transaction or open forward that is not scheduled to expire.
+    Unscheduled { uuid: Uuid },
+}
+
+/// Everything that did not reconcile after an operation. `tick` is the number of days passed when it happened.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuditReport {
+    pub tick: u32,
+    pub operation: AuditedOperation,
+    pub discrepancies: Vec<Discrepancy>,
+}
+
+/// Every transaction of the [TxService](crate::service::TxService), by uuid.
+pub struct AuditedTransactions<'a> {
+    pub buys: &'a HashMap<Uuid, BuyTx>,
+    pub sells: &'a HashMap<Uuid, SellTx>,
+    pub baskets: &'a HashMap<Uuid, BasketTx>,
+    pub forwards: &'a HashMap<Uuid, ForwardTx>,
+}
+
+/// Opt-in check that the ledgers of the market reconcile with its transactions. Only the operations that left a
+/// discrepancy are reported.
+#[derive(Debug, Clone, Default)]
+pub struct Auditor {
+    reports: Vec<AuditReport>,
+}
+
+impl Auditor {
+    pub fn new() -> Auditor {
+        Auditor { reports: vec![] }
+    }
+
+    pub fn audit<'a>(&mut self, tick: u32, operation: AuditedOperation, ops: &AccountOps, txs: AuditedTransactions,
+                     scheduled: impl Iterator<Item=&'a Uuid>) {
+        let AuditedTransactions { buys, sells, baskets, forwards } = txs;
+        let mut reservations = Ledger::new_empty();
+        let mut futures = Ledger::new_empty();
+        let mut deposits = Ledger::new_empty();
+        let mut unscheduled: HashSet<&Uuid> = HashSet::new();
+
+        for (uuid, tx) in buys.iter().filter(|(_, tx)| matches!(tx.state, BuyTxState::Reserved)) {
+            reservations.deposit(tx.buy.get_kind(), Quantity::from_f32(tx.buy.get_qty()));
+            futures.deposit(tx.bid.get_kind(), Quantity::from_f32(tx.bid.get_qty()));
+            deposits.deposit(tx.bid.get_kind(), Quantity::from_f32(tx.deposit));
+            unscheduled.insert(uuid);
+        }
+        for (uuid, tx) in sells.iter().filter(|(_, tx)| matches!(tx.state, SellTxState::Reserved)) {
+            reservations.deposit(tx.offer.get_kind(), Quantity::from_f32(tx.offer.get_qty()));
+            futures.deposit(tx.sell.get_kind(), Quantity::from_f32(tx.sell.get_qty()));
+            deposits.deposit(tx.offer.get_kind(), Quantity::from_f32(tx.deposit));
+            unscheduled.insert(uuid);
+        }
+        for (uuid, tx) in baskets.iter().filter(|(_, tx)| matches!(tx.get_state(), BuyTxState::Reserved)) {
+            for leg in &tx.legs {
+                reservations.deposit(leg.buy.get_kind(), Quantity::from_f32(leg.buy.get_qty()));
+                futures.deposit(leg.bid.get_kind(), Quantity::from_f32(leg.bid.get_qty()));
+            }
+            unscheduled.insert(uuid);
+        }
+        for (uuid, tx) in forwards.iter().filter(|(_, tx)| tx.state == ForwardTxState::Open) {
+            reservations.deposit(tx.get_outgoing().get_kind(), Quantity::from_f32(tx.get_outgoing().get_qty()));
+            futures.deposit(tx.get_incoming().get_kind(), Quantity::from_f32(tx.get_incoming().get_qty()));
+            deposits.deposit(tx.price.get_kind(), Quantity::from_f32(tx.margin));
+            unscheduled.insert(uuid);
+        }
+        for uuid in scheduled {
+            unscheduled.remove(uuid);
+        }
+
+        let mut discrepancies = vec![];
+        for kind in [GoodKind::EUR, GoodKind::USD, GoodKind::YEN, GoodKind::YUAN] {
+            if ops.reservations.get(kind) != reservations.get(kind) {
+                discrepancies.push(Discrepancy::Reservations { kind, ledger: ops.reservations.get(kind), transactions: reservations.get(kind) });
+            }
+            if ops.futures.get(kind) != futures.get(kind) {
+                discrepancies.push(Discrepancy::Futures { kind, ledger: ops.futures.get(kind), transactions: futures.get(kind) });
+            }
+            if ops.deposits.get(kind) != deposits.get(kind) {
+                discrepancies.push(Discrepancy::Deposits { kind, ledger: ops.deposits.get(kind), transactions: deposits.get(kind) });
+            }
+        }
+        discrepancies.extend(unscheduled.into_iter().map(|uuid| Discrepancy::Unscheduled { uuid: *uuid }));
+
+        if !discrepancies.is_empty() {
+            self.reports.push(AuditReport { tick, operation, discrepancies });
+        }
+    }
+
+    pub fn get_reports(&self) -> &[AuditReport] {
+        &self.reports
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+    use unitn_market_2022::good::good::Good;
+    use unitn_market_2022::good::good_kind::GoodKind::{EUR, USD, YEN};
+
+    use crate::buy_transaction::BuyTxProposal;
+    use crate::pricing_curve::FutureInventoryCurve;
+    use crate::sell_transaction::SellTxProposal;
+    use crate::service::TxService;
+    use crate::test_fixtures::uniform_account;
+
+    use super::*;
+
+    fn service() -> TxService {
+        let assets = uniform_account(100_000.);
+
+        let mut service = TxService::new(AccountOps::of_assets(assets), 2);
+        service.enable_audit();
+        service
+    }
+
+    #[test]
+    fn balanced_operations() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let mut service = service();
+
+        let buy = service.do_buy_reservation(&BuyTxProposal { buy: Good::new(USD, 10.3), bid: Good::new(EUR, 100.7), trader_name: "trader".to_string() }, &FutureInventoryCurve, 1., None, 0., &mut rng).unwrap();
+        service.do_sell_reservation(&SellTxProposal { sell: Good::new(YEN, 20.1), offer: Good::new(EUR, 1.3), trader_name: "trader".to_string() }, &FutureInventoryCurve, 1., None, 0., &mut rng).unwrap();
+        service.do_buy_reservation(&BuyTxProposal { buy: Good::new(USD, 10.), bid: Good::new(EUR, 0.1), trader_name: "trader".to_string() }, &FutureInventoryCurve, 1., None, 0., &mut rng).unwrap_err();
+        service.do_buy(&buy, &mut Good::new(EUR, 200.)).unwrap();
+        service.tick_all();
+        service.tick_all();
+
+        assert_eq!(service.get_audit_reports(), &[]);
+    }
+
+    #[test]
+    fn unbalanced_ledger() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let mut service = service();
+
+        let buy = service.do_buy_reservation(&BuyTxProposal { buy: Good::new(USD, 10.), bid: Good::new(EUR, 100.), trader_name: "trader".to_string() }, &FutureInventoryCurve, 1., None, 0., &mut rng).unwrap();
+        service.get_account_ops_mut().futures.deposit(EUR, Quantity::from_f32(0.5));
+        service.tick_all();
+        service.tick_all();
+
+        let reports = service.get_audit_reports();
+        assert_eq!(reports.len(), 2);
+        assert_eq!(reports[1], AuditReport {
+            tick: 2,
+            operation: AuditedOperation::Tick { expired: vec![buy] },
+            discrepancies: vec![Discrepancy::Futures { kind: EUR, ledger: Quantity::from_f32(0.5), transactions: Quantity::ZERO }],
+        });
+    }
+}
@@ -0,0 +1,137 @@
+use serde::{Deserialize, Serialize};
+use unitn_market_2022::good::consts::DEFAULT_GOOD_KIND;
+use unitn_market_2022::good::good::Good;
+use unitn_market_2022::good::good_kind::GoodKind;
+
+use crate::account_ops::{AccountOps, BuyPriceComputationError};
+use crate::buy_transaction::{BuyTx, BuyTxProposal, BuyTxReservationError, BuyTxState};
+use crate::per_good::PerGood;
+use crate::pricing_curve::PricingCurve;
+use crate::quantity::Quantity;
+
+/// Buys of several goods locked together: they are reserved, paid and expired all at once.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BasketTx {
+    pub legs: Vec<BuyTx>,
+}
+
+#[derive(Debug)]
+pub struct BasketLeg {
+    pub buy: Good,
+    pub bid: Good,
+}
+
+#[derive(Debug)]
+pub struct BasketTxProposal {
+    pub legs: Vec<BasketLeg>,
+    pub trader_name: String,
+}
+
+#[derive(Debug, Clone)]
+pub enum BasketTxReservationError {
+    EmptyBasket,
+    /// Every leg must be bid in the same good, so that the basket can be paid at once.
+    MixedBidKinds,
+    /// The leg at `index` could not be reserved, so none was.
+    Leg { index: usize, error: BuyTxReservationError },
+}
+
+#[derive(Debug)]
+pub enum BasketTxQuoteError {
+    EmptyBasket,
+    /// The leg at `index` could not be priced, so none was.
+    Leg { index: usize, error: BuyPriceComputationError },
+}
+
+#[derive(Debug, Clone)]
+pub enum BasketTxPaymentError {
+    InvalidState { current_state: BuyTxState },
+    WrongGoodKind { pre_agreed: GoodKind },
+    InsufficientGoodQuantity { pre_agreed: f32 },
+}
+
+impl BasketTx {
+    /// Locks every leg of the proposal, each priced with the earn percentage of the good it buys, or none of them.
+    pub fn reserve(ops: &mut AccountOps, proposal: &BasketTxProposal, curve: &dyn PricingCurve, exchange_rate_earn_percentages: &PerGood<f32>) -> Result<BasketTx, BasketTxReservationError> {
+        let bid_kind = match proposal.legs.first() {
+            Some(leg) => leg.bid.get_kind(),
+            None => return Err(BasketTxReservationError::EmptyBasket),
+        };
+        if proposal.legs.iter().any(|leg| leg.bid.get_kind() != bid_kind) {
+            return Err(BasketTxReservationError::MixedBidKinds);
+        }
+
+        // the legs are reserved one after the other on a copy, so that they compete for the same goods
+        let mut staged = ops.clone();
+        let mut legs = vec![];
+        for (index, leg) in proposal.legs.iter().enumerate() {
+            let leg_proposal = BuyTxProposal { buy: leg.buy.clone(), bid: leg.bid.clone(), trader_name: proposal.trader_name.clone() };
+            let earn_percentage = *exchange_rate_earn_percentages.get(leg.buy.get_kind());
+            let tx = BuyTx::reserve(&mut staged, &leg_proposal, curve, earn_percentage, 0.)
+                .map_err(|error| BasketTxReservationError::Leg { index, error })?;
+            legs.push(tx);
+        }
+
+        *ops = staged;
+        Ok(BasketTx { legs })
+    }
+
+    /// Lowest bids in the default good, in the order of `legs`, that [BasketTx::reserve] accepts for a basket buying
+    /// them. Each leg is priced as [BasketTx::reserve] does, after the previous ones are locked at their own price.
+    pub fn quote(ops: &AccountOps, legs: &[Good], curve: &dyn PricingCurve, exchange_rate_earn_percentages: &PerGood<f32>) -> Result<Vec<f32>, BasketTxQuoteError> {
+        if legs.is_empty() {
+            return Err(BasketTxQuoteError::EmptyBasket);
+        }
+
+        let mut staged = ops.clone();
+        let mut prices = vec![];
+        for (index, leg) in legs.iter().enumerate() {
+            let earn_percentage = *exchange_rate_earn_percentages.get(leg.get_kind());
+            let price = staged.compute_buy_price(curve, leg.get_kind(), leg.get_qty(), earn_percentage)
+                .map_err(|error| BasketTxQuoteError::Leg { index, error })?;
+            let leg_proposal = BuyTxProposal { buy: leg.clone(), bid: Good::new(DEFAULT_GOOD_KIND, price), trader_name: String::new() };
+            BuyTx::reserve_at_bid(&mut staged, &leg_proposal).expect("a priced leg is reservable");
+            prices.push(price);
+        }
+
+        Ok(prices)
+    }
+
+    /// Takes the bids of all the legs out of `with` and gives back the goods of every leg, in the order of the legs.
+    pub fn buy(&mut self, ops: &mut AccountOps, with: &mut Good) -> Result<Vec<Good>, BasketTxPaymentError> {
+        match self.get_state() {
+            BuyTxState::Reserved => {
+                let bid_kind = self.legs[0].bid.get_kind();
+                let due = self.legs.iter()
+                    .map(|leg| Quantity::from_f32(leg.bid.get_qty()))
+                    .fold(Quantity::ZERO, |due, bid| due + bid)
+                    .to_f32();
+
+                if with.get_kind() != bid_kind {
+                    Err(BasketTxPaymentError::WrongGoodKind { pre_agreed: bid_kind })
+                } else if with.get_qty() < due {
+                    Err(BasketTxPaymentError::InsufficientGoodQuantity { pre_agreed: due })
+                } else {
+                    let from_buyer = with.split(due).unwrap();
+                    ops.assets.deposit(from_buyer.get_kind(), Quantity::from_f32(from_buyer.get_qty()));
+
+                    Ok(self.legs.iter_mut().map(|leg| leg.deliver(ops)).collect())
+                }
+            }
+            other => Err(BasketTxPaymentError::InvalidState { current_state: other.clone() })
+        }
+    }
+
+    pub fn expire(&mut self, ops: &mut AccountOps) {
+        self.legs.iter_mut().for_each(|leg| leg.expire(ops));
+    }
+
+    /// The legs always share the same state.
+    pub fn get_state(&self) -> &BuyTxState {
+        &self.legs[0].state
+    }
+
+    pub fn get_trader_name(&self) -> &str {
+        &self.legs[0].trader_name
+    }
+}
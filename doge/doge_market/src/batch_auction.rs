@@ -0,0 +1,137 @@
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+use unitn_market_2022::good::good::Good;
+use uuid::Uuid;
+
+use crate::order_book::OrderSide;
+use crate::serde_defs::GoodDef;
+
+/// How the market prices the locks of [Market::lock_buy](unitn_market_2022::market::Market::lock_buy) and
+/// [Market::lock_sell](unitn_market_2022::market::Market::lock_sell).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LockClearing {
+    /// Every lock is priced as it comes, after the ones before it moved the price.
+    #[default]
+    Continuous,
+    /// Locks are queued during the day and cleared together when it ends, every lock of the same side and good at the
+    /// same unit price.
+    BatchAuction,
+}
+
+/// Lock waiting for the end of the day. `limit` is the bid of a buy or the offer of a sell, in the default good.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueuedLock {
+    pub uuid: Uuid,
+    pub side: OrderSide,
+    /// Good bought from or sold to the market.
+    #[serde(with = "GoodDef")]
+    pub good: Good,
+    pub limit: f32,
+    pub trader_name: String,
+}
+
+/// Locks queued since the day began, in the order they came, and the ones dropped by the auctions before.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BatchAuction {
+    queue: Vec<QueuedLock>,
+    #[serde(default)]
+    dropped: HashSet<Uuid>,
+}
+
+impl BatchAuction {
+    pub fn new() -> BatchAuction {
+        BatchAuction { queue: vec![], dropped: HashSet::new() }
+    }
+
+    pub fn queue(&mut self, lock: QueuedLock) {
+        self.queue.push(lock);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Empties the queue, handing over the locks to clear.
+    pub fn take(&mut self) -> Vec<QueuedLock> {
+        std::mem::take(&mut self.queue)
+    }
+
+    /// Remembers that the lock queued as `uuid` was not reserved, so that its token is not mistaken for an unknown one.
+    pub fn record_dropped(&mut self, uuid: Uuid) {
+        self.dropped.insert(uuid);
+    }
+
+    pub fn is_dropped(&self, uuid: &Uuid) -> bool {
+        self.dropped.contains(uuid)
+    }
+}
+
+/// Prices locks of the same side and good at a single unit price. `total_price` prices the whole quantity of the locks
+/// still in, `None` if the market cannot trade that much. Each lock is charged or paid its share of the total, and the
+/// least generous lock is dropped until the rest agree with their share. Returns the share of each lock, `None` for the
+/// ones dropped.
+pub fn clear(locks: &[&QueuedLock], total_price: impl Fn(f32) -> Option<f32>) -> Vec<Option<f32>> {
+    let mut cleared = vec![true; locks.len()];
+
+    while cleared.contains(&true) {
+        let quantity: f32 = locks.iter().zip(&cleared).filter(|(_, kept)| **kept).map(|(lock, _)| lock.good.get_qty()).sum();
+        let share = |lock: &QueuedLock, price: f32| {
+            // a lock alone gets the exact price of its quantity
+            if lock.good.get_qty() == quantity { price } else { price * (lock.good.get_qty() / quantity) }
+        };
+
+        if let Some(price) = total_price(quantity) {
+            let agree = locks.iter().zip(&cleared).filter(|(_, kept)| **kept).all(|(lock, _)| match lock.side {
+                OrderSide::Buy => lock.limit >= share(lock, price),
+                OrderSide::Sell => lock.limit <= share(lock, price),
+            });
+            if agree {
+                return locks.iter().zip(&cleared).map(|(lock, kept)| kept.then(|| share(lock, price))).collect();
+            }
+        }
+
+        // buyers paying less and sellers asking more per unit are the least generous
+        let generosity = |lock: &QueuedLock| match lock.side {
+            OrderSide::Buy => lock.limit / lock.good.get_qty(),
+            OrderSide::Sell => -lock.limit / lock.good.get_qty(),
+        };
+        let least_generous = (0..locks.len())
+            .filter(|index| cleared[*index])
+            .min_by(|a, b| generosity(locks[*a]).total_cmp(&generosity(locks[*b])))
+            .unwrap();
+        cleared[least_generous] = false;
+    }
+
+    vec![None; locks.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use unitn_market_2022::good::good_kind::GoodKind::USD;
+
+    use super::*;
+
+    fn lock(side: OrderSide, quantity: f32, limit: f32) -> QueuedLock {
+        QueuedLock { uuid: Uuid::new_v4(), side, good: Good::new(USD, quantity), limit, trader_name: "trader".to_string() }
+    }
+
+    #[test]
+    fn uniform_price() {
+        // the unit price starts at 1 and grows by a tenth with every unit traded
+        let total_price = |quantity: f32| (quantity <= 30.).then(|| quantity * (1. + quantity / 10.));
+
+        let buys = [lock(OrderSide::Buy, 10., 20.), lock(OrderSide::Buy, 10., 30.), lock(OrderSide::Buy, 10., 50.)];
+        let shares = clear(&buys.iter().collect::<Vec<_>>(), total_price);
+        // all three would pay 40 each, so the first one drops out and the other two pay 30 each
+        assert_eq!(shares, vec![None, Some(30.), Some(30.)]);
+
+        // too much to price, so the least generous drops out first
+        let buys = [lock(OrderSide::Buy, 20., 60.), lock(OrderSide::Buy, 20., 100.)];
+        assert_eq!(clear(&buys.iter().collect::<Vec<_>>(), total_price), vec![None, Some(60.)]);
+
+        let sells = [lock(OrderSide::Sell, 10., 20.), lock(OrderSide::Sell, 10., 10.)];
+        assert_eq!(clear(&sells.iter().collect::<Vec<_>>(), Some), vec![None, Some(10.)]);
+    }
+}
@@ -1,25 +1,46 @@
+use serde::{Deserialize, Serialize};
 use unitn_market_2022::good::consts::DEFAULT_GOOD_KIND;
 use unitn_market_2022::good::good::Good;
 use unitn_market_2022::good::good_kind::GoodKind;
 
-use doge_common::account::WithdrawError;
-
 use crate::account_ops::{AccountOps, BuyPriceComputationError};
+use crate::pricing_curve::PricingCurve;
+use crate::quantity::Quantity;
+use crate::serde_defs::GoodDef;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BuyTx {
+    #[serde(with = "GoodDef")]
     pub buy: Good,
+    #[serde(with = "GoodDef")]
     pub bid: Good,
     pub state: BuyTxState,
+    /// Number of times the lock was renewed.
+    #[serde(default)]
+    pub renewals: u32,
+    /// Name the trader gave when locking.
+    #[serde(default)]
+    pub trader_name: String,
+    /// Quantity of the bid good the trader deposited to back the lock.
+    #[serde(default)]
+    pub deposit: f32,
 }
 
-#[derive(Debug, Clone)]
-pub enum BuyTxState { Reserved, Paid, Expired }
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum BuyTxState {
+    Reserved,
+    Paid,
+    /// Paid only in part, with `bid` of the bid good in exchange for `buy` of the good.
+    PartiallyPaid { buy: f32, bid: f32 },
+    Expired,
+    Cancelled,
+}
 
 #[derive(Debug)]
 pub struct BuyTxProposal {
     pub buy: Good,
     pub bid: Good,
+    pub trader_name: String,
 }
 
 #[derive(Debug, Clone)]
@@ -28,6 +49,8 @@ pub enum BuyTxReservationError {
     NonPositiveBid,
     ExceedsReservableQuantity { reservable: f32 },
     BidTooLow { lowest: f32 },
+    /// The bid is in the good to buy, which cannot pay for itself unless it is the default good.
+    UnsupportedBid,
 }
 
 #[derive(Debug, Clone)]
@@ -40,106 +63,197 @@ pub enum BuyTxPaymentError {
 #[derive(Debug, Clone)]
 pub struct ExpireError;
 
+#[derive(Debug, Clone)]
+pub enum BuyTxCancelError {
+    InvalidState { current_state: BuyTxState },
+}
+
+#[derive(Debug, Clone)]
+pub enum BuyTxRenewalError {
+    InvalidState { current_state: BuyTxState },
+    MaxRenewalsReached { max_renewals: u32 },
+    WrongGoodKind { fee_kind: GoodKind },
+    InsufficientGoodQuantity { fee: f32 },
+}
+
 impl BuyTx {
-    pub fn reserve(ops: &mut AccountOps, proposal: &BuyTxProposal) -> Result<BuyTx, BuyTxReservationError> {
+    /// Locks the goods of the proposal, holding `deposit` of the bid good on behalf of the trader until the lock ends. The
+    /// bid can be of any good other than the one to buy, priced with [AccountOps::compute_cross_buy_price].
+    pub fn reserve(ops: &mut AccountOps, proposal: &BuyTxProposal, curve: &dyn PricingCurve, exchange_rate_earn_percentage: f32, deposit: f32) -> Result<BuyTx, BuyTxReservationError> {
+        BuyTx::check_proposal(ops, proposal)?;
+
+        let price = if proposal.bid.get_kind() == DEFAULT_GOOD_KIND {
+            ops.compute_buy_price(curve, proposal.buy.get_kind(), proposal.buy.get_qty(), exchange_rate_earn_percentage)
+        } else {
+            ops.compute_cross_buy_price(curve, proposal.buy.get_kind(), proposal.buy.get_qty(), proposal.bid.get_kind(), exchange_rate_earn_percentage)
+        };
+        match price {
+            Ok(buy_price) => {
+                if proposal.bid.get_qty() < buy_price {
+                    Err(BuyTxReservationError::BidTooLow { lowest: buy_price })
+                } else {
+                    Ok(BuyTx::lock(ops, proposal, deposit))
+                }
+            }
+            Err(err) => {
+                match err {
+                    BuyPriceComputationError::NonPositiveQuantity => unreachable!(),
+                    BuyPriceComputationError::NegativeExchangeRateEarnPercentage => unreachable!(),
+                    BuyPriceComputationError::ExceedsReservableQuantity { reservable } =>
+                        Err(BuyTxReservationError::ExceedsReservableQuantity { reservable }),
+                    BuyPriceComputationError::SameKind => Err(BuyTxReservationError::UnsupportedBid),
+                }
+            }
+        }
+    }
+
+    /// Like [BuyTx::reserve], for a bid the caller already priced, without a deposit.
+    pub fn reserve_at_bid(ops: &mut AccountOps, proposal: &BuyTxProposal) -> Result<BuyTx, BuyTxReservationError> {
+        BuyTx::check_proposal(ops, proposal)?;
+        Ok(BuyTx::lock(ops, proposal, 0.))
+    }
+
+    fn check_proposal(ops: &AccountOps, proposal: &BuyTxProposal) -> Result<(), BuyTxReservationError> {
         if proposal.buy.get_qty() <= 0. {
             Err(BuyTxReservationError::NonPositiveBuy)
         } else if proposal.bid.get_qty() <= 0. {
             Err(BuyTxReservationError::NonPositiveBid)
-        } else if proposal.buy.get_qty() > ops.get_reservable_quantity_by_kind(proposal.buy.get_kind()) {
+        } else if Quantity::from_f32(proposal.buy.get_qty()) > ops.assets.get(proposal.buy.get_kind()) {
             Err(BuyTxReservationError::ExceedsReservableQuantity { reservable: ops.get_reservable_quantity_by_kind(proposal.buy.get_kind()) })
         } else {
-            match ops.compute_buy_price(proposal.buy.get_kind(), proposal.buy.get_qty(), if let DEFAULT_GOOD_KIND = proposal.buy.get_kind() { 0. } else { 1. }) {
-                Ok(buy_price) => {
-                    if proposal.bid.get_qty() < buy_price {
-                        Err(BuyTxReservationError::BidTooLow { lowest: buy_price })
-                    } else {
-                        ops.assets.withdraw(proposal.buy.get_kind(), proposal.buy.get_qty()).unwrap();
-                        ops.reservations.deposit(proposal.buy.clone());
-                        ops.futures.deposit(proposal.bid.clone());
-
-                        Ok(BuyTx { buy: proposal.buy.clone(), bid: proposal.bid.clone(), state: BuyTxState::Reserved })
-                    }
-                }
-                Err(err) => {
-                    match err {
-                        BuyPriceComputationError::NonPositiveQuantity => unreachable!(),
-                        BuyPriceComputationError::NegativeExchangeRateEarnPercentage => unreachable!(),
-                        BuyPriceComputationError::ExceedsReservableQuantity { reservable } =>
-                            Err(BuyTxReservationError::ExceedsReservableQuantity { reservable })
-                    }
-                }
-            }
+            Ok(())
         }
     }
 
+    fn lock(ops: &mut AccountOps, proposal: &BuyTxProposal, deposit: f32) -> BuyTx {
+        let reservation = Quantity::from_f32(proposal.buy.get_qty());
+        ops.assets.withdraw(proposal.buy.get_kind(), reservation).unwrap();
+        ops.reservations.deposit(proposal.buy.get_kind(), reservation);
+        ops.futures.deposit(proposal.bid.get_kind(), Quantity::from_f32(proposal.bid.get_qty()));
+        ops.deposits.deposit(proposal.bid.get_kind(), Quantity::from_f32(deposit));
+
+        BuyTx { buy: proposal.buy.clone(), bid: proposal.bid.clone(), state: BuyTxState::Reserved, renewals: 0, trader_name: proposal.trader_name.clone(), deposit }
+    }
+
+    /// Takes the bid out of `with`, minus the deposit that is already with the market.
     pub fn buy(&mut self, ops: &mut AccountOps, with: &mut Good) -> Result<Good, BuyTxPaymentError> {
         match &self.state {
             BuyTxState::Reserved => {
+                let due = self.bid.get_qty() - self.deposit;
                 if with.get_kind() != self.bid.get_kind() {
                     Err(BuyTxPaymentError::WrongGoodKind { pre_agreed: self.bid.get_kind() })
-                } else if with.get_qty() < self.bid.get_qty() {
-                    Err(BuyTxPaymentError::InsufficientGoodQuantity { pre_agreed: self.bid.get_qty() })
+                } else if with.get_qty() < due {
+                    Err(BuyTxPaymentError::InsufficientGoodQuantity { pre_agreed: due })
                 } else {
-                    let from_buyer = with.split(self.bid.get_qty()).unwrap();
-                    ops.assets.deposit(from_buyer);
-
-                    let from_us = match ops.reservations.withdraw(self.buy.get_kind(), self.buy.get_qty()) {
-                        Ok(good) => good,
-                        Err(err) => match err {
-                            WithdrawError::NonPositiveWithdrawQuantity => unreachable!(),
-                            WithdrawError::WithdrawExcessiveQuantity { withdrawable } => {
-                                // Reached 0 quantity, but went a little below 0 due to approximation errors
-                                if withdrawable > 0. {
-                                    ops.reservations.withdraw(self.buy.get_kind(), withdrawable).unwrap();
-                                }
-                                Good::new(self.buy.get_kind(), self.buy.get_qty())
-                            }
-                        }
-                    };
-
-                    if let Err(WithdrawError::WithdrawExcessiveQuantity { withdrawable }) = ops.futures.withdraw(self.bid.get_kind(), self.bid.get_qty()) {
-                        // Reached 0 quantity, but went a little below 0 due to approximation errors
-                        if withdrawable > 0. {
-                            ops.futures.withdraw(self.bid.get_kind(), withdrawable).unwrap();
-                        }
-                    };
-
-                    self.state = BuyTxState::Paid;
-
-                    Ok(from_us)
+                    if due > 0. {
+                        let from_buyer = with.split(due).unwrap();
+                        ops.assets.deposit(from_buyer.get_kind(), Quantity::from_f32(from_buyer.get_qty()));
+                    }
+
+                    Ok(self.deliver(ops))
                 }
             }
             other => Err(BuyTxPaymentError::InvalidState { current_state: other.clone() })
         }
     }
 
+    /// Hands the goods over once the caller took the bid from the trader. Only for `Reserved` transactions.
+    pub fn deliver(&mut self, ops: &mut AccountOps) -> Good {
+        self.keep_deposit(ops);
+
+        ops.reservations.withdraw(self.buy.get_kind(), Quantity::from_f32(self.buy.get_qty())).unwrap();
+        ops.futures.withdraw(self.bid.get_kind(), Quantity::from_f32(self.bid.get_qty())).unwrap();
+
+        self.state = BuyTxState::Paid;
+
+        self.buy.clone()
+    }
+
+    /// Like [BuyTx::buy], but if `with` falls short of the bid it is taken whole and, together with the deposit, pays
+    /// for the same fraction of the goods. The rest of the lock is released and the transaction becomes `PartiallyPaid`.
+    /// Paying nothing is refused, even if the deposit alone would pay for some goods.
+    pub fn buy_partially(&mut self, ops: &mut AccountOps, with: &mut Good) -> Result<Good, BuyTxPaymentError> {
+        let due = self.bid.get_qty() - self.deposit;
+        if !matches!(self.state, BuyTxState::Reserved) || with.get_kind() != self.bid.get_kind() || with.get_qty() >= due {
+            return self.buy(ops, with);
+        }
+
+        if with.get_qty() <= 0. {
+            return Err(BuyTxPaymentError::InsufficientGoodQuantity { pre_agreed: due });
+        }
+        let paid = with.get_qty() + self.deposit;
+        let bought = self.buy.get_qty() * (paid / self.bid.get_qty());
+
+        let from_buyer = with.split(with.get_qty()).unwrap();
+        ops.assets.deposit(from_buyer.get_kind(), Quantity::from_f32(from_buyer.get_qty()));
+        self.keep_deposit(ops);
+
+        let reservation = Quantity::from_f32(self.buy.get_qty());
+        ops.reservations.withdraw(self.buy.get_kind(), reservation).unwrap();
+        ops.assets.deposit(self.buy.get_kind(), reservation - Quantity::from_f32(bought));
+        ops.futures.withdraw(self.bid.get_kind(), Quantity::from_f32(self.bid.get_qty())).unwrap();
+
+        self.state = BuyTxState::PartiallyPaid { buy: bought, bid: paid };
+
+        Ok(Good::new(self.buy.get_kind(), bought))
+    }
+
     pub fn expire(&mut self, ops: &mut AccountOps) {
         if let BuyTxState::Reserved = self.state {
-            let reservation = match ops.reservations.withdraw(self.buy.get_kind(), self.buy.get_qty()) {
-                Ok(good) => good,
-                Err(err) => match err {
-                    WithdrawError::NonPositiveWithdrawQuantity => unreachable!(),
-                    WithdrawError::WithdrawExcessiveQuantity { withdrawable } => {
-                        // Reached 0 quantity, but went a little below 0 due to approximation errors
-                        if withdrawable > 0. {
-                            ops.reservations.withdraw(self.buy.get_kind(), withdrawable).unwrap();
-                        }
-                        Good::new(self.buy.get_kind(), self.buy.get_qty())
-                    }
-                }
-            };
+            self.release(ops);
+            self.state = BuyTxState::Expired;
+        }
+    }
 
-            ops.assets.deposit(reservation);
+    /// Gives up the lock before it expires, releasing it exactly like [BuyTx::expire].
+    pub fn cancel(&mut self, ops: &mut AccountOps) -> Result<(), BuyTxCancelError> {
+        match &self.state {
+            BuyTxState::Reserved => {
+                self.release(ops);
+                self.state = BuyTxState::Cancelled;
+                Ok(())
+            }
+            other => Err(BuyTxCancelError::InvalidState { current_state: other.clone() })
+        }
+    }
 
-            if let Err(WithdrawError::WithdrawExcessiveQuantity { withdrawable }) = ops.futures.withdraw(self.bid.get_kind(), self.bid.get_qty()) {
-                // Reached 0 quantity, but went a little below 0 due to approximation errors
-                if withdrawable > 0. {
-                    ops.futures.withdraw(self.bid.get_kind(), withdrawable).unwrap();
+    /// Takes `fee` of `fee_kind` out of `with` to keep the lock alive longer. Postponing its expiration is up to the caller.
+    pub fn renew(&mut self, ops: &mut AccountOps, with: &mut Good, fee_kind: GoodKind, fee: f32, max_renewals: u32) -> Result<(), BuyTxRenewalError> {
+        match &self.state {
+            BuyTxState::Reserved => {
+                if self.renewals >= max_renewals {
+                    Err(BuyTxRenewalError::MaxRenewalsReached { max_renewals })
+                } else if with.get_kind() != fee_kind {
+                    Err(BuyTxRenewalError::WrongGoodKind { fee_kind })
+                } else if with.get_qty() < fee {
+                    Err(BuyTxRenewalError::InsufficientGoodQuantity { fee })
+                } else {
+                    if fee > 0. {
+                        let from_trader = with.split(fee).unwrap();
+                        ops.assets.deposit(from_trader.get_kind(), Quantity::from_f32(from_trader.get_qty()));
+                    }
+
+                    self.renewals += 1;
+                    Ok(())
                 }
             }
-
-            self.state = BuyTxState::Expired;
+            other => Err(BuyTxRenewalError::InvalidState { current_state: other.clone() })
         }
     }
+
+    /// Gives the goods back to the market. The deposit is kept by the market, as the trader did not pay.
+    fn release(&self, ops: &mut AccountOps) {
+        let reservation = Quantity::from_f32(self.buy.get_qty());
+        ops.reservations.withdraw(self.buy.get_kind(), reservation).unwrap();
+        ops.assets.deposit(self.buy.get_kind(), reservation);
+
+        ops.futures.withdraw(self.bid.get_kind(), Quantity::from_f32(self.bid.get_qty())).unwrap();
+        self.keep_deposit(ops);
+    }
+
+    fn keep_deposit(&self, ops: &mut AccountOps) {
+        let deposit = Quantity::from_f32(self.deposit);
+        ops.deposits.withdraw(self.bid.get_kind(), deposit).unwrap();
+        ops.assets.deposit(self.bid.get_kind(), deposit);
+    }
 }
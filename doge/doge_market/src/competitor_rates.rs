@@ -0,0 +1,117 @@
+use serde::{Deserialize, Serialize};
+use unitn_market_2022::event::event::{Event, EventKind};
+use unitn_market_2022::good::consts::DEFAULT_GOOD_KIND;
+use unitn_market_2022::good::good_kind::GoodKind;
+
+use crate::per_good::PerGood;
+
+/// Tuning of how the market follows the other markets, as seen through the events they notify.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CompetitorSettings {
+    /// Weight of the newest event in the estimate of the rate of its good, above `0` and at most `1`.
+    pub smoothing: f32,
+    /// Largest widening, as exchange rate earn percentage, of the side of a good that arbitrageurs would drain, `0` to
+    /// ignore the other markets.
+    pub max_skew_percentage: f32,
+}
+
+impl Default for CompetitorSettings {
+    fn default() -> Self {
+        CompetitorSettings {
+            smoothing: 0.2,
+            max_skew_percentage: 5.,
+        }
+    }
+}
+
+/// Rolling estimate of the exchange rate, in the default good, that the other markets trade every good at.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompetitorRates {
+    rates: PerGood<Option<f32>>,
+}
+
+impl Default for CompetitorRates {
+    fn default() -> Self {
+        CompetitorRates::new()
+    }
+}
+
+impl CompetitorRates {
+    pub fn new() -> CompetitorRates {
+        CompetitorRates { rates: PerGood::new(None, None, None, None) }
+    }
+
+    /// Folds the unit price of a trade or lock of another market into the estimate of its good, as an exponential
+    /// moving average weighing it by `smoothing`.
+    pub fn observe(&mut self, event: &Event, smoothing: f32) {
+        let priced = matches!(event.kind, EventKind::Bought | EventKind::Sold | EventKind::LockedBuy | EventKind::LockedSell);
+        if !priced || event.good_kind == DEFAULT_GOOD_KIND || event.quantity <= 0. || event.price <= 0. {
+            return;
+        }
+
+        let observed = event.price / event.quantity;
+        if !observed.is_finite() {
+            return;
+        }
+
+        let rate = self.rates.get_mut(event.good_kind);
+        *rate = Some(match *rate {
+            Some(rate) => rate + smoothing * (observed - rate),
+            None => observed,
+        });
+    }
+
+    pub fn get(&self, kind: GoodKind) -> Option<f32> {
+        *self.rates.get(kind)
+    }
+}
+
+/// Earn percentages to add to the buy and sell spreads of a good the market exchanges at `rate` while the other
+/// markets exchange it at `competitor_rate`. Arbitrageurs buy where the good is cheaper and sell where it is dearer, so
+/// the side of the market they would take is widened by the gap between the rates, up to `max_skew_percentage`.
+pub fn skew(rate: f32, competitor_rate: Option<f32>, max_skew_percentage: f32) -> (f32, f32) {
+    let gap = match competitor_rate {
+        Some(competitor_rate) if rate > 0. => (competitor_rate - rate) / rate * 100.,
+        _ => return (0., 0.),
+    };
+
+    if gap > 0. {
+        (gap.min(max_skew_percentage), 0.)
+    } else {
+        (0., (-gap).min(max_skew_percentage))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use unitn_market_2022::good::good_kind::GoodKind::{EUR, USD};
+
+    use super::*;
+
+    fn event(kind: EventKind, good_kind: GoodKind, quantity: f32, price: f32) -> Event {
+        Event { kind, good_kind, quantity, price }
+    }
+
+    #[test]
+    fn moving_average_and_skew() {
+        let mut rates = CompetitorRates::new();
+        rates.observe(&event(EventKind::Bought, USD, 10., 10.), 0.5);
+        assert_eq!(rates.get(USD), Some(1.));
+        rates.observe(&event(EventKind::Sold, USD, 10., 20.), 0.5);
+        assert_eq!(rates.get(USD), Some(1.5));
+
+        // waits, the default good and empty trades say nothing about the rates
+        rates.observe(&event(EventKind::Wait, USD, 10., 100.), 0.5);
+        rates.observe(&event(EventKind::Bought, USD, 0., 100.), 0.5);
+        rates.observe(&event(EventKind::Bought, EUR, 10., 10.), 0.5);
+        assert_eq!(rates.get(USD), Some(1.5));
+        assert_eq!(rates.get(EUR), None);
+
+        // the others sell dearer, so buying from the market gets pricier
+        assert_eq!(skew(1., rates.get(USD), 100.), (50., 0.));
+        assert_eq!(skew(1., rates.get(USD), 5.), (5., 0.));
+        assert_eq!(skew(2., rates.get(USD), 100.), (0., 25.));
+        assert_eq!(skew(1., None, 5.), (0., 0.));
+    }
+}
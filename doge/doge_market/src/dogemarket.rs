@@ -2,6 +2,8 @@ use std::cell::RefCell;
 use std::rc::Rc;
 use std::str::FromStr;
 
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
 use unitn_market_2022::event::event::Event;
 use unitn_market_2022::event::event::EventKind::{Bought, LockedBuy, LockedSell, Sold};
 use unitn_market_2022::event::notifiable::Notifiable;
@@ -17,30 +19,1038 @@ use uuid::Uuid;
 use doge_common::account::Account;
 
 use crate::account_ops::AccountOps;
+use crate::auditor::AuditReport;
+use crate::basket_transaction::{BasketLeg, BasketTxProposal};
 use crate::buy_transaction::{BuyTxProposal, BuyTxState};
+use crate::forward_contract::ForwardTxProposal;
+pub use crate::forward_contract::ForwardTxState;
+pub use crate::market_file::MarketFileError;
+pub use crate::snapshot::SnapshotError;
+use crate::lending_desk::LoanState;
 use crate::logger::Logger;
-use crate::market::{DogeBuyError, DogeBuyReservationError, DogeGetBuyPriceError, DogeGetSellPriceError, DogeMarketImpl, DogeSellError, DogeSellReservationError};
+use crate::market::{DogeBasketBuyError, DogeBasketReservationError, DogeBorrowError, DogeBuyError, DogeOrderError, DogePlaceOrderError, DogeBuyReservationError, DogeCancelBuyError, DogeCancelSellError, DogeForwardAgreementError, DogeForwardClaimError, DogeForwardDeliveryError, DogeGetBasketPriceError, DogeGetBuyPriceError, DogeGetCrossPriceError, DogeGetForwardPriceError, DogeGetSellPriceError, DogeMarketImpl, DogeRenewBuyError, DogeRenewSellError, DogeRepayLoanError, DogeSellError, DogeSellReservationError};
+use crate::market_config::{LockClearing, MarketConfig, MarketConfigError};
+use crate::market_file::MarketFile;
 use crate::mute_logger::MuteLogger;
+use crate::order_book::{LimitOrderState, OrderSide};
+use crate::refill_backend::MarketRefillBackend;
+use crate::reputation::ReputationTable;
 use crate::sell_transaction::{SellTxProposal, SellTxState};
+use crate::snapshot::MarketSnapshot;
+use crate::trader_history::TraderHistory;
 use crate::txt_file_logger::TxtFileLogger;
 
+#[derive(Debug, Clone, PartialEq)]
+pub enum CancelLockError {
+    UnrecognizedToken { unrecognized_token: String },
+    ExpiredToken { expired_token: String },
+    /// The lock was already paid or cancelled.
+    AlreadySettledToken { settled_token: String },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RenewLockError {
+    UnrecognizedToken { unrecognized_token: String },
+    ExpiredToken { expired_token: String },
+    /// The lock was already paid or cancelled.
+    AlreadySettledToken { settled_token: String },
+    /// Locks can be renewed by at least one day and at most by `max_days`.
+    InvalidDays { days: u32, max_days: u32 },
+    MaxRenewalsReached { max_renewals: u32 },
+    /// The fee must be paid in `fee_kind`, not in `wrong_good_kind`.
+    WrongGoodKind { wrong_good_kind: GoodKind, fee_kind: GoodKind },
+    /// At least `fee` of `fee_kind` is needed.
+    InsufficientFee { fee_kind: GoodKind, fee: f32 },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LockBasketError {
+    EmptyBasket,
+    /// The trader holds too many locks.
+    MaxAllowedLocksReached,
+    /// The market requires a deposit of `required` of the default good to lock the basket, which baskets cannot take.
+    DepositRequired { required: f32 },
+    /// The leg at `index` could not be locked, so none was.
+    Leg { index: usize, error: LockBuyError },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BasketPriceError {
+    EmptyBasket,
+    /// The leg at `index` could not be priced, so none was.
+    Leg { index: usize, error: MarketGetterError },
+    /// The market can take in at most `room` more of the default good, less than the bids of the legs up to `index`.
+    ExceedsCapacity { index: usize, room: f32 },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PlaceOrderError {
+    NonPositiveQuantity { quantity: f32 },
+    NonPositiveLimit { limit: f32 },
+    NonPositiveDays,
+    /// The good given to the order must hold at least `required` of `kind`.
+    InsufficientEscrow { kind: GoodKind, required: f32 },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum OrderError {
+    UnrecognizedToken { unrecognized_token: String },
+    InvalidState { current_state: LimitOrderState },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ForwardPriceError {
+    NonPositiveQuantityAsked,
+    /// Forwards mature in at least one day and at most in `max_days`.
+    InvalidDays { days: u32, max_days: u32 },
+    InsufficientGoodQuantityAvailable { requested_good_kind: GoodKind, requested_good_quantity: f32, available_good_quantity: f32 },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AgreeForwardError {
+    Price { error: ForwardPriceError },
+    NonPositivePrice { price: f32 },
+    /// Buys must pay at least the forward price.
+    PriceTooLow { low_price: f32, lowest_acceptable_price: f32 },
+    /// Sells must ask for at most the forward price.
+    PriceTooHigh { high_price: f32, highest_acceptable_price: f32 },
+    /// The market can set aside at most `available_good_quantity` of `kind` for the trader.
+    InsufficientGoodQuantityAvailable { kind: GoodKind, available_good_quantity: f32 },
+    /// The trader holds too many locks.
+    MaxAllowedLocksReached,
+    /// The market can take in at most `room` more of `kind` without exceeding its capacity.
+    ExceedsCapacity { kind: GoodKind, room: f32 },
+    /// The margin must be at least `required` of the default good.
+    InsufficientMargin { required: f32 },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ForwardError {
+    UnrecognizedToken { unrecognized_token: String },
+    InvalidState { current_state: ForwardTxState },
+    AlreadyDelivered,
+    /// The trader has to deliver `pre_agreed_quantity` of `kind`.
+    InsufficientGoodQuantity { kind: GoodKind, pre_agreed_quantity: f32 },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BorrowError {
+    NonPositiveQuantity { quantity: f32 },
+    /// Loans last at least one day and at most `max_days`.
+    InvalidDays { days: u32, max_days: u32 },
+    /// The collateral must be a good other than the one borrowed.
+    SameKindCollateral,
+    /// The collateral must hold at least `required` of its kind.
+    InsufficientCollateral { required: f32 },
+    InsufficientGoodQuantityAvailable { requested_good_kind: GoodKind, requested_good_quantity: f32, available_good_quantity: f32 },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LoanError {
+    UnrecognizedToken { unrecognized_token: String },
+    InvalidState { current_state: LoanState },
+    /// The repayment must hold at least `debt` of `kind`.
+    InsufficientRepayment { kind: GoodKind, debt: f32 },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CrossPriceError {
+    NonPositiveQuantityAsked,
+    /// A good cannot be paid for with itself.
+    SameKind,
+    /// The market can set aside at most `available_good_quantity` of `kind` for the trader.
+    InsufficientGoodQuantityAvailable { kind: GoodKind, available_good_quantity: f32 },
+    /// The market can take in at most `room` more of `kind` without exceeding its capacity.
+    ExceedsCapacity { kind: GoodKind, room: f32 },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LockWithDepositError<E> {
+    Lock { error: E },
+    /// The deposit must be at least `required` of the default good.
+    InsufficientDeposit { required: f32 },
+}
+
 pub struct DogeMarket {
     doge_impl: DogeMarketImpl,
     subscribers: Vec<Box<(dyn Notifiable + 'static)>>,
     logger: Box<dyn Logger>,
 }
 
-impl DogeMarket {
-    fn new_with_goods(eur: &Good, yen: &Good, usd: &Good, yuan: &Good) -> Rc<RefCell<dyn Market>> where Self: Sized {
-        let assets = Account { eur: eur.clone(), usd: usd.clone(), yen: yen.clone(), yuan: yuan.clone() };
-        let ops = AccountOps::of_assets(assets);
+impl DogeMarket {
+    fn new_with_goods(eur: &Good, yen: &Good, usd: &Good, yuan: &Good, config: MarketConfig) -> Result<Rc<RefCell<DogeMarket>>, MarketConfigError> {
+        let assets = Account { eur: eur.clone(), usd: usd.clone(), yen: yen.clone(), yuan: yuan.clone() };
+        let ops = AccountOps::of_assets(assets);
+
+        Ok(DogeMarket::new_with_impl(DogeMarketImpl::new(ops, config)?, eur, yen, usd, yuan))
+    }
+
+    /// Like [Market::new_with_quantities], but tuned by `config` instead of [MarketConfig::default]. Like the other
+    /// constructors of DogeMarket, it keeps the concrete type so that the methods beyond [Market] can be called, and
+    /// coerces to `Rc<RefCell<dyn Market>>` where the trait is enough.
+    pub fn new_with_config(eur: f32, yen: f32, usd: f32, yuan: f32, config: MarketConfig) -> Result<Rc<RefCell<DogeMarket>>, MarketConfigError> {
+        let eur = Good::new(EUR, eur);
+        let usd = Good::new(USD, usd);
+        let yen = Good::new(YEN, yen);
+        let yuan = Good::new(YUAN, yuan);
+
+        DogeMarket::new_with_goods(&eur, &yen, &usd, &yuan, config)
+    }
+
+    /// Like [Market::new_random], but draws the quantities, and every later random choice of the market, from `seed`.
+    pub fn new_random_with_seed(seed: u64) -> Rc<RefCell<DogeMarket>> {
+        let quantities = new_random_quantities(&mut ChaCha8Rng::seed_from_u64(seed));
+        let config = MarketConfig { seed: Some(seed), ..MarketConfig::default() };
+        DogeMarket::new_with_goods(&quantities[0], &quantities[1], &quantities[2], &quantities[3], config)
+            .expect("the default configuration is valid")
+    }
+
+    /// Like [Market::new_file], but reports why the file could not be loaded instead of panicking.
+    pub fn try_new_file(path: &str) -> Result<Rc<RefCell<DogeMarket>>, MarketFileError> {
+        let file = MarketFile::read(path)?;
+
+        let eur = Good::new(EUR, file.goods.eur);
+        let usd = Good::new(USD, file.goods.usd);
+        let yen = Good::new(YEN, file.goods.yen);
+        let yuan = Good::new(YUAN, file.goods.yuan);
+
+        DogeMarket::new_with_goods(&eur, &yen, &usd, &yuan, file.get_config())
+            .map_err(|reason| MarketFileError::InvalidConfig { reason })
+    }
+
+    /// Starts a market from a snapshot written with [DogeMarket::save_snapshot].
+    pub fn try_new_snapshot(path: &str) -> Result<Rc<RefCell<DogeMarket>>, SnapshotError> {
+        let doge_impl = DogeMarketImpl::restore(MarketSnapshot::read(path)?)
+            .map_err(|reason| SnapshotError::InvalidConfig { reason })?;
+
+        let assets = doge_impl.get_tx_service().get_account_ops().assets.to_account();
+        let (eur, usd, yen, yuan) = (assets.eur.clone(), assets.usd.clone(), assets.yen.clone(), assets.yuan.clone());
+
+        Ok(DogeMarket::new_with_impl(doge_impl, &eur, &yen, &usd, &yuan))
+    }
+
+    /// Writes the full state of the market to `path`, so that it can be resumed with [DogeMarket::try_new_snapshot].
+    pub fn save_snapshot(&self, path: &str) -> Result<(), SnapshotError> {
+        self.doge_impl.snapshot().write(path)
+    }
+
+    pub fn get_config(&self) -> &MarketConfig {
+        self.doge_impl.get_config()
+    }
+
+    /// Operations after which the ledgers did not reconcile with the transactions. Always empty unless
+    /// [MarketConfig::audit] is set.
+    pub fn get_audit_reports(&self) -> &[AuditReport] {
+        self.doge_impl.get_tx_service().get_audit_reports()
+    }
+
+    /// Days left before a lock expires, `None` if the token is not of a lock still waiting to be paid. Large locks may
+    /// expire sooner than small ones, see [MarketConfig::lock_ttl_tiers].
+    pub fn get_lock_days_left(&self, token: String) -> Option<u32> {
+        let uuid = Uuid::from_str(&token).ok()?;
+        self.doge_impl.get_tx_service().get_ticks_left(&uuid)
+    }
+
+    /// How the locks of every trader ended, which decides how the market treats them, see [MarketConfig::reputation].
+    pub fn get_reputation_table(&self) -> &ReputationTable {
+        self.doge_impl.get_tx_service().get_reputation_table()
+    }
+
+    /// Locks the trader made under `trader_name`, with the ones it let expire and what it traded, for post-mortems.
+    pub fn get_trader_history(&self, trader_name: &str) -> TraderHistory {
+        let service = self.doge_impl.get_tx_service();
+
+        // the legs of a basket share its token
+        let mut buy_tokens: Vec<String> = service.get_buys_by_trader(trader_name).map(|(uuid, _)| uuid.to_string()).collect();
+        buy_tokens.dedup();
+
+        TraderHistory {
+            buy_tokens,
+            sell_tokens: service.get_sells_by_trader(trader_name).map(|(uuid, _)| uuid.to_string()).collect(),
+            forward_tokens: service.get_forwards_by_trader(trader_name).map(|(uuid, _)| uuid.to_string()).collect(),
+            expired_tokens: service.get_expired_by_trader(trader_name).iter().map(|uuid| uuid.to_string()).collect(),
+            volume: service.get_volume_by_trader(trader_name),
+        }
+    }
+
+    /// Gives up a lock obtained with [Market::lock_buy], giving the locked goods back to the market right away instead
+    /// of when the lock expires. It still counts as an expired lock in the reputation of the trader.
+    pub fn cancel_buy_lock(&mut self, token: String) -> Result<(), CancelLockError> {
+        let uuid = if let Ok(uuid) = Uuid::from_str(&token) { uuid } else { return Err(CancelLockError::UnrecognizedToken { unrecognized_token: token }); };
+        self.close_auction_day();
+
+        let result = self.doge_impl.cancel_buy_lock(&uuid);
+        self.logger.log_cancel_buy(&uuid, result.is_ok());
+
+        match result {
+            Ok(()) => {
+                self.advance_a_day();
+                Ok(())
+            }
+            Err(err) => Err(match err {
+                DogeCancelBuyError::UnrecognizedUuid => CancelLockError::UnrecognizedToken { unrecognized_token: token },
+                DogeCancelBuyError::InvalidState { current_state } => match current_state {
+                    BuyTxState::Reserved => unreachable!(),
+                    BuyTxState::Expired => CancelLockError::ExpiredToken { expired_token: token },
+                    BuyTxState::Paid | BuyTxState::PartiallyPaid { .. } | BuyTxState::Cancelled => CancelLockError::AlreadySettledToken { settled_token: token },
+                }
+            }),
+        }
+    }
+
+    /// Gives up a lock obtained with [Market::lock_sell], giving the locked default good back to the market right away
+    /// instead of when the lock expires. It still counts as an expired lock in the reputation of the trader.
+    pub fn cancel_sell_lock(&mut self, token: String) -> Result<(), CancelLockError> {
+        let uuid = if let Ok(uuid) = Uuid::from_str(&token) { uuid } else { return Err(CancelLockError::UnrecognizedToken { unrecognized_token: token }); };
+        self.close_auction_day();
+
+        let result = self.doge_impl.cancel_sell_lock(&uuid);
+        self.logger.log_cancel_sell(&uuid, result.is_ok());
+
+        match result {
+            Ok(()) => {
+                self.advance_a_day();
+                Ok(())
+            }
+            Err(err) => Err(match err {
+                DogeCancelSellError::UnrecognizedUuid => CancelLockError::UnrecognizedToken { unrecognized_token: token },
+                DogeCancelSellError::InvalidState { current_state } => match current_state {
+                    SellTxState::Reserved => unreachable!(),
+                    SellTxState::Expired => CancelLockError::ExpiredToken { expired_token: token },
+                    SellTxState::Paid | SellTxState::PartiallyPaid { .. } | SellTxState::Cancelled => CancelLockError::AlreadySettledToken { settled_token: token },
+                }
+            }),
+        }
+    }
+
+    /// Keeps a lock obtained with [Market::lock_buy] alive for `days` more days. Every day costs the renewal fee of the
+    /// market in the default good, taken out of `fee`, and a lock can be renewed only a limited number of times.
+    pub fn renew_buy_lock(&mut self, token: String, days: u32, fee: &mut Good) -> Result<(), RenewLockError> {
+        let uuid = if let Ok(uuid) = Uuid::from_str(&token) { uuid } else { return Err(RenewLockError::UnrecognizedToken { unrecognized_token: token }); };
+        self.close_auction_day();
+
+        let result = self.doge_impl.renew_buy_lock(&uuid, days, fee);
+        self.logger.log_renew_buy(&uuid, days, result.is_ok());
+
+        match result {
+            Ok(()) => {
+                self.advance_a_day();
+                Ok(())
+            }
+            Err(err) => Err(match err {
+                DogeRenewBuyError::UnrecognizedUuid => RenewLockError::UnrecognizedToken { unrecognized_token: token },
+                DogeRenewBuyError::InvalidTicks { max_ticks } => RenewLockError::InvalidDays { days, max_days: max_ticks },
+                DogeRenewBuyError::InvalidState { current_state } => match current_state {
+                    BuyTxState::Reserved => unreachable!(),
+                    BuyTxState::Expired => RenewLockError::ExpiredToken { expired_token: token },
+                    BuyTxState::Paid | BuyTxState::PartiallyPaid { .. } | BuyTxState::Cancelled => RenewLockError::AlreadySettledToken { settled_token: token },
+                },
+                DogeRenewBuyError::MaxRenewalsReached { max_renewals } => RenewLockError::MaxRenewalsReached { max_renewals },
+                DogeRenewBuyError::WrongGoodKind { fee_kind } => RenewLockError::WrongGoodKind { wrong_good_kind: fee.get_kind(), fee_kind },
+                DogeRenewBuyError::InsufficientGoodQuantity { fee } => RenewLockError::InsufficientFee { fee_kind: DEFAULT_GOOD_KIND, fee },
+            }),
+        }
+    }
+
+    /// Keeps a lock obtained with [Market::lock_sell] alive for `days` more days, like [DogeMarket::renew_buy_lock].
+    pub fn renew_sell_lock(&mut self, token: String, days: u32, fee: &mut Good) -> Result<(), RenewLockError> {
+        let uuid = if let Ok(uuid) = Uuid::from_str(&token) { uuid } else { return Err(RenewLockError::UnrecognizedToken { unrecognized_token: token }); };
+        self.close_auction_day();
+
+        let result = self.doge_impl.renew_sell_lock(&uuid, days, fee);
+        self.logger.log_renew_sell(&uuid, days, result.is_ok());
+
+        match result {
+            Ok(()) => {
+                self.advance_a_day();
+                Ok(())
+            }
+            Err(err) => Err(match err {
+                DogeRenewSellError::UnrecognizedUuid => RenewLockError::UnrecognizedToken { unrecognized_token: token },
+                DogeRenewSellError::InvalidTicks { max_ticks } => RenewLockError::InvalidDays { days, max_days: max_ticks },
+                DogeRenewSellError::InvalidState { current_state } => match current_state {
+                    SellTxState::Reserved => unreachable!(),
+                    SellTxState::Expired => RenewLockError::ExpiredToken { expired_token: token },
+                    SellTxState::Paid | SellTxState::PartiallyPaid { .. } | SellTxState::Cancelled => RenewLockError::AlreadySettledToken { settled_token: token },
+                },
+                DogeRenewSellError::MaxRenewalsReached { max_renewals } => RenewLockError::MaxRenewalsReached { max_renewals },
+                DogeRenewSellError::WrongGoodKind { fee_kind } => RenewLockError::WrongGoodKind { wrong_good_kind: fee.get_kind(), fee_kind },
+                DogeRenewSellError::InsufficientGoodQuantity { fee } => RenewLockError::InsufficientFee { fee_kind: DEFAULT_GOOD_KIND, fee },
+            }),
+        }
+    }
+
+    /// Like [Market::lock_buy], for markets that require a deposit to lock: the deposit, in the default good, is taken
+    /// out of `deposit` when the lock succeeds. It counts towards the payment of [Market::buy] and is kept by the market
+    /// if the lock is not paid. [Market::lock_buy] refuses every bid in these markets.
+    pub fn lock_buy_with_deposit(&mut self, kind_to_buy: GoodKind, quantity_to_buy: f32, bid: f32, trader_name: String, deposit: &mut Good) -> Result<String, LockWithDepositError<LockBuyError>> {
+        let required = self.doge_impl.get_lock_deposit(bid);
+        if deposit.get_kind() != DEFAULT_GOOD_KIND || deposit.get_qty() < required {
+            return Err(LockWithDepositError::InsufficientDeposit { required });
+        }
+
+        let token = self.do_lock_buy(kind_to_buy, quantity_to_buy, Good::new(DEFAULT_GOOD_KIND, bid), trader_name, required).map_err(|error| LockWithDepositError::Lock { error })?;
+        if required > 0. {
+            deposit.split(required).unwrap();
+        }
+
+        Ok(token)
+    }
+
+    /// Like [Market::lock_sell], for markets that require a deposit to lock, see [DogeMarket::lock_buy_with_deposit].
+    /// The deposit is given back together with the offer by [Market::sell].
+    pub fn lock_sell_with_deposit(&mut self, kind_to_sell: GoodKind, quantity_to_sell: f32, offer: f32, trader_name: String, deposit: &mut Good) -> Result<String, LockWithDepositError<LockSellError>> {
+        let required = self.doge_impl.get_lock_deposit(offer);
+        if deposit.get_kind() != DEFAULT_GOOD_KIND || deposit.get_qty() < required {
+            return Err(LockWithDepositError::InsufficientDeposit { required });
+        }
+
+        let token = self.do_lock_sell(kind_to_sell, quantity_to_sell, Good::new(DEFAULT_GOOD_KIND, offer), trader_name, required).map_err(|error| LockWithDepositError::Lock { error })?;
+        if required > 0. {
+            deposit.split(required).unwrap();
+        }
+
+        Ok(token)
+    }
+
+    /// Like [Market::buy], but if `cash` falls short of the pre-agreed bid it is taken whole, together with the deposit
+    /// of the lock, in exchange for the same fraction of the locked goods. The rest of the lock goes back to the market.
+    pub fn buy_partially(&mut self, token: String, cash: &mut Good) -> Result<Good, BuyError> {
+        self.do_buy(token, cash, true)
+    }
+
+    /// Like [Market::sell], but if `good` falls short of the pre-agreed quantity it is taken whole in exchange for the
+    /// same fraction of the offer. The rest of the lock goes back to the market.
+    pub fn sell_partially(&mut self, token: String, good: &mut Good) -> Result<Good, SellError> {
+        self.do_sell(token, good, true)
+    }
+
+    /// Lowest bids, in the order of the legs, that [DogeMarket::lock_basket] accepts for a basket buying every kind and
+    /// quantity of `legs`. The legs compete for the same goods, so each of them can be priced above its
+    /// [Market::get_buy_price].
+    pub fn get_basket_price(&self, legs: &[(GoodKind, f32)]) -> Result<Vec<f32>, BasketPriceError> {
+        let goods: Vec<Good> = legs.iter().map(|(kind, quantity)| Good::new(*kind, *quantity)).collect();
+        self.doge_impl.get_basket_price(&goods).map_err(|err| match err {
+            DogeGetBasketPriceError::EmptyBasket => BasketPriceError::EmptyBasket,
+            DogeGetBasketPriceError::Leg { index, error } => {
+                let (kind, quantity) = legs[index];
+                BasketPriceError::Leg { index, error: buy_price_error(error, kind, quantity) }
+            }
+            DogeGetBasketPriceError::ExceedsCapacity { index, room } => BasketPriceError::ExceedsCapacity { index, room },
+        })
+    }
+
+    /// Locks buys of several goods under a single token, all or nothing. Every leg is the kind, quantity and bid of a
+    /// [Market::lock_buy], at least the bid of [DogeMarket::get_basket_price]. The basket is paid at once with
+    /// [DogeMarket::buy_basket], or expires as a whole. Markets that require a deposit to lock refuse baskets.
+    pub fn lock_basket(&mut self, legs: &[(GoodKind, f32, f32)], trader_name: String) -> Result<String, LockBasketError> {
+        let total_bid: f32 = legs.iter().map(|(_, _, bid)| bid).sum();
+        let required = self.doge_impl.get_lock_deposit(total_bid);
+        if required > 0. {
+            return Err(LockBasketError::DepositRequired { required });
+        }
+
+        let proposal = BasketTxProposal {
+            legs: legs.iter().map(|(kind, quantity, bid)| BasketLeg { buy: Good::new(*kind, *quantity), bid: Good::new(DEFAULT_GOOD_KIND, *bid) }).collect(),
+            trader_name: trader_name.clone(),
+        };
+
+        match self.doge_impl.do_basket_reservation(&proposal) {
+            Ok(uuid) => {
+                for leg in &proposal.legs {
+                    self.logger.log_lock_buy(&leg.buy, &trader_name, &leg.bid, Some(&uuid));
+                }
+
+                self.advance_a_day();
+
+                self.subscribers.iter_mut().for_each(|sub| {
+                    for (kind, quantity, bid) in legs {
+                        let event = Event {
+                            kind: LockedBuy,
+                            good_kind: *kind,
+                            quantity: *quantity,
+                            price: *bid,
+                        };
+                        sub.on_event(event);
+                    }
+                });
+
+                Ok(uuid.to_string())
+            }
+            Err(err) => {
+                for leg in &proposal.legs {
+                    self.logger.log_lock_buy(&leg.buy, &trader_name, &leg.bid, None);
+                }
+
+                Err(match err {
+                    DogeBasketReservationError::EmptyBasket => LockBasketError::EmptyBasket,
+                    DogeBasketReservationError::MixedBidKinds => unreachable!(),
+                    DogeBasketReservationError::TooManyLocks => LockBasketError::MaxAllowedLocksReached,
+                    DogeBasketReservationError::ExceedsTraderExposure { kind, max_quantity } => {
+                        let index = legs.iter().position(|(leg_kind, _, _)| *leg_kind == kind).unwrap();
+                        let (_, quantity, _) = legs[index];
+                        LockBasketError::Leg {
+                            index,
+                            error: LockBuyError::InsufficientGoodQuantityAvailable { requested_good_kind: kind, requested_good_quantity: quantity, available_good_quantity: max_quantity },
+                        }
+                    }
+                    DogeBasketReservationError::Leg { index, error } => {
+                        let (kind, quantity, bid) = legs[index];
+                        LockBasketError::Leg { index, error: lock_buy_error(error, kind, quantity, bid) }
+                    }
+                })
+            }
+        }
+    }
+
+    /// Pays for every leg of a basket locked with [DogeMarket::lock_basket], with the sum of their bids. The goods are
+    /// given back in the order of the legs.
+    pub fn buy_basket(&mut self, token: String, cash: &mut Good) -> Result<Vec<Good>, BuyError> {
+        let uuid = if let Ok(uuid) = Uuid::from_str(&token) { uuid } else { return Err(BuyError::UnrecognizedToken { unrecognized_token: token }); };
+
+        match self.doge_impl.buy_basket(&uuid, cash) {
+            Ok(bought) => {
+                self.logger.log_buy(&uuid, true);
+
+                self.advance_a_day();
+
+                let tx = self.doge_impl.get_tx_service().get_basket(&uuid).unwrap();
+
+                self.subscribers.iter_mut().for_each(|sub| {
+                    for leg in &tx.legs {
+                        let event = Event {
+                            kind: Bought,
+                            good_kind: leg.buy.get_kind(),
+                            quantity: leg.buy.get_qty(),
+                            price: leg.bid.get_qty(),
+                        };
+                        sub.on_event(event);
+                    }
+                });
+
+                Ok(bought)
+            }
+            Err(err) => {
+                self.logger.log_buy(&uuid, false);
+
+                Err(match err {
+                    DogeBasketBuyError::UnrecognizedUuid => BuyError::UnrecognizedToken { unrecognized_token: token },
+                    DogeBasketBuyError::InvalidState { current_state } => match current_state {
+                        BuyTxState::Reserved | BuyTxState::PartiallyPaid { .. } => unreachable!(),
+                        BuyTxState::Paid => BuyError::UnrecognizedToken { unrecognized_token: token },
+                        BuyTxState::Expired | BuyTxState::Cancelled => BuyError::ExpiredToken { expired_token: token }
+                    },
+                    DogeBasketBuyError::WrongGoodKind { .. } => BuyError::GoodKindNotDefault { non_default_good_kind: cash.get_kind() },
+                    DogeBasketBuyError::InsufficientGoodQuantity { pre_agreed } => BuyError::InsufficientGoodQuantity { contained_quantity: cash.get_qty(), pre_agreed_quantity: pre_agreed }
+                })
+            }
+        }
+    }
+
+    /// Leaves an order to buy `quantity` of `kind` as soon as its price drops to `limit` of the default good or less,
+    /// open for `days` days. The limit is taken out of `cash` and held by the order. The market checks the open orders
+    /// every day and fills the matching ones at its own price, notifying a [Bought](unitn_market_2022::event::event::EventKind::Bought)
+    /// event. The goods and the change are then given back by [DogeMarket::claim_order].
+    pub fn place_buy_order(&mut self, kind: GoodKind, quantity: f32, limit: f32, days: u32, trader_name: String, cash: &mut Good) -> Result<String, PlaceOrderError> {
+        let result = self.doge_impl.place_buy_order(kind, quantity, limit, days, trader_name, cash);
+        self.place_order(result, quantity, limit)
+    }
+
+    /// Leaves an order to sell `quantity` of `kind` as soon as its price rises to `limit` of the default good or more,
+    /// like [DogeMarket::place_buy_order]. The goods to sell are taken out of `good` and held by the order.
+    pub fn place_sell_order(&mut self, kind: GoodKind, quantity: f32, limit: f32, days: u32, trader_name: String, good: &mut Good) -> Result<String, PlaceOrderError> {
+        let result = self.doge_impl.place_sell_order(kind, quantity, limit, days, trader_name, good);
+        self.place_order(result, quantity, limit)
+    }
+
+    fn place_order(&mut self, result: Result<Uuid, DogePlaceOrderError>, quantity: f32, limit: f32) -> Result<String, PlaceOrderError> {
+        match result {
+            Ok(uuid) => {
+                // the order may already match
+                self.advance_a_day();
+                Ok(uuid.to_string())
+            }
+            Err(err) => Err(match err {
+                DogePlaceOrderError::NonPositiveQuantity => PlaceOrderError::NonPositiveQuantity { quantity },
+                DogePlaceOrderError::NonPositiveLimit => PlaceOrderError::NonPositiveLimit { limit },
+                DogePlaceOrderError::NonPositiveTtl => PlaceOrderError::NonPositiveDays,
+                DogePlaceOrderError::InsufficientEscrow { kind, required } => PlaceOrderError::InsufficientEscrow { kind, required },
+            }),
+        }
+    }
+
+    /// State of an order, `None` if the token is not of an order.
+    pub fn get_order_state(&self, token: String) -> Option<LimitOrderState> {
+        let uuid = Uuid::from_str(&token).ok()?;
+        self.doge_impl.get_order(&uuid).map(|order| order.state.clone())
+    }
+
+    /// Closes an open order, giving back what it held.
+    pub fn cancel_order(&mut self, token: String) -> Result<Good, OrderError> {
+        let uuid = if let Ok(uuid) = Uuid::from_str(&token) { uuid } else { return Err(OrderError::UnrecognizedToken { unrecognized_token: token }); };
+
+        self.doge_impl.cancel_order(&uuid).map_err(|err| match err {
+            DogeOrderError::UnrecognizedUuid => OrderError::UnrecognizedToken { unrecognized_token: token },
+            DogeOrderError::InvalidState { current_state } => OrderError::InvalidState { current_state },
+        })
+    }
+
+    /// Gives back the goods of a filled order, followed by the change if any, or what an expired order held.
+    pub fn claim_order(&mut self, token: String) -> Result<Vec<Good>, OrderError> {
+        let uuid = if let Ok(uuid) = Uuid::from_str(&token) { uuid } else { return Err(OrderError::UnrecognizedToken { unrecognized_token: token }); };
+
+        self.doge_impl.claim_order(&uuid).map_err(|err| match err {
+            DogeOrderError::UnrecognizedUuid => OrderError::UnrecognizedToken { unrecognized_token: token },
+            DogeOrderError::InvalidState { current_state } => OrderError::InvalidState { current_state },
+        })
+    }
+
+    /// Price, in `in_kind`, of buying `quantity` of `kind` with [DogeMarket::lock_cross_buy]. It is refused for the same
+    /// inventory limits as the lock.
+    pub fn get_cross_buy_price(&self, kind: GoodKind, quantity: f32, in_kind: GoodKind) -> Result<f32, CrossPriceError> {
+        self.doge_impl.get_cross_buy_price(kind, quantity, in_kind).map_err(|err| cross_price_error(err, kind, in_kind))
+    }
+
+    /// Price, in `in_kind`, of selling `quantity` of `kind` with [DogeMarket::lock_cross_sell]. It is refused for the
+    /// same inventory limits as the lock.
+    pub fn get_cross_sell_price(&self, kind: GoodKind, quantity: f32, in_kind: GoodKind) -> Result<f32, CrossPriceError> {
+        self.doge_impl.get_cross_sell_price(kind, quantity, in_kind).map_err(|err| cross_price_error(err, in_kind, kind))
+    }
+
+    /// Like [Market::lock_buy], but bid in `bid_kind` at the price of [DogeMarket::get_cross_buy_price]. The lock is paid
+    /// with [Market::buy] in `bid_kind`. Bids in the good to buy, or in a good other than the default one when locks are
+    /// cleared by batch auctions or backed by deposits, are refused as [LockBuyError::BidTooLow] with an infinite lowest
+    /// acceptable bid.
+    pub fn lock_cross_buy(&mut self, kind_to_buy: GoodKind, quantity_to_buy: f32, bid_kind: GoodKind, bid: f32, trader_name: String) -> Result<String, LockBuyError> {
+        if self.doge_impl.get_lock_deposit(bid) > 0. {
+            return Err(LockBuyError::BidTooLow { requested_good_kind: kind_to_buy, requested_good_quantity: quantity_to_buy, low_bid: bid, lowest_acceptable_bid: f32::INFINITY });
+        }
+
+        self.do_lock_buy(kind_to_buy, quantity_to_buy, Good::new(bid_kind, bid), trader_name, 0.)
+    }
+
+    /// Like [Market::lock_sell], but offered in `offer_kind` at the price of [DogeMarket::get_cross_sell_price], see
+    /// [DogeMarket::lock_cross_buy]. Refused offers are reported as [LockSellError::OfferTooHigh] with a highest
+    /// acceptable offer of 0.
+    pub fn lock_cross_sell(&mut self, kind_to_sell: GoodKind, quantity_to_sell: f32, offer_kind: GoodKind, offer: f32, trader_name: String) -> Result<String, LockSellError> {
+        if self.doge_impl.get_lock_deposit(offer) > 0. {
+            return Err(LockSellError::OfferTooHigh { offered_good_kind: kind_to_sell, offered_good_quantity: quantity_to_sell, high_offer: offer, highest_acceptable_offer: 0. });
+        }
+
+        self.do_lock_sell(kind_to_sell, quantity_to_sell, Good::new(offer_kind, offer), trader_name, 0.)
+    }
+
+    /// Price, in the default good, of a forward buying `quantity` of `kind` in `days` days, see
+    /// [DogeMarket::agree_forward_buy].
+    pub fn get_forward_buy_price(&self, kind: GoodKind, quantity: f32, days: u32) -> Result<f32, ForwardPriceError> {
+        self.get_forward_price(OrderSide::Buy, kind, quantity, days)
+    }
+
+    /// Price, in the default good, of a forward selling `quantity` of `kind` in `days` days, see
+    /// [DogeMarket::agree_forward_sell].
+    pub fn get_forward_sell_price(&self, kind: GoodKind, quantity: f32, days: u32) -> Result<f32, ForwardPriceError> {
+        self.get_forward_price(OrderSide::Sell, kind, quantity, days)
+    }
+
+    fn get_forward_price(&self, side: OrderSide, kind: GoodKind, quantity: f32, days: u32) -> Result<f32, ForwardPriceError> {
+        self.doge_impl.get_forward_price(side, kind, quantity, days).map_err(|err| forward_price_error(err, kind, quantity, days))
+    }
+
+    /// Agrees to buy `quantity` of `kind` for `price` of the default good in `days` days, at no less than
+    /// [DogeMarket::get_forward_buy_price]. The market sets the goods aside until then. The margin, in the default good,
+    /// is taken out of `margin` and counts towards the price: the rest is handed over with [DogeMarket::deliver_forward]
+    /// before maturity. If it is not, the market keeps the margin. The goods are given by [DogeMarket::claim_forward].
+    pub fn agree_forward_buy(&mut self, kind: GoodKind, quantity: f32, price: f32, days: u32, trader_name: String, margin: &mut Good) -> Result<String, AgreeForwardError> {
+        let proposal = ForwardTxProposal { side: OrderSide::Buy, good: Good::new(kind, quantity), price: Good::new(DEFAULT_GOOD_KIND, price), trader_name };
+        self.agree_forward(proposal, days, margin)
+    }
+
+    /// Agrees to sell `quantity` of `kind` for `price` of the default good in `days` days, at no more than
+    /// [DogeMarket::get_forward_sell_price], like [DogeMarket::agree_forward_buy]. The goods are handed over with
+    /// [DogeMarket::deliver_forward], and the price is given back together with the margin by
+    /// [DogeMarket::claim_forward].
+    pub fn agree_forward_sell(&mut self, kind: GoodKind, quantity: f32, price: f32, days: u32, trader_name: String, margin: &mut Good) -> Result<String, AgreeForwardError> {
+        let proposal = ForwardTxProposal { side: OrderSide::Sell, good: Good::new(kind, quantity), price: Good::new(DEFAULT_GOOD_KIND, price), trader_name };
+        self.agree_forward(proposal, days, margin)
+    }
+
+    fn agree_forward(&mut self, proposal: ForwardTxProposal, days: u32, margin: &mut Good) -> Result<String, AgreeForwardError> {
+        let (kind, quantity, price) = (proposal.good.get_kind(), proposal.good.get_qty(), proposal.price.get_qty());
+        let required = self.doge_impl.get_forward_margin(price);
+        if margin.get_kind() != DEFAULT_GOOD_KIND || margin.get_qty() < required {
+            return Err(AgreeForwardError::InsufficientMargin { required });
+        }
+
+        let (outgoing_kind, incoming_kind) = match proposal.side {
+            OrderSide::Buy => (kind, DEFAULT_GOOD_KIND),
+            OrderSide::Sell => (DEFAULT_GOOD_KIND, kind),
+        };
+        match self.doge_impl.agree_forward(&proposal, days) {
+            Ok(uuid) => {
+                if required > 0. {
+                    margin.split(required).unwrap();
+                }
+                self.advance_a_day();
+                Ok(uuid.to_string())
+            }
+            Err(err) => Err(match err {
+                DogeForwardAgreementError::Price { error } => AgreeForwardError::Price { error: forward_price_error(error, kind, quantity, days) },
+                DogeForwardAgreementError::NonPositivePrice => AgreeForwardError::NonPositivePrice { price },
+                DogeForwardAgreementError::PriceTooLow { lowest } => AgreeForwardError::PriceTooLow { low_price: price, lowest_acceptable_price: lowest },
+                DogeForwardAgreementError::PriceTooHigh { highest } => AgreeForwardError::PriceTooHigh { high_price: price, highest_acceptable_price: highest },
+                DogeForwardAgreementError::ExceedsReservableQuantity { reservable } => AgreeForwardError::InsufficientGoodQuantityAvailable { kind: outgoing_kind, available_good_quantity: reservable },
+                DogeForwardAgreementError::BreachesReserveFloor { floor, reservable } => AgreeForwardError::InsufficientGoodQuantityAvailable { kind: outgoing_kind, available_good_quantity: (reservable - floor).max(0.) },
+                DogeForwardAgreementError::ExceedsCapacity { room } => AgreeForwardError::ExceedsCapacity { kind: incoming_kind, room },
+                DogeForwardAgreementError::BadReputation { max_quantity } => AgreeForwardError::InsufficientGoodQuantityAvailable { kind: outgoing_kind, available_good_quantity: max_quantity },
+                DogeForwardAgreementError::TooManyLocks => AgreeForwardError::MaxAllowedLocksReached,
+                DogeForwardAgreementError::ExceedsTraderExposure { max_quantity } => AgreeForwardError::InsufficientGoodQuantityAvailable { kind: outgoing_kind, available_good_quantity: max_quantity },
+            }),
+        }
+    }
+
+    /// Hands over what the trader owes on a forward before it matures: the price less the margin of a buy, the goods of
+    /// a sell. It is taken out of `good` and held until maturity.
+    pub fn deliver_forward(&mut self, token: String, good: &mut Good) -> Result<(), ForwardError> {
+        let uuid = if let Ok(uuid) = Uuid::from_str(&token) { uuid } else { return Err(ForwardError::UnrecognizedToken { unrecognized_token: token }); };
+
+        match self.doge_impl.deliver_forward(&uuid, good) {
+            Ok(()) => {
+                self.advance_a_day();
+                Ok(())
+            }
+            Err(err) => Err(match err {
+                DogeForwardDeliveryError::UnrecognizedUuid => ForwardError::UnrecognizedToken { unrecognized_token: token },
+                DogeForwardDeliveryError::InvalidState { current_state } => ForwardError::InvalidState { current_state },
+                DogeForwardDeliveryError::AlreadyDelivered => ForwardError::AlreadyDelivered,
+                DogeForwardDeliveryError::WrongGoodKind { pre_agreed } => {
+                    let pre_agreed_quantity = self.doge_impl.get_forward(&uuid).unwrap().get_due().get_qty();
+                    ForwardError::InsufficientGoodQuantity { kind: pre_agreed, pre_agreed_quantity }
+                }
+                DogeForwardDeliveryError::InsufficientGoodQuantity { pre_agreed } => ForwardError::InsufficientGoodQuantity { kind: good.get_kind(), pre_agreed_quantity: pre_agreed },
+            }),
+        }
+    }
+
+    /// Gives the proceeds of a forward that settled: the goods of a buy, the price and the margin of a sell.
+    pub fn claim_forward(&mut self, token: String) -> Result<Good, ForwardError> {
+        let uuid = if let Ok(uuid) = Uuid::from_str(&token) { uuid } else { return Err(ForwardError::UnrecognizedToken { unrecognized_token: token }); };
+
+        self.doge_impl.claim_forward(&uuid).map_err(|err| match err {
+            DogeForwardClaimError::UnrecognizedUuid => ForwardError::UnrecognizedToken { unrecognized_token: token },
+            DogeForwardClaimError::InvalidState { current_state } => ForwardError::InvalidState { current_state },
+        })
+    }
+
+    /// State of a forward, `None` if the token is not of a forward.
+    pub fn get_forward_state(&self, token: String) -> Option<ForwardTxState> {
+        let uuid = Uuid::from_str(&token).ok()?;
+        self.doge_impl.get_forward(&uuid).map(|forward| forward.state.clone())
+    }
+
+    /// Restocks the goods running low by trading with `suppliers` instead of converting other goods at the default rates,
+    /// or goes back to the default rates if there are none. The market only plans the trades at the end of the day, and
+    /// [DogeMarket::restock] carries them out. Suppliers are not part of snapshots.
+    pub fn set_refill_suppliers(&mut self, suppliers: Vec<Rc<RefCell<dyn Market>>>) {
+        let backend = (!suppliers.is_empty()).then(|| MarketRefillBackend::new(suppliers, self.doge_impl.market_name.to_string()));
+        self.doge_impl.set_refill_backend(backend);
+    }
+
+    /// Trades with the suppliers set by [DogeMarket::set_refill_suppliers] the refill planned last, if any, logging every
+    /// trade. The market is not borrowed while it trades, so the suppliers may notify it of the trades.
+    pub fn restock(market: &Rc<RefCell<DogeMarket>>) {
+        let queued = market.borrow_mut().doge_impl.take_queued_refill();
+        let (backend, refill, mut held) = if let Some(queued) = queued { queued } else { return; };
+
+        let trades = backend.restock(&mut held, &refill);
+
+        let mut market = market.borrow_mut();
+        market.doge_impl.return_refill_goods(&held);
+        for trade in &trades {
+            market.logger.log_refill_trade(trade);
+        }
+    }
+
+    /// Collateral of `collateral_kind` that [DogeMarket::borrow] takes for a loan of `quantity` of `kind`.
+    pub fn get_loan_collateral(&self, kind: GoodKind, quantity: f32, collateral_kind: GoodKind) -> f32 {
+        self.doge_impl.get_loan_collateral(kind, quantity, collateral_kind)
+    }
+
+    /// Borrows `quantity` of `kind` from the goods of the market for `days` days, backed by the collateral of
+    /// [DogeMarket::get_loan_collateral] taken out of `collateral`. The loan accrues interest every day and is repaid
+    /// with [DogeMarket::repay_loan]. The market keeps the collateral if the loan is not repaid in time, or as soon as the
+    /// collateral is no longer worth enough. Returns the token of the loan and the goods borrowed.
+    pub fn borrow(&mut self, kind: GoodKind, quantity: f32, days: u32, trader_name: String, collateral: &mut Good) -> Result<(String, Good), BorrowError> {
+        match self.doge_impl.borrow(kind, quantity, days, trader_name, collateral) {
+            Ok((uuid, borrowed)) => {
+                self.advance_a_day();
+                Ok((uuid.to_string(), borrowed))
+            }
+            Err(err) => Err(match err {
+                DogeBorrowError::NonPositiveQuantity => BorrowError::NonPositiveQuantity { quantity },
+                DogeBorrowError::InvalidDays { max_days } => BorrowError::InvalidDays { days, max_days },
+                DogeBorrowError::SameKindCollateral => BorrowError::SameKindCollateral,
+                DogeBorrowError::InsufficientCollateral { required } => BorrowError::InsufficientCollateral { required },
+                DogeBorrowError::ExceedsReservableQuantity { reservable } =>
+                    BorrowError::InsufficientGoodQuantityAvailable { requested_good_kind: kind, requested_good_quantity: quantity, available_good_quantity: reservable },
+                DogeBorrowError::BreachesReserveFloor { floor, reservable } =>
+                    BorrowError::InsufficientGoodQuantityAvailable { requested_good_kind: kind, requested_good_quantity: quantity, available_good_quantity: (reservable - floor).max(0.) },
+                DogeBorrowError::BadReputation { max_quantity } =>
+                    BorrowError::InsufficientGoodQuantityAvailable { requested_good_kind: kind, requested_good_quantity: quantity, available_good_quantity: max_quantity },
+            }),
+        }
+    }
+
+    /// Repays an open loan with the debt of [DogeMarket::get_loan_debt], taken out of `with`, giving back the collateral.
+    pub fn repay_loan(&mut self, token: String, with: &mut Good) -> Result<Good, LoanError> {
+        let uuid = if let Ok(uuid) = Uuid::from_str(&token) { uuid } else { return Err(LoanError::UnrecognizedToken { unrecognized_token: token }); };
+
+        match self.doge_impl.repay_loan(&uuid, with) {
+            Ok(collateral) => {
+                self.advance_a_day();
+                Ok(collateral)
+            }
+            Err(err) => Err(match err {
+                DogeRepayLoanError::UnrecognizedUuid => LoanError::UnrecognizedToken { unrecognized_token: token },
+                DogeRepayLoanError::InvalidState { current_state } => LoanError::InvalidState { current_state },
+                DogeRepayLoanError::WrongGoodKind { pre_agreed } => {
+                    let debt = self.doge_impl.get_loan(&uuid).unwrap().get_debt();
+                    LoanError::InsufficientRepayment { kind: pre_agreed, debt }
+                }
+                DogeRepayLoanError::InsufficientGoodQuantity { debt } => LoanError::InsufficientRepayment { kind: with.get_kind(), debt },
+            }),
+        }
+    }
+
+    /// Principal plus the interest accrued so far of a loan, in the good borrowed, `None` if the token is not of a loan.
+    pub fn get_loan_debt(&self, token: String) -> Option<f32> {
+        let uuid = Uuid::from_str(&token).ok()?;
+        self.doge_impl.get_loan(&uuid).map(|loan| loan.get_debt())
+    }
+
+    /// State of a loan, `None` if the token is not of a loan.
+    pub fn get_loan_state(&self, token: String) -> Option<LoanState> {
+        let uuid = Uuid::from_str(&token).ok()?;
+        self.doge_impl.get_loan(&uuid).map(|loan| loan.state.clone())
+    }
+
+    /// Advances the market by a day, notifying the subscribers of the locks the batch auction cleared and of the orders
+    /// filled.
+    fn advance_a_day(&mut self) {
+        let outcome = self.doge_impl.advance_a_day();
+
+        for cleared in outcome.cleared_locks {
+            let lock = cleared.lock;
+            let price = Good::new(DEFAULT_GOOD_KIND, cleared.price.unwrap_or(lock.limit));
+            let kind = match lock.side {
+                OrderSide::Buy => {
+                    self.logger.log_lock_buy(&lock.good, &lock.trader_name, &price, cleared.price.map(|_| &lock.uuid));
+                    LockedBuy
+                }
+                OrderSide::Sell => {
+                    self.logger.log_lock_sell(&lock.good, &lock.trader_name, &price, cleared.price.map(|_| &lock.uuid));
+                    LockedSell
+                }
+            };
+
+            if cleared.price.is_some() {
+                let event = Event { kind, good_kind: lock.good.get_kind(), quantity: lock.good.get_qty(), price: price.get_qty() };
+                self.subscribers.iter_mut().for_each(|sub| sub.on_event(event.clone()));
+            }
+        }
+
+        for uuid in outcome.filled_orders {
+            let order = self.doge_impl.get_order(&uuid).unwrap();
+            let event = match order.side {
+                OrderSide::Buy => Event { kind: Bought, good_kind: order.kind, quantity: order.quantity, price: order.limit - order.escrow.get_qty() },
+                OrderSide::Sell => Event { kind: Sold, good_kind: order.kind, quantity: order.quantity, price: order.proceeds.get_qty() },
+            };
+            self.subscribers.iter_mut().for_each(|sub| sub.on_event(event.clone()));
+        }
+    }
+
+    /// Ends the day of a batch auction before a lock is settled, so that the locks queued during it are cleared.
+    fn close_auction_day(&mut self) {
+        if self.doge_impl.has_queued_locks() {
+            self.advance_a_day();
+        }
+    }
+
+    /// Locks a buy backed by `deposit` of the good of the bid, already checked by the caller.
+    fn do_lock_buy(&mut self, kind_to_buy: GoodKind, quantity_to_buy: f32, bid: Good, trader_name: String, deposit: f32) -> Result<String, LockBuyError> {
+        let proposal = BuyTxProposal { buy: Good::new(kind_to_buy, quantity_to_buy), bid, trader_name: trader_name.clone() };
+        let bid = proposal.bid.get_qty();
+
+        // the lock is logged and notified once the auction clears it
+        if self.get_config().lock_clearing == LockClearing::BatchAuction {
+            return self.doge_impl.queue_buy_reservation(&proposal)
+                .map(|uuid| uuid.to_string())
+                .map_err(|err| lock_buy_error(err, kind_to_buy, quantity_to_buy, bid));
+        }
+
+        match self.doge_impl.do_buy_reservation(&proposal, deposit) {
+            Ok(uuid) => {
+                self.logger.log_lock_buy(&proposal.buy, &trader_name, &proposal.bid, Some(&uuid));
+
+                self.advance_a_day();
+
+                // the other markets read the prices of the events in the default good
+                if proposal.bid.get_kind() != DEFAULT_GOOD_KIND {
+                    return Ok(uuid.to_string());
+                }
+                self.subscribers.iter_mut().for_each(|sub| {
+                    let event = Event {
+                        kind: LockedBuy,
+                        good_kind: kind_to_buy,
+                        quantity: quantity_to_buy,
+                        price: bid,
+                    };
+                    sub.on_event(event);
+                });
+
+                Ok(uuid.to_string())
+            }
+            Err(err) => Err(lock_buy_error(err, kind_to_buy, quantity_to_buy, bid)),
+        }
+    }
+
+    /// Locks a sell backed by `deposit` of the good of the offer, already checked by the caller.
+    fn do_lock_sell(&mut self, kind_to_sell: GoodKind, quantity_to_sell: f32, offer: Good, trader_name: String, deposit: f32) -> Result<String, LockSellError> {
+        let proposal = SellTxProposal { sell: Good::new(kind_to_sell, quantity_to_sell), offer, trader_name: trader_name.clone() };
+        let offer = proposal.offer.get_qty();
+
+        // the lock is logged and notified once the auction clears it
+        if self.get_config().lock_clearing == LockClearing::BatchAuction {
+            return self.doge_impl.queue_sell_reservation(&proposal)
+                .map(|uuid| uuid.to_string())
+                .map_err(|err| lock_sell_error(err, kind_to_sell, quantity_to_sell, offer));
+        }
+
+        match self.doge_impl.do_sell_reservation(&proposal, deposit) {
+            Ok(uuid) => {
+                self.logger.log_lock_sell(&proposal.sell, &trader_name, &proposal.offer, Some(&uuid));
+
+                self.advance_a_day();
+
+                if proposal.offer.get_kind() != DEFAULT_GOOD_KIND {
+                    return Ok(uuid.to_string());
+                }
+                self.subscribers.iter_mut().for_each(|sub| {
+                    let event = Event {
+                        kind: LockedSell,
+                        good_kind: kind_to_sell,
+                        quantity: quantity_to_sell,
+                        price: offer,
+                    };
+                    sub.on_event(event);
+                });
+
+                Ok(uuid.to_string())
+            }
+            Err(err) => Err(lock_sell_error(err, kind_to_sell, quantity_to_sell, offer)),
+        }
+    }
+
+    fn do_buy(&mut self, token: String, cash: &mut Good, partial: bool) -> Result<Good, BuyError> {
+        let uuid = if let Ok(uuid) = Uuid::from_str(&token) { uuid } else { return Err(BuyError::UnrecognizedToken { unrecognized_token: token }); };
+        self.close_auction_day();
+
+        let result = if partial { self.doge_impl.buy_partially(&uuid, cash) } else { self.doge_impl.buy(&uuid, cash) };
+        match result {
+            Ok(bought) => {
+                self.logger.log_buy(&uuid, true);
+
+                self.advance_a_day();
+
+                let tx = self.doge_impl.get_tx_service().get_buy(&uuid).unwrap();
+                let (quantity, price) = match tx.state {
+                    BuyTxState::PartiallyPaid { buy, bid } => (buy, bid),
+                    _ => (tx.buy.get_qty(), tx.bid.get_qty()),
+                };
+
+                self.subscribers.iter_mut().for_each(|sub| {
+                    let event = Event {
+                        kind: Bought,
+                        good_kind: tx.buy.get_kind(),
+                        quantity,
+                        price,
+                    };
+                    sub.on_event(event);
+                });
+
+                Ok(bought)
+            }
+            Err(err) => {
+                self.logger.log_buy(&uuid, false);
+
+                Err(match err {
+                    DogeBuyError::UnrecognizedUuid => BuyError::UnrecognizedToken { unrecognized_token: token },
+                    // the lock never was, which to the trader is a lock that expired before it was paid
+                    DogeBuyError::DroppedByAuction => BuyError::ExpiredToken { expired_token: token },
+                    DogeBuyError::InvalidState { current_state } => match current_state {
+                        BuyTxState::Reserved => unreachable!(),
+                        BuyTxState::Paid | BuyTxState::PartiallyPaid { .. } => BuyError::UnrecognizedToken { unrecognized_token: token },
+                        BuyTxState::Expired | BuyTxState::Cancelled => BuyError::ExpiredToken { expired_token: token }
+                    },
+                    DogeBuyError::WrongGoodKind { .. } => BuyError::GoodKindNotDefault { non_default_good_kind: cash.get_kind() },
+                    DogeBuyError::InsufficientGoodQuantity { pre_agreed } => BuyError::InsufficientGoodQuantity { contained_quantity: cash.get_qty(), pre_agreed_quantity: pre_agreed }
+                })
+            }
+        }
+    }
+
+    fn do_sell(&mut self, token: String, good: &mut Good, partial: bool) -> Result<Good, SellError> {
+        let uuid = if let Ok(uuid) = Uuid::from_str(&token) { uuid } else { return Err(SellError::UnrecognizedToken { unrecognized_token: token }); };
+        self.close_auction_day();
+
+        let result = if partial { self.doge_impl.sell_partially(&uuid, good) } else { self.doge_impl.sell(&uuid, good) };
+        match result {
+            Ok(sold) => {
+                self.logger.log_sell(&uuid, true);
+
+                self.advance_a_day();
+
+                let tx = self.doge_impl.get_tx_service().get_sell(&uuid).unwrap();
+                let (quantity, price) = match tx.state {
+                    SellTxState::PartiallyPaid { sell, offer } => (sell, offer),
+                    _ => (tx.sell.get_qty(), tx.offer.get_qty()),
+                };
+
+                self.subscribers.iter_mut().for_each(|sub| {
+                    let event = Event {
+                        kind: Sold,
+                        good_kind: tx.sell.get_kind(),
+                        quantity,
+                        price,
+                    };
+                    sub.on_event(event);
+                });
 
-        let doge_impl = DogeMarketImpl::new(ops, 10);
+                Ok(sold)
+            }
+            Err(err) => {
+                self.logger.log_sell(&uuid, false);
+
+                Err(match err {
+                    DogeSellError::UnrecognizedUuid => SellError::UnrecognizedToken { unrecognized_token: token },
+                    // the lock never was, which to the trader is a lock that expired before it was paid
+                    DogeSellError::DroppedByAuction => SellError::ExpiredToken { expired_token: token },
+                    DogeSellError::InvalidState { current_state } => match current_state {
+                        SellTxState::Reserved => unreachable!(),
+                        SellTxState::Paid | SellTxState::PartiallyPaid { .. } => SellError::UnrecognizedToken { unrecognized_token: token },
+                        SellTxState::Expired | SellTxState::Cancelled => SellError::ExpiredToken { expired_token: token }
+                    }
+                    DogeSellError::WrongGoodKind { pre_agreed } => SellError::WrongGoodKind { wrong_good_kind: good.get_kind(), pre_agreed_kind: pre_agreed },
+                    DogeSellError::InsufficientGoodQuantity { pre_agreed } => SellError::InsufficientGoodQuantity { contained_quantity: good.get_qty(), pre_agreed_quantity: pre_agreed },
+                })
+            }
+        }
+    }
 
+    fn new_with_impl(doge_impl: DogeMarketImpl, eur: &Good, yen: &Good, usd: &Good, yuan: &Good) -> Rc<RefCell<DogeMarket>> {
         // Fixme: Why does this not compile?
         // let mut logger: Box<dyn Logger> = Box::new(TxtFileLogger::try_new("DogeMarket").unwrap_or(MuteLogger::new()));
 
-        let mut logger: Box<dyn Logger> = match TxtFileLogger::try_new("DogeMarket") {
+        let mut logger: Box<dyn Logger> = match TxtFileLogger::try_new(doge_impl.market_name) {
             Some(txt_file_logger) => {
                 Box::new(txt_file_logger)
             }
@@ -50,6 +1060,7 @@ impl DogeMarket {
         };
 
         logger.log_initialization(eur, usd, yen, yuan);
+        logger.log_seed(doge_impl.get_seed());
 
         Rc::new(RefCell::new(Self {
             doge_impl,
@@ -64,15 +1075,15 @@ impl Notifiable for DogeMarket {
         self.subscribers.push(subscriber);
     }
 
-    fn on_event(&mut self, _event: Event) {
-        self.doge_impl.advance_a_day();
+    fn on_event(&mut self, event: Event) {
+        self.doge_impl.observe_competitor_event(&event);
+        self.advance_a_day();
     }
 }
 
 impl Market for DogeMarket {
     fn new_random() -> Rc<RefCell<dyn Market>> where Self: Sized {
-        let quantities = new_random_quantities();
-        DogeMarket::new_with_goods(&quantities[0], &quantities[1], &quantities[2], &quantities[3])
+        DogeMarket::new_random_with_seed(rand::random())
     }
 
 
@@ -82,11 +1093,14 @@ impl Market for DogeMarket {
         let yen = Good::new(YEN, yen);
         let yuan = Good::new(YUAN, yuan);
 
-        DogeMarket::new_with_goods(&eur, &yen, &usd, &yuan)
+        DogeMarket::new_with_goods(&eur, &yen, &usd, &yuan, MarketConfig::default())
+            .expect("the default configuration is valid")
     }
 
-    fn new_file(_path: &str) -> Rc<RefCell<dyn Market>> where Self: Sized {
-        unimplemented!()
+    /// Panics if the file cannot be loaded, since the trait has no way to report it. See [DogeMarket::try_new_file].
+    fn new_file(path: &str) -> Rc<RefCell<dyn Market>> where Self: Sized {
+        DogeMarket::try_new_file(path)
+            .unwrap_or_else(|err| panic!("Could not load DogeMarket from {}: {:?}", path, err))
     }
 
     fn get_name(&self) -> &'static str {
@@ -100,13 +1114,7 @@ impl Market for DogeMarket {
     }
 
     fn get_buy_price(&self, kind: GoodKind, quantity: f32) -> Result<f32, MarketGetterError> {
-        self.doge_impl.get_buy_price(kind, quantity)
-            .map_err(|err| match err {
-                DogeGetBuyPriceError::NonPositiveRequest =>
-                    MarketGetterError::NonPositiveQuantityAsked,
-                DogeGetBuyPriceError::ExceedsReservableQuantity { reservable } =>
-                    MarketGetterError::InsufficientGoodQuantityAvailable { requested_good_kind: kind, requested_good_quantity: quantity, available_good_quantity: reservable }
-            })
+        self.doge_impl.get_buy_price(kind, quantity).map_err(|err| buy_price_error(err, kind, quantity))
     }
 
     fn get_sell_price(&self, kind: GoodKind, quantity: f32) -> Result<f32, MarketGetterError> {
@@ -126,156 +1134,133 @@ impl Market for DogeMarket {
     }
 
 
+    /// Markets that require a deposit to lock accept no bid here, since there is no way to take the deposit: they refuse
+    /// every lock as [LockBuyError::BidTooLow] with an infinite lowest acceptable bid. See
+    /// [DogeMarket::lock_buy_with_deposit].
     fn lock_buy(&mut self, kind_to_buy: GoodKind, quantity_to_buy: f32, bid: f32, trader_name: String) -> Result<String, LockBuyError> {
-        let proposal = BuyTxProposal { buy: Good::new(kind_to_buy, quantity_to_buy), bid: Good::new(DEFAULT_GOOD_KIND, bid) };
-
-        match self.doge_impl.do_buy_reservation(&proposal) {
-            Ok(uuid) => {
-                self.logger.log_lock_buy(&proposal.buy, &trader_name, &proposal.bid, Some(&uuid));
-
-                self.doge_impl.advance_a_day();
-
-                self.subscribers.iter_mut().for_each(|sub| {
-                    let event = Event {
-                        kind: LockedBuy,
-                        good_kind: kind_to_buy,
-                        quantity: quantity_to_buy,
-                        price: bid,
-                    };
-                    sub.on_event(event);
-                });
-
-                Ok(uuid.to_string())
-            }
-            Err(err) => Err(match err {
-                DogeBuyReservationError::NonPositiveBuy =>
-                    LockBuyError::NonPositiveQuantityToBuy { negative_quantity_to_buy: quantity_to_buy },
-                DogeBuyReservationError::NonPositiveBid =>
-                    LockBuyError::NonPositiveBid { negative_bid: bid },
-                DogeBuyReservationError::ExceedsReservableQuantity { reservable } =>
-                    LockBuyError::InsufficientGoodQuantityAvailable { requested_good_kind: kind_to_buy, requested_good_quantity: quantity_to_buy, available_good_quantity: reservable },
-                DogeBuyReservationError::BidTooLow { lowest } =>
-                    LockBuyError::BidTooLow { requested_good_kind: kind_to_buy, requested_good_quantity: quantity_to_buy, low_bid: bid, lowest_acceptable_bid: lowest }
-            }),
+        if self.doge_impl.get_lock_deposit(bid) > 0. {
+            return Err(LockBuyError::BidTooLow { requested_good_kind: kind_to_buy, requested_good_quantity: quantity_to_buy, low_bid: bid, lowest_acceptable_bid: f32::INFINITY });
         }
+
+        self.do_lock_buy(kind_to_buy, quantity_to_buy, Good::new(DEFAULT_GOOD_KIND, bid), trader_name, 0.)
     }
 
     fn buy(&mut self, token: String, cash: &mut Good) -> Result<Good, BuyError> {
-        let uuid = if let Ok(uuid) = Uuid::from_str(&token) { uuid } else { return Err(BuyError::UnrecognizedToken { unrecognized_token: token }); };
-
-        match self.doge_impl.buy(&uuid, cash) {
-            Ok(bought) => {
-                self.logger.log_buy(&uuid, true);
-
-                self.doge_impl.advance_a_day();
-
-                let tx = self.doge_impl.get_tx_service().get_buy(&uuid).unwrap();
-
-                self.subscribers.iter_mut().for_each(|sub| {
-                    let event = Event {
-                        kind: Bought,
-                        good_kind: tx.buy.get_kind(),
-                        quantity: tx.buy.get_qty(),
-                        price: tx.bid.get_qty(),
-                    };
-                    sub.on_event(event);
-                });
-
-                Ok(bought)
-            }
-            Err(err) => {
-                self.logger.log_buy(&uuid, false);
-
-                Err(match err {
-                    DogeBuyError::UnrecognizedUuid => BuyError::UnrecognizedToken { unrecognized_token: token },
-                    DogeBuyError::InvalidState { current_state } => match current_state {
-                        BuyTxState::Reserved => unreachable!(),
-                        BuyTxState::Paid => BuyError::UnrecognizedToken { unrecognized_token: token },
-                        BuyTxState::Expired => BuyError::ExpiredToken { expired_token: token }
-                    },
-                    DogeBuyError::WrongGoodKind { .. } => BuyError::GoodKindNotDefault { non_default_good_kind: cash.get_kind() },
-                    DogeBuyError::InsufficientGoodQuantity { pre_agreed } => BuyError::InsufficientGoodQuantity { contained_quantity: cash.get_qty(), pre_agreed_quantity: pre_agreed }
-                })
-            }
-        }
+        self.do_buy(token, cash, false)
     }
 
+    /// Markets that require a deposit to lock accept no offer here, like [DogeMarket::lock_buy]: they refuse every lock as
+    /// [LockSellError::OfferTooHigh] with a highest acceptable offer of 0. See [DogeMarket::lock_sell_with_deposit].
     fn lock_sell(&mut self, kind_to_sell: GoodKind, quantity_to_sell: f32, offer: f32, trader_name: String) -> Result<String, LockSellError> {
-        let proposal = SellTxProposal { sell: Good::new(kind_to_sell, quantity_to_sell), offer: Good::new(DEFAULT_GOOD_KIND, offer) };
-
-        match self.doge_impl.do_sell_reservation(&proposal) {
-            Ok(uuid) => {
-                self.logger.log_lock_sell(&proposal.sell, &trader_name, &proposal.offer, Some(&uuid));
-
-                self.doge_impl.advance_a_day();
-
-                self.subscribers.iter_mut().for_each(|sub| {
-                    let event = Event {
-                        kind: LockedSell,
-                        good_kind: kind_to_sell,
-                        quantity: quantity_to_sell,
-                        price: offer,
-                    };
-                    sub.on_event(event);
-                });
-
-                Ok(uuid.to_string())
-            }
-            Err(err) => Err(match err {
-                DogeSellReservationError::NonPositiveSell =>
-                    LockSellError::NonPositiveQuantityToSell { negative_quantity_to_sell: quantity_to_sell },
-                DogeSellReservationError::NonPositiveOffer =>
-                    LockSellError::NonPositiveOffer { negative_offer: offer },
-                DogeSellReservationError::ExceedsReservableQuantity { reservable } =>
-                    LockSellError::InsufficientDefaultGoodQuantityAvailable { offered_good_kind: kind_to_sell, offered_good_quantity: quantity_to_sell, available_good_quantity: reservable },
-                DogeSellReservationError::OfferTooHigh { highest } =>
-                    LockSellError::OfferTooHigh { offered_good_kind: kind_to_sell, offered_good_quantity: quantity_to_sell, high_offer: offer, highest_acceptable_offer: highest }
-            }),
+        if self.doge_impl.get_lock_deposit(offer) > 0. {
+            return Err(LockSellError::OfferTooHigh { offered_good_kind: kind_to_sell, offered_good_quantity: quantity_to_sell, high_offer: offer, highest_acceptable_offer: 0. });
         }
+
+        self.do_lock_sell(kind_to_sell, quantity_to_sell, Good::new(DEFAULT_GOOD_KIND, offer), trader_name, 0.)
     }
 
     fn sell(&mut self, token: String, good: &mut Good) -> Result<Good, SellError> {
-        let uuid = if let Ok(uuid) = Uuid::from_str(&token) { uuid } else { return Err(SellError::UnrecognizedToken { unrecognized_token: token }); };
-
-        match self.doge_impl.sell(&uuid, good) {
-            Ok(sold) => {
-                self.logger.log_sell(&uuid, true);
+        self.do_sell(token, good, false)
+    }
+}
 
-                self.doge_impl.advance_a_day();
+/// Error of [Market::lock_buy] for a reservation of `quantity_to_buy` of `kind_to_buy` refused by the market.
+fn lock_buy_error(err: DogeBuyReservationError, kind_to_buy: GoodKind, quantity_to_buy: f32, bid: f32) -> LockBuyError {
+    match err {
+        DogeBuyReservationError::NonPositiveBuy =>
+            LockBuyError::NonPositiveQuantityToBuy { negative_quantity_to_buy: quantity_to_buy },
+        DogeBuyReservationError::NonPositiveBid =>
+            LockBuyError::NonPositiveBid { negative_bid: bid },
+        DogeBuyReservationError::ExceedsReservableQuantity { reservable } =>
+            LockBuyError::InsufficientGoodQuantityAvailable { requested_good_kind: kind_to_buy, requested_good_quantity: quantity_to_buy, available_good_quantity: reservable },
+        DogeBuyReservationError::BidTooLow { lowest } =>
+            LockBuyError::BidTooLow { requested_good_kind: kind_to_buy, requested_good_quantity: quantity_to_buy, low_bid: bid, lowest_acceptable_bid: lowest },
+        // no bid in that good is acceptable
+        DogeBuyReservationError::UnsupportedBid =>
+            LockBuyError::BidTooLow { requested_good_kind: kind_to_buy, requested_good_quantity: quantity_to_buy, low_bid: bid, lowest_acceptable_bid: f32::INFINITY },
+        DogeBuyReservationError::BreachesReserveFloor { floor, reservable } =>
+            LockBuyError::InsufficientGoodQuantityAvailable { requested_good_kind: kind_to_buy, requested_good_quantity: quantity_to_buy, available_good_quantity: (reservable - floor).max(0.) },
+        // the bid does not fit in the capacity of the default good, but a proportionally smaller lock would
+        DogeBuyReservationError::ExceedsCapacity { room } =>
+            LockBuyError::InsufficientGoodQuantityAvailable { requested_good_kind: kind_to_buy, requested_good_quantity: quantity_to_buy, available_good_quantity: room / bid * quantity_to_buy },
+        DogeBuyReservationError::BadReputation { max_quantity } =>
+            LockBuyError::InsufficientGoodQuantityAvailable { requested_good_kind: kind_to_buy, requested_good_quantity: quantity_to_buy, available_good_quantity: max_quantity },
+        DogeBuyReservationError::TooManyLocks =>
+            LockBuyError::MaxAllowedLocksReached,
+        DogeBuyReservationError::ExceedsTraderExposure { max_quantity } =>
+            LockBuyError::InsufficientGoodQuantityAvailable { requested_good_kind: kind_to_buy, requested_good_quantity: quantity_to_buy, available_good_quantity: max_quantity }
+    }
+}
 
-                let tx = self.doge_impl.get_tx_service().get_sell(&uuid).unwrap();
+/// Error of [Market::lock_sell] for a reservation of `quantity_to_sell` of `kind_to_sell` refused by the market.
+fn lock_sell_error(err: DogeSellReservationError, kind_to_sell: GoodKind, quantity_to_sell: f32, offer: f32) -> LockSellError {
+    match err {
+        DogeSellReservationError::NonPositiveSell =>
+            LockSellError::NonPositiveQuantityToSell { negative_quantity_to_sell: quantity_to_sell },
+        DogeSellReservationError::NonPositiveOffer =>
+            LockSellError::NonPositiveOffer { negative_offer: offer },
+        DogeSellReservationError::ExceedsReservableQuantity { reservable } =>
+            LockSellError::InsufficientDefaultGoodQuantityAvailable { offered_good_kind: kind_to_sell, offered_good_quantity: quantity_to_sell, available_good_quantity: reservable },
+        DogeSellReservationError::OfferTooHigh { highest } =>
+            LockSellError::OfferTooHigh { offered_good_kind: kind_to_sell, offered_good_quantity: quantity_to_sell, high_offer: offer, highest_acceptable_offer: highest },
+        // no offer in that good is acceptable
+        DogeSellReservationError::UnsupportedOffer =>
+            LockSellError::OfferTooHigh { offered_good_kind: kind_to_sell, offered_good_quantity: quantity_to_sell, high_offer: offer, highest_acceptable_offer: 0. },
+        DogeSellReservationError::BreachesReserveFloor { floor, reservable } =>
+            LockSellError::InsufficientDefaultGoodQuantityAvailable { offered_good_kind: kind_to_sell, offered_good_quantity: quantity_to_sell, available_good_quantity: (reservable - floor).max(0.) },
+        // the market only has room for part of the good, and only pays the matching part of the offer
+        DogeSellReservationError::ExceedsCapacity { room } =>
+            LockSellError::InsufficientDefaultGoodQuantityAvailable { offered_good_kind: kind_to_sell, offered_good_quantity: quantity_to_sell, available_good_quantity: room / quantity_to_sell * offer },
+        DogeSellReservationError::BadReputation { max_quantity } =>
+            LockSellError::InsufficientDefaultGoodQuantityAvailable { offered_good_kind: kind_to_sell, offered_good_quantity: quantity_to_sell, available_good_quantity: max_quantity },
+        DogeSellReservationError::TooManyLocks =>
+            LockSellError::MaxAllowedLocksReached,
+        DogeSellReservationError::ExceedsTraderExposure { max_quantity } =>
+            LockSellError::InsufficientDefaultGoodQuantityAvailable { offered_good_kind: kind_to_sell, offered_good_quantity: quantity_to_sell, available_good_quantity: max_quantity }
+    }
+}
 
-                self.subscribers.iter_mut().for_each(|sub| {
-                    let event = Event {
-                        kind: Sold,
-                        good_kind: tx.sell.get_kind(),
-                        quantity: tx.sell.get_qty(),
-                        price: tx.offer.get_qty(),
-                    };
-                    sub.on_event(event);
-                });
+/// Error of [Market::get_buy_price] for a price of `quantity` of `kind`.
+fn buy_price_error(err: DogeGetBuyPriceError, kind: GoodKind, quantity: f32) -> MarketGetterError {
+    match err {
+        DogeGetBuyPriceError::NonPositiveRequest =>
+            MarketGetterError::NonPositiveQuantityAsked,
+        DogeGetBuyPriceError::ExceedsReservableQuantity { reservable } =>
+            MarketGetterError::InsufficientGoodQuantityAvailable { requested_good_kind: kind, requested_good_quantity: quantity, available_good_quantity: reservable },
+        DogeGetBuyPriceError::BreachesReserveFloor { floor, reservable } =>
+            MarketGetterError::InsufficientGoodQuantityAvailable { requested_good_kind: kind, requested_good_quantity: quantity, available_good_quantity: (reservable - floor).max(0.) }
+    }
+}
 
-                Ok(sold)
-            }
-            Err(err) => {
-                self.logger.log_sell(&uuid, false);
+/// Error of [DogeMarket::get_cross_buy_price] and [DogeMarket::get_cross_sell_price] for an exchange in which the
+/// market gives `outgoing_kind` and takes in `incoming_kind`.
+fn cross_price_error(err: DogeGetCrossPriceError, outgoing_kind: GoodKind, incoming_kind: GoodKind) -> CrossPriceError {
+    match err {
+        DogeGetCrossPriceError::NonPositiveRequest => CrossPriceError::NonPositiveQuantityAsked,
+        DogeGetCrossPriceError::SameKind => CrossPriceError::SameKind,
+        DogeGetCrossPriceError::ExceedsReservableQuantity { reservable } =>
+            CrossPriceError::InsufficientGoodQuantityAvailable { kind: outgoing_kind, available_good_quantity: reservable },
+        DogeGetCrossPriceError::BreachesReserveFloor { floor, reservable } =>
+            CrossPriceError::InsufficientGoodQuantityAvailable { kind: outgoing_kind, available_good_quantity: (reservable - floor).max(0.) },
+        DogeGetCrossPriceError::ExceedsCapacity { room } => CrossPriceError::ExceedsCapacity { kind: incoming_kind, room },
+    }
+}
 
-                Err(match err {
-                    DogeSellError::UnrecognizedUuid => SellError::UnrecognizedToken { unrecognized_token: token },
-                    DogeSellError::InvalidState { current_state } => match current_state {
-                        SellTxState::Reserved => unreachable!(),
-                        SellTxState::Paid => SellError::UnrecognizedToken { unrecognized_token: token },
-                        SellTxState::Expired => SellError::ExpiredToken { expired_token: token }
-                    }
-                    DogeSellError::WrongGoodKind { pre_agreed } => SellError::WrongGoodKind { wrong_good_kind: good.get_kind(), pre_agreed_kind: pre_agreed },
-                    DogeSellError::InsufficientGoodQuantity { pre_agreed } => SellError::InsufficientGoodQuantity { contained_quantity: good.get_qty(), pre_agreed_quantity: pre_agreed },
-                })
-            }
-        }
+/// Error of [DogeMarket::get_forward_buy_price] and [DogeMarket::get_forward_sell_price] for a forward of `quantity` of
+/// `kind` in `days` days.
+fn forward_price_error(err: DogeGetForwardPriceError, kind: GoodKind, quantity: f32, days: u32) -> ForwardPriceError {
+    match err {
+        DogeGetForwardPriceError::NonPositiveRequest => ForwardPriceError::NonPositiveQuantityAsked,
+        DogeGetForwardPriceError::InvalidDays { max_days } => ForwardPriceError::InvalidDays { days, max_days },
+        DogeGetForwardPriceError::ExceedsReservableQuantity { reservable } =>
+            ForwardPriceError::InsufficientGoodQuantityAvailable { requested_good_kind: kind, requested_good_quantity: quantity, available_good_quantity: reservable },
+        DogeGetForwardPriceError::BreachesReserveFloor { floor, reservable } =>
+            ForwardPriceError::InsufficientGoodQuantityAvailable { requested_good_kind: kind, requested_good_quantity: quantity, available_good_quantity: (reservable - floor).max(0.) },
     }
 }
 
-fn new_random_quantities() -> [Good; 4] {
+/// Splits the starting capital among the goods, then cuts each of them by up to half at random.
+fn new_random_quantities(rng: &mut impl Rng) -> [Good; 4] {
     const EUR_TO_USD_RATE: f32 = DEFAULT_EUR_USD_EXCHANGE_RATE;
     const USD_TO_EUR_RATE: f32 = 1. / EUR_TO_USD_RATE;
     const EUR_TO_YEN_RATE: f32 = DEFAULT_EUR_YEN_EXCHANGE_RATE;
@@ -297,13 +1282,30 @@ fn new_random_quantities() -> [Good; 4] {
     const YEN_BUDGET: f32 = YEN_BUDGET_WITHOUT_ERROR - YEN_BUDGET_WITHOUT_ERROR * 1. / 10_000_000.;
     const YUAN_BUDGET: f32 = YUAN_BUDGET_WITHOUT_ERROR - YUAN_BUDGET_WITHOUT_ERROR * 1. / 10_000_000.;
 
-    [Good::new(EUR, EUR_BUDGET), Good::new(YEN, YEN_BUDGET), Good::new(USD, USD_BUDGET), Good::new(YUAN, YUAN_BUDGET)]
+    [(EUR, EUR_BUDGET), (YEN, YEN_BUDGET), (USD, USD_BUDGET), (YUAN, YUAN_BUDGET)]
+        .map(|(kind, budget)| Good::new(kind, budget * rng.gen_range(0.5..=1.)))
 }
 
 #[cfg(test)]
 mod tests {
+    use assert_approx_eq::assert_approx_eq;
+    use unitn_market_2022::event::event::EventKind;
+
+    use crate::market_config::LockTtlTier;
+    use crate::per_good::PerGood;
+    use crate::refill_policy::RefillPolicyKind;
+    use crate::test_fixtures::{stub_market, uniform_market};
+
     use super::*;
 
+    fn wait(market: &mut DogeMarket) {
+        market.on_event(Event { kind: EventKind::Wait, good_kind: EUR, quantity: 0., price: 0. });
+    }
+
+    fn reservable(market: &DogeMarket, kind: GoodKind) -> f32 {
+        market.get_goods().into_iter().find(|label| label.good_kind == kind).unwrap().quantity
+    }
+
     #[test]
     fn test_starting_capital() {
         let market = DogeMarket::new_random();
@@ -315,4 +1317,415 @@ mod tests {
             .sum();
         assert!(sum <= 1_000_000.);
     }
+
+    #[test]
+    fn test_new_random_with_seed() {
+        let quantities = |market: Rc<RefCell<dyn Market>>| market.borrow().get_goods().into_iter().map(|label| label.quantity).collect::<Vec<_>>();
+
+        assert_eq!(quantities(DogeMarket::new_random_with_seed(7)), quantities(DogeMarket::new_random_with_seed(7)));
+        assert_ne!(quantities(DogeMarket::new_random_with_seed(7)), quantities(DogeMarket::new_random_with_seed(8)));
+    }
+
+    #[test]
+    fn test_new_file() {
+        let path = std::env::temp_dir().join("doge_market_test_new_file.json");
+        std::fs::write(&path, r#"{
+            "goods": { "eur": 1000.0, "usd": 2000.0, "yen": 3000.0, "yuan": 4000.0 },
+            "lock_ttl": 3,
+            "sell_earn_percentages": { "eur": 0.0, "usd": 5.0, "yen": 1.0, "yuan": 1.0 }
+        }"#).unwrap();
+
+        let market = DogeMarket::try_new_file(path.to_str().unwrap()).unwrap();
+        let goods = market.borrow().get_goods();
+
+        assert_eq!(goods.iter().map(|label| label.quantity).collect::<Vec<f32>>(), vec![1000., 2000., 3000., 4000.]);
+        assert_eq!(market.borrow().get_sell_price(USD, 100.).unwrap(), 100. * 1000. / (2000. + 100.) * 0.95);
+    }
+
+    #[test]
+    fn test_new_file_invalid() {
+        let path = std::env::temp_dir().join("doge_market_test_new_file_invalid.json");
+        std::fs::write(&path, r#"{ "goods": { "eur": 1000.0, "usd": 2000.0, "yen": 3000.0, "yuan": 4000.0 } }"#).unwrap();
+
+        assert!(matches!(DogeMarket::try_new_file(path.to_str().unwrap()), Err(MarketFileError::Malformed { .. })));
+    }
+
+    #[test]
+    #[should_panic(expected = "Malformed")]
+    fn test_new_file_panics() {
+        let path = std::env::temp_dir().join("doge_market_test_new_file_panics.json");
+        std::fs::write(&path, r#"{ "goods": { "eur": 1000.0, "usd": 2000.0, "yen": 3000.0, "yuan": 4000.0 } }"#).unwrap();
+
+        DogeMarket::new_file(path.to_str().unwrap());
+    }
+
+    #[test]
+    fn test_markets_side_by_side() {
+        let config = MarketConfig {
+            market_name: "DogeMarketWide".to_string(),
+            buy_earn_percentages: PerGood::new(0., 10., 10., 10.),
+            ..MarketConfig::default()
+        };
+
+        let default_market = DogeMarket::new_with_quantities(100_000., 100_000., 100_000., 100_000.);
+        let wide_market = uniform_market(100_000., config);
+
+        assert_eq!(default_market.borrow().get_name(), "DogeMarket");
+        assert_eq!(wide_market.borrow().get_name(), "DogeMarketWide");
+
+        let default_price = default_market.borrow().get_buy_price(USD, 100.).unwrap();
+        let wide_price = wide_market.borrow().get_buy_price(USD, 100.).unwrap();
+        assert_approx_eq!(wide_price, default_price / 1.01 * 1.1, 0.01);
+
+        assert_eq!(default_market.borrow().get_sell_price(USD, 100.).unwrap(), wide_market.borrow().get_sell_price(USD, 100.).unwrap());
+    }
+
+    #[test]
+    fn test_public_path() {
+        let config = MarketConfig { renewal_fee: 1., audit: true, ..MarketConfig::default() };
+        let market = uniform_market(100_000., config);
+        let as_trait: Rc<RefCell<dyn Market>> = market.clone();
+        assert_eq!(as_trait.borrow().get_name(), "DogeMarket");
+
+        let mut doge = market.borrow_mut();
+        let price = doge.get_buy_price(USD, 100.).unwrap();
+        let cancelled = doge.lock_buy(USD, 100., price, "trader".to_string()).unwrap();
+        let price = doge.get_buy_price(USD, 100.).unwrap();
+        let renewed = doge.lock_buy(USD, 100., price, "trader".to_string()).unwrap();
+        doge.cancel_buy_lock(cancelled.clone()).unwrap();
+        assert_eq!(doge.cancel_buy_lock(cancelled.clone()), Err(CancelLockError::AlreadySettledToken { settled_token: cancelled }));
+
+        // renewing is a day passing as well
+        let days_left = doge.get_lock_days_left(renewed.clone()).unwrap();
+        let mut fee = Good::new(USD, 10.);
+        assert_eq!(doge.renew_buy_lock(renewed.clone(), 2, &mut fee), Err(RenewLockError::WrongGoodKind { wrong_good_kind: USD, fee_kind: EUR }));
+        let mut fee = Good::new(EUR, 10.);
+        doge.renew_buy_lock(renewed.clone(), 2, &mut fee).unwrap();
+        assert_eq!(fee.get_qty(), 8.);
+        assert_eq!(doge.get_lock_days_left(renewed.clone()), Some(days_left + 1));
+
+        let mut cash = Good::new(EUR, price / 2.);
+        assert_approx_eq!(doge.buy_partially(renewed.clone(), &mut cash).unwrap().get_qty(), 50., 0.01);
+        assert_eq!(doge.get_trader_history("trader").buy_tokens.len(), 2);
+        assert_eq!(doge.get_reputation_table().get_record("trader").expired, 1);
+        assert_eq!(doge.get_audit_reports(), &[]);
+
+        let path = std::env::temp_dir().join("doge_market_test_public_path.json");
+        doge.save_snapshot(path.to_str().unwrap()).unwrap();
+        let restored = DogeMarket::try_new_snapshot(path.to_str().unwrap()).unwrap();
+        assert_eq!(restored.borrow().get_budget(), doge.get_budget());
+        assert_eq!(restored.borrow().get_trader_history("trader").volume, doge.get_trader_history("trader").volume);
+    }
+
+    #[test]
+    fn test_lock_days_left() {
+        let config = MarketConfig { lock_ttl: 5, lock_ttl_tiers: vec![LockTtlTier { from_quantity: 10_000., lock_ttl: 2 }], ..MarketConfig::default() };
+        let market = uniform_market(100_000., config);
+        let mut market = market.borrow_mut();
+
+        let price = market.get_buy_price(USD, 10.).unwrap();
+        let small = market.lock_buy(USD, 10., price, "trader".to_string()).unwrap();
+        let price = market.get_buy_price(USD, 20_000.).unwrap();
+        let huge = market.lock_buy(USD, 20_000., price, "trader".to_string()).unwrap();
+
+        // every successful lock is a day passing for the market
+        assert_eq!(market.get_lock_days_left(small.clone()), Some(3));
+        assert_eq!(market.get_lock_days_left(huge.clone()), Some(1));
+        assert_eq!(market.get_lock_days_left("not a token".to_string()), None);
+
+        // paid and cancelled locks are no longer waiting to be paid
+        market.cancel_buy_lock(huge.clone()).unwrap();
+        assert_eq!(market.get_lock_days_left(huge), None);
+        let price = market.get_buy_price(USD, 10.).unwrap();
+        market.buy(small.clone(), &mut Good::new(EUR, price * 2.)).unwrap();
+        assert_eq!(market.get_lock_days_left(small), None);
+    }
+
+    #[test]
+    fn test_cross_locks() {
+        let market = uniform_market(100_000., MarketConfig::default());
+        let mut market = market.borrow_mut();
+
+        assert_eq!(market.get_cross_buy_price(USD, 100., USD), Err(CrossPriceError::SameKind));
+        assert_eq!(market.lock_cross_buy(USD, 100., USD, 1_000., "trader".to_string()),
+            Err(LockBuyError::BidTooLow { requested_good_kind: USD, requested_good_quantity: 100., low_bid: 1_000., lowest_acceptable_bid: f32::INFINITY }));
+
+        let price = market.get_cross_buy_price(USD, 100., YEN).unwrap();
+        let token = market.lock_cross_buy(USD, 100., YEN, price, "trader".to_string()).unwrap();
+        assert_eq!(market.buy(token, &mut Good::new(YEN, price)).unwrap().get_qty(), 100.);
+
+        let offer = market.get_cross_sell_price(USD, 100., YEN).unwrap();
+        let token = market.lock_cross_sell(USD, 100., YEN, offer, "trader".to_string()).unwrap();
+        let sold = market.sell(token, &mut Good::new(USD, 100.)).unwrap();
+        assert_eq!((sold.get_kind(), sold.get_qty()), (YEN, offer));
+
+        // the auction clears at a single price in the default good
+        let config = MarketConfig { lock_clearing: LockClearing::BatchAuction, ..MarketConfig::default() };
+        let market = uniform_market(100_000., config);
+        let mut market = market.borrow_mut();
+        let offer = market.get_cross_sell_price(USD, 100., YEN).unwrap();
+        assert_eq!(market.lock_cross_sell(USD, 100., YEN, offer, "trader".to_string()),
+            Err(LockSellError::OfferTooHigh { offered_good_kind: USD, offered_good_quantity: 100., high_offer: offer, highest_acceptable_offer: 0. }));
+    }
+
+    #[test]
+    fn test_lock_deposits() {
+        let config = MarketConfig { lock_ttl: 2, lock_deposit_percentage: 10., audit: true, ..MarketConfig::default() };
+        let market = uniform_market(100_000., config);
+        let mut market = market.borrow_mut();
+
+        let price = market.get_buy_price(USD, 100.).unwrap();
+        assert_eq!(market.lock_buy(USD, 100., price, "trader".to_string()), Err(LockBuyError::BidTooLow { requested_good_kind: USD, requested_good_quantity: 100., low_bid: price, lowest_acceptable_bid: f32::INFINITY }));
+        assert_eq!(market.lock_sell(USD, 100., 1., "trader".to_string()), Err(LockSellError::OfferTooHigh { offered_good_kind: USD, offered_good_quantity: 100., high_offer: 1., highest_acceptable_offer: 0. }));
+        assert_eq!(market.lock_basket(&[(USD, 100., price)], "trader".to_string()), Err(LockBasketError::DepositRequired { required: price * 0.1 }));
+        let mut wallet = Good::new(EUR, price * 0.05);
+        assert_eq!(market.lock_buy_with_deposit(USD, 100., price, "trader".to_string(), &mut wallet), Err(LockWithDepositError::InsufficientDeposit { required: price * 0.1 }));
+
+        // the deposit counts towards the payment
+        let mut wallet = Good::new(EUR, 1_000.);
+        let before = market.get_budget();
+        let token = market.lock_buy_with_deposit(USD, 100., price, "trader".to_string(), &mut wallet).unwrap();
+        assert_approx_eq!(wallet.get_qty(), 1_000. - price * 0.1, 0.001);
+        market.buy(token, &mut wallet).unwrap();
+        assert_approx_eq!(wallet.get_qty(), 1_000. - price, 0.001);
+        assert_approx_eq!(market.get_budget(), before + price, 0.01);
+
+        // and is kept by the market when the lock expires
+        let offer = market.get_sell_price(YEN, 100.).unwrap();
+        let before = market.get_budget();
+        market.lock_sell_with_deposit(YEN, 100., offer, "trader".to_string(), &mut wallet).unwrap();
+        wait(&mut market);
+        wait(&mut market);
+        assert_approx_eq!(market.get_budget(), before + offer * 0.1, 0.01);
+
+        assert_eq!(market.get_audit_reports(), &[]);
+    }
+
+    #[test]
+    fn test_basket() {
+        let market = uniform_market(100_000., MarketConfig::default());
+        let mut market = market.borrow_mut();
+
+        let prices = market.get_basket_price(&[(USD, 100.), (YUAN, 100.)]).unwrap();
+        let (usd_price, yuan_price) = (prices[0], prices[1]);
+        assert_eq!(usd_price, market.get_buy_price(USD, 100.).unwrap());
+        // the first leg raises the price of the second one
+        let yuan_alone = market.get_buy_price(YUAN, 100.).unwrap();
+        assert!(yuan_price > yuan_alone);
+        assert!(matches!(market.lock_basket(&[(USD, 100., usd_price), (YUAN, 100., yuan_alone)], "trader".to_string()),
+            Err(LockBasketError::Leg { index: 1, error: LockBuyError::BidTooLow { .. } })));
+        assert_eq!(market.lock_basket(&[], "trader".to_string()), Err(LockBasketError::EmptyBasket));
+        assert_eq!(market.get_basket_price(&[]), Err(BasketPriceError::EmptyBasket));
+        assert!(matches!(market.get_basket_price(&[(USD, 100.), (YUAN, 200_000.)]),
+            Err(BasketPriceError::Leg { index: 1, error: MarketGetterError::InsufficientGoodQuantityAvailable { .. } })));
+
+        let token = market.lock_basket(&[(USD, 100., usd_price), (YUAN, 100., yuan_price)], "trader".to_string()).unwrap();
+        let mut cash = Good::new(EUR, usd_price + yuan_price);
+        let bought = market.buy_basket(token.clone(), &mut cash).unwrap();
+        assert_eq!(bought.iter().map(|good| good.get_kind()).collect::<Vec<_>>(), vec![USD, YUAN]);
+        assert_approx_eq!(cash.get_qty(), 0., 0.01);
+        assert_eq!(market.buy_basket(token.clone(), &mut cash), Err(BuyError::UnrecognizedToken { unrecognized_token: token.clone() }));
+
+        let history = market.get_trader_history("trader");
+        assert_eq!(history.buy_tokens, vec![token]);
+        assert_eq!(history.volume.bought, PerGood::new(0., 100., 0., 100.));
+    }
+
+    #[test]
+    fn test_batch_auction() {
+        let config = MarketConfig { lock_clearing: LockClearing::BatchAuction, ..MarketConfig::default() };
+        let market = uniform_market(100_000., config);
+        let mut market = market.borrow_mut();
+
+        let price = market.get_buy_price(USD, 200.).unwrap();
+        let lowest_alone = market.get_buy_price(USD, 100.).unwrap();
+        assert!(matches!(market.lock_buy(USD, 100., lowest_alone / 2., "carol".to_string()), Err(LockBuyError::BidTooLow { .. })));
+        let first = market.lock_buy(USD, 100., price, "alice".to_string()).unwrap();
+        let second = market.lock_buy(USD, 100., price, "bob".to_string()).unwrap();
+        let dropped = market.lock_buy(USD, 100., lowest_alone, "carol".to_string()).unwrap();
+        // nothing is priced before the day ends
+        assert_eq!(market.get_buy_price(USD, 200.).unwrap(), price);
+
+        // the first lock to be paid ends the day, and both locks pay the same half of the price of the two
+        let mut cash = Good::new(EUR, price);
+        assert_eq!(market.buy(first, &mut cash).unwrap().get_qty(), 100.);
+        assert_approx_eq!(cash.get_qty(), price / 2., 0.01);
+        market.buy(second, &mut cash).unwrap();
+        assert_approx_eq!(cash.get_qty(), 0., 0.01);
+
+        // carol was not willing to pay a third of the price of the three locks
+        assert_eq!(market.buy(dropped.clone(), &mut Good::new(EUR, price)), Err(BuyError::ExpiredToken { expired_token: dropped }));
+    }
+
+    #[test]
+    fn test_limit_orders() {
+        let market = uniform_market(100_000., MarketConfig::default());
+        let mut market = market.borrow_mut();
+
+        let usd_price = market.get_buy_price(USD, 100.).unwrap();
+        let mut cash = Good::new(EUR, usd_price * 2.);
+        assert_eq!(market.place_buy_order(USD, 100., usd_price * 3., 5, "trader".to_string(), &mut cash),
+            Err(PlaceOrderError::InsufficientEscrow { kind: EUR, required: usd_price * 3. }));
+        assert_eq!(market.place_buy_order(USD, 100., 0., 5, "trader".to_string(), &mut cash), Err(PlaceOrderError::NonPositiveLimit { limit: 0. }));
+
+        // a generous limit matches right away
+        let buy = market.place_buy_order(USD, 100., usd_price * 1.1, 5, "trader".to_string(), &mut cash).unwrap();
+        assert_eq!(market.get_order_state(buy.clone()), Some(LimitOrderState::Filled));
+        let claimed = market.claim_order(buy.clone()).unwrap();
+        assert_eq!((claimed[0].get_kind(), claimed[0].get_qty()), (USD, 100.));
+        assert_eq!(claimed[1].get_kind(), EUR);
+        assert_eq!(market.claim_order(buy), Err(OrderError::InvalidState { current_state: LimitOrderState::Claimed }));
+
+        // a greedy one waits until it is cancelled
+        let usd_sell_price = market.get_sell_price(USD, 100.).unwrap();
+        let mut usd = Good::new(USD, 100.);
+        let sell = market.place_sell_order(USD, 100., usd_sell_price * 2., 5, "trader".to_string(), &mut usd).unwrap();
+        assert_eq!(usd.get_qty(), 0.);
+        assert_eq!(market.get_order_state(sell.clone()), Some(LimitOrderState::Open));
+        assert_eq!(market.cancel_order(sell.clone()).unwrap().get_qty(), 100.);
+        assert_eq!(market.get_order_state(sell), Some(LimitOrderState::Cancelled));
+        assert_eq!(market.get_order_state("token".to_string()), None);
+    }
+
+    #[test]
+    fn test_forwards() {
+        let config = MarketConfig { audit: true, ..MarketConfig::default() };
+        let market = uniform_market(100_000., config);
+        let mut market = market.borrow_mut();
+
+        // the carry makes buying later dearer and selling later cheaper
+        let price = market.get_forward_buy_price(USD, 100., 5).unwrap();
+        assert!(price > market.get_buy_price(USD, 100.).unwrap());
+        assert!(market.get_forward_sell_price(USD, 100., 5).unwrap() < market.get_sell_price(USD, 100.).unwrap());
+        assert_eq!(market.get_forward_buy_price(USD, 100., 31), Err(ForwardPriceError::InvalidDays { days: 31, max_days: 30 }));
+
+        let mut wallet = Good::new(EUR, 1_000.);
+        assert!(matches!(market.agree_forward_buy(USD, 100., price * 0.9, 5, "trader".to_string(), &mut wallet), Err(AgreeForwardError::PriceTooLow { .. })));
+        assert_eq!(market.agree_forward_buy(USD, 100., price, 5, "trader".to_string(), &mut Good::new(EUR, 0.)),
+            Err(AgreeForwardError::InsufficientMargin { required: price * 0.1 }));
+
+        // the margin counts towards the price
+        let buy = market.agree_forward_buy(USD, 100., price, 5, "trader".to_string(), &mut wallet).unwrap();
+        market.deliver_forward(buy.clone(), &mut wallet).unwrap();
+        assert_approx_eq!(wallet.get_qty(), 1_000. - price, 0.001);
+        assert_eq!(market.deliver_forward(buy.clone(), &mut wallet), Err(ForwardError::AlreadyDelivered));
+        assert_eq!(market.get_forward_state(buy.clone()), Some(ForwardTxState::Open));
+
+        for _ in 0..3 {
+            wait(&mut market);
+        }
+        assert_eq!(market.get_forward_state(buy.clone()), Some(ForwardTxState::Settled));
+        assert_eq!(market.claim_forward(buy.clone()).unwrap().get_qty(), 100.);
+        assert_eq!(market.claim_forward(buy), Err(ForwardError::InvalidState { current_state: ForwardTxState::Claimed }));
+
+        // the market keeps the margin of a forward that is not delivered
+        let offer = market.get_forward_sell_price(YEN, 100., 2).unwrap();
+        let before = market.get_budget();
+        let sell = market.agree_forward_sell(YEN, 100., offer, 2, "trader".to_string(), &mut wallet).unwrap();
+        wait(&mut market);
+        assert_eq!(market.get_forward_state(sell.clone()), Some(ForwardTxState::Defaulted));
+        assert_approx_eq!(market.get_budget(), before + offer * 0.1, 0.01);
+        assert_eq!(market.claim_forward(sell.clone()), Err(ForwardError::InvalidState { current_state: ForwardTxState::Defaulted }));
+
+        // the settled forward is traded volume, the defaulted one an expired lock that cost its margin
+        let history = market.get_trader_history("trader");
+        assert_eq!(history.forward_tokens.len(), 2);
+        assert_eq!(history.expired_tokens, vec![sell]);
+        assert_eq!(history.volume.bought, PerGood::new(0., 100., 0., 0.));
+        assert_approx_eq!(*history.volume.sold.get(EUR), price + offer * 0.1, 0.01);
+
+        assert_eq!(market.get_audit_reports(), &[]);
+    }
+
+    #[test]
+    fn test_lending() {
+        let market = uniform_market(100_000., MarketConfig::default());
+        let mut market = market.borrow_mut();
+
+        let required = market.get_loan_collateral(USD, 100., EUR);
+        assert_eq!(market.borrow(USD, 100., 5, "trader".to_string(), &mut Good::new(USD, 1_000.)), Err(BorrowError::SameKindCollateral));
+        assert_eq!(market.borrow(USD, 100., 5, "trader".to_string(), &mut Good::new(EUR, required / 2.)), Err(BorrowError::InsufficientCollateral { required }));
+
+        // the debt grows by the interest of every day
+        let mut wallet = Good::new(EUR, 1_000.);
+        let before = reservable(&market, USD);
+        let (loan, mut borrowed) = market.borrow(USD, 100., 5, "trader".to_string(), &mut wallet).unwrap();
+        assert_eq!(borrowed.get_qty(), 100.);
+        assert_approx_eq!(wallet.get_qty(), 1_000. - required, 0.001);
+        assert_approx_eq!(market.get_loan_debt(loan.clone()).unwrap(), 100.05, 0.001);
+        assert_eq!(market.repay_loan(loan.clone(), &mut borrowed), Err(LoanError::InsufficientRepayment { kind: USD, debt: market.get_loan_debt(loan.clone()).unwrap() }));
+
+        borrowed.merge(Good::new(USD, 1.)).unwrap();
+        assert_approx_eq!(market.repay_loan(loan.clone(), &mut borrowed).unwrap().get_qty(), required, 0.001);
+        assert_eq!(market.get_loan_state(loan.clone()), Some(LoanState::Repaid));
+        assert_approx_eq!(reservable(&market, USD), before + 0.05, 0.01);
+
+        // the market keeps the collateral of a loan that is not repaid in time
+        let wallet_before = wallet.get_qty();
+        let (loan, _) = market.borrow(YEN, 100., 2, "trader".to_string(), &mut wallet).unwrap();
+        wait(&mut market);
+        assert_eq!(market.get_loan_state(loan.clone()), Some(LoanState::Defaulted));
+        assert_eq!(market.repay_loan(loan, &mut Good::new(YEN, 1_000.)), Err(LoanError::InvalidState { current_state: LoanState::Defaulted }));
+        assert!(wallet.get_qty() < wallet_before);
+    }
+
+    #[test]
+    fn test_liquidation() {
+        let market = uniform_market(100_000., MarketConfig::default());
+        let mut market = market.borrow_mut();
+
+        let mut collateral = Good::new(YUAN, 100_000.);
+        let (loan, _) = market.borrow(USD, 100., 30, "trader".to_string(), &mut collateral).unwrap();
+        assert_eq!(market.get_loan_state(loan.clone()), Some(LoanState::Open));
+
+        // selling a lot of yuan to the market makes the collateral worth less than the debt needs
+        let offer = market.get_sell_price(YUAN, 50_000.).unwrap();
+        let token = market.lock_sell(YUAN, 50_000., offer, "whale".to_string()).unwrap();
+        market.sell(token, &mut Good::new(YUAN, 50_000.)).unwrap();
+        assert_eq!(market.get_loan_state(loan), Some(LoanState::Liquidated));
+    }
+
+    #[test]
+    fn test_competitor_events() {
+        let market = uniform_market(100_000., MarketConfig::default());
+        let mut market = market.borrow_mut();
+
+        let buy_price = market.get_buy_price(USD, 100.).unwrap();
+        let sell_price = market.get_sell_price(USD, 100.).unwrap();
+
+        // another market sells usd far dearer, so buying it here gets pricier while selling it here does not change
+        market.on_event(Event { kind: Bought, good_kind: USD, quantity: 100., price: buy_price * 2. });
+        assert_approx_eq!(market.get_buy_price(USD, 100.).unwrap(), buy_price / 1.01 * 1.06, buy_price * 0.001);
+        assert_approx_eq!(market.get_sell_price(USD, 100.).unwrap(), sell_price, 0.01);
+    }
+
+    /// Passes the events on to a market, borrowing it like any subscriber would.
+    struct Forward(Rc<RefCell<DogeMarket>>);
+
+    impl Notifiable for Forward {
+        fn add_subscriber(&mut self, _subscriber: Box<dyn Notifiable>) {}
+
+        fn on_event(&mut self, event: Event) {
+            self.0.borrow_mut().on_event(event);
+        }
+    }
+
+    #[test]
+    fn test_refill_suppliers() {
+        let refill_policy = RefillPolicyKind::TargetWeights { weights: PerGood::new(1., 1., 1., 1.), tolerance_percentage: 5. };
+        let market = uniform_market(10_000., MarketConfig { refill_policy, ..MarketConfig::default() });
+        let supplier = stub_market(0.1);
+        // the supplier notifies the market of the trades it restocks with
+        supplier.borrow_mut().add_subscriber(Box::new(Forward(market.clone())));
+        market.borrow_mut().set_refill_suppliers(vec![supplier.clone()]);
+        let yen = |market: &Rc<RefCell<DogeMarket>>| market.borrow().doge_impl.get_tx_service().get_account_ops().assets.get_quantity_by_kind(YEN);
+
+        // the refill is only planned at the end of the day
+        market.borrow_mut().on_event(Event { kind: EventKind::Wait, good_kind: EUR, quantity: 0., price: 0. });
+        assert_eq!(yen(&market), 10_000.);
+
+        DogeMarket::restock(&market);
+        assert!(yen(&market) > 10_000.);
+        assert!(supplier.borrow().eur > 0.);
+    }
 }
@@ -1,6 +1,7 @@
 #[cfg(test)]
 mod e2e_tests {
-    use std::cell::RefMut;
+    use std::cell::{RefCell, RefMut};
+    use std::rc::Rc;
 
     use unitn_market_2022::good::good::Good;
     use unitn_market_2022::good::good_kind::GoodKind;
@@ -9,6 +10,8 @@ mod e2e_tests {
     use unitn_market_2022::market::market_test::*;
 
     use crate::dogemarket::DogeMarket;
+    use crate::market_config::{LockClearing, MarketConfig};
+    use crate::test_fixtures::uniform_market;
 
     #[test]
     pub fn test_name_run() {
@@ -137,7 +140,16 @@ mod e2e_tests {
 
     #[test]
     pub fn test_lock_buy_insufficient_good_quantity_available() {
-        let market = DogeMarket::new_with_quantities(100000.0, 100000.0, 100000.0, 100000.0);
+        lock_buy_insufficient_good_quantity_available(continuous_market);
+    }
+
+    #[test]
+    pub fn test_lock_buy_insufficient_good_quantity_available_batch_auction() {
+        lock_buy_insufficient_good_quantity_available(batch_auction_market);
+    }
+
+    fn lock_buy_insufficient_good_quantity_available(new_market: fn(f32) -> Rc<RefCell<dyn Market>>) {
+        let market = new_market(100000.0);
         // let quantity = 1000.0;
         // let lowest_bid = f32::MIN_POSITIVE; // it's temporary because Lowest Bid must be the last error to verify
         let trader_name = String::from("TEST");
@@ -164,7 +176,16 @@ mod e2e_tests {
 
     #[test]
     pub fn test_lock_buy_bid_too_low() {
-        let market = DogeMarket::new_with_quantities(100000.0, 100000.0, 100000.0, 100000.0);
+        lock_buy_bid_too_low(continuous_market);
+    }
+
+    #[test]
+    pub fn test_lock_buy_bid_too_low_batch_auction() {
+        lock_buy_bid_too_low(batch_auction_market);
+    }
+
+    fn lock_buy_bid_too_low(new_market: fn(f32) -> Rc<RefCell<dyn Market>>) {
+        let market = new_market(100000.0);
         let quantity = 1000.0;
         let lowest_bid = f32::MIN_POSITIVE; // it's temporary because Lowest Bid must be the last error to verify
         let trader_name = String::from("TEST");
@@ -195,7 +216,16 @@ mod e2e_tests {
 
     #[test]
     pub fn test_buy_unrecognized_token() {
-        let market = DogeMarket::new_with_quantities(100000.0, 100000.0, 100000.0, 100000.0);
+        buy_unrecognized_token(continuous_market);
+    }
+
+    #[test]
+    pub fn test_buy_unrecognized_token_batch_auction() {
+        buy_unrecognized_token(batch_auction_market);
+    }
+
+    fn buy_unrecognized_token(new_market: fn(f32) -> Rc<RefCell<dyn Market>>) {
+        let market = new_market(100000.0);
         let labels = market.borrow().get_goods();
 
         for good_label in labels {
@@ -218,7 +248,16 @@ mod e2e_tests {
 
     #[test]
     pub fn test_buy_good_kind_not_default() {
-        let market = DogeMarket::new_with_quantities(100000.0, 100000.0, 100000.0, 100000.0);
+        buy_good_kind_not_default(continuous_market);
+    }
+
+    #[test]
+    pub fn test_buy_good_kind_not_default_batch_auction() {
+        buy_good_kind_not_default(batch_auction_market);
+    }
+
+    fn buy_good_kind_not_default(new_market: fn(f32) -> Rc<RefCell<dyn Market>>) {
+        let market = new_market(100000.0);
         let labels = market.borrow().get_goods();
 
         for good_label in labels {
@@ -246,7 +285,16 @@ mod e2e_tests {
 
     #[test]
     pub fn test_buy_insufficient_good_quantity() {
-        let market = DogeMarket::new_with_quantities(100000.0, 100000.0, 100000.0, 100000.0);
+        buy_insufficient_good_quantity(continuous_market);
+    }
+
+    #[test]
+    pub fn test_buy_insufficient_good_quantity_batch_auction() {
+        buy_insufficient_good_quantity(batch_auction_market);
+    }
+
+    fn buy_insufficient_good_quantity(new_market: fn(f32) -> Rc<RefCell<dyn Market>>) {
+        let market = new_market(100000.0);
         let labels = market.borrow().get_goods();
 
         for good_label in labels {
@@ -271,7 +319,16 @@ mod e2e_tests {
 
     #[test]
     pub fn test_buy_success() {
-        let market = DogeMarket::new_with_quantities(100000.0, 100000.0, 100000.0, 100000.0);
+        buy_success(continuous_market);
+    }
+
+    #[test]
+    pub fn test_buy_success_batch_auction() {
+        buy_success(batch_auction_market);
+    }
+
+    fn buy_success(new_market: fn(f32) -> Rc<RefCell<dyn Market>>) {
+        let market = new_market(100000.0);
         let labels = market.borrow().get_goods();
 
         for good_label in labels {
@@ -356,6 +413,16 @@ mod e2e_tests {
         }
     }
 
+    fn continuous_market(quantity: f32) -> Rc<RefCell<dyn Market>> {
+        DogeMarket::new_with_quantities(quantity, quantity, quantity, quantity)
+    }
+
+    // locks are only priced once they are paid, when the day closes
+    fn batch_auction_market(quantity: f32) -> Rc<RefCell<dyn Market>> {
+        let config = MarketConfig { lock_clearing: LockClearing::BatchAuction, ..MarketConfig::default() };
+        uniform_market(quantity, config)
+    }
+
     //function used in several tests
     //default version uses f32::MAX as bid but this is not okay for our market because our prices depend also on bids
     fn get_lock_token(
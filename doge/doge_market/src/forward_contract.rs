@@ -0,0 +1,272 @@
+use serde::{Deserialize, Serialize};
+use unitn_market_2022::good::consts::DEFAULT_GOOD_KIND;
+use unitn_market_2022::good::good::Good;
+use unitn_market_2022::good::good_kind::GoodKind;
+
+use crate::account_ops::AccountOps;
+use crate::order_book::OrderSide;
+use crate::quantity::Quantity;
+use crate::serde_defs::GoodDef;
+
+/// Agreement to exchange `good` for `price` of the default good when the contract matures: the trader buys the good on
+/// the `Buy` side and sells it on the `Sell` side. Until then the market sets aside what it gives away, and the trader
+/// backs the contract with `margin` of the default good, which the market keeps if the trader does not deliver.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ForwardTx {
+    pub side: OrderSide,
+    #[serde(with = "GoodDef")]
+    pub good: Good,
+    #[serde(with = "GoodDef")]
+    pub price: Good,
+    pub margin: f32,
+    pub trader_name: String,
+    pub state: ForwardTxState,
+    /// What the trader delivered ahead of maturity, see [ForwardTx::get_due].
+    #[serde(with = "GoodDef")]
+    pub delivered: Good,
+    /// What the trader gets once the contract settled.
+    #[serde(with = "GoodDef")]
+    pub proceeds: Good,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ForwardTxState {
+    /// Waiting for maturity.
+    Open,
+    /// Exchanged at maturity, with the proceeds waiting to be claimed.
+    Settled,
+    /// Matured before the trader delivered, so the market kept the margin.
+    Defaulted,
+    Claimed,
+}
+
+/// Tuning of the forward contracts of the market.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ForwardSettings {
+    /// Longest maturity, in days, of a contract.
+    pub max_days: u32,
+    /// Percentage of the spot price added to the price of buys, and taken off the price of sells, for every day to
+    /// maturity: the cost of holding the goods that long.
+    pub carry_percentage: f32,
+    /// Percentage of the price the trader backs the contract with.
+    pub margin_percentage: f32,
+}
+
+impl Default for ForwardSettings {
+    fn default() -> Self {
+        ForwardSettings {
+            max_days: 30,
+            carry_percentage: 0.1,
+            margin_percentage: 10.,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ForwardTxProposal {
+    pub side: OrderSide,
+    pub good: Good,
+    pub price: Good,
+    pub trader_name: String,
+}
+
+#[derive(Debug, Clone)]
+pub enum ForwardTxAgreementError {
+    NonPositiveQuantity,
+    NonPositivePrice,
+    ExceedsReservableQuantity { reservable: f32 },
+}
+
+#[derive(Debug, Clone)]
+pub enum ForwardTxDeliveryError {
+    InvalidState { current_state: ForwardTxState },
+    AlreadyDelivered,
+    WrongGoodKind { pre_agreed: GoodKind },
+    InsufficientGoodQuantity { pre_agreed: f32 },
+}
+
+#[derive(Debug, Clone)]
+pub enum ForwardTxClaimError {
+    InvalidState { current_state: ForwardTxState },
+}
+
+impl ForwardTx {
+    /// Sets aside the good of a buy, or the price of a sell, holding `margin` of the default good on behalf of the
+    /// trader until the contract matures. Pricing the contract is up to the caller.
+    pub fn agree(ops: &mut AccountOps, proposal: &ForwardTxProposal, margin: f32) -> Result<ForwardTx, ForwardTxAgreementError> {
+        let (outgoing, incoming) = match proposal.side {
+            OrderSide::Buy => (&proposal.good, &proposal.price),
+            OrderSide::Sell => (&proposal.price, &proposal.good),
+        };
+
+        if proposal.good.get_qty() <= 0. {
+            Err(ForwardTxAgreementError::NonPositiveQuantity)
+        } else if proposal.price.get_qty() <= 0. {
+            Err(ForwardTxAgreementError::NonPositivePrice)
+        } else if Quantity::from_f32(outgoing.get_qty()) > ops.assets.get(outgoing.get_kind()) {
+            Err(ForwardTxAgreementError::ExceedsReservableQuantity { reservable: ops.get_reservable_quantity_by_kind(outgoing.get_kind()) })
+        } else {
+            let reservation = Quantity::from_f32(outgoing.get_qty());
+            ops.assets.withdraw(outgoing.get_kind(), reservation).unwrap();
+            ops.reservations.deposit(outgoing.get_kind(), reservation);
+            ops.futures.deposit(incoming.get_kind(), Quantity::from_f32(incoming.get_qty()));
+            ops.deposits.deposit(DEFAULT_GOOD_KIND, Quantity::from_f32(margin));
+
+            let due_kind = incoming.get_kind();
+            Ok(ForwardTx {
+                side: proposal.side,
+                good: proposal.good.clone(),
+                price: proposal.price.clone(),
+                margin,
+                trader_name: proposal.trader_name.clone(),
+                state: ForwardTxState::Open,
+                delivered: Good::new(due_kind, 0.),
+                proceeds: Good::new(outgoing.get_kind(), 0.),
+            })
+        }
+    }
+
+    /// Good set aside by the market.
+    pub fn get_outgoing(&self) -> &Good {
+        match self.side {
+            OrderSide::Buy => &self.good,
+            OrderSide::Sell => &self.price,
+        }
+    }
+
+    /// Good the market gets at maturity.
+    pub fn get_incoming(&self) -> &Good {
+        match self.side {
+            OrderSide::Buy => &self.price,
+            OrderSide::Sell => &self.good,
+        }
+    }
+
+    /// What the trader has to deliver before maturity: the price minus the margin of a buy, the good of a sell.
+    pub fn get_due(&self) -> Good {
+        match self.side {
+            OrderSide::Buy => Good::new(self.price.get_kind(), (self.price.get_qty() - self.margin).max(0.)),
+            OrderSide::Sell => self.good.clone(),
+        }
+    }
+
+    fn is_delivered(&self) -> bool {
+        self.delivered.get_qty() >= self.get_due().get_qty()
+    }
+
+    /// Takes what is due out of `with`, holding it until the contract matures.
+    pub fn deliver(&mut self, with: &mut Good) -> Result<(), ForwardTxDeliveryError> {
+        let due = self.get_due();
+        match &self.state {
+            ForwardTxState::Open => {
+                if self.delivered.get_qty() > 0. {
+                    Err(ForwardTxDeliveryError::AlreadyDelivered)
+                } else if with.get_kind() != due.get_kind() {
+                    Err(ForwardTxDeliveryError::WrongGoodKind { pre_agreed: due.get_kind() })
+                } else if with.get_qty() < due.get_qty() {
+                    Err(ForwardTxDeliveryError::InsufficientGoodQuantity { pre_agreed: due.get_qty() })
+                } else {
+                    if due.get_qty() > 0. {
+                        self.delivered = with.split(due.get_qty()).unwrap();
+                    }
+                    Ok(())
+                }
+            }
+            other => Err(ForwardTxDeliveryError::InvalidState { current_state: other.clone() })
+        }
+    }
+
+    /// Exchanges the goods if the trader delivered, otherwise gives what was set aside back to the market, which keeps
+    /// the margin. Returns whether the contract settled.
+    pub fn mature(&mut self, ops: &mut AccountOps) -> bool {
+        if self.state != ForwardTxState::Open {
+            return false;
+        }
+
+        let outgoing = self.get_outgoing().clone();
+        let incoming = self.get_incoming().clone();
+        let margin = Quantity::from_f32(self.margin);
+        ops.reservations.withdraw(outgoing.get_kind(), Quantity::from_f32(outgoing.get_qty())).unwrap();
+        ops.futures.withdraw(incoming.get_kind(), Quantity::from_f32(incoming.get_qty())).unwrap();
+        ops.deposits.withdraw(DEFAULT_GOOD_KIND, margin).unwrap();
+
+        if self.is_delivered() {
+            let delivered = std::mem::replace(&mut self.delivered, Good::new(incoming.get_kind(), 0.));
+            ops.assets.deposit(delivered.get_kind(), Quantity::from_f32(delivered.get_qty()));
+            match self.side {
+                // the margin counts towards the price
+                OrderSide::Buy => {
+                    ops.assets.deposit(DEFAULT_GOOD_KIND, margin);
+                    self.proceeds = outgoing;
+                }
+                OrderSide::Sell => self.proceeds = Good::new(outgoing.get_kind(), outgoing.get_qty() + self.margin),
+            }
+            self.state = ForwardTxState::Settled;
+            true
+        } else {
+            ops.assets.deposit(outgoing.get_kind(), Quantity::from_f32(outgoing.get_qty()));
+            ops.assets.deposit(DEFAULT_GOOD_KIND, margin);
+            self.state = ForwardTxState::Defaulted;
+            false
+        }
+    }
+
+    /// Gives the proceeds of a settled contract to the trader.
+    pub fn claim(&mut self) -> Result<Good, ForwardTxClaimError> {
+        match &self.state {
+            ForwardTxState::Settled => {
+                let kind = self.proceeds.get_kind();
+                self.state = ForwardTxState::Claimed;
+                Ok(std::mem::replace(&mut self.proceeds, Good::new(kind, 0.)))
+            }
+            other => Err(ForwardTxClaimError::InvalidState { current_state: other.clone() })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use unitn_market_2022::good::good_kind::GoodKind::{EUR, USD, YEN, YUAN};
+
+    use doge_common::account::Account;
+
+    use super::*;
+
+    fn ops() -> AccountOps {
+        AccountOps::of_assets(Account {
+            eur: Good::new(EUR, 1_000.),
+            usd: Good::new(USD, 1_000.),
+            yen: Good::new(YEN, 1_000.),
+            yuan: Good::new(YUAN, 1_000.),
+        })
+    }
+
+    #[test]
+    fn settle_and_default() {
+        let mut ops = ops();
+        let proposal = ForwardTxProposal { side: OrderSide::Buy, good: Good::new(USD, 100.), price: Good::new(EUR, 90.), trader_name: "trader".to_string() };
+        let mut buy = ForwardTx::agree(&mut ops, &proposal, 9.).unwrap();
+        assert_eq!(ops.reservations.get_quantity_by_kind(USD), 100.);
+        assert_eq!(ops.futures.get_quantity_by_kind(EUR), 90.);
+
+        let mut cash = Good::new(EUR, 100.);
+        buy.deliver(&mut cash).unwrap();
+        assert_eq!(cash.get_qty(), 19.);
+        assert!(matches!(buy.deliver(&mut cash), Err(ForwardTxDeliveryError::AlreadyDelivered)));
+        assert!(buy.mature(&mut ops));
+        assert_eq!(ops.assets.get_quantity_by_kind(EUR), 1_090.);
+        assert_eq!(ops.assets.get_quantity_by_kind(USD), 900.);
+        assert_eq!(buy.claim().unwrap().get_qty(), 100.);
+
+        let proposal = ForwardTxProposal { side: OrderSide::Sell, good: Good::new(YEN, 100.), price: Good::new(EUR, 50.), trader_name: "trader".to_string() };
+        let mut sell = ForwardTx::agree(&mut ops, &proposal, 5.).unwrap();
+        assert!(!sell.mature(&mut ops));
+        assert_eq!(sell.state, ForwardTxState::Defaulted);
+        // the market kept the margin
+        assert_eq!(ops.assets.get_quantity_by_kind(EUR), 1_095.);
+        assert_eq!(ops.reservations.get_quantity_by_kind(EUR), 0.);
+        assert_eq!(ops.futures.get_quantity_by_kind(YEN), 0.);
+        assert!(matches!(sell.claim(), Err(ForwardTxClaimError::InvalidState { current_state: ForwardTxState::Defaulted })));
+    }
+}
@@ -0,0 +1,95 @@
+use std::fmt::{Display, Formatter};
+
+use serde::{Deserialize, Serialize};
+use unitn_market_2022::good::good::Good;
+use unitn_market_2022::good::good_kind::GoodKind;
+use unitn_market_2022::good::good_kind::GoodKind::{EUR, USD, YEN, YUAN};
+
+use doge_common::account::Account;
+
+use crate::per_good::PerGood;
+use crate::quantity::Quantity;
+
+/// Exact counterpart of an [Account], holding a [Quantity] of every good.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Ledger {
+    quantities: PerGood<Quantity>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LedgerWithdrawError {
+    pub withdrawable: Quantity,
+}
+
+impl Ledger {
+    pub fn new_empty() -> Ledger {
+        Ledger { quantities: PerGood::new(Quantity::ZERO, Quantity::ZERO, Quantity::ZERO, Quantity::ZERO) }
+    }
+
+    pub fn of_account(account: &Account) -> Ledger {
+        let mut ledger = Ledger::new_empty();
+        for kind in [EUR, USD, YEN, YUAN] {
+            ledger.deposit(kind, Quantity::from_f32(account.get_quantity_by_kind(kind)));
+        }
+        ledger
+    }
+
+    pub fn to_account(&self) -> Account {
+        Account {
+            eur: Good::new(EUR, self.get_quantity_by_kind(EUR)),
+            usd: Good::new(USD, self.get_quantity_by_kind(USD)),
+            yen: Good::new(YEN, self.get_quantity_by_kind(YEN)),
+            yuan: Good::new(YUAN, self.get_quantity_by_kind(YUAN)),
+        }
+    }
+
+    pub fn deposit(&mut self, kind: GoodKind, quantity: Quantity) {
+        *self.quantities.get_mut(kind) += quantity;
+    }
+
+    pub fn withdraw(&mut self, kind: GoodKind, quantity: Quantity) -> Result<(), LedgerWithdrawError> {
+        let available = self.quantities.get_mut(kind);
+        if quantity > *available {
+            return Err(LedgerWithdrawError { withdrawable: *available });
+        }
+
+        *available -= quantity;
+        Ok(())
+    }
+
+    pub fn get(&self, kind: GoodKind) -> Quantity {
+        *self.quantities.get(kind)
+    }
+
+    pub fn get_quantity_by_kind(&self, kind: GoodKind) -> f32 {
+        self.get(kind).to_f32()
+    }
+}
+
+impl Display for Ledger {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "EUR: {}, USD: {}, YEN: {}, YUAN: {}",
+            self.get(EUR),
+            self.get(USD),
+            self.get(YEN),
+            self.get(YUAN)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn withdraw_excessive_quantity() {
+        let mut ledger = Ledger::new_empty();
+        ledger.deposit(USD, Quantity::from_f32(10.));
+
+        assert_eq!(ledger.withdraw(USD, Quantity::from_f32(10.5)), Err(LedgerWithdrawError { withdrawable: Quantity::from_f32(10.) }));
+        assert_eq!(ledger.withdraw(USD, Quantity::from_f32(10.)), Ok(()));
+        assert_eq!(ledger.get(USD), Quantity::ZERO);
+    }
+}
@@ -0,0 +1,185 @@
+use std::collections::HashMap;
+
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use unitn_market_2022::good::good::Good;
+use uuid::Uuid;
+
+use crate::serde_defs::GoodDef;
+use crate::service::new_uuid;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LoanState {
+    /// Waiting to be repaid.
+    Open,
+    /// Repaid, with the collateral given back.
+    Repaid,
+    /// The collateral was no longer worth enough, so the market kept it.
+    Liquidated,
+    /// Not repaid in time, so the market kept the collateral.
+    Defaulted,
+}
+
+/// Goods lent by the market to a trader, to be repaid together with the daily interest within `days` days. The trader
+/// backs the loan with `collateral` of another good.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Loan {
+    #[serde(with = "GoodDef")]
+    pub principal: Good,
+    #[serde(with = "GoodDef")]
+    pub collateral: Good,
+    pub daily_interest_percentage: f32,
+    pub days: u32,
+    pub days_passed: u32,
+    pub trader_name: String,
+    pub state: LoanState,
+}
+
+/// Tuning of the loans of the market.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LendingSettings {
+    /// Longest term, in days, of a loan.
+    pub max_days: u32,
+    /// Percentage of the principal added to the debt for every day the loan is open.
+    pub daily_interest_percentage: f32,
+    /// Value of the collateral, as a percentage of the value of the principal, that the trader posts when borrowing.
+    pub collateral_percentage: f32,
+    /// Loans whose collateral is worth less than this percentage of the debt are liquidated.
+    pub liquidation_percentage: f32,
+}
+
+impl Default for LendingSettings {
+    fn default() -> Self {
+        LendingSettings {
+            max_days: 30,
+            daily_interest_percentage: 0.05,
+            collateral_percentage: 150.,
+            liquidation_percentage: 120.,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum LendingDeskError {
+    UnrecognizedUuid,
+    InvalidState { current_state: LoanState },
+}
+
+impl Loan {
+    /// Principal plus the interest of the days passed, in the good lent.
+    pub fn get_debt(&self) -> f32 {
+        self.principal.get_qty() * (1. + self.daily_interest_percentage / 100. * self.days_passed as f32)
+    }
+
+    fn take_collateral(&mut self) -> Good {
+        let kind = self.collateral.get_kind();
+        std::mem::replace(&mut self.collateral, Good::new(kind, 0.))
+    }
+}
+
+/// Loans of the market, each open until it is repaid, liquidated or reaches its term.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LendingDesk {
+    loans: HashMap<Uuid, Loan>,
+}
+
+impl LendingDesk {
+    pub fn new() -> LendingDesk {
+        LendingDesk { loans: HashMap::new() }
+    }
+
+    /// Keeps `loan` open under a token drawn from `rng`.
+    pub fn lend(&mut self, loan: Loan, rng: &mut dyn RngCore) -> Uuid {
+        let uuid = new_uuid(rng);
+        self.loans.insert(uuid, loan);
+        uuid
+    }
+
+    pub fn get(&self, uuid: &Uuid) -> Option<&Loan> {
+        self.loans.get(uuid)
+    }
+
+    pub fn get_open_loans(&self) -> Vec<Uuid> {
+        self.loans.iter()
+            .filter(|(_, loan)| loan.state == LoanState::Open)
+            .map(|(uuid, _)| *uuid)
+            .collect()
+    }
+
+    /// Closes an open loan the caller took the debt of, giving back its collateral.
+    pub fn repay(&mut self, uuid: &Uuid) -> Result<Good, LendingDeskError> {
+        self.close(uuid, LoanState::Repaid)
+    }
+
+    /// Closes an open loan whose collateral the market keeps, handing the collateral over.
+    pub fn liquidate(&mut self, uuid: &Uuid) -> Result<Good, LendingDeskError> {
+        self.close(uuid, LoanState::Liquidated)
+    }
+
+    fn close(&mut self, uuid: &Uuid, state: LoanState) -> Result<Good, LendingDeskError> {
+        let loan = self.loans.get_mut(uuid).ok_or(LendingDeskError::UnrecognizedUuid)?;
+        match loan.state {
+            LoanState::Open => {
+                loan.state = state;
+                Ok(loan.take_collateral())
+            }
+            ref other => Err(LendingDeskError::InvalidState { current_state: other.clone() })
+        }
+    }
+
+    /// Accrues a day of interest on the open loans, then defaults the ones that reached their term, handing over their
+    /// collateral.
+    pub fn tick(&mut self) -> Vec<Good> {
+        self.loans.values_mut()
+            .filter(|loan| loan.state == LoanState::Open)
+            .filter_map(|loan| {
+                loan.days_passed += 1;
+                (loan.days_passed >= loan.days).then(|| {
+                    loan.state = LoanState::Defaulted;
+                    loan.take_collateral()
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+    use unitn_market_2022::good::good_kind::GoodKind::{EUR, USD};
+
+    use super::*;
+
+    fn loan(days: u32) -> Loan {
+        Loan {
+            principal: Good::new(USD, 100.),
+            collateral: Good::new(EUR, 150.),
+            daily_interest_percentage: 1.,
+            days,
+            days_passed: 0,
+            trader_name: "trader".to_string(),
+            state: LoanState::Open,
+        }
+    }
+
+    #[test]
+    fn lifecycle() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let mut desk = LendingDesk::new();
+        let repaid = desk.lend(loan(3), &mut rng);
+        let defaulted = desk.lend(loan(2), &mut rng);
+
+        assert!(desk.tick().is_empty());
+        assert_eq!(desk.get(&repaid).unwrap().get_debt(), 101.);
+        let collateral = desk.tick();
+        assert_eq!(collateral.iter().map(|good| good.get_qty()).collect::<Vec<_>>(), vec![150.]);
+        assert_eq!(desk.get(&defaulted).unwrap().state, LoanState::Defaulted);
+        assert_eq!(desk.get_open_loans(), vec![repaid]);
+
+        assert_eq!(desk.repay(&repaid).unwrap().get_qty(), 150.);
+        assert!(matches!(desk.liquidate(&repaid), Err(LendingDeskError::InvalidState { current_state: LoanState::Repaid })));
+        assert!(matches!(desk.repay(&Uuid::new_v4()), Err(LendingDeskError::UnrecognizedUuid)));
+    }
+}
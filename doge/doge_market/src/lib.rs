@@ -1,5 +1,9 @@
 pub mod dogemarket;
 mod buy_transaction;
+mod basket_transaction;
+mod forward_contract;
+mod batch_auction;
+mod competitor_rates;
 mod service;
 mod mute_logger;
 mod stdout_logger;
@@ -7,7 +11,24 @@ mod txt_file_logger;
 mod logger;
 mod tick_deque;
 mod refiller;
+mod refill_backend;
+mod refill_policy;
+pub mod reputation;
 mod e2e;
+#[cfg(test)]
+mod test_fixtures;
 mod market;
 mod account_ops;
-mod sell_transaction;
\ No newline at end of file
+mod sell_transaction;
+pub mod auditor;
+mod ledger;
+pub mod lending_desk;
+pub mod market_config;
+mod market_file;
+pub mod order_book;
+pub mod per_good;
+pub mod pricing_curve;
+mod quantity;
+mod serde_defs;
+mod snapshot;
+pub mod trader_history;
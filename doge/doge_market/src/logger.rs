@@ -1,10 +1,20 @@
 use unitn_market_2022::good::good::Good;
 use uuid::Uuid;
 
+use crate::refill_backend::RefillTrade;
+
 pub trait Logger {
     fn log_initialization(&mut self, eur: &Good, usd: &Good, jpy: &Good, cny: &Good);
     fn log_lock_buy(&mut self, locked: &Good, trader_name: &str, bid: &Good, token: Option<&Uuid>);
     fn log_lock_sell(&mut self, locked: &Good, trader_name: &str, offer: &Good, token: Option<&Uuid>);
     fn log_buy(&mut self, token: &Uuid, success: bool);
     fn log_sell(&mut self, token: &Uuid, success: bool);
+    fn log_cancel_buy(&mut self, token: &Uuid, success: bool);
+    fn log_cancel_sell(&mut self, token: &Uuid, success: bool);
+    fn log_renew_buy(&mut self, token: &Uuid, days: u32, success: bool);
+    fn log_renew_sell(&mut self, token: &Uuid, days: u32, success: bool);
+    /// Seed of the random choices of the market, logged right after the initialization so that the run can be replayed.
+    fn log_seed(&mut self, seed: u64);
+    /// Trade with another market to restock, including the ones whose lock was refused or left unpaid.
+    fn log_refill_trade(&mut self, trade: &RefillTrade);
 }
@@ -1,21 +1,68 @@
 use std::convert::identity;
+use std::sync::Mutex;
 
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use unitn_market_2022::event::event::Event;
 use unitn_market_2022::good::consts::DEFAULT_GOOD_KIND;
 use unitn_market_2022::good::good::Good;
 use unitn_market_2022::good::good_kind::GoodKind;
+use unitn_market_2022::good::good_kind::GoodKind::{EUR, USD, YEN, YUAN};
 use unitn_market_2022::market::good_label::GoodLabel;
 use uuid::Uuid;
 
 use crate::account_ops::{AccountOps, BuyExchangeRateComputationError, BuyPriceComputationError, SellExchangeRateComputationError, SellPriceComputationError};
-use crate::buy_transaction::{BuyTxProposal, BuyTxState};
-use crate::refiller::GoodRefiller;
+use crate::basket_transaction::{BasketLeg, BasketTx, BasketTxProposal, BasketTxQuoteError};
+use crate::batch_auction::{BatchAuction, QueuedLock};
+use crate::buy_transaction::{BuyTxProposal, BuyTxReservationError, BuyTxState};
+use crate::competitor_rates::CompetitorRates;
+use crate::competitor_rates;
+use crate::forward_contract::{ForwardTx, ForwardTxProposal, ForwardTxState};
+use crate::ledger::Ledger;
+use crate::batch_auction;
+use crate::lending_desk::{LendingDesk, Loan, LoanState};
+use crate::market_config::{MarketConfig, MarketConfigError};
+use crate::order_book::{LimitOrder, LimitOrderState, OrderBook, OrderBookError, OrderSide};
+use crate::pricing_curve::PricingCurve;
+use crate::quantity::Quantity;
+use crate::refill_backend::MarketRefillBackend;
+use crate::refill_policy::{Refill, RefillPolicy};
 use crate::sell_transaction::{SellTxProposal, SellTxState};
-use crate::service::{ServiceBuyError, ServiceBuyReservationError, ServiceSellError, ServiceSellReservationError, TxService};
+use crate::service::{ServiceBasketBuyError, ServiceBasketReservationError, ServiceBuyError, ServiceBuyReservationError, ServiceCancelBuyError, ServiceCancelSellError, ServiceForwardAgreementError, ServiceForwardClaimError, ServiceForwardDeliveryError, ServiceRenewBuyError, ServiceRenewSellError, ServiceSellError, ServiceSellReservationError, TxService, new_uuid};
+use crate::snapshot::MarketSnapshot;
 
 pub struct DogeMarketImpl {
     pub market_name: &'static str,
     service: TxService,
-    refiller: GoodRefiller,
+    refill_policy: Box<dyn RefillPolicy>,
+    /// Restocks by trading with other markets instead of converting at the default rates, when set.
+    refill_backend: Option<MarketRefillBackend>,
+    /// Refill planned for the backend, traded by [DogeMarket::restock](crate::dogemarket::DogeMarket::restock) once the
+    /// market is no longer borrowed.
+    queued_refill: Option<Refill>,
+    config: MarketConfig,
+    pricing_curve: Box<dyn PricingCurve>,
+    order_book: OrderBook,
+    auction: BatchAuction,
+    lending_desk: LendingDesk,
+    competitor_rates: CompetitorRates,
+    /// Source of every random choice of the market, seeded by the `seed` of the configuration, tokens included.
+    rng: ChaCha8Rng,
+}
+
+/// Lock of the batch auction cleared at the end of a day, with the default good it was priced at, `None` if it was
+/// refused.
+#[derive(Debug, Clone)]
+pub struct ClearedLock {
+    pub lock: QueuedLock,
+    pub price: Option<f32>,
+}
+
+/// What the market did on its own at the end of a day.
+#[derive(Debug, Clone)]
+pub struct DayOutcome {
+    pub cleared_locks: Vec<ClearedLock>,
+    pub filled_orders: Vec<Uuid>,
 }
 
 #[derive(Debug)]
@@ -24,6 +71,21 @@ pub enum DogeBuyReservationError {
     NonPositiveBid,
     ExceedsReservableQuantity { reservable: f32 },
     BidTooLow { lowest: f32 },
+    /// The bid is in the good to buy, which cannot pay for itself unless it is the default good, or in a good other than
+    /// the default one while locks are cleared by batch auctions.
+    UnsupportedBid,
+    /// The market would be left with less than `floor` of the good to buy.
+    BreachesReserveFloor { floor: f32, reservable: f32 },
+    /// The market would own more than its capacity of the default good once the lock is paid. It can take in at most
+    /// `room` more.
+    ExceedsCapacity { room: f32 },
+    /// The trader let too many locks expire to lock more than `max_quantity`, possibly nothing at all.
+    BadReputation { max_quantity: f32 },
+    /// The trader already holds as many locks waiting to be paid as it is allowed to.
+    TooManyLocks,
+    /// The locks of the trader would reserve more of the good than its [TraderLimits](crate::market_config::TraderLimits)
+    /// allow. It can lock at most `max_quantity` more.
+    ExceedsTraderExposure { max_quantity: f32 },
 }
 
 #[derive(Debug)]
@@ -32,11 +94,53 @@ pub enum DogeSellReservationError {
     NonPositiveOffer,
     ExceedsReservableQuantity { reservable: f32 },
     OfferTooHigh { highest: f32 },
+    /// The offer is in the good to sell, which cannot pay for itself unless it is the default good, or in a good other
+    /// than the default one while locks are cleared by batch auctions.
+    UnsupportedOffer,
+    /// The market would be left with less than `floor` of the default good.
+    BreachesReserveFloor { floor: f32, reservable: f32 },
+    /// The market would own more than its capacity of the good to sell once the lock is paid. It can take in at most
+    /// `room` more.
+    ExceedsCapacity { room: f32 },
+    /// The trader let too many locks expire to be offered more than `max_quantity`, possibly nothing at all.
+    BadReputation { max_quantity: f32 },
+    /// The trader already holds as many locks waiting to be paid as it is allowed to.
+    TooManyLocks,
+    /// The locks of the trader would reserve more of the good than its [TraderLimits](crate::market_config::TraderLimits)
+    /// allow. It can lock at most `max_quantity` more.
+    ExceedsTraderExposure { max_quantity: f32 },
+}
+
+#[derive(Debug)]
+pub enum DogeBasketReservationError {
+    EmptyBasket,
+    MixedBidKinds,
+    /// The trader does not have room for one more lock for every leg.
+    TooManyLocks,
+    /// The locks of the trader would reserve more of `kind` than its [TraderLimits](crate::market_config::TraderLimits)
+    /// allow. It can lock at most `max_quantity` more.
+    ExceedsTraderExposure { kind: GoodKind, max_quantity: f32 },
+    /// The leg at `index` could not be reserved, so none was. Limits on the goods of the market are checked against the
+    /// legs up to `index` together.
+    Leg { index: usize, error: DogeBuyReservationError },
+}
+
+#[derive(Debug)]
+pub enum DogeGetBasketPriceError {
+    EmptyBasket,
+    /// The leg at `index` could not be priced, so none was. Limits on the goods of the market are checked against the
+    /// legs up to `index` together.
+    Leg { index: usize, error: DogeGetBuyPriceError },
+    /// The market would own more than its capacity of the default good once the legs up to `index` are paid. It can
+    /// take in at most `room` more.
+    ExceedsCapacity { index: usize, room: f32 },
 }
 
 #[derive(Debug)]
 pub enum DogeBuyError {
     UnrecognizedUuid,
+    /// The lock was queued, but the batch auction dropped it instead of reserving it.
+    DroppedByAuction,
     InvalidState { current_state: BuyTxState },
     WrongGoodKind { pre_agreed: GoodKind },
     InsufficientGoodQuantity { pre_agreed: f32 },
@@ -45,15 +149,150 @@ pub enum DogeBuyError {
 #[derive(Debug)]
 pub enum DogeSellError {
     UnrecognizedUuid,
+    /// The lock was queued, but the batch auction dropped it instead of reserving it.
+    DroppedByAuction,
     InvalidState { current_state: SellTxState },
     WrongGoodKind { pre_agreed: GoodKind },
     InsufficientGoodQuantity { pre_agreed: f32 },
 }
 
+#[derive(Debug)]
+pub enum DogeBasketBuyError {
+    UnrecognizedUuid,
+    InvalidState { current_state: BuyTxState },
+    WrongGoodKind { pre_agreed: GoodKind },
+    InsufficientGoodQuantity { pre_agreed: f32 },
+}
+
+#[derive(Debug)]
+pub enum DogeCancelBuyError {
+    UnrecognizedUuid,
+    InvalidState { current_state: BuyTxState },
+}
+
+#[derive(Debug)]
+pub enum DogeCancelSellError {
+    UnrecognizedUuid,
+    InvalidState { current_state: SellTxState },
+}
+
+#[derive(Debug)]
+pub enum DogeRenewBuyError {
+    UnrecognizedUuid,
+    /// A lock can be renewed by at least one day and at most by the lock TTL.
+    InvalidTicks { max_ticks: u32 },
+    InvalidState { current_state: BuyTxState },
+    MaxRenewalsReached { max_renewals: u32 },
+    WrongGoodKind { fee_kind: GoodKind },
+    InsufficientGoodQuantity { fee: f32 },
+}
+
+#[derive(Debug)]
+pub enum DogeRenewSellError {
+    UnrecognizedUuid,
+    /// A lock can be renewed by at least one day and at most by the lock TTL.
+    InvalidTicks { max_ticks: u32 },
+    InvalidState { current_state: SellTxState },
+    MaxRenewalsReached { max_renewals: u32 },
+    WrongGoodKind { fee_kind: GoodKind },
+    InsufficientGoodQuantity { fee: f32 },
+}
+
+#[derive(Debug)]
+pub enum DogePlaceOrderError {
+    NonPositiveQuantity,
+    NonPositiveLimit,
+    NonPositiveTtl,
+    /// The escrow must hold at least `required` of `kind`.
+    InsufficientEscrow { kind: GoodKind, required: f32 },
+}
+
+#[derive(Debug)]
+pub enum DogeOrderError {
+    UnrecognizedUuid,
+    InvalidState { current_state: LimitOrderState },
+}
+
 #[derive(Debug)]
 pub enum DogeGetBuyPriceError {
     NonPositiveRequest,
     ExceedsReservableQuantity { reservable: f32 },
+    BreachesReserveFloor { floor: f32, reservable: f32 },
+}
+
+#[derive(Debug)]
+pub enum DogeGetForwardPriceError {
+    NonPositiveRequest,
+    /// Forwards mature in at least one day and at most in `max_days`.
+    InvalidDays { max_days: u32 },
+    ExceedsReservableQuantity { reservable: f32 },
+    BreachesReserveFloor { floor: f32, reservable: f32 },
+}
+
+#[derive(Debug)]
+pub enum DogeForwardAgreementError {
+    Price { error: DogeGetForwardPriceError },
+    NonPositivePrice,
+    PriceTooLow { lowest: f32 },
+    PriceTooHigh { highest: f32 },
+    ExceedsReservableQuantity { reservable: f32 },
+    BreachesReserveFloor { floor: f32, reservable: f32 },
+    ExceedsCapacity { room: f32 },
+    BadReputation { max_quantity: f32 },
+    TooManyLocks,
+    /// The locks of the trader would reserve more of the good than its [TraderLimits](crate::market_config::TraderLimits)
+    /// allow. It can lock at most `max_quantity` more.
+    ExceedsTraderExposure { max_quantity: f32 },
+}
+
+#[derive(Debug)]
+pub enum DogeForwardDeliveryError {
+    UnrecognizedUuid,
+    InvalidState { current_state: ForwardTxState },
+    AlreadyDelivered,
+    WrongGoodKind { pre_agreed: GoodKind },
+    InsufficientGoodQuantity { pre_agreed: f32 },
+}
+
+#[derive(Debug)]
+pub enum DogeForwardClaimError {
+    UnrecognizedUuid,
+    InvalidState { current_state: ForwardTxState },
+}
+
+#[derive(Debug)]
+pub enum DogeBorrowError {
+    NonPositiveQuantity,
+    /// Loans last at least one day and at most `max_days`.
+    InvalidDays { max_days: u32 },
+    /// The collateral must be a good other than the one borrowed.
+    SameKindCollateral,
+    InsufficientCollateral { required: f32 },
+    ExceedsReservableQuantity { reservable: f32 },
+    BreachesReserveFloor { floor: f32, reservable: f32 },
+    BadReputation { max_quantity: f32 },
+}
+
+#[derive(Debug)]
+pub enum DogeRepayLoanError {
+    UnrecognizedUuid,
+    InvalidState { current_state: LoanState },
+    WrongGoodKind { pre_agreed: GoodKind },
+    InsufficientGoodQuantity { debt: f32 },
+}
+
+enum InventoryLimitError {
+    BreachesReserveFloor { floor: f32, reservable: f32 },
+    ExceedsCapacity { room: f32 },
+}
+
+struct ReserveFloorError {
+    floor: f32,
+    reservable: f32,
+}
+
+struct ReputationLimitError {
+    max_quantity: f32,
 }
 
 #[derive(Debug)]
@@ -61,35 +300,507 @@ pub enum DogeGetSellPriceError {
     NonPositiveRequest,
 }
 
+#[derive(Debug)]
+pub enum DogeGetCrossPriceError {
+    NonPositiveRequest,
+    /// A good cannot be paid for with itself.
+    SameKind,
+    ExceedsReservableQuantity { reservable: f32 },
+    /// The market would be left with less than `floor` of the good it gives.
+    BreachesReserveFloor { floor: f32, reservable: f32 },
+    /// The market would own more than its capacity of the good it takes in. It can take in at most `room` more.
+    ExceedsCapacity { room: f32 },
+}
+
 impl DogeMarketImpl {
-    pub fn new(ops: AccountOps, max_ticks: u32) -> DogeMarketImpl {
-        DogeMarketImpl {
-            market_name: "DogeMarket",
-            service: TxService::new(ops, max_ticks),
-            refiller: GoodRefiller::new(),
+    /// Draws a seed for the market when the configuration has none, keeping it in the configuration so that it ends up
+    /// in the snapshots. Fails when the configuration does not [validate](MarketConfig::validate).
+    pub fn new(ops: AccountOps, mut config: MarketConfig) -> Result<DogeMarketImpl, MarketConfigError> {
+        config.validate()?;
+        let seed = *config.seed.get_or_insert_with(rand::random);
+        let mut service = TxService::new(ops, config.lock_ttl);
+        service.set_trader_limits(config.trader_limits.clone());
+        if config.audit {
+            service.enable_audit();
+        }
+
+        Ok(DogeMarketImpl {
+            market_name: DogeMarketImpl::leak_market_name(&config),
+            service,
+            refill_policy: config.refill_policy.build(&config.refiller),
+            refill_backend: None,
+            queued_refill: None,
+            pricing_curve: config.pricing_curve.build(),
+            config,
+            order_book: OrderBook::new(),
+            auction: BatchAuction::new(),
+            lending_desk: LendingDesk::new(),
+            competitor_rates: CompetitorRates::new(),
+            rng: ChaCha8Rng::seed_from_u64(seed),
+        })
+    }
+
+    /// [Market::get_name](unitn_market_2022::market::Market::get_name) returns a `&'static str`, so a configured name
+    /// has to live as long as the program. Each distinct name is leaked once, and shared by every market that uses it.
+    fn leak_market_name(config: &MarketConfig) -> &'static str {
+        static MARKET_NAMES: Mutex<Vec<&'static str>> = Mutex::new(Vec::new());
+
+        let mut names = MARKET_NAMES.lock().unwrap();
+        if let Some(name) = names.iter().find(|name| **name == config.market_name) {
+            return name;
+        }
+        let name: &'static str = Box::leak(config.market_name.clone().into_boxed_str());
+        names.push(name);
+        name
+    }
+
+    /// Restores a market from a [MarketSnapshot] taken with [DogeMarketImpl::snapshot]. The refill backend is not part of
+    /// the snapshot and has to be set again. Fails when the configuration of the snapshot does not
+    /// [validate](MarketConfig::validate).
+    pub fn restore(snapshot: MarketSnapshot) -> Result<DogeMarketImpl, MarketConfigError> {
+        snapshot.config.validate()?;
+        let rng = snapshot.rng.unwrap_or_else(|| ChaCha8Rng::seed_from_u64(snapshot.config.seed.unwrap_or_default()));
+        let mut service = snapshot.service;
+        service.set_trader_limits(snapshot.config.trader_limits.clone());
+        if snapshot.config.audit {
+            service.enable_audit();
+        }
+
+        Ok(DogeMarketImpl {
+            market_name: DogeMarketImpl::leak_market_name(&snapshot.config),
+            service,
+            refill_policy: snapshot.config.refill_policy.restore(&snapshot.config.refiller, snapshot.refiller),
+            refill_backend: None,
+            queued_refill: None,
+            pricing_curve: snapshot.config.pricing_curve.build(),
+            config: snapshot.config,
+            order_book: snapshot.order_book,
+            auction: snapshot.auction,
+            lending_desk: snapshot.lending_desk,
+            competitor_rates: snapshot.competitor_rates,
+            rng,
+        })
+    }
+
+    pub fn snapshot(&self) -> MarketSnapshot {
+        MarketSnapshot {
+            service: self.service.clone(),
+            refiller: self.refill_policy.get_trackers().cloned(),
+            config: self.config.clone(),
+            order_book: self.order_book.clone(),
+            auction: self.auction.clone(),
+            lending_desk: self.lending_desk.clone(),
+            competitor_rates: self.competitor_rates.clone(),
+            rng: Some(self.rng.clone()),
+        }
+    }
+
+    pub fn get_config(&self) -> &MarketConfig {
+        &self.config
+    }
+
+    pub fn get_seed(&self) -> u64 {
+        self.config.seed.unwrap_or_default()
+    }
+
+    pub fn set_refill_backend(&mut self, backend: Option<MarketRefillBackend>) {
+        self.refill_backend = backend;
+        self.queued_refill = None;
+    }
+
+    /// Takes the refill queued for the backend, along with the backend and a ledger holding the goods it exports, taken
+    /// out of the assets. `None` if no refill is queued or the goods are no longer available.
+    pub fn take_queued_refill(&mut self) -> Option<(MarketRefillBackend, Refill, Ledger)> {
+        let refill = self.queued_refill.take()?;
+        let backend = self.refill_backend.clone()?;
+        let exported = Quantity::from_f32(refill.export_quantity);
+        self.service.get_account_ops_mut().assets.withdraw(refill.export, exported).ok()?;
+
+        let mut held = Ledger::new_empty();
+        held.deposit(refill.export, exported);
+        Some((backend, refill, held))
+    }
+
+    /// Puts the goods a refill taken with [DogeMarketImpl::take_queued_refill] left in `held` back in the assets.
+    pub fn return_refill_goods(&mut self, held: &Ledger) {
+        let assets = &mut self.service.get_account_ops_mut().assets;
+        for kind in [EUR, USD, YEN, YUAN] {
+            assets.deposit(kind, held.get(kind));
+        }
+    }
+
+    /// Checks that a lock taking `outgoing` out of the reservable goods leaves at least the reserve floor of its kind,
+    /// and that bringing `incoming` in does not exceed the capacity of its kind. Requests that are invalid for other
+    /// reasons are left to the [TxService] to refuse.
+    fn check_inventory_limits(&self, outgoing: &Good, incoming: &Good) -> Result<(), InventoryLimitError> {
+        self.check_reserve_floor(outgoing)
+            .map_err(|err| InventoryLimitError::BreachesReserveFloor { floor: err.floor, reservable: err.reservable })?;
+
+        // a lock the market cannot serve anyway is refused for that by the service
+        let ops = self.service.get_account_ops();
+        if Quantity::from_f32(outgoing.get_qty()) > ops.assets.get(outgoing.get_kind()) {
+            return Ok(());
+        }
+
+        // without a capacity, the market still cannot own more than it can count
+        let kind = incoming.get_kind();
+        let capacity = self.config.get_capacity(kind).unwrap_or(f32::MAX);
+        let owned = ops.assets.get(kind) + ops.reservations.get(kind) + ops.futures.get(kind);
+        if incoming.get_qty() > 0. && owned + Quantity::from_f32(incoming.get_qty()) > Quantity::from_f32(capacity) {
+            return Err(InventoryLimitError::ExceedsCapacity { room: (capacity - owned.to_f32()).max(0.) });
+        }
+
+        Ok(())
+    }
+
+    /// Checks that taking `outgoing` out of the reservable goods leaves at least the reserve floor of its kind, for the
+    /// requests that bring nothing in, or nothing the market owns.
+    fn check_reserve_floor(&self, outgoing: &Good) -> Result<(), ReserveFloorError> {
+        let reservable = self.service.get_account_ops().assets.get(outgoing.get_kind());
+        let outgoing_quantity = Quantity::from_f32(outgoing.get_qty());
+        let floor = self.config.get_reserve_floor(outgoing.get_kind());
+        if outgoing.get_qty() > 0. && outgoing_quantity <= reservable && reservable - outgoing_quantity < Quantity::from_f32(floor) {
+            return Err(ReserveFloorError { floor, reservable: reservable.to_f32() });
+        }
+
+        Ok(())
+    }
+
+    /// Score of the trader, as seen through the reputation settings of the market.
+    fn get_trader_score(&self, trader_name: &str) -> f32 {
+        let record = self.service.get_reputation_table().get_record(trader_name);
+        self.config.reputation.get_effective_score(&record)
+    }
+
+    /// Refuses every lock of a trader scoring below `refuse_below`, and locks taking out more than the score times the
+    /// reservable quantity of a trader scoring below `limit_below`.
+    fn check_reputation(&self, score: f32, outgoing: &Good) -> Result<(), ReputationLimitError> {
+        let settings = &self.config.reputation;
+
+        if score < settings.refuse_below {
+            return Err(ReputationLimitError { max_quantity: 0. });
+        }
+
+        if score < settings.limit_below {
+            let max_quantity = score * self.service.get_account_ops().get_reservable_quantity_by_kind(outgoing.get_kind());
+            if outgoing.get_qty() > max_quantity {
+                return Err(ReputationLimitError { max_quantity });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Folds an event notified by another market into the estimate of the rates of the other markets.
+    pub fn observe_competitor_event(&mut self, event: &Event) {
+        self.competitor_rates.observe(event, self.config.competitors.smoothing);
+    }
+
+    /// Spread applied when a trader buys `kind`, widened if the other markets sell it dearer.
+    fn get_buy_earn_percentage(&self, kind: GoodKind) -> f32 {
+        self.config.get_buy_earn_percentage(kind) + self.get_competitor_skew(kind).0
+    }
+
+    /// Spread applied when a trader sells `kind`, widened if the other markets buy it cheaper.
+    fn get_sell_earn_percentage(&self, kind: GoodKind) -> f32 {
+        self.config.get_sell_earn_percentage(kind) + self.get_competitor_skew(kind).1
+    }
+
+    fn get_competitor_skew(&self, kind: GoodKind) -> (f32, f32) {
+        let rate = self.get_default_good_value(&Good::new(kind, 1.));
+        competitor_rates::skew(rate, self.competitor_rates.get(kind), self.config.competitors.max_skew_percentage)
+    }
+
+    /// Earn percentage widened by the reputation penalty of a trader with `score`.
+    fn widen_earn_percentage(&self, earn_percentage: f32, score: f32) -> f32 {
+        earn_percentage + self.config.reputation.spread_penalty * (1. - score)
+    }
+
+    /// Value of `good` in the default good, at the rate the market buys a unit of it at without a spread.
+    fn get_default_good_value(&self, good: &Good) -> f32 {
+        let ops = self.service.get_account_ops();
+        good.get_qty() * ops.compute_sell_price(self.pricing_curve.as_ref(), good.get_kind(), 1., 0.).unwrap()
+    }
+
+    /// Time to live of a lock exchanging `exchanged` for the goods of the market, tiered on its value in the default
+    /// good whichever side of the lock it is. `None` if the lock expires after the default `lock_ttl`.
+    fn get_lock_ttl(&self, exchanged: &Good) -> Option<u32> {
+        self.config.get_lock_ttl_tier(self.get_default_good_value(exchanged)).map(|tier| tier.lock_ttl)
+    }
+
+    /// Deposit that a lock paying or being paid `quantity` of a good has to be backed by, in the same good.
+    pub fn get_lock_deposit(&self, quantity: f32) -> f32 {
+        (quantity * self.config.lock_deposit_percentage / 100.).max(0.)
+    }
+
+    /// Reserves the proposal, backed by `deposit` of the bid good that the caller took from the trader, 0 if it took
+    /// none. The deposit counts towards the payment, see [DogeMarketImpl::get_lock_deposit] for the one to take.
+    pub fn do_buy_reservation(&mut self, proposal: &BuyTxProposal, deposit: f32) -> Result<Uuid, DogeBuyReservationError> {
+        self.reserve_buy(proposal, deposit)
+    }
+
+    fn reserve_buy(&mut self, proposal: &BuyTxProposal, deposit: f32) -> Result<Uuid, DogeBuyReservationError> {
+        let score = self.check_buy_limits(proposal)?;
+
+        let earn_percentage = self.widen_earn_percentage(self.get_buy_earn_percentage(proposal.buy.get_kind()), score);
+        let lock_ttl = self.get_lock_ttl(&proposal.bid);
+        self.service.do_buy_reservation(proposal, self.pricing_curve.as_ref(), earn_percentage, lock_ttl, deposit, &mut self.rng)
+            .map_err(buy_reservation_error)
+    }
+
+    /// Checks the inventory limits of the market and the reputation of the trader, returning its score.
+    fn check_buy_limits(&self, proposal: &BuyTxProposal) -> Result<f32, DogeBuyReservationError> {
+        self.check_inventory_limits(&proposal.buy, &proposal.bid).map_err(|err| match err {
+            InventoryLimitError::BreachesReserveFloor { floor, reservable } => DogeBuyReservationError::BreachesReserveFloor { floor, reservable },
+            InventoryLimitError::ExceedsCapacity { room } => DogeBuyReservationError::ExceedsCapacity { room },
+        })?;
+
+        let score = self.get_trader_score(&proposal.trader_name);
+        self.check_reputation(score, &proposal.buy)
+            .map_err(|err| DogeBuyReservationError::BadReputation { max_quantity: err.max_quantity })?;
+        Ok(score)
+    }
+
+    /// Reserves the proposal, backed by `deposit` of the offer good that the caller took from the trader, 0 if it took
+    /// none. The deposit is given back with the offer, see [DogeMarketImpl::get_lock_deposit] for the one to take.
+    pub fn do_sell_reservation(&mut self, proposal: &SellTxProposal, deposit: f32) -> Result<Uuid, DogeSellReservationError> {
+        self.reserve_sell(proposal, deposit)
+    }
+
+    fn reserve_sell(&mut self, proposal: &SellTxProposal, deposit: f32) -> Result<Uuid, DogeSellReservationError> {
+        let score = self.check_sell_limits(proposal)?;
+
+        let earn_percentage = self.widen_earn_percentage(self.get_sell_earn_percentage(proposal.sell.get_kind()), score);
+        let lock_ttl = self.get_lock_ttl(&proposal.offer);
+        self.service.do_sell_reservation(proposal, self.pricing_curve.as_ref(), earn_percentage, lock_ttl, deposit, &mut self.rng)
+            .map_err(sell_reservation_error)
+    }
+
+    /// Checks the inventory limits of the market and the reputation of the trader, returning its score.
+    fn check_sell_limits(&self, proposal: &SellTxProposal) -> Result<f32, DogeSellReservationError> {
+        self.check_inventory_limits(&proposal.offer, &proposal.sell).map_err(|err| match err {
+            InventoryLimitError::BreachesReserveFloor { floor, reservable } => DogeSellReservationError::BreachesReserveFloor { floor, reservable },
+            InventoryLimitError::ExceedsCapacity { room } => DogeSellReservationError::ExceedsCapacity { room },
+        })?;
+
+        let score = self.get_trader_score(&proposal.trader_name);
+        self.check_reputation(score, &proposal.offer)
+            .map_err(|err| DogeSellReservationError::BadReputation { max_quantity: err.max_quantity })?;
+        Ok(score)
+    }
+
+    /// Queues the proposal for the batch auction that clears at the end of the day. It is refused right away if its bid
+    /// does not even cover the price of its own quantity, which the auction never goes below.
+    pub fn queue_buy_reservation(&mut self, proposal: &BuyTxProposal) -> Result<Uuid, DogeBuyReservationError> {
+        if proposal.buy.get_qty() <= 0. {
+            return Err(DogeBuyReservationError::NonPositiveBuy);
+        }
+        if proposal.bid.get_qty() <= 0. {
+            return Err(DogeBuyReservationError::NonPositiveBid);
+        }
+        // the auction clears at a single price in the default good
+        if proposal.bid.get_kind() != DEFAULT_GOOD_KIND {
+            return Err(DogeBuyReservationError::UnsupportedBid);
+        }
+
+        let lowest = self.get_buy_price(proposal.buy.get_kind(), proposal.buy.get_qty()).map_err(|err| match err {
+            DogeGetBuyPriceError::NonPositiveRequest => DogeBuyReservationError::NonPositiveBuy,
+            DogeGetBuyPriceError::ExceedsReservableQuantity { reservable } => DogeBuyReservationError::ExceedsReservableQuantity { reservable },
+            DogeGetBuyPriceError::BreachesReserveFloor { floor, reservable } => DogeBuyReservationError::BreachesReserveFloor { floor, reservable },
+        })?;
+        if proposal.bid.get_qty() < lowest {
+            return Err(DogeBuyReservationError::BidTooLow { lowest });
+        }
+
+        let uuid = new_uuid(&mut self.rng);
+        self.auction.queue(QueuedLock { uuid, side: OrderSide::Buy, good: proposal.buy.clone(), limit: proposal.bid.get_qty(), trader_name: proposal.trader_name.clone() });
+        Ok(uuid)
+    }
+
+    /// Queues the proposal for the batch auction that clears at the end of the day. It is refused right away if its offer
+    /// is above the price of its own quantity, which the auction never goes above.
+    pub fn queue_sell_reservation(&mut self, proposal: &SellTxProposal) -> Result<Uuid, DogeSellReservationError> {
+        if proposal.sell.get_qty() <= 0. {
+            return Err(DogeSellReservationError::NonPositiveSell);
+        }
+        if proposal.offer.get_qty() <= 0. {
+            return Err(DogeSellReservationError::NonPositiveOffer);
+        }
+        if proposal.offer.get_kind() != DEFAULT_GOOD_KIND {
+            return Err(DogeSellReservationError::UnsupportedOffer);
         }
+
+        let ops = self.service.get_account_ops();
+        if Quantity::from_f32(proposal.offer.get_qty()) > ops.assets.get(proposal.offer.get_kind()) {
+            return Err(DogeSellReservationError::ExceedsReservableQuantity { reservable: ops.get_reservable_quantity_by_kind(proposal.offer.get_kind()) });
+        }
+
+        let highest = self.get_sell_price(proposal.sell.get_kind(), proposal.sell.get_qty()).map_err(|err| match err {
+            DogeGetSellPriceError::NonPositiveRequest => DogeSellReservationError::NonPositiveSell,
+        })?;
+        if proposal.offer.get_qty() > highest {
+            return Err(DogeSellReservationError::OfferTooHigh { highest });
+        }
+
+        let uuid = new_uuid(&mut self.rng);
+        self.auction.queue(QueuedLock { uuid, side: OrderSide::Sell, good: proposal.sell.clone(), limit: proposal.offer.get_qty(), trader_name: proposal.trader_name.clone() });
+        Ok(uuid)
+    }
+
+    pub fn has_queued_locks(&self) -> bool {
+        !self.auction.is_empty()
     }
 
-    pub fn do_buy_reservation(&mut self, proposal: &BuyTxProposal) -> Result<Uuid, DogeBuyReservationError> {
-        self.service.do_buy_reservation(proposal).map_err(|err| match err {
-            ServiceBuyReservationError::NonPositiveBuy => DogeBuyReservationError::NonPositiveBuy,
-            ServiceBuyReservationError::NonPositiveBid => DogeBuyReservationError::NonPositiveBid,
-            ServiceBuyReservationError::ExceedsReservableQuantity { reservable } => DogeBuyReservationError::ExceedsReservableQuantity { reservable },
-            ServiceBuyReservationError::BidTooLow { lowest } => DogeBuyReservationError::BidTooLow { lowest }
+    /// Clears the locks queued during the day at the price of each side and good, then reserves the ones that agreed
+    /// with it under the uuid they were queued with. Cleared locks still have to pass the inventory limits, the
+    /// reputation of the trader and its limits, one after the other.
+    fn clear_auction(&mut self) -> Vec<ClearedLock> {
+        let queued = self.auction.take();
+        let mut cleared = vec![];
+
+        for side in [OrderSide::Buy, OrderSide::Sell] {
+            for kind in [EUR, USD, YEN, YUAN] {
+                let locks: Vec<&QueuedLock> = queued.iter().filter(|lock| lock.side == side && lock.good.get_kind() == kind).collect();
+                if locks.is_empty() {
+                    continue;
+                }
+
+                let prices = match side {
+                    OrderSide::Buy => batch_auction::clear(&locks, |quantity| self.get_buy_price(kind, quantity).ok()),
+                    OrderSide::Sell => batch_auction::clear(&locks, |quantity| self.get_sell_price(kind, quantity).ok()),
+                };
+
+                for (lock, price) in locks.into_iter().zip(prices) {
+                    let price = price.filter(|price| self.reserve_cleared(lock, *price));
+                    if price.is_none() {
+                        self.auction.record_dropped(lock.uuid);
+                    }
+                    cleared.push(ClearedLock { lock: lock.clone(), price });
+                }
+            }
+        }
+
+        cleared
+    }
+
+    /// Reserves a lock cleared at `price`, telling whether it was.
+    fn reserve_cleared(&mut self, lock: &QueuedLock, price: f32) -> bool {
+        let price = Good::new(DEFAULT_GOOD_KIND, price);
+        let lock_ttl = self.get_lock_ttl(&price);
+
+        match lock.side {
+            OrderSide::Buy => {
+                let proposal = BuyTxProposal { buy: lock.good.clone(), bid: price, trader_name: lock.trader_name.clone() };
+                self.check_buy_limits(&proposal).is_ok() && self.service.do_cleared_buy_reservation(lock.uuid, &proposal, lock_ttl).is_ok()
+            }
+            OrderSide::Sell => {
+                let proposal = SellTxProposal { sell: lock.good.clone(), offer: price, trader_name: lock.trader_name.clone() };
+                self.check_sell_limits(&proposal).is_ok() && self.service.do_cleared_sell_reservation(lock.uuid, &proposal, lock_ttl).is_ok()
+            }
+        }
+    }
+
+    /// Reserves all the legs of the basket under a single uuid, or none of them. Baskets take no deposit and are given
+    /// the lock TTL tier of their whole bid.
+    pub fn do_basket_reservation(&mut self, proposal: &BasketTxProposal) -> Result<Uuid, DogeBasketReservationError> {
+        let score = self.get_trader_score(&proposal.trader_name);
+        for (index, (outgoing, incoming)) in basket_totals(&proposal.legs).iter().enumerate() {
+            let leg_error = |error| DogeBasketReservationError::Leg { index, error };
+            self.check_inventory_limits(outgoing, incoming).map_err(|err| leg_error(match err {
+                InventoryLimitError::BreachesReserveFloor { floor, reservable } => DogeBuyReservationError::BreachesReserveFloor { floor, reservable },
+                InventoryLimitError::ExceedsCapacity { room } => DogeBuyReservationError::ExceedsCapacity { room },
+            }))?;
+            self.check_reputation(score, outgoing)
+                .map_err(|err| leg_error(DogeBuyReservationError::BadReputation { max_quantity: err.max_quantity }))?;
+        }
+        let bid_value: f32 = proposal.legs.iter().map(|leg| self.get_default_good_value(&leg.bid)).sum();
+
+        let mut earn_percentages = self.config.buy_earn_percentages;
+        for kind in [GoodKind::EUR, GoodKind::USD, GoodKind::YEN, GoodKind::YUAN] {
+            *earn_percentages.get_mut(kind) = self.widen_earn_percentage(self.get_buy_earn_percentage(kind), score);
+        }
+        let lock_ttl = self.config.get_lock_ttl_tier(bid_value).map(|tier| tier.lock_ttl);
+        self.service.do_basket_reservation(proposal, self.pricing_curve.as_ref(), &earn_percentages, lock_ttl, &mut self.rng).map_err(|err| match err {
+            ServiceBasketReservationError::EmptyBasket => DogeBasketReservationError::EmptyBasket,
+            ServiceBasketReservationError::MixedBidKinds => DogeBasketReservationError::MixedBidKinds,
+            ServiceBasketReservationError::TooManyLocks => DogeBasketReservationError::TooManyLocks,
+            ServiceBasketReservationError::ExceedsTraderExposure { kind, max_quantity } => DogeBasketReservationError::ExceedsTraderExposure { kind, max_quantity },
+            ServiceBasketReservationError::Leg { index, error } => DogeBasketReservationError::Leg {
+                index,
+                error: match error {
+                    BuyTxReservationError::NonPositiveBuy => DogeBuyReservationError::NonPositiveBuy,
+                    BuyTxReservationError::NonPositiveBid => DogeBuyReservationError::NonPositiveBid,
+                    BuyTxReservationError::ExceedsReservableQuantity { reservable } => DogeBuyReservationError::ExceedsReservableQuantity { reservable },
+                    BuyTxReservationError::BidTooLow { lowest } => DogeBuyReservationError::BidTooLow { lowest },
+                    BuyTxReservationError::UnsupportedBid => DogeBuyReservationError::UnsupportedBid,
+                },
+            },
         })
     }
 
-    pub fn do_sell_reservation(&mut self, proposal: &SellTxProposal) -> Result<Uuid, DogeSellReservationError> {
-        self.service.do_sell_reservation(proposal).map_err(|err| match err {
-            ServiceSellReservationError::NonPositiveSell => DogeSellReservationError::NonPositiveSell,
-            ServiceSellReservationError::NonPositiveOffer => DogeSellReservationError::NonPositiveOffer,
-            ServiceSellReservationError::ExceedsReservableQuantity { reservable } => DogeSellReservationError::ExceedsReservableQuantity { reservable },
-            ServiceSellReservationError::OfferTooHigh { highest } => DogeSellReservationError::OfferTooHigh { highest },
+    /// Lowest bids, in the default good and in the order of `legs`, that [DogeMarketImpl::do_basket_reservation] accepts
+    /// for a basket buying them, see [BasketTx::quote]. Like [DogeMarketImpl::get_buy_price], the bids are those of a
+    /// trader in good standing.
+    pub fn get_basket_price(&self, legs: &[Good]) -> Result<Vec<f32>, DogeGetBasketPriceError> {
+        let mut earn_percentages = self.config.buy_earn_percentages;
+        for kind in [GoodKind::EUR, GoodKind::USD, GoodKind::YEN, GoodKind::YUAN] {
+            *earn_percentages.get_mut(kind) = self.get_buy_earn_percentage(kind);
+        }
+        let prices = BasketTx::quote(self.service.get_account_ops(), legs, self.pricing_curve.as_ref(), &earn_percentages).map_err(|err| match err {
+            BasketTxQuoteError::EmptyBasket => DogeGetBasketPriceError::EmptyBasket,
+            BasketTxQuoteError::Leg { index, error } => DogeGetBasketPriceError::Leg {
+                index,
+                error: match error {
+                    BuyPriceComputationError::NonPositiveQuantity => DogeGetBuyPriceError::NonPositiveRequest,
+                    BuyPriceComputationError::ExceedsReservableQuantity { reservable } => DogeGetBuyPriceError::ExceedsReservableQuantity { reservable },
+                    BuyPriceComputationError::NegativeExchangeRateEarnPercentage | BuyPriceComputationError::SameKind => unreachable!(),
+                },
+            },
+        })?;
+
+        let legs: Vec<BasketLeg> = legs.iter().zip(&prices).map(|(buy, price)| BasketLeg { buy: buy.clone(), bid: Good::new(DEFAULT_GOOD_KIND, *price) }).collect();
+        for (index, (outgoing, incoming)) in basket_totals(&legs).iter().enumerate() {
+            self.check_inventory_limits(outgoing, incoming).map_err(|err| match err {
+                InventoryLimitError::BreachesReserveFloor { floor, reservable } =>
+                    DogeGetBasketPriceError::Leg { index, error: DogeGetBuyPriceError::BreachesReserveFloor { floor, reservable } },
+                InventoryLimitError::ExceedsCapacity { room } => DogeGetBasketPriceError::ExceedsCapacity { index, room },
+            })?;
+        }
+
+        Ok(prices)
+    }
+
+    pub fn buy_basket(&mut self, uuid: &Uuid, cash: &mut Good) -> Result<Vec<Good>, DogeBasketBuyError> {
+        self.service.do_basket_buy(uuid, cash).map_err(|err| match err {
+            ServiceBasketBuyError::UnrecognizedUuid => DogeBasketBuyError::UnrecognizedUuid,
+            ServiceBasketBuyError::InvalidState { current_state } => DogeBasketBuyError::InvalidState { current_state },
+            ServiceBasketBuyError::WrongGoodKind { pre_agreed } => DogeBasketBuyError::WrongGoodKind { pre_agreed },
+            ServiceBasketBuyError::InsufficientGoodQuantity { pre_agreed } => DogeBasketBuyError::InsufficientGoodQuantity { pre_agreed }
         })
     }
 
     pub fn buy(&mut self, uuid: &Uuid, cash: &mut Good) -> Result<Good, DogeBuyError> {
-        self.service.do_buy(uuid, cash).map_err(|err| match err {
+        self.pay_buy(uuid, cash, false)
+    }
+
+    /// Like [DogeMarketImpl::buy], but settles for part of the goods if `cash` falls short of the bid.
+    pub fn buy_partially(&mut self, uuid: &Uuid, cash: &mut Good) -> Result<Good, DogeBuyError> {
+        self.pay_buy(uuid, cash, true)
+    }
+
+    pub fn sell(&mut self, uuid: &Uuid, good: &mut Good) -> Result<Good, DogeSellError> {
+        self.pay_sell(uuid, good, false)
+    }
+
+    /// Like [DogeMarketImpl::sell], but settles for part of the offer if `good` falls short of the good to sell.
+    pub fn sell_partially(&mut self, uuid: &Uuid, good: &mut Good) -> Result<Good, DogeSellError> {
+        self.pay_sell(uuid, good, true)
+    }
+
+    fn pay_buy(&mut self, uuid: &Uuid, cash: &mut Good, partial: bool) -> Result<Good, DogeBuyError> {
+        let result = if partial { self.service.do_partial_buy(uuid, cash) } else { self.service.do_buy(uuid, cash) };
+        result.map_err(|err| match err {
+            ServiceBuyError::UnrecognizedUuid if self.auction.is_dropped(uuid) => DogeBuyError::DroppedByAuction,
             ServiceBuyError::UnrecognizedUuid => DogeBuyError::UnrecognizedUuid,
             ServiceBuyError::InvalidState { current_state } => DogeBuyError::InvalidState { current_state },
             ServiceBuyError::WrongGoodKind { pre_agreed } => DogeBuyError::WrongGoodKind { pre_agreed },
@@ -97,8 +808,10 @@ impl DogeMarketImpl {
         })
     }
 
-    pub fn sell(&mut self, uuid: &Uuid, good: &mut Good) -> Result<Good, DogeSellError> {
-        self.service.do_sell(uuid, good).map_err(|err| match err {
+    fn pay_sell(&mut self, uuid: &Uuid, good: &mut Good, partial: bool) -> Result<Good, DogeSellError> {
+        let result = if partial { self.service.do_partial_sell(uuid, good) } else { self.service.do_sell(uuid, good) };
+        result.map_err(|err| match err {
+            ServiceSellError::UnrecognizedUuid if self.auction.is_dropped(uuid) => DogeSellError::DroppedByAuction,
             ServiceSellError::UnrecognizedUuid => DogeSellError::UnrecognizedUuid,
             ServiceSellError::InvalidState { current_state } => DogeSellError::InvalidState { current_state },
             ServiceSellError::WrongGoodKind { pre_agreed } => DogeSellError::WrongGoodKind { pre_agreed },
@@ -106,50 +819,439 @@ impl DogeMarketImpl {
         })
     }
 
+    pub fn cancel_buy_lock(&mut self, uuid: &Uuid) -> Result<(), DogeCancelBuyError> {
+        self.service.do_cancel_buy(uuid).map_err(|err| match err {
+            ServiceCancelBuyError::UnrecognizedUuid => DogeCancelBuyError::UnrecognizedUuid,
+            ServiceCancelBuyError::InvalidState { current_state } => DogeCancelBuyError::InvalidState { current_state }
+        })
+    }
+
+    pub fn cancel_sell_lock(&mut self, uuid: &Uuid) -> Result<(), DogeCancelSellError> {
+        self.service.do_cancel_sell(uuid).map_err(|err| match err {
+            ServiceCancelSellError::UnrecognizedUuid => DogeCancelSellError::UnrecognizedUuid,
+            ServiceCancelSellError::InvalidState { current_state } => DogeCancelSellError::InvalidState { current_state }
+        })
+    }
+
+    /// Keeps a buy lock alive for `ticks` more days, taking the renewal fee for each of them out of `fee`.
+    pub fn renew_buy_lock(&mut self, uuid: &Uuid, ticks: u32, fee: &mut Good) -> Result<(), DogeRenewBuyError> {
+        if !(1..=self.config.lock_ttl).contains(&ticks) {
+            return Err(DogeRenewBuyError::InvalidTicks { max_ticks: self.config.lock_ttl });
+        }
+
+        let fee_quantity = self.config.renewal_fee * ticks as f32;
+        self.service.do_renew_buy(uuid, ticks, fee, DEFAULT_GOOD_KIND, fee_quantity, self.config.max_renewals).map_err(|err| match err {
+            ServiceRenewBuyError::UnrecognizedUuid => DogeRenewBuyError::UnrecognizedUuid,
+            ServiceRenewBuyError::InvalidState { current_state } => DogeRenewBuyError::InvalidState { current_state },
+            ServiceRenewBuyError::MaxRenewalsReached { max_renewals } => DogeRenewBuyError::MaxRenewalsReached { max_renewals },
+            ServiceRenewBuyError::WrongGoodKind { fee_kind } => DogeRenewBuyError::WrongGoodKind { fee_kind },
+            ServiceRenewBuyError::InsufficientGoodQuantity { fee } => DogeRenewBuyError::InsufficientGoodQuantity { fee }
+        })
+    }
+
+    /// Keeps a sell lock alive for `ticks` more days, taking the renewal fee for each of them out of `fee`.
+    pub fn renew_sell_lock(&mut self, uuid: &Uuid, ticks: u32, fee: &mut Good) -> Result<(), DogeRenewSellError> {
+        if !(1..=self.config.lock_ttl).contains(&ticks) {
+            return Err(DogeRenewSellError::InvalidTicks { max_ticks: self.config.lock_ttl });
+        }
+
+        let fee_quantity = self.config.renewal_fee * ticks as f32;
+        self.service.do_renew_sell(uuid, ticks, fee, DEFAULT_GOOD_KIND, fee_quantity, self.config.max_renewals).map_err(|err| match err {
+            ServiceRenewSellError::UnrecognizedUuid => DogeRenewSellError::UnrecognizedUuid,
+            ServiceRenewSellError::InvalidState { current_state } => DogeRenewSellError::InvalidState { current_state },
+            ServiceRenewSellError::MaxRenewalsReached { max_renewals } => DogeRenewSellError::MaxRenewalsReached { max_renewals },
+            ServiceRenewSellError::WrongGoodKind { fee_kind } => DogeRenewSellError::WrongGoodKind { fee_kind },
+            ServiceRenewSellError::InsufficientGoodQuantity { fee } => DogeRenewSellError::InsufficientGoodQuantity { fee }
+        })
+    }
+
+    /// Price, in the default good, of a forward trading `quantity` of `kind` in `days` days: the price of the same lock
+    /// today, raised for buys and lowered for sells by the carry of every day to maturity.
+    pub fn get_forward_price(&self, side: OrderSide, kind: GoodKind, quantity: f32, days: u32) -> Result<f32, DogeGetForwardPriceError> {
+        let settings = &self.config.forwards;
+        if !(1..=settings.max_days).contains(&days) {
+            return Err(DogeGetForwardPriceError::InvalidDays { max_days: settings.max_days });
+        }
+
+        let carry = settings.carry_percentage / 100. * days as f32;
+        match side {
+            OrderSide::Buy => self.get_buy_price(kind, quantity).map(|price| price * (1. + carry)).map_err(|err| match err {
+                DogeGetBuyPriceError::NonPositiveRequest => DogeGetForwardPriceError::NonPositiveRequest,
+                DogeGetBuyPriceError::ExceedsReservableQuantity { reservable } => DogeGetForwardPriceError::ExceedsReservableQuantity { reservable },
+                DogeGetBuyPriceError::BreachesReserveFloor { floor, reservable } => DogeGetForwardPriceError::BreachesReserveFloor { floor, reservable },
+            }),
+            OrderSide::Sell => self.get_sell_price(kind, quantity).map(|price| price * (1. - carry)).map_err(|err| match err {
+                DogeGetSellPriceError::NonPositiveRequest => DogeGetForwardPriceError::NonPositiveRequest,
+            }),
+        }
+    }
+
+    /// Margin of the default good that backs a forward at `price`.
+    pub fn get_forward_margin(&self, price: f32) -> f32 {
+        (price * self.config.forwards.margin_percentage / 100.).max(0.)
+    }
+
+    /// Agrees on a forward maturing in `days` days, backed by the margin of [DogeMarketImpl::get_forward_margin] that the
+    /// caller took from the trader. Buys have to pay at least the forward price and sells to ask for at most that.
+    pub fn agree_forward(&mut self, proposal: &ForwardTxProposal, days: u32) -> Result<Uuid, DogeForwardAgreementError> {
+        let forward_price = self.get_forward_price(proposal.side, proposal.good.get_kind(), proposal.good.get_qty(), days)
+            .map_err(|error| DogeForwardAgreementError::Price { error })?;
+        let price = proposal.price.get_qty();
+        if price <= 0. {
+            return Err(DogeForwardAgreementError::NonPositivePrice);
+        }
+
+        let (outgoing, incoming) = match proposal.side {
+            OrderSide::Buy if price < forward_price => return Err(DogeForwardAgreementError::PriceTooLow { lowest: forward_price }),
+            OrderSide::Sell if price > forward_price => return Err(DogeForwardAgreementError::PriceTooHigh { highest: forward_price }),
+            OrderSide::Buy => (&proposal.good, &proposal.price),
+            OrderSide::Sell => (&proposal.price, &proposal.good),
+        };
+        self.check_inventory_limits(outgoing, incoming).map_err(|err| match err {
+            InventoryLimitError::BreachesReserveFloor { floor, reservable } => DogeForwardAgreementError::BreachesReserveFloor { floor, reservable },
+            InventoryLimitError::ExceedsCapacity { room } => DogeForwardAgreementError::ExceedsCapacity { room },
+        })?;
+        self.check_reputation(self.get_trader_score(&proposal.trader_name), outgoing)
+            .map_err(|err| DogeForwardAgreementError::BadReputation { max_quantity: err.max_quantity })?;
+
+        self.service.do_forward_agreement(proposal, self.get_forward_margin(price), days, &mut self.rng).map_err(|err| match err {
+            ServiceForwardAgreementError::NonPositiveQuantity => DogeForwardAgreementError::Price { error: DogeGetForwardPriceError::NonPositiveRequest },
+            ServiceForwardAgreementError::NonPositivePrice => DogeForwardAgreementError::NonPositivePrice,
+            ServiceForwardAgreementError::ExceedsReservableQuantity { reservable } => DogeForwardAgreementError::ExceedsReservableQuantity { reservable },
+            ServiceForwardAgreementError::TooManyLocks => DogeForwardAgreementError::TooManyLocks,
+            ServiceForwardAgreementError::ExceedsTraderExposure { max_quantity } => DogeForwardAgreementError::ExceedsTraderExposure { max_quantity },
+        })
+    }
+
+    pub fn deliver_forward(&mut self, uuid: &Uuid, with: &mut Good) -> Result<(), DogeForwardDeliveryError> {
+        self.service.do_forward_delivery(uuid, with).map_err(|err| match err {
+            ServiceForwardDeliveryError::UnrecognizedUuid => DogeForwardDeliveryError::UnrecognizedUuid,
+            ServiceForwardDeliveryError::InvalidState { current_state } => DogeForwardDeliveryError::InvalidState { current_state },
+            ServiceForwardDeliveryError::AlreadyDelivered => DogeForwardDeliveryError::AlreadyDelivered,
+            ServiceForwardDeliveryError::WrongGoodKind { pre_agreed } => DogeForwardDeliveryError::WrongGoodKind { pre_agreed },
+            ServiceForwardDeliveryError::InsufficientGoodQuantity { pre_agreed } => DogeForwardDeliveryError::InsufficientGoodQuantity { pre_agreed },
+        })
+    }
+
+    pub fn claim_forward(&mut self, uuid: &Uuid) -> Result<Good, DogeForwardClaimError> {
+        self.service.do_forward_claim(uuid).map_err(|err| match err {
+            ServiceForwardClaimError::UnrecognizedUuid => DogeForwardClaimError::UnrecognizedUuid,
+            ServiceForwardClaimError::InvalidState { current_state } => DogeForwardClaimError::InvalidState { current_state },
+        })
+    }
+
+    pub fn get_forward(&self, uuid: &Uuid) -> Option<&ForwardTx> {
+        self.service.get_forward(uuid)
+    }
+
+    /// Collateral of `collateral_kind` worth the collateral percentage of `quantity` of `kind`, valued like
+    /// [DogeMarketImpl::get_default_good_value].
+    pub fn get_loan_collateral(&self, kind: GoodKind, quantity: f32, collateral_kind: GoodKind) -> f32 {
+        let value = self.get_default_good_value(&Good::new(kind, quantity)) * self.config.lending.collateral_percentage / 100.;
+        (value / self.get_default_good_value(&Good::new(collateral_kind, 1.))).max(0.)
+    }
+
+    /// Lends `quantity` of `kind` out of the goods of the market for `days` days, taking the collateral of
+    /// [DogeMarketImpl::get_loan_collateral] out of `collateral`. Returns the loan and the goods lent.
+    pub fn borrow(&mut self, kind: GoodKind, quantity: f32, days: u32, trader_name: String, collateral: &mut Good) -> Result<(Uuid, Good), DogeBorrowError> {
+        let settings = &self.config.lending;
+        if quantity <= 0. {
+            return Err(DogeBorrowError::NonPositiveQuantity);
+        }
+        if !(1..=settings.max_days).contains(&days) {
+            return Err(DogeBorrowError::InvalidDays { max_days: settings.max_days });
+        }
+        if collateral.get_kind() == kind {
+            return Err(DogeBorrowError::SameKindCollateral);
+        }
+
+        let required = self.get_loan_collateral(kind, quantity, collateral.get_kind());
+        if collateral.get_qty() < required {
+            return Err(DogeBorrowError::InsufficientCollateral { required });
+        }
+
+        let principal = Good::new(kind, quantity);
+        let ops = self.service.get_account_ops();
+        if Quantity::from_f32(quantity) > ops.assets.get(kind) {
+            return Err(DogeBorrowError::ExceedsReservableQuantity { reservable: ops.get_reservable_quantity_by_kind(kind) });
+        }
+        // the collateral is not the market's until the loan is liquidated, so it does not count towards the capacity
+        self.check_reserve_floor(&principal)
+            .map_err(|err| DogeBorrowError::BreachesReserveFloor { floor: err.floor, reservable: err.reservable })?;
+        self.check_reputation(self.get_trader_score(&trader_name), &principal)
+            .map_err(|err| DogeBorrowError::BadReputation { max_quantity: err.max_quantity })?;
+
+        self.service.get_account_ops_mut().assets.withdraw(kind, Quantity::from_f32(quantity)).unwrap();
+        let loan = Loan {
+            principal: principal.clone(),
+            collateral: collateral.split(required).unwrap(),
+            daily_interest_percentage: self.config.lending.daily_interest_percentage,
+            days,
+            days_passed: 0,
+            trader_name,
+            state: LoanState::Open,
+        };
+        Ok((self.lending_desk.lend(loan, &mut self.rng), principal))
+    }
+
+    /// Takes the debt of an open loan out of `with`, giving back the collateral.
+    pub fn repay_loan(&mut self, uuid: &Uuid, with: &mut Good) -> Result<Good, DogeRepayLoanError> {
+        let loan = self.lending_desk.get(uuid).ok_or(DogeRepayLoanError::UnrecognizedUuid)?;
+        let (kind, debt) = (loan.principal.get_kind(), loan.get_debt());
+        if loan.state != LoanState::Open {
+            return Err(DogeRepayLoanError::InvalidState { current_state: loan.state.clone() });
+        }
+        if with.get_kind() != kind {
+            return Err(DogeRepayLoanError::WrongGoodKind { pre_agreed: kind });
+        }
+        if with.get_qty() < debt {
+            return Err(DogeRepayLoanError::InsufficientGoodQuantity { debt });
+        }
+
+        let payment = with.split(debt).unwrap();
+        self.service.get_account_ops_mut().assets.deposit(kind, Quantity::from_f32(payment.get_qty()));
+        Ok(self.lending_desk.repay(uuid).unwrap())
+    }
+
+    pub fn get_loan(&self, uuid: &Uuid) -> Option<&Loan> {
+        self.lending_desk.get(uuid)
+    }
+
+    /// Accrues a day of interest on the loans, then keeps the collateral of the ones that reached their term and of the
+    /// ones whose collateral is worth less than the liquidation percentage of their debt.
+    fn settle_loans(&mut self) {
+        let mut kept = self.lending_desk.tick();
+
+        let liquidation_percentage = self.config.lending.liquidation_percentage;
+        for uuid in self.lending_desk.get_open_loans() {
+            let loan = self.lending_desk.get(&uuid).unwrap();
+            let debt_value = self.get_default_good_value(&Good::new(loan.principal.get_kind(), loan.get_debt()));
+            if self.get_default_good_value(&loan.collateral) < debt_value * liquidation_percentage / 100. {
+                kept.push(self.lending_desk.liquidate(&uuid).unwrap());
+            }
+        }
+
+        let ops = self.service.get_account_ops_mut();
+        for collateral in kept {
+            ops.assets.deposit(collateral.get_kind(), Quantity::from_f32(collateral.get_qty()));
+        }
+    }
+
     pub fn get_tx_service(&self) -> &TxService {
         &self.service
     }
 
-    pub fn advance_a_day(&mut self) {
+    /// Clears the locks queued for the batch auction, expires locks and settles the forwards that matured, refills the
+    /// goods, or queues the refill for the refill backend, settles the loans and fills the limit orders that match the
+    /// new prices, before expiring the ones that reached their deadline.
+    pub fn advance_a_day(&mut self) -> DayOutcome {
+        let cleared_locks = self.clear_auction();
+
         self.service.tick_all();
         let ops = self.service.get_account_ops_mut();
-        self.refiller.refill_goods(&mut ops.assets, &ops.reservations);
+        if let Some(refill) = self.refill_policy.plan_refill(&ops.assets, &ops.reservations, &mut self.rng) {
+            match &self.refill_backend {
+                // the suppliers may notify this market, so the trades wait until it is no longer borrowed
+                Some(_) => self.queued_refill = Some(refill),
+                None => refill.apply(&mut ops.assets),
+            }
+        }
+        self.settle_loans();
+
+        let filled_orders = self.match_orders();
+        self.order_book.tick();
+        DayOutcome { cleared_locks, filled_orders }
+    }
+
+    /// Leaves an order to buy `quantity` of `kind` for at most `limit` of the default good, open for `ttl` days. The
+    /// limit is taken out of `cash` until the order is filled, expires or is cancelled.
+    pub fn place_buy_order(&mut self, kind: GoodKind, quantity: f32, limit: f32, ttl: u32, trader_name: String, cash: &mut Good) -> Result<Uuid, DogePlaceOrderError> {
+        self.check_order(quantity, limit, ttl, cash, DEFAULT_GOOD_KIND, limit)?;
+
+        let escrow = cash.split(limit).unwrap();
+        let order = LimitOrder { side: OrderSide::Buy, kind, quantity, limit, trader_name, state: LimitOrderState::Open, escrow, proceeds: Good::new(kind, 0.) };
+        Ok(self.order_book.place(order, ttl, &mut self.rng))
+    }
+
+    /// Leaves an order to sell `quantity` of `kind` for at least `limit` of the default good, open for `ttl` days. The
+    /// goods are taken out of `good` until the order is filled, expires or is cancelled.
+    pub fn place_sell_order(&mut self, kind: GoodKind, quantity: f32, limit: f32, ttl: u32, trader_name: String, good: &mut Good) -> Result<Uuid, DogePlaceOrderError> {
+        self.check_order(quantity, limit, ttl, good, kind, quantity)?;
+
+        let escrow = good.split(quantity).unwrap();
+        let order = LimitOrder { side: OrderSide::Sell, kind, quantity, limit, trader_name, state: LimitOrderState::Open, escrow, proceeds: Good::new(DEFAULT_GOOD_KIND, 0.) };
+        Ok(self.order_book.place(order, ttl, &mut self.rng))
+    }
+
+    fn check_order(&self, quantity: f32, limit: f32, ttl: u32, escrow: &Good, escrow_kind: GoodKind, required: f32) -> Result<(), DogePlaceOrderError> {
+        if quantity <= 0. {
+            Err(DogePlaceOrderError::NonPositiveQuantity)
+        } else if limit <= 0. {
+            Err(DogePlaceOrderError::NonPositiveLimit)
+        } else if ttl == 0 {
+            Err(DogePlaceOrderError::NonPositiveTtl)
+        } else if escrow.get_kind() != escrow_kind || escrow.get_qty() < required {
+            Err(DogePlaceOrderError::InsufficientEscrow { kind: escrow_kind, required })
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn get_order(&self, uuid: &Uuid) -> Option<&LimitOrder> {
+        self.order_book.get(uuid)
+    }
+
+    /// Closes an open order, giving back its escrow.
+    pub fn cancel_order(&mut self, uuid: &Uuid) -> Result<Good, DogeOrderError> {
+        self.order_book.cancel(uuid).map_err(|err| match err {
+            OrderBookError::UnrecognizedUuid => DogeOrderError::UnrecognizedUuid,
+            OrderBookError::InvalidState { current_state } => DogeOrderError::InvalidState { current_state },
+        })
+    }
+
+    /// Gives back the proceeds of a filled order together with the change, or the escrow of an expired order.
+    pub fn claim_order(&mut self, uuid: &Uuid) -> Result<Vec<Good>, DogeOrderError> {
+        self.order_book.claim(uuid).map_err(|err| match err {
+            OrderBookError::UnrecognizedUuid => DogeOrderError::UnrecognizedUuid,
+            OrderBookError::InvalidState { current_state } => DogeOrderError::InvalidState { current_state },
+        })
+    }
+
+    /// Fills every open order whose limit the current price reaches, the ones expiring first first, by locking and
+    /// paying at the market price out of its escrow. The price is the one the trader would lock at, its reputation
+    /// penalty included. Orders are backed by their escrow, so they take no deposit, and the locks filling them are
+    /// paid on the spot, so they leave the reputation of the trader untouched. Returns the orders filled.
+    pub fn match_orders(&mut self) -> Vec<Uuid> {
+        let mut filled = vec![];
+
+        for uuid in self.order_book.get_open_orders() {
+            let order = self.order_book.get(&uuid).unwrap();
+            let (kind, quantity, limit, trader_name) = (order.kind, order.quantity, order.limit, order.trader_name.clone());
+            let score = self.get_trader_score(&trader_name);
+            let ops = self.service.get_account_ops();
+
+            let proceeds = match order.side {
+                OrderSide::Buy => {
+                    let earn_percentage = self.widen_earn_percentage(self.get_buy_earn_percentage(kind), score);
+                    let price = match ops.compute_buy_price(self.pricing_curve.as_ref(), kind, quantity, earn_percentage) {
+                        Ok(price) if price <= limit => price,
+                        _ => continue,
+                    };
+                    let proposal = BuyTxProposal { buy: Good::new(kind, quantity), bid: Good::new(DEFAULT_GOOD_KIND, price), trader_name };
+                    let tx = if let Ok(tx) = self.reserve_buy(&proposal, 0.) { tx } else { continue; };
+
+                    let mut payment = self.order_book.get_mut(&uuid).unwrap().escrow.split(price).unwrap();
+                    self.service.do_order_buy(&tx, &mut payment).unwrap()
+                }
+                OrderSide::Sell => {
+                    let earn_percentage = self.widen_earn_percentage(self.get_sell_earn_percentage(kind), score);
+                    let price = match ops.compute_sell_price(self.pricing_curve.as_ref(), kind, quantity, earn_percentage) {
+                        Ok(price) if price >= limit => price,
+                        _ => continue,
+                    };
+                    let proposal = SellTxProposal { sell: Good::new(kind, quantity), offer: Good::new(DEFAULT_GOOD_KIND, price), trader_name };
+                    let tx = if let Ok(tx) = self.reserve_sell(&proposal, 0.) { tx } else { continue; };
+
+                    let mut goods = self.order_book.get_mut(&uuid).unwrap().escrow.split(quantity).unwrap();
+                    self.service.do_order_sell(&tx, &mut goods).unwrap()
+                }
+            };
+
+            self.order_book.get_mut(&uuid).unwrap().fill(proceeds);
+            filled.push(uuid);
+        }
+
+        filled
     }
 
     pub fn get_buy_price(&self, of_kind: GoodKind, of_quantity: f32) -> Result<f32, DogeGetBuyPriceError> {
+        self.check_reserve_floor(&Good::new(of_kind, of_quantity))
+            .map_err(|err| DogeGetBuyPriceError::BreachesReserveFloor { floor: err.floor, reservable: err.reservable })?;
+
         self.get_tx_service()
             .get_account_ops()
-            .compute_buy_price(of_kind, of_quantity, if let DEFAULT_GOOD_KIND = of_kind { 0. } else { 1. })
+            .compute_buy_price(self.pricing_curve.as_ref(), of_kind, of_quantity, self.get_buy_earn_percentage(of_kind))
             .map_err(|err| match err {
                 BuyPriceComputationError::NonPositiveQuantity =>
                     DogeGetBuyPriceError::NonPositiveRequest,
-                BuyPriceComputationError::NegativeExchangeRateEarnPercentage => unreachable!(),
+                BuyPriceComputationError::NegativeExchangeRateEarnPercentage | BuyPriceComputationError::SameKind => unreachable!(),
                 BuyPriceComputationError::ExceedsReservableQuantity { reservable } =>
                     DogeGetBuyPriceError::ExceedsReservableQuantity { reservable }
             })
     }
 
+    /// Price, in `in_kind`, of buying `of_quantity` of `of_kind` in a single lock, see
+    /// [AccountOps::compute_cross_buy_price]. Such a lock is reserved by passing a bid of `in_kind` to
+    /// [DogeMarketImpl::do_buy_reservation] and paid by passing `in_kind` to [DogeMarketImpl::buy], so the price is
+    /// refused for the same inventory limits as the lock.
+    pub fn get_cross_buy_price(&self, of_kind: GoodKind, of_quantity: f32, in_kind: GoodKind) -> Result<f32, DogeGetCrossPriceError> {
+        self.check_reserve_floor(&Good::new(of_kind, of_quantity))
+            .map_err(|err| DogeGetCrossPriceError::BreachesReserveFloor { floor: err.floor, reservable: err.reservable })?;
+
+        let price = self.get_tx_service()
+            .get_account_ops()
+            .compute_cross_buy_price(self.pricing_curve.as_ref(), of_kind, of_quantity, in_kind, self.get_buy_earn_percentage(of_kind))
+            .map_err(|err| match err {
+                BuyPriceComputationError::NonPositiveQuantity => DogeGetCrossPriceError::NonPositiveRequest,
+                BuyPriceComputationError::NegativeExchangeRateEarnPercentage => unreachable!(),
+                BuyPriceComputationError::ExceedsReservableQuantity { reservable } => DogeGetCrossPriceError::ExceedsReservableQuantity { reservable },
+                BuyPriceComputationError::SameKind => DogeGetCrossPriceError::SameKind,
+            })?;
+
+        self.check_cross_limits(&Good::new(of_kind, of_quantity), &Good::new(in_kind, price))?;
+        Ok(price)
+    }
+
+    /// Price, in `in_kind`, of selling `of_quantity` of `of_kind` in a single lock, see
+    /// [AccountOps::compute_cross_sell_price]. Such a lock is reserved by passing an offer of `in_kind` to
+    /// [DogeMarketImpl::do_sell_reservation], so the price is refused for the same inventory limits as the lock.
+    pub fn get_cross_sell_price(&self, of_kind: GoodKind, of_quantity: f32, in_kind: GoodKind) -> Result<f32, DogeGetCrossPriceError> {
+        let price = self.get_tx_service()
+            .get_account_ops()
+            .compute_cross_sell_price(self.pricing_curve.as_ref(), of_kind, of_quantity, in_kind, self.get_sell_earn_percentage(of_kind))
+            .map_err(|err| match err {
+                SellPriceComputationError::NonPositiveQuantity => DogeGetCrossPriceError::NonPositiveRequest,
+                SellPriceComputationError::NegativeExchangeEarnRatePercentage => unreachable!(),
+                SellPriceComputationError::SameKind => DogeGetCrossPriceError::SameKind,
+            })?;
+
+        self.check_cross_limits(&Good::new(in_kind, price), &Good::new(of_kind, of_quantity))?;
+        Ok(price)
+    }
+
+    fn check_cross_limits(&self, outgoing: &Good, incoming: &Good) -> Result<(), DogeGetCrossPriceError> {
+        let ops = self.get_tx_service().get_account_ops();
+        if Quantity::from_f32(outgoing.get_qty()) > ops.assets.get(outgoing.get_kind()) {
+            return Err(DogeGetCrossPriceError::ExceedsReservableQuantity { reservable: ops.get_reservable_quantity_by_kind(outgoing.get_kind()) });
+        }
+
+        self.check_inventory_limits(outgoing, incoming).map_err(|err| match err {
+            InventoryLimitError::BreachesReserveFloor { floor, reservable } => DogeGetCrossPriceError::BreachesReserveFloor { floor, reservable },
+            InventoryLimitError::ExceedsCapacity { room } => DogeGetCrossPriceError::ExceedsCapacity { room },
+        })
+    }
+
     pub fn get_sell_price(&self, of_kind: GoodKind, of_quantity: f32) -> Result<f32, DogeGetSellPriceError> {
         self.get_tx_service()
             .get_account_ops()
-            .compute_sell_price(of_kind, of_quantity, if let DEFAULT_GOOD_KIND = of_kind { 0. } else { 1. })
+            .compute_sell_price(self.pricing_curve.as_ref(), of_kind, of_quantity, self.get_sell_earn_percentage(of_kind))
             .map_err(|err| match err {
                 SellPriceComputationError::NonPositiveQuantity =>
                     DogeGetSellPriceError::NonPositiveRequest,
-                SellPriceComputationError::NegativeExchangeEarnRatePercentage => unreachable!()
+                SellPriceComputationError::NegativeExchangeEarnRatePercentage | SellPriceComputationError::SameKind => unreachable!()
             })
     }
 
     pub fn make_label_for_kind(&self, kind: GoodKind) -> GoodLabel {
         let ops = self.get_tx_service().get_account_ops();
 
-        let exchange_rate_buy = ops.compute_buy_exchange_rate(kind, if let DEFAULT_GOOD_KIND = kind { 0. } else { 1. })
+        let exchange_rate_buy = ops.compute_buy_exchange_rate(self.pricing_curve.as_ref(), kind, self.get_buy_earn_percentage(kind))
             .map_or_else(|err| match err {
                 BuyExchangeRateComputationError::NonPositiveExchangeRateEarnPercentage => unreachable!(),
                 BuyExchangeRateComputationError::ExceedsReservableQuantity { .. } => f32::MAX
             }, &identity);
 
-        let exchange_rate_sell = ops.compute_sell_exchange_rate(kind, if let DEFAULT_GOOD_KIND = kind { 0. } else { 1. })
+        let exchange_rate_sell = ops.compute_sell_exchange_rate(self.pricing_curve.as_ref(), kind, self.get_sell_earn_percentage(kind))
             .map_or_else(|err| match err {
                 SellExchangeRateComputationError::NonPositiveExchangeRateEarnPercentage => unreachable!()
             }, &identity);
@@ -158,12 +1260,56 @@ impl DogeMarketImpl {
     }
 }
 
+/// Goods the market gives and takes in for every leg of a basket, together with the legs before it: the good bought by
+/// the leg and the good of its bid.
+fn basket_totals(legs: &[BasketLeg]) -> Vec<(Good, Good)> {
+    let mut outgoing = Ledger::new_empty();
+    let mut incoming = Ledger::new_empty();
+    legs.iter().map(|leg| {
+        outgoing.deposit(leg.buy.get_kind(), Quantity::from_f32(leg.buy.get_qty()));
+        incoming.deposit(leg.bid.get_kind(), Quantity::from_f32(leg.bid.get_qty()));
+        (Good::new(leg.buy.get_kind(), outgoing.get_quantity_by_kind(leg.buy.get_kind())), Good::new(leg.bid.get_kind(), incoming.get_quantity_by_kind(leg.bid.get_kind())))
+    }).collect()
+}
+
+fn buy_reservation_error(err: ServiceBuyReservationError) -> DogeBuyReservationError {
+    match err {
+        ServiceBuyReservationError::NonPositiveBuy => DogeBuyReservationError::NonPositiveBuy,
+        ServiceBuyReservationError::NonPositiveBid => DogeBuyReservationError::NonPositiveBid,
+        ServiceBuyReservationError::ExceedsReservableQuantity { reservable } => DogeBuyReservationError::ExceedsReservableQuantity { reservable },
+        ServiceBuyReservationError::BidTooLow { lowest } => DogeBuyReservationError::BidTooLow { lowest },
+        ServiceBuyReservationError::UnsupportedBid => DogeBuyReservationError::UnsupportedBid,
+        ServiceBuyReservationError::TooManyLocks => DogeBuyReservationError::TooManyLocks,
+        ServiceBuyReservationError::ExceedsTraderExposure { max_quantity } => DogeBuyReservationError::ExceedsTraderExposure { max_quantity },
+    }
+}
+
+fn sell_reservation_error(err: ServiceSellReservationError) -> DogeSellReservationError {
+    match err {
+        ServiceSellReservationError::NonPositiveSell => DogeSellReservationError::NonPositiveSell,
+        ServiceSellReservationError::NonPositiveOffer => DogeSellReservationError::NonPositiveOffer,
+        ServiceSellReservationError::ExceedsReservableQuantity { reservable } => DogeSellReservationError::ExceedsReservableQuantity { reservable },
+        ServiceSellReservationError::OfferTooHigh { highest } => DogeSellReservationError::OfferTooHigh { highest },
+        ServiceSellReservationError::UnsupportedOffer => DogeSellReservationError::UnsupportedOffer,
+        ServiceSellReservationError::TooManyLocks => DogeSellReservationError::TooManyLocks,
+        ServiceSellReservationError::ExceedsTraderExposure { max_quantity } => DogeSellReservationError::ExceedsTraderExposure { max_quantity },
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use unitn_market_2022::good::good_kind::GoodKind::{EUR, USD, YEN, YUAN};
+    use assert_approx_eq::assert_approx_eq;
 
     use doge_common::account::Account;
 
+    use crate::market_config::LockTtlTier;
+    use crate::per_good::PerGood;
+    use crate::pricing_curve::PricingCurveKind;
+    use crate::refill_policy::RefillPolicyKind;
+    use crate::refiller::RefillerSettings;
+    use crate::reputation::{ReputationSettings, TraderRecord};
+    use crate::test_fixtures::uniform_account;
+
     use super::*;
 
     //checks what happens when a good contains max quantity and we add to it
@@ -177,46 +1323,459 @@ mod tests {
             yuan: Good::new(YUAN, 500_000.),
         };
 
-        let mut market = DogeMarketImpl::new(AccountOps::of_assets(assets), 10);
+        let mut market = DogeMarketImpl::new(AccountOps::of_assets(assets), MarketConfig::default()).unwrap();
 
         let buy_price = market.get_buy_price(USD, 50.).unwrap();
 
         let proposal = BuyTxProposal {
             buy: Good::new(USD, 50.),
             bid: Good::new(EUR, buy_price),
+            trader_name: "trader".to_string(),
         };
 
-        let uuid = market.do_buy_reservation(&proposal).unwrap();
-
-        market.buy(&uuid, &mut Good::new(EUR, buy_price)).unwrap();
-
-        assert_eq!(market.make_label_for_kind(EUR).quantity, f32::INFINITY);
+        // even without a capacity, the market refuses to own more EUR than an f32 can hold
+        assert!(matches!(market.do_buy_reservation(&proposal, 0.), Err(DogeBuyReservationError::ExceedsCapacity { room }) if room == 0.));
+        assert_eq!(market.make_label_for_kind(EUR).quantity, f32::MAX);
     }
 
     //checks what happens when we try to buy all quantity of a good:
-    //result: buy price is inf, if we try to lock buy with inf the program crashes
+    //result: the whole stock has no finite price, so it can be neither priced nor locked, and the market stays usable
     #[test]
     fn test_buy_everything() {
+        let assets = uniform_account(500_000.);
+
+        let mut market = DogeMarketImpl::new(AccountOps::of_assets(assets), MarketConfig::default()).unwrap();
+
+        assert!(matches!(market.get_buy_price(YEN, 500_000.), Err(DogeGetBuyPriceError::ExceedsReservableQuantity { reservable }) if reservable == 500_000.));
+
+        let proposal = BuyTxProposal {
+            buy: Good::new(YEN, 500_000.),
+            bid: Good::new(EUR, 1e30),
+            trader_name: "trader".to_string(),
+        };
+        assert!(matches!(market.do_buy_reservation(&proposal, 0.), Err(DogeBuyReservationError::ExceedsReservableQuantity { .. })));
+
+        assert!(market.get_buy_price(YEN, 499_999.).unwrap().is_finite());
+        assert_eq!(market.make_label_for_kind(EUR).quantity, 500_000.);
+    }
+
+    #[test]
+    fn test_inventory_limits() {
+        let assets = uniform_account(500_000.);
+
+        let config = MarketConfig {
+            reserve_floors: PerGood::new(400_000., 100_000., 0., 0.),
+            capacities: PerGood::new(Some(600_000.), None, Some(550_000.), None),
+            ..MarketConfig::default()
+        };
+        let mut market = DogeMarketImpl::new(AccountOps::of_assets(assets), config).unwrap();
+
+        let proposal = BuyTxProposal { buy: Good::new(USD, 400_001.), bid: Good::new(EUR, f32::MAX), trader_name: "trader".to_string() };
+        assert!(matches!(market.do_buy_reservation(&proposal, 0.), Err(DogeBuyReservationError::BreachesReserveFloor { floor, .. }) if floor == 100_000.));
+
+        let proposal = BuyTxProposal { buy: Good::new(USD, 1_000.), bid: Good::new(EUR, 100_001.), trader_name: "trader".to_string() };
+        assert!(matches!(market.do_buy_reservation(&proposal, 0.), Err(DogeBuyReservationError::ExceedsCapacity { room }) if room == 100_000.));
+
+        let proposal = SellTxProposal { sell: Good::new(YEN, 50_001.), offer: Good::new(EUR, 1.), trader_name: "trader".to_string() };
+        assert!(matches!(market.do_sell_reservation(&proposal, 0.), Err(DogeSellReservationError::ExceedsCapacity { room }) if room == 50_000.));
+
+        let proposal = SellTxProposal { sell: Good::new(YUAN, 200_000.), offer: Good::new(EUR, 100_001.), trader_name: "trader".to_string() };
+        assert!(matches!(market.do_sell_reservation(&proposal, 0.), Err(DogeSellReservationError::BreachesReserveFloor { floor, .. }) if floor == 400_000.));
+
+        // cross prices are refused like the locks they quote
+        assert!(matches!(market.get_cross_buy_price(USD, 100_000., YEN), Err(DogeGetCrossPriceError::ExceedsCapacity { room }) if room == 50_000.));
+        assert!(matches!(market.get_cross_sell_price(YEN, 50_001., USD), Err(DogeGetCrossPriceError::ExceedsCapacity { room }) if room == 50_000.));
+        assert!(matches!(market.get_cross_buy_price(USD, 400_001., YUAN), Err(DogeGetCrossPriceError::BreachesReserveFloor { floor, .. }) if floor == 100_000.));
+
+        let buy_price = market.get_buy_price(USD, 1_000.).unwrap();
+        market.do_buy_reservation(&BuyTxProposal { buy: Good::new(USD, 1_000.), bid: Good::new(EUR, buy_price), trader_name: "trader".to_string() }, 0.).unwrap();
+    }
+
+    #[test]
+    fn test_snapshot_restore() {
+        let assets = uniform_account(500_000.);
+
+        let mut market = DogeMarketImpl::new(AccountOps::of_assets(assets), MarketConfig { lock_ttl: 3, ..MarketConfig::default() }).unwrap();
+
+        let buy_price = market.get_buy_price(USD, 1_000.).unwrap();
+        let buy_uuid = market.do_buy_reservation(&BuyTxProposal { buy: Good::new(USD, 1_000.), bid: Good::new(EUR, buy_price), trader_name: "trader".to_string() }, 0.).unwrap();
+        market.advance_a_day();
+
+        let sell_price = market.get_sell_price(YEN, 2_000.).unwrap();
+        let sell_uuid = market.do_sell_reservation(&SellTxProposal { sell: Good::new(YEN, 2_000.), offer: Good::new(EUR, sell_price), trader_name: "trader".to_string() }, 0.).unwrap();
+        market.advance_a_day();
+
+        let json = market.snapshot().to_json().unwrap();
+        let mut restored = DogeMarketImpl::restore(MarketSnapshot::from_json(&json).unwrap()).unwrap();
+
+        for kind in [EUR, USD, YEN, YUAN] {
+            assert_eq!(restored.make_label_for_kind(kind).quantity, market.make_label_for_kind(kind).quantity);
+            assert_eq!(restored.get_buy_price(kind, 10.).unwrap(), market.get_buy_price(kind, 10.).unwrap());
+        }
+
+        // the buy lock has one day left, the sell lock two
+        restored.advance_a_day();
+        assert!(matches!(restored.buy(&buy_uuid, &mut Good::new(EUR, buy_price)), Err(DogeBuyError::InvalidState { current_state: BuyTxState::Expired })));
+
+        let sold = restored.sell(&sell_uuid, &mut Good::new(YEN, 2_000.)).unwrap();
+        assert_eq!(sold.get_qty(), sell_price);
+    }
+
+    #[test]
+    fn test_pricing_curve() {
+        let assets = uniform_account(500_000.);
+
+        let config = MarketConfig { pricing_curve: PricingCurveKind::ConstantProduct, ..MarketConfig::default() };
+        let mut market = DogeMarketImpl::new(AccountOps::of_assets(assets), config).unwrap();
+
+        // 1% spread over the x*y=k rate
+        let buy_price = market.get_buy_price(USD, 100_000.).unwrap();
+        assert_eq!(buy_price, 100_000. * 500_000. / 400_000. * 1.01);
+        assert_eq!(market.make_label_for_kind(USD).exchange_rate_buy, market.get_buy_price(USD, 1.).unwrap());
+
+        let proposal = BuyTxProposal { buy: Good::new(USD, 100_000.), bid: Good::new(EUR, buy_price * 0.99), trader_name: "trader".to_string() };
+        assert!(matches!(market.do_buy_reservation(&proposal, 0.), Err(DogeBuyReservationError::BidTooLow { lowest }) if lowest == buy_price));
+    }
+
+    #[test]
+    fn test_seeded_runs() {
         let assets = Account {
             eur: Good::new(EUR, 500_000.),
-            usd: Good::new(USD, 500_000.),
-            yen: Good::new(YEN, 500_000.),
-            yuan: Good::new(YUAN, 500_000.),
+            usd: Good::new(USD, 1_000.),
+            yen: Good::new(YEN, 250_000. * YEN.get_default_exchange_rate()),
+            yuan: Good::new(YUAN, 1_000.),
         };
+        let refiller = RefillerSettings { min_days_as_importer: 1, min_days_as_exporter: 1, min_days_in_shortage: 1, shortage_probability_percent: 50, ..RefillerSettings::default() };
+        let config = MarketConfig { refiller, seed: Some(3), ..MarketConfig::default() };
 
-        let mut market = DogeMarketImpl::new(AccountOps::of_assets(assets), 10);
+        let mut market = DogeMarketImpl::new(AccountOps::of_assets(assets.clone()), config.clone()).unwrap();
+        let mut twin = DogeMarketImpl::new(AccountOps::of_assets(assets), config).unwrap();
+        for _ in 0..20 {
+            market.advance_a_day();
+            twin.advance_a_day();
+            assert_eq!(market.get_tx_service().get_account_ops().assets, twin.get_tx_service().get_account_ops().assets);
+        }
+        // tokens are drawn from the seed too
+        let proposal = BuyTxProposal { buy: Good::new(USD, 10.), bid: Good::new(EUR, 1_000.), trader_name: "trader".to_string() };
+        assert_eq!(market.do_buy_reservation(&proposal, 0.).unwrap(), twin.do_buy_reservation(&proposal, 0.).unwrap());
+        let mut restored = DogeMarketImpl::restore(market.snapshot()).unwrap();
+        assert_eq!(restored.get_seed(), 3);
 
-        let buy_price = market.get_buy_price(YEN, 500_000.).unwrap();
+        // half of the refills turn into shortages, so the goods part ways at the first different draw
+        for _ in 0..20 {
+            market.advance_a_day();
+            twin.advance_a_day();
+            restored.advance_a_day();
 
-        let proposal = BuyTxProposal {
-            buy: Good::new(YEN, 500_000.),
-            bid: Good::new(EUR, buy_price),
+            let assets = &market.get_tx_service().get_account_ops().assets;
+            assert_eq!(assets, &twin.get_tx_service().get_account_ops().assets);
+            assert_eq!(assets, &restored.get_tx_service().get_account_ops().assets);
+        }
+    }
+
+    #[test]
+    fn test_refill_policy() {
+        let assets = Account {
+            eur: Good::new(EUR, 1_000_000.),
+            usd: Good::new(USD, 0.),
+            yen: Good::new(YEN, 0.),
+            yuan: Good::new(YUAN, 0.),
         };
 
-        let uuid = market.do_buy_reservation(&proposal).unwrap();
+        let mut market = DogeMarketImpl::new(AccountOps::of_assets(assets.clone()), MarketConfig { refill_policy: RefillPolicyKind::NoRefill, ..MarketConfig::default() }).unwrap();
+        market.advance_a_day();
+        assert_eq!(market.get_tx_service().get_account_ops().assets.get_quantity_by_kind(EUR), 1_000_000.);
+
+        let refill_policy = RefillPolicyKind::TargetWeights { weights: PerGood::new(1., 1., 1., 1.), tolerance_percentage: 5. };
+        let mut market = DogeMarketImpl::new(AccountOps::of_assets(assets), MarketConfig { refill_policy, ..MarketConfig::default() }).unwrap();
+        market.advance_a_day();
+
+        // a quarter of the value goes to yuan, less the 25% import tax
+        let ops = market.get_tx_service().get_account_ops();
+        assert_approx_eq!(ops.assets.get_quantity_by_kind(EUR), 1_000_000. - 250_000. / 0.75, 1.);
+        assert_approx_eq!(ops.assets.get_quantity_by_kind(YUAN), 250_000. * YUAN.get_default_exchange_rate(), 10.);
+
+        // the policy comes back with the configuration of the snapshot
+        let mut market = DogeMarketImpl::restore(market.snapshot()).unwrap();
+        market.advance_a_day();
+        assert!(market.get_tx_service().get_account_ops().assets.get_quantity_by_kind(YEN) > 0.);
+    }
 
+    #[test]
+    fn test_conservation() {
+        let assets = uniform_account(500_000.);
+
+        let mut market = DogeMarketImpl::new(AccountOps::of_assets(assets), MarketConfig { lock_ttl: 1, ..MarketConfig::default() }).unwrap();
+        let initial = market.get_tx_service().get_account_ops().assets.clone();
+
+        // every lock expires without being paid, so the market must end up with exactly what it started with
+        for i in 1..200 {
+            let quantity = i as f32 * 0.37;
+            let buy_price = market.get_buy_price(USD, quantity).unwrap();
+            market.do_buy_reservation(&BuyTxProposal { buy: Good::new(USD, quantity), bid: Good::new(EUR, buy_price * 1.3), trader_name: "trader".to_string() }, 0.).unwrap();
+            let sell_price = market.get_sell_price(YEN, quantity).unwrap();
+            market.do_sell_reservation(&SellTxProposal { sell: Good::new(YEN, quantity), offer: Good::new(EUR, sell_price / 1.3), trader_name: "trader".to_string() }, 0.).unwrap();
+            market.service.tick_all();
+        }
+        market.service.tick_all();
+
+        let ops = market.get_tx_service().get_account_ops();
+        assert_eq!(ops.assets, initial);
+        assert_eq!(ops.reservations, Ledger::new_empty());
+        assert_eq!(ops.futures, Ledger::new_empty());
+
+        // paid locks move exactly the agreed quantities
+        let buy_price = market.get_buy_price(USD, 0.1).unwrap();
+        let uuid = market.do_buy_reservation(&BuyTxProposal { buy: Good::new(USD, 0.1), bid: Good::new(EUR, buy_price), trader_name: "trader".to_string() }, 0.).unwrap();
         market.buy(&uuid, &mut Good::new(EUR, buy_price)).unwrap();
 
-        market.get_buy_price(USD, 1.).unwrap(); // this fails
+        let ops = market.get_tx_service().get_account_ops();
+        assert_eq!(ops.assets.get(USD), initial.get(USD) - Quantity::from_f32(0.1));
+        assert_eq!(ops.assets.get(EUR), initial.get(EUR) + Quantity::from_f32(buy_price));
+        assert_eq!(ops.futures, Ledger::new_empty());
+    }
+
+    #[test]
+    fn test_cancel() {
+        let assets = uniform_account(500_000.);
+
+        let mut market = DogeMarketImpl::new(AccountOps::of_assets(assets), MarketConfig::default()).unwrap();
+        let initial = market.get_tx_service().get_account_ops().clone();
+
+        let buy_price = market.get_buy_price(USD, 1_000.).unwrap();
+        let buy = market.do_buy_reservation(&BuyTxProposal { buy: Good::new(USD, 1_000.), bid: Good::new(EUR, buy_price), trader_name: "trader".to_string() }, 0.).unwrap();
+        let sell_price = market.get_sell_price(YEN, 1_000.).unwrap();
+        let sell = market.do_sell_reservation(&SellTxProposal { sell: Good::new(YEN, 1_000.), offer: Good::new(EUR, sell_price), trader_name: "trader".to_string() }, 0.).unwrap();
+
+        market.cancel_buy_lock(&buy).unwrap();
+        market.cancel_sell_lock(&sell).unwrap();
+
+        let ops = market.get_tx_service().get_account_ops();
+        assert_eq!(ops.assets, initial.assets);
+        assert_eq!(ops.reservations, initial.reservations);
+        assert_eq!(ops.futures, initial.futures);
+
+        assert!(matches!(market.buy(&buy, &mut Good::new(EUR, buy_price)), Err(DogeBuyError::InvalidState { current_state: BuyTxState::Cancelled })));
+        assert!(matches!(market.cancel_buy_lock(&buy), Err(DogeCancelBuyError::InvalidState { current_state: BuyTxState::Cancelled })));
+        assert!(matches!(market.cancel_sell_lock(&sell), Err(DogeCancelSellError::InvalidState { current_state: SellTxState::Cancelled })));
+        assert!(matches!(market.cancel_sell_lock(&buy), Err(DogeCancelSellError::UnrecognizedUuid)));
+    }
+
+    #[test]
+    fn test_partial_fills() {
+        let assets = uniform_account(500_000.);
+
+        let config = MarketConfig { lock_deposit_percentage: 10., audit: true, ..MarketConfig::default() };
+        let mut market = DogeMarketImpl::new(AccountOps::of_assets(assets), config).unwrap();
+        let initial = market.get_tx_service().get_account_ops().clone();
+
+        let buy_price = market.get_buy_price(USD, 1_000.).unwrap();
+        let buy = market.do_buy_reservation(&BuyTxProposal { buy: Good::new(USD, 1_000.), bid: Good::new(EUR, buy_price), trader_name: "trader".to_string() }, market.get_lock_deposit(buy_price)).unwrap();
+        let sell_price = market.get_sell_price(YEN, 1_000.).unwrap();
+        let sell = market.do_sell_reservation(&SellTxProposal { sell: Good::new(YEN, 1_000.), offer: Good::new(EUR, sell_price), trader_name: "trader".to_string() }, market.get_lock_deposit(sell_price)).unwrap();
+
+        // the deposit alone pays for nothing
+        assert!(matches!(market.buy_partially(&buy, &mut Good::new(EUR, 0.)), Err(DogeBuyError::InsufficientGoodQuantity { .. })));
+
+        // together with the deposit, half of the bid is paid
+        let mut cash = Good::new(EUR, buy_price * 0.4);
+        let bought = market.buy_partially(&buy, &mut cash).unwrap();
+        assert_eq!(cash.get_qty(), 0.);
+        assert_approx_eq!(bought.get_qty(), 500., 0.01);
+
+        let mut good = Good::new(YEN, 250.);
+        let offered = market.sell_partially(&sell, &mut good).unwrap();
+        assert_eq!(good.get_qty(), 0.);
+        assert_approx_eq!(offered.get_qty(), sell_price * 0.35, 0.01);
+
+        let ops = market.get_tx_service().get_account_ops();
+        assert_eq!(ops.assets.get(USD), initial.assets.get(USD) - Quantity::from_f32(bought.get_qty()));
+        assert_eq!(ops.assets.get(YEN), initial.assets.get(YEN) + Quantity::from_f32(250.));
+        assert_eq!(ops.reservations, initial.reservations);
+        assert_eq!(ops.futures, initial.futures);
+        assert_eq!(ops.deposits, initial.deposits);
+        assert!(matches!(market.get_tx_service().get_buy(&buy).unwrap().state, BuyTxState::PartiallyPaid { .. }));
+        assert!(matches!(market.buy(&buy, &mut Good::new(EUR, buy_price)), Err(DogeBuyError::InvalidState { current_state: BuyTxState::PartiallyPaid { .. } })));
+
+        let volume = market.get_tx_service().get_volume_by_trader("trader");
+        assert_approx_eq!(*volume.bought.get(USD), 500., 0.01);
+        assert_approx_eq!(*volume.sold.get(EUR), buy_price * 0.5, 0.01);
+        assert_eq!(*volume.sold.get(YEN), 250.);
+
+        // settling half of a lock counts as completing it, settling a quarter as abandoning it
+        assert_eq!(market.get_tx_service().get_reputation_table().get_record("trader"), TraderRecord { completed: 1, expired: 1 });
+
+        // paying the whole bid is a normal buy
+        let buy = market.do_buy_reservation(&BuyTxProposal { buy: Good::new(USD, 10.), bid: Good::new(EUR, buy_price), trader_name: "trader".to_string() }, 0.).unwrap();
+        assert_eq!(market.buy_partially(&buy, &mut Good::new(EUR, buy_price)).unwrap().get_qty(), 10.);
+        assert!(matches!(market.get_tx_service().get_buy(&buy).unwrap().state, BuyTxState::Paid));
+
+        assert_eq!(market.get_tx_service().get_audit_reports(), &[]);
+    }
+
+    #[test]
+    fn test_cross_buy() {
+        let assets = uniform_account(500_000.);
+
+        let config = MarketConfig { audit: true, ..MarketConfig::default() };
+        let mut market = DogeMarketImpl::new(AccountOps::of_assets(assets), config).unwrap();
+        let initial = market.get_tx_service().get_account_ops().clone();
+
+        let price = market.get_cross_buy_price(USD, 1_000., YEN).unwrap();
+        assert_eq!(market.get_cross_buy_price(USD, 1_000., EUR).unwrap(), market.get_buy_price(USD, 1_000.).unwrap());
+        assert!(matches!(market.get_cross_buy_price(USD, 1_000., USD), Err(DogeGetCrossPriceError::SameKind)));
+        assert!(matches!(market.do_buy_reservation(&BuyTxProposal { buy: Good::new(USD, 1_000.), bid: Good::new(USD, 2_000.), trader_name: "trader".to_string() }, 0.),
+            Err(DogeBuyReservationError::UnsupportedBid)));
+        assert!(matches!(market.do_buy_reservation(&BuyTxProposal { buy: Good::new(USD, 1_000.), bid: Good::new(YEN, price * 0.99), trader_name: "trader".to_string() }, 0.),
+            Err(DogeBuyReservationError::BidTooLow { .. })));
+
+        let buy = market.do_buy_reservation(&BuyTxProposal { buy: Good::new(USD, 1_000.), bid: Good::new(YEN, price), trader_name: "trader".to_string() }, 0.).unwrap();
+        assert!(matches!(market.buy(&buy, &mut Good::new(EUR, price)), Err(DogeBuyError::WrongGoodKind { pre_agreed: YEN })));
+        assert_eq!(market.buy(&buy, &mut Good::new(YEN, price)).unwrap().get_qty(), 1_000.);
+
+        let ops = market.get_tx_service().get_account_ops();
+        assert_eq!(ops.assets.get(EUR), initial.assets.get(EUR));
+        assert_eq!(ops.assets.get(USD), initial.assets.get(USD) - Quantity::from_f32(1_000.));
+        assert_eq!(ops.assets.get(YEN), initial.assets.get(YEN) + Quantity::from_f32(price));
+        assert_eq!(market.get_tx_service().get_audit_reports(), &[]);
+    }
+
+    #[test]
+    fn test_cross_sell() {
+        let assets = uniform_account(500_000.);
+
+        let config = MarketConfig { audit: true, ..MarketConfig::default() };
+        let mut market = DogeMarketImpl::new(AccountOps::of_assets(assets), config).unwrap();
+        let initial = market.get_tx_service().get_account_ops().clone();
+
+        // the offer is priced in its own good, not compared against a price in the default good
+        let offer = market.get_cross_sell_price(YEN, 1_000., USD).unwrap();
+        assert_eq!(market.get_cross_sell_price(YEN, 1_000., EUR).unwrap(), market.get_sell_price(YEN, 1_000.).unwrap());
+        assert!(matches!(market.get_cross_sell_price(YEN, 1_000., YEN), Err(DogeGetCrossPriceError::SameKind)));
+        assert!(matches!(market.do_sell_reservation(&SellTxProposal { sell: Good::new(YEN, 1_000.), offer: Good::new(USD, offer * 1.01), trader_name: "trader".to_string() }, 0.),
+            Err(DogeSellReservationError::OfferTooHigh { highest }) if highest == offer));
+        assert!(matches!(market.do_sell_reservation(&SellTxProposal { sell: Good::new(YEN, 1_000.), offer: Good::new(YEN, 1.), trader_name: "trader".to_string() }, 0.),
+            Err(DogeSellReservationError::UnsupportedOffer)));
+
+        let sell = market.do_sell_reservation(&SellTxProposal { sell: Good::new(YEN, 1_000.), offer: Good::new(USD, offer), trader_name: "trader".to_string() }, 0.).unwrap();
+        assert_eq!(market.sell(&sell, &mut Good::new(YEN, 1_000.)).unwrap().get_qty(), offer);
+
+        let ops = market.get_tx_service().get_account_ops();
+        assert_eq!(ops.assets.get(EUR), initial.assets.get(EUR));
+        assert_eq!(ops.assets.get(USD), initial.assets.get(USD) - Quantity::from_f32(offer));
+        assert_eq!(ops.assets.get(YEN), initial.assets.get(YEN) + Quantity::from_f32(1_000.));
+        assert_eq!(market.get_tx_service().get_audit_reports(), &[]);
+    }
+
+    #[test]
+    fn test_renewal() {
+        let assets = uniform_account(500_000.);
+
+        let config = MarketConfig { lock_ttl: 2, renewal_fee: 1.5, max_renewals: 1, ..MarketConfig::default() };
+        let mut market = DogeMarketImpl::new(AccountOps::of_assets(assets), config).unwrap();
+        let initial_eur = market.get_tx_service().get_account_ops().assets.get(EUR);
+
+        let buy_price = market.get_buy_price(USD, 1_000.).unwrap();
+        let buy = market.do_buy_reservation(&BuyTxProposal { buy: Good::new(USD, 1_000.), bid: Good::new(EUR, buy_price), trader_name: "trader".to_string() }, 0.).unwrap();
+        let sell_price = market.get_sell_price(YEN, 1_000.).unwrap();
+        let sell = market.do_sell_reservation(&SellTxProposal { sell: Good::new(YEN, 1_000.), offer: Good::new(EUR, sell_price), trader_name: "trader".to_string() }, 0.).unwrap();
+
+        assert!(matches!(market.renew_buy_lock(&buy, 3, &mut Good::new(EUR, 10.)), Err(DogeRenewBuyError::InvalidTicks { max_ticks: 2 })));
+        assert!(matches!(market.renew_buy_lock(&buy, 2, &mut Good::new(USD, 10.)), Err(DogeRenewBuyError::WrongGoodKind { fee_kind: EUR })));
+        assert!(matches!(market.renew_sell_lock(&sell, 2, &mut Good::new(EUR, 2.)), Err(DogeRenewSellError::InsufficientGoodQuantity { fee }) if fee == 3.));
+
+        let mut fee = Good::new(EUR, 10.);
+        market.renew_buy_lock(&buy, 2, &mut fee).unwrap();
+        assert_eq!(fee.get_qty(), 7.);
+        assert_eq!(market.get_tx_service().get_account_ops().assets.get(EUR), initial_eur - Quantity::from_f32(sell_price) + Quantity::from_f32(3.));
+        assert!(matches!(market.renew_buy_lock(&buy, 1, &mut fee), Err(DogeRenewBuyError::MaxRenewalsReached { max_renewals: 1 })));
+
+        // the sell expires after the usual two days, the renewed buy two days later
+        market.service.tick_all();
+        market.service.tick_all();
+        assert!(matches!(market.get_tx_service().get_sell(&sell).unwrap().state, SellTxState::Expired));
+        assert!(matches!(market.get_tx_service().get_buy(&buy).unwrap().state, BuyTxState::Reserved));
+        market.service.tick_all();
+        market.service.tick_all();
+        assert!(matches!(market.get_tx_service().get_buy(&buy).unwrap().state, BuyTxState::Expired));
+        assert!(matches!(market.renew_sell_lock(&sell, 1, &mut fee), Err(DogeRenewSellError::InvalidState { current_state: SellTxState::Expired })));
+    }
+
+    #[test]
+    fn test_lock_ttl_tiers() {
+        let assets = uniform_account(500_000.);
+
+        let config = MarketConfig { lock_ttl: 3, lock_ttl_tiers: vec![LockTtlTier { from_quantity: 10_000., lock_ttl: 1 }], ..MarketConfig::default() };
+        let mut market = DogeMarketImpl::new(AccountOps::of_assets(assets), config).unwrap();
+
+        let buy_price = market.get_buy_price(USD, 100.).unwrap();
+        let small = market.do_buy_reservation(&BuyTxProposal { buy: Good::new(USD, 100.), bid: Good::new(EUR, buy_price), trader_name: "trader".to_string() }, 0.).unwrap();
+        let buy_price = market.get_buy_price(USD, 100_000.).unwrap();
+        let huge = market.do_buy_reservation(&BuyTxProposal { buy: Good::new(USD, 100_000.), bid: Good::new(EUR, buy_price), trader_name: "trader".to_string() }, 0.).unwrap();
+
+        assert_eq!(market.get_tx_service().get_ticks_left(&small), Some(3));
+        assert_eq!(market.get_tx_service().get_ticks_left(&huge), Some(1));
+
+        // sells are tiered on the value of the offer, like buys on the value of the bid
+        let sell_price = market.get_sell_price(YEN, 50_000.).unwrap();
+        let huge_sell = market.do_sell_reservation(&SellTxProposal { sell: Good::new(YEN, 50_000.), offer: Good::new(EUR, sell_price), trader_name: "trader".to_string() }, 0.).unwrap();
+        assert_eq!(market.get_tx_service().get_ticks_left(&huge_sell), Some(1));
+
+        market.service.tick_all();
+        assert!(matches!(market.get_tx_service().get_buy(&huge).unwrap().state, BuyTxState::Expired));
+        assert!(matches!(market.get_tx_service().get_buy(&small).unwrap().state, BuyTxState::Reserved));
+    }
+
+    #[test]
+    fn test_reputation() {
+        let assets = uniform_account(500_000.);
+
+        let reputation = ReputationSettings { grace_locks: 2, spread_penalty: 10., limit_below: 0.8, refuse_below: 0.3 };
+        let mut market = DogeMarketImpl::new(AccountOps::of_assets(assets), MarketConfig { lock_ttl: 1, reputation, ..MarketConfig::default() }).unwrap();
+
+        let lock = |market: &mut DogeMarketImpl, trader_name: &str, quantity: f32| {
+            let buy_price = market.get_buy_price(USD, quantity).unwrap();
+            market.do_buy_reservation(&BuyTxProposal { buy: Good::new(USD, quantity), bid: Good::new(EUR, buy_price * 1.2), trader_name: trader_name.to_string() }, 0.)
+        };
+
+        // one lock paid and one abandoned: score 0.5
+        let paid = lock(&mut market, "mixed", 100.).unwrap();
+        market.buy(&paid, &mut Good::new(EUR, f32::MAX)).unwrap();
+        lock(&mut market, "mixed", 100.).unwrap();
+        // two locks abandoned: score 0
+        lock(&mut market, "flaky", 100.).unwrap();
+        market.service.tick_all();
+        lock(&mut market, "flaky", 100.).unwrap();
+        market.service.tick_all();
+
+        assert!(matches!(lock(&mut market, "flaky", 1.), Err(DogeBuyReservationError::BadReputation { max_quantity }) if max_quantity == 0.));
+        assert!(matches!(lock(&mut market, "mixed", 300_000.), Err(DogeBuyReservationError::BadReputation { max_quantity }) if max_quantity > 200_000. && max_quantity < 300_000.));
+        lock(&mut market, "honest", 300_000.).unwrap();
+
+        // quoted prices do not include the 5% penalty on the spread of the mixed trader
+        let buy_price = market.get_buy_price(USD, 100.).unwrap();
+        let proposal = BuyTxProposal { buy: Good::new(USD, 100.), bid: Good::new(EUR, buy_price), trader_name: "mixed".to_string() };
+        assert!(matches!(market.do_buy_reservation(&proposal, 0.), Err(DogeBuyReservationError::BidTooLow { lowest }) if lowest > buy_price));
+
+        // its orders fill at that penalized price, and filling them does not mend its score
+        let record = market.get_tx_service().get_reputation_table().get_record("mixed");
+        let mut cash = Good::new(EUR, buy_price * 1.2);
+        let order = market.place_buy_order(USD, 100., buy_price * 1.2, 5, "mixed".to_string(), &mut cash).unwrap();
+        assert_eq!(market.match_orders(), vec![order]);
+        assert!(market.get_order(&order).unwrap().escrow.get_qty() < buy_price * 0.2);
+        assert_eq!(market.get_tx_service().get_reputation_table().get_record("mixed"), record);
+    }
+
+    #[test]
+    fn test_invalid_config() {
+        let assets = uniform_account(500_000.);
+
+        let config = MarketConfig { lock_ttl: 0, ..MarketConfig::default() };
+        assert_eq!(DogeMarketImpl::new(AccountOps::of_assets(assets.clone()), config).err(), Some(MarketConfigError::NonPositiveLockTtl));
+
+        // markets sharing a name share the leaked string
+        let first = DogeMarketImpl::new(AccountOps::of_assets(assets.clone()), MarketConfig::default()).unwrap();
+        let second = DogeMarketImpl::new(AccountOps::of_assets(assets), MarketConfig::default()).unwrap();
+        assert!(std::ptr::eq(first.market_name, second.market_name));
     }
 }
@@ -0,0 +1,381 @@
+use serde::{Deserialize, Serialize};
+use unitn_market_2022::good::good_kind::GoodKind;
+
+pub use crate::batch_auction::LockClearing;
+pub use crate::competitor_rates::CompetitorSettings;
+pub use crate::forward_contract::ForwardSettings;
+pub use crate::lending_desk::LendingSettings;
+use crate::per_good::PerGood;
+pub use crate::pricing_curve::PricingCurveKind;
+pub use crate::refill_policy::RefillPolicyKind;
+pub use crate::refiller::RefillerSettings;
+pub use crate::reputation::ReputationSettings;
+
+/// Tuning of a single DogeMarket, so that differently tuned markets can run side by side.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MarketConfig {
+    pub market_name: String,
+    /// Number of days after which a lock that was not paid expires.
+    pub lock_ttl: u32,
+    /// Locks exchanging at least `from_quantity` of the default good expire after the `lock_ttl` of the largest such
+    /// tier instead of the one above.
+    pub lock_ttl_tiers: Vec<LockTtlTier>,
+    /// Spread, as exchange rate earn percentage, applied when a trader buys the good from the market.
+    pub buy_earn_percentages: PerGood<f32>,
+    /// Spread, as exchange rate earn percentage, applied when a trader sells the good to the market.
+    pub sell_earn_percentages: PerGood<f32>,
+    pub refiller: RefillerSettings,
+    /// How the market converts the goods it has plenty of into the ones running low, at the end of every day.
+    pub refill_policy: RefillPolicyKind,
+    /// Curve that turns the inventories into exchange rates, for prices, labels and locks alike.
+    pub pricing_curve: PricingCurveKind,
+    /// Reconciles the ledgers with the transactions after every operation, see [DogeMarket::get_audit_reports](crate::dogemarket::DogeMarket::get_audit_reports).
+    pub audit: bool,
+    /// Locks must leave at least this quantity of the good they take out of the market.
+    pub reserve_floors: PerGood<f32>,
+    /// Locks must not bring the quantity of the good they pay the market with above this, `None` for no limit but
+    /// `f32::MAX`.
+    pub capacities: PerGood<Option<f32>>,
+    /// Default good charged for every day a lock is renewed by.
+    pub renewal_fee: f32,
+    /// Number of times the same lock can be renewed.
+    pub max_renewals: u32,
+    /// Wider spreads, smaller locks or no locks at all for traders that let their locks expire.
+    pub reputation: ReputationSettings,
+    /// Limits on the locks every trader can hold at the same time.
+    pub trader_limits: TraderLimits,
+    /// Percentage of the default good exchanged by a lock that the trader deposits when locking, `0` for no deposit.
+    /// The deposit counts towards the payment of the lock and is kept by the market if the lock is not paid.
+    pub lock_deposit_percentage: f32,
+    /// Whether locks are priced as they come or together at the end of the day. Only the locks of
+    /// [Market::lock_buy](unitn_market_2022::market::Market::lock_buy) and
+    /// [Market::lock_sell](unitn_market_2022::market::Market::lock_sell) go through the auction: baskets and limit orders
+    /// are always priced as they come.
+    pub lock_clearing: LockClearing,
+    /// Pricing and margin of the forwards of [DogeMarket::agree_forward_buy](crate::dogemarket::DogeMarket::agree_forward_buy)
+    /// and [DogeMarket::agree_forward_sell](crate::dogemarket::DogeMarket::agree_forward_sell).
+    pub forwards: ForwardSettings,
+    /// Term, interest and collateral of the loans of [DogeMarket::borrow](crate::dogemarket::DogeMarket::borrow).
+    pub lending: LendingSettings,
+    /// How far the spreads follow the rates of the other markets, as seen through the events they notify.
+    pub competitors: CompetitorSettings,
+    /// Seed of every random choice of the market, such as the shortages of the refiller. Markets with the same seed and
+    /// the same trades end up with the same goods. `None` draws a seed when the market starts.
+    pub seed: Option<u64>,
+}
+
+/// Limits on the locks a single trader can hold at the same time. The defaults do not limit anything.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TraderLimits {
+    /// Maximum number of locks of the same trader waiting to be paid, `None` for no limit.
+    pub max_locks: Option<u32>,
+    /// Fraction, in `(0, 1]`, of the quantity of each good owned by the market that the locks of the same trader can
+    /// reserve at most.
+    pub max_reserved_fractions: PerGood<f32>,
+}
+
+impl Default for TraderLimits {
+    fn default() -> Self {
+        TraderLimits {
+            max_locks: None,
+            max_reserved_fractions: PerGood::new(1., 1., 1., 1.),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LockTtlTier {
+    pub from_quantity: f32,
+    pub lock_ttl: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MarketConfigError {
+    EmptyMarketName,
+    NonPositiveLockTtl,
+    InvalidLockTtlTier { tier: LockTtlTier },
+    InvalidBuyEarnPercentage { kind: GoodKind, percentage: f32 },
+    InvalidSellEarnPercentage { kind: GoodKind, percentage: f32 },
+    InvalidRefillerSetting { field: &'static str },
+    InvalidRefillPolicy { field: &'static str },
+    InvalidSlippage { slippage: f32 },
+    InvalidReserveFloor { kind: GoodKind, floor: f32 },
+    /// A capacity is not finite or is not above the reserve floor of its good.
+    InvalidCapacity { kind: GoodKind, capacity: f32 },
+    InvalidRenewalFee { fee: f32 },
+    InvalidReputationSetting { field: &'static str },
+    InvalidTraderLimit { field: &'static str },
+    InvalidLockDepositPercentage { percentage: f32 },
+    /// Batch auctions price locks after the trader locked, so they cannot take a deposit out of the price.
+    DepositInBatchAuction,
+    InvalidForwardSetting { field: &'static str },
+    InvalidLendingSetting { field: &'static str },
+    InvalidCompetitorSetting { field: &'static str },
+}
+
+impl Default for MarketConfig {
+    fn default() -> Self {
+        MarketConfig {
+            market_name: "DogeMarket".to_string(),
+            lock_ttl: 10,
+            lock_ttl_tiers: vec![],
+            buy_earn_percentages: PerGood::default_earn_percentages(),
+            sell_earn_percentages: PerGood::default_earn_percentages(),
+            refiller: RefillerSettings::default(),
+            refill_policy: RefillPolicyKind::default(),
+            pricing_curve: PricingCurveKind::default(),
+            audit: false,
+            reserve_floors: PerGood::new(0., 0., 0., 0.),
+            capacities: PerGood::new(None, None, None, None),
+            renewal_fee: 0.,
+            max_renewals: 3,
+            reputation: ReputationSettings::default(),
+            trader_limits: TraderLimits::default(),
+            lock_deposit_percentage: 0.,
+            lock_clearing: LockClearing::Continuous,
+            forwards: ForwardSettings::default(),
+            lending: LendingSettings::default(),
+            competitors: CompetitorSettings::default(),
+            seed: None,
+        }
+    }
+}
+
+impl MarketConfig {
+    pub fn validate(&self) -> Result<(), MarketConfigError> {
+        if self.market_name.is_empty() {
+            return Err(MarketConfigError::EmptyMarketName);
+        }
+
+        if self.lock_ttl == 0 {
+            return Err(MarketConfigError::NonPositiveLockTtl);
+        }
+
+        if let Some(tier) = self.lock_ttl_tiers.iter().find(|tier| !tier.from_quantity.is_finite() || tier.from_quantity < 0. || tier.lock_ttl == 0) {
+            return Err(MarketConfigError::InvalidLockTtlTier { tier: *tier });
+        }
+
+        if let Some((kind, percentage)) = self.buy_earn_percentages.iter().find(|(_, percentage)| !percentage.is_finite() || **percentage < 0.) {
+            return Err(MarketConfigError::InvalidBuyEarnPercentage { kind, percentage: *percentage });
+        }
+
+        // selling at 100% or more would make the market pay nothing, or less than nothing
+        if let Some((kind, percentage)) = self.sell_earn_percentages.iter().find(|(_, percentage)| !(0. ..100.).contains(*percentage)) {
+            return Err(MarketConfigError::InvalidSellEarnPercentage { kind, percentage: *percentage });
+        }
+
+        let refiller = &self.refiller;
+        if !(0. ..1.).contains(&refiller.import_tax) {
+            return Err(MarketConfigError::InvalidRefillerSetting { field: "import_tax" });
+        }
+        if !(0..=100).contains(&refiller.shortage_probability_percent) {
+            return Err(MarketConfigError::InvalidRefillerSetting { field: "shortage_probability_percent" });
+        }
+        if !refiller.careful_fraction.is_finite() || refiller.careful_fraction <= 0. {
+            return Err(MarketConfigError::InvalidRefillerSetting { field: "careful_fraction" });
+        }
+
+        if let RefillPolicyKind::TargetWeights { weights, tolerance_percentage } = self.refill_policy {
+            if weights.iter().any(|(_, weight)| !weight.is_finite() || *weight < 0.) || weights.iter().all(|(_, weight)| *weight == 0.) {
+                return Err(MarketConfigError::InvalidRefillPolicy { field: "weights" });
+            }
+            if !(0. ..100.).contains(&tolerance_percentage) {
+                return Err(MarketConfigError::InvalidRefillPolicy { field: "tolerance_percentage" });
+            }
+        }
+
+        if let PricingCurveKind::LinearSlippage { slippage } = self.pricing_curve {
+            if !slippage.is_finite() || slippage < 0. {
+                return Err(MarketConfigError::InvalidSlippage { slippage });
+            }
+        }
+
+        if let Some((kind, floor)) = self.reserve_floors.iter().find(|(_, floor)| !floor.is_finite() || **floor < 0.) {
+            return Err(MarketConfigError::InvalidReserveFloor { kind, floor: *floor });
+        }
+
+        for (kind, capacity) in self.capacities.iter() {
+            if let Some(capacity) = *capacity {
+                if !capacity.is_finite() || capacity <= self.get_reserve_floor(kind) {
+                    return Err(MarketConfigError::InvalidCapacity { kind, capacity });
+                }
+            }
+        }
+
+        if !self.renewal_fee.is_finite() || self.renewal_fee < 0. {
+            return Err(MarketConfigError::InvalidRenewalFee { fee: self.renewal_fee });
+        }
+
+        let reputation = &self.reputation;
+        // the penalty must not bring the spread of sells to 100% or more, like the sell earn percentages themselves
+        if !reputation.spread_penalty.is_finite() || reputation.spread_penalty < 0. ||
+            self.sell_earn_percentages.iter().any(|(_, percentage)| percentage + reputation.spread_penalty >= 100.) {
+            return Err(MarketConfigError::InvalidReputationSetting { field: "spread_penalty" });
+        }
+        if !(0. ..=1.).contains(&reputation.limit_below) {
+            return Err(MarketConfigError::InvalidReputationSetting { field: "limit_below" });
+        }
+        if !(0. ..=1.).contains(&reputation.refuse_below) {
+            return Err(MarketConfigError::InvalidReputationSetting { field: "refuse_below" });
+        }
+
+        if self.trader_limits.max_locks == Some(0) {
+            return Err(MarketConfigError::InvalidTraderLimit { field: "max_locks" });
+        }
+        if self.trader_limits.max_reserved_fractions.iter().any(|(_, fraction)| !(*fraction > 0. && *fraction <= 1.)) {
+            return Err(MarketConfigError::InvalidTraderLimit { field: "max_reserved_fractions" });
+        }
+
+        if !(0. ..=100.).contains(&self.lock_deposit_percentage) {
+            return Err(MarketConfigError::InvalidLockDepositPercentage { percentage: self.lock_deposit_percentage });
+        }
+        if self.lock_clearing == LockClearing::BatchAuction && self.lock_deposit_percentage > 0. {
+            return Err(MarketConfigError::DepositInBatchAuction);
+        }
+
+        let forwards = &self.forwards;
+        if forwards.max_days == 0 {
+            return Err(MarketConfigError::InvalidForwardSetting { field: "max_days" });
+        }
+        // the carry must not bring the price of a sell maturing as late as possible to zero or less
+        if !forwards.carry_percentage.is_finite() || forwards.carry_percentage < 0. || forwards.carry_percentage * forwards.max_days as f32 >= 100. {
+            return Err(MarketConfigError::InvalidForwardSetting { field: "carry_percentage" });
+        }
+        if !(0. ..100.).contains(&forwards.margin_percentage) {
+            return Err(MarketConfigError::InvalidForwardSetting { field: "margin_percentage" });
+        }
+
+        let lending = &self.lending;
+        if lending.max_days == 0 {
+            return Err(MarketConfigError::InvalidLendingSetting { field: "max_days" });
+        }
+        if !lending.daily_interest_percentage.is_finite() || lending.daily_interest_percentage < 0. {
+            return Err(MarketConfigError::InvalidLendingSetting { field: "daily_interest_percentage" });
+        }
+        // a loan must not be liquidated as soon as it is lent, nor be allowed to be worth less than its debt
+        if !lending.liquidation_percentage.is_finite() || lending.liquidation_percentage < 100. {
+            return Err(MarketConfigError::InvalidLendingSetting { field: "liquidation_percentage" });
+        }
+        if !lending.collateral_percentage.is_finite() || lending.collateral_percentage <= lending.liquidation_percentage {
+            return Err(MarketConfigError::InvalidLendingSetting { field: "collateral_percentage" });
+        }
+
+        let competitors = &self.competitors;
+        if !(competitors.smoothing > 0. && competitors.smoothing <= 1.) {
+            return Err(MarketConfigError::InvalidCompetitorSetting { field: "smoothing" });
+        }
+        // like the reputation penalty, the skew must not bring the spread of sells to 100% or more, even on top of it
+        if !competitors.max_skew_percentage.is_finite() || competitors.max_skew_percentage < 0. ||
+            self.sell_earn_percentages.iter().any(|(_, percentage)| percentage + reputation.spread_penalty + competitors.max_skew_percentage >= 100.) {
+            return Err(MarketConfigError::InvalidCompetitorSetting { field: "max_skew_percentage" });
+        }
+
+        Ok(())
+    }
+
+    /// Tier of a lock exchanging `quantity` of the default good, `None` if it expires after the default `lock_ttl`.
+    pub fn get_lock_ttl_tier(&self, quantity: f32) -> Option<&LockTtlTier> {
+        self.lock_ttl_tiers.iter()
+            .filter(|tier| tier.from_quantity <= quantity)
+            .max_by(|a, b| a.from_quantity.total_cmp(&b.from_quantity))
+    }
+
+    pub fn get_buy_earn_percentage(&self, kind: GoodKind) -> f32 {
+        *self.buy_earn_percentages.get(kind)
+    }
+
+    pub fn get_sell_earn_percentage(&self, kind: GoodKind) -> f32 {
+        *self.sell_earn_percentages.get(kind)
+    }
+
+    pub fn get_reserve_floor(&self, kind: GoodKind) -> f32 {
+        *self.reserve_floors.get(kind)
+    }
+
+    pub fn get_capacity(&self, kind: GoodKind) -> Option<f32> {
+        *self.capacities.get(kind)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use unitn_market_2022::good::good_kind::GoodKind::{USD, YUAN};
+
+    use super::*;
+
+    #[test]
+    fn default_is_valid() {
+        assert_eq!(MarketConfig::default().validate(), Ok(()));
+    }
+
+    #[test]
+    fn lock_ttl_tiers() {
+        let config = MarketConfig {
+            lock_ttl: 10,
+            lock_ttl_tiers: vec![LockTtlTier { from_quantity: 100_000., lock_ttl: 2 }, LockTtlTier { from_quantity: 1_000., lock_ttl: 5 }],
+            ..MarketConfig::default()
+        };
+
+        assert_eq!(config.get_lock_ttl_tier(999.), None);
+        assert_eq!(config.get_lock_ttl_tier(1_000.).unwrap().lock_ttl, 5);
+        assert_eq!(config.get_lock_ttl_tier(500_000.).unwrap().lock_ttl, 2);
+    }
+
+    #[test]
+    fn invalid_configs() {
+        let config = MarketConfig { market_name: "".to_string(), ..MarketConfig::default() };
+        assert_eq!(config.validate(), Err(MarketConfigError::EmptyMarketName));
+
+        let config = MarketConfig { lock_ttl: 0, ..MarketConfig::default() };
+        assert_eq!(config.validate(), Err(MarketConfigError::NonPositiveLockTtl));
+
+        let config = MarketConfig { buy_earn_percentages: PerGood::new(0., -1., 1., 1.), ..MarketConfig::default() };
+        assert_eq!(config.validate(), Err(MarketConfigError::InvalidBuyEarnPercentage { kind: USD, percentage: -1. }));
+
+        let config = MarketConfig { sell_earn_percentages: PerGood::new(0., 1., 1., 100.), ..MarketConfig::default() };
+        assert_eq!(config.validate(), Err(MarketConfigError::InvalidSellEarnPercentage { kind: YUAN, percentage: 100. }));
+
+        let config = MarketConfig { refill_policy: RefillPolicyKind::TargetWeights { weights: PerGood::new(0., 0., 0., 0.), tolerance_percentage: 5. }, ..MarketConfig::default() };
+        assert_eq!(config.validate(), Err(MarketConfigError::InvalidRefillPolicy { field: "weights" }));
+
+        let config = MarketConfig { pricing_curve: PricingCurveKind::LinearSlippage { slippage: -0.5 }, ..MarketConfig::default() };
+        assert_eq!(config.validate(), Err(MarketConfigError::InvalidSlippage { slippage: -0.5 }));
+
+        let config = MarketConfig {
+            reserve_floors: PerGood::new(0., 10., 0., 0.),
+            capacities: PerGood::new(None, Some(10.), None, None),
+            ..MarketConfig::default()
+        };
+        assert_eq!(config.validate(), Err(MarketConfigError::InvalidCapacity { kind: USD, capacity: 10. }));
+
+        let tier = LockTtlTier { from_quantity: 1_000., lock_ttl: 0 };
+        let config = MarketConfig { lock_ttl_tiers: vec![tier], ..MarketConfig::default() };
+        assert_eq!(config.validate(), Err(MarketConfigError::InvalidLockTtlTier { tier }));
+
+        let config = MarketConfig { reputation: ReputationSettings { spread_penalty: 99.5, ..ReputationSettings::default() }, ..MarketConfig::default() };
+        assert_eq!(config.validate(), Err(MarketConfigError::InvalidReputationSetting { field: "spread_penalty" }));
+
+        let trader_limits = TraderLimits { max_reserved_fractions: PerGood::new(1., 0., 1., 1.), ..TraderLimits::default() };
+        let config = MarketConfig { trader_limits, ..MarketConfig::default() };
+        assert_eq!(config.validate(), Err(MarketConfigError::InvalidTraderLimit { field: "max_reserved_fractions" }));
+
+        let config = MarketConfig { renewal_fee: f32::NAN, ..MarketConfig::default() };
+        assert!(matches!(config.validate(), Err(MarketConfigError::InvalidRenewalFee { .. })));
+
+        let config = MarketConfig { lock_deposit_percentage: 101., ..MarketConfig::default() };
+        assert_eq!(config.validate(), Err(MarketConfigError::InvalidLockDepositPercentage { percentage: 101. }));
+
+        let config = MarketConfig { lock_deposit_percentage: 1., lock_clearing: LockClearing::BatchAuction, ..MarketConfig::default() };
+        assert_eq!(config.validate(), Err(MarketConfigError::DepositInBatchAuction));
+
+        let config = MarketConfig { forwards: ForwardSettings { carry_percentage: 5., ..ForwardSettings::default() }, ..MarketConfig::default() };
+        assert_eq!(config.validate(), Err(MarketConfigError::InvalidForwardSetting { field: "carry_percentage" }));
+
+        let config = MarketConfig { lending: LendingSettings { collateral_percentage: 110., ..LendingSettings::default() }, ..MarketConfig::default() };
+        assert_eq!(config.validate(), Err(MarketConfigError::InvalidLendingSetting { field: "collateral_percentage" }));
+
+        let config = MarketConfig { competitors: CompetitorSettings { smoothing: 0., ..CompetitorSettings::default() }, ..MarketConfig::default() };
+        assert_eq!(config.validate(), Err(MarketConfigError::InvalidCompetitorSetting { field: "smoothing" }));
+    }
+}
@@ -0,0 +1,295 @@
+use std::fs;
+
+use serde::Deserialize;
+use unitn_market_2022::good::good_kind::GoodKind;
+
+use crate::batch_auction::LockClearing;
+use crate::competitor_rates::CompetitorSettings;
+use crate::forward_contract::ForwardSettings;
+use crate::lending_desk::LendingSettings;
+use crate::market_config::{LockTtlTier, MarketConfig, MarketConfigError};
+use crate::per_good::PerGood;
+use crate::pricing_curve::PricingCurveKind;
+use crate::refill_policy::RefillPolicyKind;
+use crate::refiller::RefillerSettings;
+use crate::reputation::ReputationSettings;
+use crate::market_config::TraderLimits;
+
+/// Starting state of a DogeMarket, as read by `DogeMarket::new_file`.
+///
+/// The file is JSON. `goods` and `lock_ttl` are mandatory, every other field falls back to the value of
+/// [MarketConfig::default] when omitted. Unknown fields are rejected, so that a typo does not silently start the market
+/// with a default value. Files of the first version of the format are still read: `lock_expiry_ticks` stands for
+/// `lock_ttl`, and `earn_percentages` sets both the buy and the sell earn percentages that are not given.
+///
+/// ```json
+/// {
+///     "goods": { "eur": 250000.0, "usd": 258940.0, "yen": 36196000.0, "yuan": 1800000.0 },
+///     "lock_ttl": 10,
+///     "lock_ttl_tiers": [{ "from_quantity": 100000.0, "lock_ttl": 3 }],
+///     "market_name": "DogeMarket",
+///     "buy_earn_percentages": { "eur": 0.0, "usd": 1.0, "yen": 1.0, "yuan": 1.0 },
+///     "sell_earn_percentages": { "eur": 0.0, "usd": 1.0, "yen": 1.0, "yuan": 1.0 },
+///     "refiller": {
+///         "min_days_as_importer": 100,
+///         "min_days_as_exporter": 100,
+///         "min_days_in_shortage": 100,
+///         "import_tax": 0.25,
+///         "shortage_probability_percent": 5,
+///         "careful_fraction": 8.0
+///     },
+///     "refill_policy": { "target_weights": { "weights": { "eur": 1.0, "usd": 1.0, "yen": 1.0, "yuan": 1.0 }, "tolerance_percentage": 5.0 } },
+///     "pricing_curve": { "linear_slippage": { "slippage": 0.5 } },
+///     "audit": false,
+///     "reserve_floors": { "eur": 1000.0, "usd": 1000.0, "yen": 100000.0, "yuan": 5000.0 },
+///     "capacities": { "eur": null, "usd": 10000000.0, "yen": null, "yuan": null },
+///     "renewal_fee": 10.0,
+///     "max_renewals": 3,
+///     "reputation": { "grace_locks": 5, "spread_penalty": 10.0, "limit_below": 0.8, "refuse_below": 0.3 },
+///     "trader_limits": { "max_locks": 5, "max_reserved_fractions": { "eur": 0.5, "usd": 0.5, "yen": 0.5, "yuan": 0.5 } },
+///     "lock_deposit_percentage": 2.0,
+///     "lock_clearing": "continuous",
+///     "forwards": { "max_days": 30, "carry_percentage": 0.1, "margin_percentage": 10.0 },
+///     "lending": { "max_days": 30, "daily_interest_percentage": 0.05, "collateral_percentage": 150.0, "liquidation_percentage": 120.0 },
+///     "competitors": { "smoothing": 0.2, "max_skew_percentage": 5.0 },
+///     "seed": 42
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MarketFile {
+    /// Quantity of each good the market starts with.
+    pub goods: PerGood<f32>,
+    #[serde(alias = "lock_expiry_ticks")]
+    pub lock_ttl: u32,
+    #[serde(default)]
+    pub lock_ttl_tiers: Vec<LockTtlTier>,
+    #[serde(default = "default_market_name")]
+    pub market_name: String,
+    #[serde(default)]
+    pub earn_percentages: Option<PerGood<f32>>,
+    #[serde(default)]
+    pub buy_earn_percentages: Option<PerGood<f32>>,
+    #[serde(default)]
+    pub sell_earn_percentages: Option<PerGood<f32>>,
+    #[serde(default)]
+    pub refiller: RefillerSettings,
+    #[serde(default)]
+    pub refill_policy: RefillPolicyKind,
+    #[serde(default)]
+    pub pricing_curve: PricingCurveKind,
+    #[serde(default)]
+    pub audit: bool,
+    #[serde(default = "default_reserve_floors")]
+    pub reserve_floors: PerGood<f32>,
+    #[serde(default = "default_capacities")]
+    pub capacities: PerGood<Option<f32>>,
+    #[serde(default)]
+    pub renewal_fee: f32,
+    #[serde(default = "default_max_renewals")]
+    pub max_renewals: u32,
+    #[serde(default)]
+    pub reputation: ReputationSettings,
+    #[serde(default)]
+    pub trader_limits: TraderLimits,
+    #[serde(default)]
+    pub lock_deposit_percentage: f32,
+    #[serde(default)]
+    pub lock_clearing: LockClearing,
+    #[serde(default)]
+    pub forwards: ForwardSettings,
+    #[serde(default)]
+    pub lending: LendingSettings,
+    #[serde(default)]
+    pub competitors: CompetitorSettings,
+    #[serde(default)]
+    pub seed: Option<u64>,
+}
+
+fn default_market_name() -> String {
+    MarketConfig::default().market_name
+}
+
+fn default_reserve_floors() -> PerGood<f32> {
+    MarketConfig::default().reserve_floors
+}
+
+fn default_capacities() -> PerGood<Option<f32>> {
+    MarketConfig::default().capacities
+}
+
+fn default_max_renewals() -> u32 {
+    MarketConfig::default().max_renewals
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MarketFileError {
+    Unreadable { path: String, reason: String },
+    /// The content is not valid JSON, a field is missing or has the wrong type. The reason names the field and its position.
+    Malformed { reason: String },
+    InvalidGoodQuantity { kind: GoodKind, quantity: f32 },
+    InvalidConfig { reason: MarketConfigError },
+}
+
+impl MarketFile {
+    pub fn read(path: &str) -> Result<MarketFile, MarketFileError> {
+        let content = fs::read_to_string(path)
+            .map_err(|err| MarketFileError::Unreadable { path: path.to_string(), reason: err.to_string() })?;
+
+        MarketFile::parse(&content)
+    }
+
+    pub fn parse(content: &str) -> Result<MarketFile, MarketFileError> {
+        let file: MarketFile = serde_json::from_str(content)
+            .map_err(|err| MarketFileError::Malformed { reason: err.to_string() })?;
+
+        if let Some((kind, quantity)) = file.goods.iter().find(|(_, quantity)| !quantity.is_finite() || **quantity < 0.) {
+            return Err(MarketFileError::InvalidGoodQuantity { kind, quantity: *quantity });
+        }
+
+        file.get_config().validate()
+            .map_err(|reason| MarketFileError::InvalidConfig { reason })?;
+
+        Ok(file)
+    }
+
+    pub fn get_config(&self) -> MarketConfig {
+        MarketConfig {
+            market_name: self.market_name.clone(),
+            lock_ttl: self.lock_ttl,
+            lock_ttl_tiers: self.lock_ttl_tiers.clone(),
+            buy_earn_percentages: self.buy_earn_percentages.or(self.earn_percentages).unwrap_or_else(PerGood::default_earn_percentages),
+            sell_earn_percentages: self.sell_earn_percentages.or(self.earn_percentages).unwrap_or_else(PerGood::default_earn_percentages),
+            refiller: self.refiller.clone(),
+            refill_policy: self.refill_policy,
+            pricing_curve: self.pricing_curve,
+            audit: self.audit,
+            reserve_floors: self.reserve_floors,
+            capacities: self.capacities,
+            renewal_fee: self.renewal_fee,
+            max_renewals: self.max_renewals,
+            reputation: self.reputation.clone(),
+            trader_limits: self.trader_limits.clone(),
+            lock_deposit_percentage: self.lock_deposit_percentage,
+            lock_clearing: self.lock_clearing,
+            forwards: self.forwards.clone(),
+            lending: self.lending.clone(),
+            competitors: self.competitors.clone(),
+            seed: self.seed,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use unitn_market_2022::good::good_kind::GoodKind::{USD, YEN};
+
+    use super::*;
+
+    #[test]
+    fn parse_minimal_file() {
+        let file = MarketFile::parse(r#"{
+            "goods": { "eur": 1000.0, "usd": 2000.0, "yen": 3000.0, "yuan": 4000.0 },
+            "lock_ttl": 5
+        }"#).unwrap();
+
+        assert_eq!(file.goods, PerGood::new(1000., 2000., 3000., 4000.));
+        assert_eq!(file.get_config(), MarketConfig { lock_ttl: 5, ..MarketConfig::default() });
+    }
+
+    #[test]
+    fn parse_full_file() {
+        let file = MarketFile::parse(r#"{
+            "goods": { "eur": 1000.0, "usd": 2000.0, "yen": 3000.0, "yuan": 4000.0 },
+            "lock_ttl": 5,
+            "market_name": "DogeMarket2",
+            "buy_earn_percentages": { "eur": 0.0, "usd": 2.0, "yen": 3.0, "yuan": 4.0 },
+            "sell_earn_percentages": { "eur": 0.0, "usd": 5.0, "yen": 6.0, "yuan": 7.0 },
+            "refiller": {
+                "min_days_as_importer": 1,
+                "min_days_as_exporter": 2,
+                "min_days_in_shortage": 3,
+                "import_tax": 0.5,
+                "shortage_probability_percent": 0,
+                "careful_fraction": 4.0
+            },
+            "refill_policy": "no_refill",
+            "pricing_curve": "constant_product",
+            "renewal_fee": 2.5,
+            "max_renewals": 1,
+            "lock_ttl_tiers": [{ "from_quantity": 500.0, "lock_ttl": 2 }],
+            "lock_clearing": "batch_auction",
+            "forwards": { "max_days": 10, "carry_percentage": 0.5, "margin_percentage": 20.0 }
+        }"#).unwrap();
+
+        assert_eq!(file.market_name, "DogeMarket2");
+        assert_eq!(file.get_config().buy_earn_percentages, PerGood::new(0., 2., 3., 4.));
+        assert_eq!(file.get_config().sell_earn_percentages, PerGood::new(0., 5., 6., 7.));
+        assert_eq!(file.refiller.min_days_in_shortage, 3);
+        assert_eq!(file.refiller.shortage_probability_percent, 0);
+        assert_eq!(file.refill_policy, RefillPolicyKind::NoRefill);
+        assert_eq!(file.pricing_curve, PricingCurveKind::ConstantProduct);
+        assert_eq!(file.renewal_fee, 2.5);
+        assert_eq!(file.max_renewals, 1);
+        assert_eq!(file.lock_ttl_tiers, vec![LockTtlTier { from_quantity: 500., lock_ttl: 2 }]);
+        assert_eq!(file.lock_clearing, LockClearing::BatchAuction);
+        assert_eq!(file.forwards, ForwardSettings { max_days: 10, carry_percentage: 0.5, margin_percentage: 20. });
+    }
+
+    #[test]
+    fn parse_first_version_file() {
+        let file = MarketFile::parse(r#"{
+            "goods": { "eur": 1000.0, "usd": 2000.0, "yen": 3000.0, "yuan": 4000.0 },
+            "lock_expiry_ticks": 5,
+            "earn_percentages": { "eur": 0.0, "usd": 2.0, "yen": 3.0, "yuan": 4.0 },
+            "sell_earn_percentages": { "eur": 0.0, "usd": 5.0, "yen": 6.0, "yuan": 7.0 }
+        }"#).unwrap();
+
+        let config = file.get_config();
+        assert_eq!(config.lock_ttl, 5);
+        assert_eq!(config.buy_earn_percentages, PerGood::new(0., 2., 3., 4.));
+        assert_eq!(config.sell_earn_percentages, PerGood::new(0., 5., 6., 7.));
+    }
+
+    #[test]
+    fn missing_field() {
+        let err = MarketFile::parse(r#"{ "goods": { "eur": 1000.0, "usd": 2000.0, "yen": 3000.0, "yuan": 4000.0 } }"#).unwrap_err();
+
+        match err {
+            MarketFileError::Malformed { reason } => assert!(reason.contains("lock_ttl"), "{}", reason),
+            other => panic!("{:?}", other)
+        }
+    }
+
+    #[test]
+    fn missing_good() {
+        let err = MarketFile::parse(r#"{ "goods": { "eur": 1000.0, "usd": 2000.0, "yuan": 4000.0 }, "lock_ttl": 5 }"#).unwrap_err();
+
+        match err {
+            MarketFileError::Malformed { reason } => assert!(reason.contains("yen"), "{}", reason),
+            other => panic!("{:?}", other)
+        }
+    }
+
+    #[test]
+    fn invalid_fields() {
+        let err = MarketFile::parse(r#"{ "goods": { "eur": 1000.0, "usd": -2000.0, "yen": 3000.0, "yuan": 4000.0 }, "lock_ttl": 5 }"#).unwrap_err();
+        assert_eq!(err, MarketFileError::InvalidGoodQuantity { kind: USD, quantity: -2000. });
+
+        let err = MarketFile::parse(r#"{ "goods": { "eur": 1000.0, "usd": 2000.0, "yen": 3000.0, "yuan": 4000.0 }, "lock_ttl": 0 }"#).unwrap_err();
+        assert_eq!(err, MarketFileError::InvalidConfig { reason: MarketConfigError::NonPositiveLockTtl });
+
+        let err = MarketFile::parse(r#"{
+            "goods": { "eur": 1000.0, "usd": 2000.0, "yen": 3000.0, "yuan": 4000.0 },
+            "lock_ttl": 5,
+            "buy_earn_percentages": { "eur": 0.0, "usd": 1.0, "yen": -1.0, "yuan": 1.0 }
+        }"#).unwrap_err();
+        assert_eq!(err, MarketFileError::InvalidConfig { reason: MarketConfigError::InvalidBuyEarnPercentage { kind: YEN, percentage: -1. } });
+    }
+
+    #[test]
+    fn unreadable_file() {
+        let err = MarketFile::read("this/file/does/not/exist.json").unwrap_err();
+        assert!(matches!(err, MarketFileError::Unreadable { .. }));
+    }
+}
@@ -2,6 +2,7 @@ use unitn_market_2022::good::good::Good;
 use uuid::Uuid;
 
 use crate::logger::Logger;
+use crate::refill_backend::RefillTrade;
 
 /// Logger that does not log anything.
 pub struct MuteLogger {}
@@ -22,4 +23,16 @@ impl Logger for MuteLogger {
     fn log_buy(&mut self, _token: &Uuid, _success: bool) {}
 
     fn log_sell(&mut self, _token: &Uuid, _success: bool) {}
+
+    fn log_cancel_buy(&mut self, _token: &Uuid, _success: bool) {}
+
+    fn log_cancel_sell(&mut self, _token: &Uuid, _success: bool) {}
+
+    fn log_renew_buy(&mut self, _token: &Uuid, _days: u32, _success: bool) {}
+
+    fn log_renew_sell(&mut self, _token: &Uuid, _days: u32, _success: bool) {}
+
+    fn log_seed(&mut self, _seed: u64) {}
+
+    fn log_refill_trade(&mut self, _trade: &RefillTrade) {}
 }
\ No newline at end of file
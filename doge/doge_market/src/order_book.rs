@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use unitn_market_2022::good::good::Good;
+use unitn_market_2022::good::good_kind::GoodKind;
+use uuid::Uuid;
+
+use crate::serde_defs::{GoodDef, GoodKindDef};
+use crate::service::new_uuid;
+use crate::tick_deque::TickDeque;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OrderSide { Buy, Sell }
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LimitOrderState {
+    Open,
+    /// Traded, with the proceeds and the change waiting to be claimed.
+    Filled,
+    /// Not matched in time, with the escrow waiting to be claimed.
+    Expired,
+    Cancelled,
+    Claimed,
+}
+
+/// Order to trade `quantity` of `kind` for the default good as soon as the price of the market reaches `limit`: at most
+/// `limit` for buys, at least `limit` for sells.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LimitOrder {
+    pub side: OrderSide,
+    #[serde(with = "GoodKindDef")]
+    pub kind: GoodKind,
+    pub quantity: f32,
+    pub limit: f32,
+    pub trader_name: String,
+    pub state: LimitOrderState,
+    /// Goods of the trader held by the order: the limit of a buy, the goods of a sell.
+    #[serde(with = "GoodDef")]
+    pub escrow: Good,
+    /// Goods the order was filled with.
+    #[serde(with = "GoodDef")]
+    pub proceeds: Good,
+}
+
+#[derive(Debug, Clone)]
+pub enum OrderBookError {
+    UnrecognizedUuid,
+    InvalidState { current_state: LimitOrderState },
+}
+
+impl LimitOrder {
+    /// Records that the order traded for `proceeds`, taking what it paid out of the escrow beforehand is up to the caller.
+    pub fn fill(&mut self, proceeds: Good) {
+        self.proceeds = proceeds;
+        self.state = LimitOrderState::Filled;
+    }
+
+    fn take_escrow(&mut self) -> Good {
+        let kind = self.escrow.get_kind();
+        std::mem::replace(&mut self.escrow, Good::new(kind, 0.))
+    }
+}
+
+/// Limit orders left on the market, each open until it is filled, cancelled or reaches its own deadline.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrderBook {
+    orders: HashMap<Uuid, LimitOrder>,
+    deque: TickDeque<Uuid>,
+}
+
+impl Default for OrderBook {
+    fn default() -> Self {
+        OrderBook::new()
+    }
+}
+
+impl OrderBook {
+    pub fn new() -> OrderBook {
+        OrderBook { orders: HashMap::new(), deque: TickDeque::new(1) }
+    }
+
+    /// Keeps `order` open for `ttl` ticks, under a token drawn from `rng`.
+    pub fn place(&mut self, order: LimitOrder, ttl: u32, rng: &mut dyn RngCore) -> Uuid {
+        let uuid = new_uuid(rng);
+        self.orders.insert(uuid, order);
+        self.deque.push_back_with_ttl(uuid, ttl);
+        uuid
+    }
+
+    pub fn get(&self, uuid: &Uuid) -> Option<&LimitOrder> {
+        self.orders.get(uuid)
+    }
+
+    pub fn get_mut(&mut self, uuid: &Uuid) -> Option<&mut LimitOrder> {
+        self.orders.get_mut(uuid)
+    }
+
+    /// Open orders, the ones expiring first first.
+    pub fn get_open_orders(&self) -> Vec<Uuid> {
+        self.deque.iter()
+            .filter(|uuid| matches!(self.orders[*uuid].state, LimitOrderState::Open))
+            .copied()
+            .collect()
+    }
+
+    /// Closes an open order, giving back its escrow.
+    pub fn cancel(&mut self, uuid: &Uuid) -> Result<Good, OrderBookError> {
+        let order = self.orders.get_mut(uuid).ok_or(OrderBookError::UnrecognizedUuid)?;
+        match order.state {
+            LimitOrderState::Open => {
+                order.state = LimitOrderState::Cancelled;
+                Ok(order.take_escrow())
+            }
+            ref other => Err(OrderBookError::InvalidState { current_state: other.clone() })
+        }
+    }
+
+    /// Gives back what is left of a filled or expired order: the proceeds, if filled, then the escrow, if not empty.
+    pub fn claim(&mut self, uuid: &Uuid) -> Result<Vec<Good>, OrderBookError> {
+        let order = self.orders.get_mut(uuid).ok_or(OrderBookError::UnrecognizedUuid)?;
+        let mut claimed = vec![];
+        match order.state {
+            LimitOrderState::Filled => {
+                let kind = order.proceeds.get_kind();
+                claimed.push(std::mem::replace(&mut order.proceeds, Good::new(kind, 0.)));
+            }
+            LimitOrderState::Expired => {}
+            ref other => return Err(OrderBookError::InvalidState { current_state: other.clone() }),
+        }
+
+        let escrow = order.take_escrow();
+        if escrow.get_qty() > 0. {
+            claimed.push(escrow);
+        }
+        order.state = LimitOrderState::Claimed;
+        Ok(claimed)
+    }
+
+    /// Expires the open orders that reached their deadline, returning them.
+    pub fn tick(&mut self) -> Vec<Uuid> {
+        self.deque.tick().into_iter()
+            .filter(|uuid| {
+                let order = self.orders.get_mut(uuid).unwrap();
+                let open = matches!(order.state, LimitOrderState::Open);
+                if open {
+                    order.state = LimitOrderState::Expired;
+                }
+                open
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+    use unitn_market_2022::good::good_kind::GoodKind::{EUR, USD};
+
+    use super::*;
+
+    fn order() -> LimitOrder {
+        LimitOrder {
+            side: OrderSide::Buy,
+            kind: USD,
+            quantity: 10.,
+            limit: 9.,
+            trader_name: "trader".to_string(),
+            state: LimitOrderState::Open,
+            escrow: Good::new(EUR, 9.),
+            proceeds: Good::new(USD, 0.),
+        }
+    }
+
+    #[test]
+    fn lifecycle() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let mut book = OrderBook::new();
+        let expiring = book.place(order(), 1, &mut rng);
+        let cancelled = book.place(order(), 2, &mut rng);
+        let filled = book.place(order(), 2, &mut rng);
+        assert_eq!(book.get_open_orders(), vec![expiring, cancelled, filled]);
+
+        assert_eq!(book.cancel(&cancelled).unwrap().get_qty(), 9.);
+        let order = book.get_mut(&filled).unwrap();
+        order.escrow.split(8.).unwrap();
+        order.fill(Good::new(USD, 10.));
+        assert_eq!(book.get_open_orders(), vec![expiring]);
+        assert!(matches!(book.claim(&expiring), Err(OrderBookError::InvalidState { current_state: LimitOrderState::Open })));
+
+        assert_eq!(book.tick(), vec![expiring]);
+        assert!(book.tick().is_empty());
+
+        let claimed = book.claim(&filled).unwrap();
+        assert_eq!(claimed.iter().map(|good| (good.get_kind(), good.get_qty())).collect::<Vec<_>>(), vec![(USD, 10.), (EUR, 1.)]);
+        assert_eq!(book.claim(&expiring).unwrap()[0].get_qty(), 9.);
+        assert!(matches!(book.claim(&expiring), Err(OrderBookError::InvalidState { current_state: LimitOrderState::Claimed })));
+    }
+}
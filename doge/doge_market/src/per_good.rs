@@ -0,0 +1,52 @@
+use serde::{Deserialize, Serialize};
+use unitn_market_2022::good::consts::DEFAULT_GOOD_KIND;
+use unitn_market_2022::good::good_kind::GoodKind;
+use unitn_market_2022::good::good_kind::GoodKind::{EUR, USD, YEN, YUAN};
+
+/// One value for each [GoodKind] handled by the market.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PerGood<T> {
+    pub eur: T,
+    pub usd: T,
+    pub yen: T,
+    pub yuan: T,
+}
+
+impl<T> PerGood<T> {
+    pub fn new(eur: T, usd: T, yen: T, yuan: T) -> PerGood<T> {
+        PerGood { eur, usd, yen, yuan }
+    }
+
+    pub fn get(&self, kind: GoodKind) -> &T {
+        match kind {
+            EUR => &self.eur,
+            USD => &self.usd,
+            YEN => &self.yen,
+            YUAN => &self.yuan,
+        }
+    }
+
+    pub fn get_mut(&mut self, kind: GoodKind) -> &mut T {
+        match kind {
+            EUR => &mut self.eur,
+            USD => &mut self.usd,
+            YEN => &mut self.yen,
+            YUAN => &mut self.yuan,
+        }
+    }
+
+    /// Pairs every value with its kind, in the order EUR, USD, YEN, YUAN.
+    pub fn iter(&self) -> impl Iterator<Item=(GoodKind, &T)> {
+        [(EUR, &self.eur), (USD, &self.usd), (YEN, &self.yen), (YUAN, &self.yuan)].into_iter()
+    }
+}
+
+impl PerGood<f32> {
+    /// Default exchange rate earn percentages: the default good is exchanged at par, every other good with a 1% spread.
+    pub fn default_earn_percentages() -> PerGood<f32> {
+        let mut percentages = PerGood::new(1., 1., 1., 1.);
+        *percentages.get_mut(DEFAULT_GOOD_KIND) = 0.;
+        percentages
+    }
+}
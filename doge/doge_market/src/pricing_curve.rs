@@ -0,0 +1,154 @@
+use serde::{Deserialize, Serialize};
+use unitn_market_2022::good::good_kind::GoodKind;
+use unitn_market_2022::good::good_kind::GoodKind::EUR;
+
+/// Inventories a [PricingCurve] prices against, re-exported so that curves can be written outside of the crate.
+pub use crate::account_ops::AccountOps;
+
+/// Exchange rate, in EUR per unit of a good, at which the market trades a given quantity of that good, before the spread
+/// is applied. Curves are only asked about goods other than EUR, which is always traded at par.
+pub trait PricingCurve {
+    /// Rate for the market selling `of_quantity` of `of_kind`. `of_quantity` is positive and at most the reservable quantity.
+    fn buy_exchange_rate(&self, ops: &AccountOps, of_kind: GoodKind, of_quantity: f32) -> f32;
+
+    /// Rate for the market buying `of_quantity` of `of_kind`. `of_quantity` is positive.
+    fn sell_exchange_rate(&self, ops: &AccountOps, of_kind: GoodKind, of_quantity: f32) -> f32;
+}
+
+/// The formula of `doge_strategy/explanation.md`: buys are priced against all the EUR the market will own once the
+/// outstanding locks are settled, sells against all the units of the good it will own.
+#[derive(Debug, Clone, Copy)]
+pub struct FutureInventoryCurve;
+
+impl PricingCurve for FutureInventoryCurve {
+    fn buy_exchange_rate(&self, ops: &AccountOps, of_kind: GoodKind, of_quantity: f32) -> f32 {
+        (ops.get_reservable_quantity_by_kind(EUR) + ops.get_reserved_quantity_by_kind(EUR) + ops.get_future_quantity_by_kind(EUR)) /
+            (ops.get_reservable_quantity_by_kind(of_kind) - of_quantity)
+    }
+
+    fn sell_exchange_rate(&self, ops: &AccountOps, of_kind: GoodKind, of_quantity: f32) -> f32 {
+        ops.get_reservable_quantity_by_kind(EUR) /
+            (ops.get_reservable_quantity_by_kind(of_kind) + ops.get_reserved_quantity_by_kind(of_kind) + ops.get_future_quantity_by_kind(of_kind) + of_quantity)
+    }
+}
+
+/// Keeps the product of the reservable EUR and the reservable good constant across the trade, like an x*y=k pool.
+#[derive(Debug, Clone, Copy)]
+pub struct ConstantProductCurve;
+
+impl PricingCurve for ConstantProductCurve {
+    fn buy_exchange_rate(&self, ops: &AccountOps, of_kind: GoodKind, of_quantity: f32) -> f32 {
+        ops.get_reservable_quantity_by_kind(EUR) / (ops.get_reservable_quantity_by_kind(of_kind) - of_quantity)
+    }
+
+    fn sell_exchange_rate(&self, ops: &AccountOps, of_kind: GoodKind, of_quantity: f32) -> f32 {
+        ops.get_reservable_quantity_by_kind(EUR) / (ops.get_reservable_quantity_by_kind(of_kind) + of_quantity)
+    }
+}
+
+/// Starts from the spot rate of the reservable inventories and moves it by `slippage` times the fraction of the
+/// inventory that is traded. Sells are priced from the inventory after the sale, so that a good the market does not
+/// hold still has a price, and never go below zero.
+#[derive(Debug, Clone, Copy)]
+pub struct LinearSlippageCurve {
+    pub slippage: f32,
+}
+
+impl PricingCurve for LinearSlippageCurve {
+    fn buy_exchange_rate(&self, ops: &AccountOps, of_kind: GoodKind, of_quantity: f32) -> f32 {
+        let inventory = ops.get_reservable_quantity_by_kind(of_kind);
+        let spot = ops.get_reservable_quantity_by_kind(EUR) / inventory;
+
+        spot * (1. + self.slippage * of_quantity / inventory)
+    }
+
+    fn sell_exchange_rate(&self, ops: &AccountOps, of_kind: GoodKind, of_quantity: f32) -> f32 {
+        let inventory = ops.get_reservable_quantity_by_kind(of_kind) + of_quantity;
+        let spot = ops.get_reservable_quantity_by_kind(EUR) / inventory;
+
+        spot * (1. - self.slippage * of_quantity / inventory).max(0.)
+    }
+}
+
+/// Serializable choice of [PricingCurve], as set in the market configuration.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PricingCurveKind {
+    #[default]
+    FutureInventory,
+    ConstantProduct,
+    LinearSlippage { slippage: f32 },
+}
+
+impl PricingCurveKind {
+    pub fn build(&self) -> Box<dyn PricingCurve> {
+        match *self {
+            PricingCurveKind::FutureInventory => Box::new(FutureInventoryCurve),
+            PricingCurveKind::ConstantProduct => Box::new(ConstantProductCurve),
+            PricingCurveKind::LinearSlippage { slippage } => Box::new(LinearSlippageCurve { slippage }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_approx_eq::assert_approx_eq;
+    use unitn_market_2022::good::good::Good;
+    use unitn_market_2022::good::good_kind::GoodKind::{USD, YEN, YUAN};
+
+    use doge_common::account::Account;
+
+    use crate::ledger::Ledger;
+
+    use super::*;
+
+    fn ops() -> AccountOps {
+        let assets = Account {
+            eur: Good::new(EUR, 10_000.),
+            usd: Good::new(USD, 5_000.),
+            yen: Good::new(YEN, 0.),
+            yuan: Good::new(YUAN, 0.),
+        };
+        let futures = Account {
+            eur: Good::new(EUR, 2_000.),
+            usd: Good::new(USD, 0.),
+            yen: Good::new(YEN, 0.),
+            yuan: Good::new(YUAN, 0.),
+        };
+
+        AccountOps { assets: Ledger::of_account(&assets), reservations: Ledger::new_empty(), futures: Ledger::of_account(&futures), deposits: Ledger::new_empty() }
+    }
+
+    #[test]
+    fn constant_product_keeps_product() {
+        let ops = ops();
+        let curve = ConstantProductCurve;
+
+        let paid = 1_000. * curve.buy_exchange_rate(&ops, USD, 1_000.);
+        assert_approx_eq!((10_000. + paid) * (5_000. - 1_000.), 10_000. * 5_000., 1.);
+
+        let received = 1_000. * curve.sell_exchange_rate(&ops, USD, 1_000.);
+        assert_approx_eq!((10_000. - received) * (5_000. + 1_000.), 10_000. * 5_000., 1.);
+    }
+
+    #[test]
+    fn linear_slippage() {
+        let ops = ops();
+        let curve = LinearSlippageCurve { slippage: 0.5 };
+
+        assert_approx_eq!(curve.buy_exchange_rate(&ops, USD, 1_000.), 2. * 1.1);
+        assert_approx_eq!(curve.sell_exchange_rate(&ops, USD, 1_000.), 10_000. / 6_000. * (1. - 0.5 / 6.));
+
+        let curve = LinearSlippageCurve { slippage: 100. };
+        assert_eq!(curve.sell_exchange_rate(&ops, USD, 1_000.), 0.);
+        assert!(curve.sell_exchange_rate(&ops, YEN, 1.).is_finite());
+    }
+
+    #[test]
+    fn future_inventory_counts_futures() {
+        let ops = ops();
+
+        assert_approx_eq!(FutureInventoryCurve.buy_exchange_rate(&ops, USD, 1_000.), 12_000. / 4_000.);
+        assert_approx_eq!(ConstantProductCurve.buy_exchange_rate(&ops, USD, 1_000.), 10_000. / 4_000.);
+    }
+}
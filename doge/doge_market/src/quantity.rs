@@ -0,0 +1,195 @@
+use std::cmp::Ordering;
+use std::fmt::{Display, Formatter};
+use std::ops::{Add, AddAssign, Sub, SubAssign};
+
+use serde::{Deserialize, Serialize};
+
+/// Fixed-point quantity of a good, counted in whole units plus millionths of a unit, used for the market bookkeeping.
+///
+/// Sums and differences are exact, so reserving, paying and expiring a lock gives back exactly what was taken.
+/// Conversions from `f32` happen only at the [Market](unitn_market_2022::market::Market) boundary and always give the
+/// same result for the same `f32`, so the quantity deposited by a lock is the same one withdrawn when it is settled.
+/// The units are a sign and a `u128`, so every finite `f32`, `f32::MAX` included, is represented. Quantities beyond
+/// that saturate at [Quantity::MAX] or [Quantity::MIN], which read back as infinite like an overflowing `f32`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Quantity {
+    /// Never set for zero, so that equal quantities have equal fields.
+    #[serde(default)]
+    negative: bool,
+    units: u128,
+    /// Always in `0..MICROS_PER_UNIT`, and counted away from zero like the units.
+    micros: i64,
+}
+
+const MICROS_PER_UNIT: i64 = 1_000_000;
+
+impl Quantity {
+    pub const ZERO: Quantity = Quantity { negative: false, units: 0, micros: 0 };
+    pub const MAX: Quantity = Quantity { negative: false, units: u128::MAX, micros: MICROS_PER_UNIT - 1 };
+    pub const MIN: Quantity = Quantity { negative: true, units: u128::MAX, micros: MICROS_PER_UNIT - 1 };
+
+    /// Rounds to the nearest millionth. Infinities clamp to [Quantity::MAX] and [Quantity::MIN], NaN becomes zero.
+    pub fn from_f32(value: f32) -> Quantity {
+        if value.is_nan() {
+            return Quantity::ZERO;
+        }
+        if value.is_infinite() {
+            return if value > 0. { Quantity::MAX } else { Quantity::MIN };
+        }
+
+        let magnitude = value.abs() as f64;
+        let units = magnitude.floor();
+        let micros = ((magnitude - units) * MICROS_PER_UNIT as f64).round() as i64;
+
+        Quantity::normalized(value < 0., units as u128, micros)
+    }
+
+    /// Saturated quantities are infinite.
+    pub fn to_f32(self) -> f32 {
+        if self.units == u128::MAX {
+            return if self.negative { f32::NEG_INFINITY } else { f32::INFINITY };
+        }
+
+        let magnitude = self.units as f64 + self.micros as f64 / MICROS_PER_UNIT as f64;
+        (if self.negative { -magnitude } else { magnitude }) as f32
+    }
+
+    /// `micros` is in `0..2 * MICROS_PER_UNIT`.
+    fn normalized(negative: bool, units: u128, micros: i64) -> Quantity {
+        let units = units.saturating_add((micros / MICROS_PER_UNIT) as u128);
+        let micros = if units == u128::MAX { MICROS_PER_UNIT - 1 } else { micros % MICROS_PER_UNIT };
+
+        Quantity { negative: negative && (units, micros) != (0, 0), units, micros }
+    }
+
+    fn magnitude(self) -> (u128, i64) {
+        (self.units, self.micros)
+    }
+
+    fn negated(self) -> Quantity {
+        Quantity::normalized(!self.negative, self.units, self.micros)
+    }
+
+    fn sum(self, rhs: Quantity) -> Quantity {
+        if self.negative == rhs.negative {
+            Quantity::normalized(self.negative, self.units.saturating_add(rhs.units), self.micros + rhs.micros)
+        } else {
+            self.sub_magnitudes(rhs)
+        }
+    }
+
+    /// Difference of the magnitudes of `self` and `rhs`, with the sign of the larger one.
+    fn sub_magnitudes(self, rhs: Quantity) -> Quantity {
+        let (larger, smaller) = if self.magnitude() >= rhs.magnitude() { (self, rhs) } else { (rhs, self) };
+        if larger.units == u128::MAX {
+            return larger;
+        }
+
+        let (units, micros) = if larger.micros >= smaller.micros {
+            (larger.units - smaller.units, larger.micros - smaller.micros)
+        } else {
+            (larger.units - smaller.units - 1, larger.micros + MICROS_PER_UNIT - smaller.micros)
+        };
+        Quantity::normalized(larger.negative, units, micros)
+    }
+}
+
+impl Ord for Quantity {
+    fn cmp(&self, other: &Quantity) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, false) => self.magnitude().cmp(&other.magnitude()),
+            (true, true) => other.magnitude().cmp(&self.magnitude()),
+            (negative, _) => if negative { Ordering::Less } else { Ordering::Greater },
+        }
+    }
+}
+
+impl PartialOrd for Quantity {
+    fn partial_cmp(&self, other: &Quantity) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Add for Quantity {
+    type Output = Quantity;
+
+    fn add(self, rhs: Quantity) -> Quantity {
+        self.sum(rhs)
+    }
+}
+
+impl AddAssign for Quantity {
+    fn add_assign(&mut self, rhs: Quantity) {
+        *self = *self + rhs;
+    }
+}
+
+impl Sub for Quantity {
+    type Output = Quantity;
+
+    fn sub(self, rhs: Quantity) -> Quantity {
+        self.sum(rhs.negated())
+    }
+}
+
+impl SubAssign for Quantity {
+    fn sub_assign(&mut self, rhs: Quantity) {
+        *self = *self - rhs;
+    }
+}
+
+impl Display for Quantity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:.2}", self.to_f32())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conversions() {
+        assert_eq!(Quantity::from_f32(1.5), Quantity { negative: false, units: 1, micros: 500_000 });
+        assert_eq!(Quantity::from_f32(-0.25), Quantity { negative: true, units: 0, micros: 250_000 });
+        assert_eq!(Quantity::from_f32(0.1).to_f32(), 0.1);
+        assert_eq!(Quantity::from_f32(f32::NAN), Quantity::ZERO);
+        assert_eq!(Quantity::from_f32(-0.), Quantity::ZERO);
+        assert_eq!(Quantity::from_f32(f32::MAX).to_f32(), f32::MAX);
+        assert_eq!(Quantity::from_f32(f32::MIN).to_f32(), f32::MIN);
+        assert_eq!(Quantity::from_f32(f32::INFINITY).to_f32(), f32::INFINITY);
+        assert_eq!(Quantity::from_f32(f32::NEG_INFINITY).to_f32(), f32::NEG_INFINITY);
+        assert!(Quantity::from_f32(-0.25) < Quantity::ZERO);
+        assert!(Quantity::from_f32(-1.5) < Quantity::from_f32(-0.25));
+    }
+
+    #[test]
+    fn arithmetic() {
+        let quantity = |value: f32| Quantity::from_f32(value);
+
+        assert_eq!(quantity(1.25) - quantity(2.5), quantity(-1.25));
+        assert_eq!(quantity(-1.75) + quantity(0.5), quantity(-1.25));
+        assert_eq!(quantity(-1.75) - quantity(-1.75), Quantity::ZERO);
+        assert_eq!(quantity(0.75) + quantity(0.5), quantity(1.25));
+
+        // like f32, a sum beyond f32::MAX is infinite, and stays so
+        let overflowed = quantity(f32::MAX) + quantity(f32::MAX);
+        assert_eq!(overflowed, Quantity::MAX);
+        assert_eq!((overflowed - quantity(1.)).to_f32(), f32::INFINITY);
+    }
+
+    #[test]
+    fn no_drift() {
+        let mut total = Quantity::from_f32(100_000.);
+        let step = Quantity::from_f32(0.1);
+
+        for _ in 0..1_000_000 {
+            total += step;
+        }
+        for _ in 0..1_000_000 {
+            total -= step;
+        }
+
+        assert_eq!(total, Quantity::from_f32(100_000.));
+    }
+}
@@ -0,0 +1,153 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use unitn_market_2022::good::consts::DEFAULT_GOOD_KIND;
+use unitn_market_2022::good::good::Good;
+use unitn_market_2022::good::good_kind::GoodKind;
+use unitn_market_2022::market::Market;
+
+use crate::ledger::Ledger;
+use crate::quantity::Quantity;
+use crate::refill_policy::Refill;
+
+/// Number of times the quantity to buy is scaled down before giving up on an import the cash cannot afford.
+const MAX_QUOTES: u32 = 5;
+
+/// Carries out the conversions of a [RefillPolicy](crate::refill_policy::RefillPolicy) by trading with other markets
+/// instead of converting at the default rates. The exported good is sold to the supplier paying the most for it, and
+/// the imported good is bought from the supplier asking the least, with what the sale brought in. The trades are locked
+/// and paid like those of any trader.
+#[derive(Clone)]
+pub struct MarketRefillBackend {
+    suppliers: Vec<Rc<RefCell<dyn Market>>>,
+    trader_name: String,
+}
+
+/// Trade of a [MarketRefillBackend] with a supplier: `sold` given for `bought`.
+#[derive(Debug, Clone)]
+pub struct RefillTrade {
+    pub supplier: &'static str,
+    pub sold: Good,
+    pub bought: Good,
+    /// Token of the lock, `None` if the supplier refused it.
+    pub token: Option<String>,
+    /// Whether the lock was paid. A lock that was not is left open at the supplier until it expires.
+    pub paid: bool,
+}
+
+impl MarketRefillBackend {
+    pub fn new(suppliers: Vec<Rc<RefCell<dyn Market>>>, trader_name: String) -> MarketRefillBackend {
+        MarketRefillBackend { suppliers, trader_name }
+    }
+
+    /// Trades as much of `refill` as the suppliers accept, taking the goods sold out of `assets` and putting the goods
+    /// bought, and whatever was not spent, back in. Returns the trades tried, in order.
+    pub fn restock(&self, assets: &mut Ledger, refill: &Refill) -> Vec<RefillTrade> {
+        let mut trades = Vec::new();
+        assets.withdraw(refill.export, Quantity::from_f32(refill.export_quantity)).unwrap();
+        let mut exported = Good::new(refill.export, refill.export_quantity);
+
+        let mut cash = if refill.export == DEFAULT_GOOD_KIND {
+            exported
+        } else {
+            let cash = self.sell(&mut exported, &mut trades);
+            assets.deposit(exported.get_kind(), Quantity::from_f32(exported.get_qty()));
+            if let Some(cash) = cash { cash } else { return trades; }
+        };
+
+        if refill.import != DEFAULT_GOOD_KIND {
+            if let Some(imported) = self.buy(refill.import, &mut cash, &mut trades) {
+                assets.deposit(imported.get_kind(), Quantity::from_f32(imported.get_qty()));
+            }
+        }
+        assets.deposit(cash.get_kind(), Quantity::from_f32(cash.get_qty()));
+        trades
+    }
+
+    /// Sells the whole of `good` for the default good.
+    fn sell(&self, good: &mut Good, trades: &mut Vec<RefillTrade>) -> Option<Good> {
+        let (kind, quantity) = (good.get_kind(), good.get_qty());
+        let (supplier, offer) = self.suppliers.iter()
+            .filter_map(|supplier| supplier.borrow().get_sell_price(kind, quantity).ok().map(|offer| (supplier, offer)))
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))?;
+
+        let mut supplier = supplier.borrow_mut();
+        let token = supplier.lock_sell(kind, quantity, offer, self.trader_name.clone()).ok();
+        let cash = token.clone().and_then(|token| supplier.sell(token, good).ok());
+        trades.push(RefillTrade {
+            supplier: supplier.get_name(),
+            sold: Good::new(kind, quantity),
+            bought: Good::new(DEFAULT_GOOD_KIND, offer),
+            token,
+            paid: cash.is_some(),
+        });
+        cash
+    }
+
+    /// Buys as much of `kind` as `cash` affords. Prices grow with the quantity, so the quantity that the cash is worth
+    /// at the default rate is scaled down until a supplier asks for no more than the cash.
+    fn buy(&self, kind: GoodKind, cash: &mut Good, trades: &mut Vec<RefillTrade>) -> Option<Good> {
+        let mut quantity = cash.get_qty() * kind.get_default_exchange_rate();
+
+        for _ in 0..MAX_QUOTES {
+            let (supplier, bid) = self.suppliers.iter()
+                .filter_map(|supplier| supplier.borrow().get_buy_price(kind, quantity).ok().map(|bid| (supplier, bid)))
+                .min_by(|(_, a), (_, b)| a.total_cmp(b))?;
+
+            if bid <= cash.get_qty() {
+                let mut supplier = supplier.borrow_mut();
+                let token = supplier.lock_buy(kind, quantity, bid, self.trader_name.clone()).ok();
+                let bought = token.clone().and_then(|token| supplier.buy(token, cash).ok());
+                trades.push(RefillTrade {
+                    supplier: supplier.get_name(),
+                    sold: Good::new(DEFAULT_GOOD_KIND, bid),
+                    bought: Good::new(kind, quantity),
+                    token,
+                    paid: bought.is_some(),
+                });
+                return bought;
+            }
+            // a little below the cash, so that rounding does not make it fall short again
+            quantity *= cash.get_qty() / bid * 0.99;
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_approx_eq::assert_approx_eq;
+    use unitn_market_2022::good::good_kind::GoodKind::{EUR, USD, YEN, YUAN};
+
+    use doge_common::account::Account;
+
+    use crate::test_fixtures::stub_market;
+
+    use super::*;
+
+    #[test]
+    fn restock_from_the_best_supplier() {
+        let cheap = stub_market(0.1);
+        let dear = stub_market(0.2);
+        let backend = MarketRefillBackend::new(vec![dear.clone(), cheap.clone()], "DogeMarket".to_string());
+
+        let mut assets = Ledger::of_account(&Account {
+            eur: Good::new(EUR, 0.),
+            usd: Good::new(USD, 1_000.),
+            yen: Good::new(YEN, 0.),
+            yuan: Good::new(YUAN, 0.),
+        });
+        let trades = backend.restock(&mut assets, &Refill { export: USD, export_quantity: 100., import: YEN, import_quantity: 0. });
+        assert_eq!(trades.len(), 2);
+        assert!(trades.iter().all(|trade| trade.paid));
+
+        // the usd are sold for 90% of their value, which buys yen for 99% of it, at 110% of their value
+        let cash = 100. / USD.get_default_exchange_rate() * 0.9;
+        assert_eq!(assets.get_quantity_by_kind(USD), 900.);
+        assert_approx_eq!(assets.get_quantity_by_kind(YEN), cash * 0.99 / 1.1 * YEN.get_default_exchange_rate(), 1.);
+        assert_approx_eq!(assets.get_quantity_by_kind(EUR), cash * 0.01, 0.01);
+        assert_approx_eq!(cheap.borrow().eur, cash * 0.99 - cash, 0.01);
+        assert_eq!(dear.borrow().eur, 0.);
+    }
+}
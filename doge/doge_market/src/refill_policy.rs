@@ -0,0 +1,164 @@
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use unitn_market_2022::good::good_kind::GoodKind;
+
+use crate::ledger::Ledger;
+use crate::per_good::PerGood;
+use crate::quantity::Quantity;
+use crate::refiller::{GoodRefiller, RefillerSettings};
+
+/// Conversion decided by a [RefillPolicy]: `export_quantity` of `export` for `import`, of which the default rates less
+/// the import tax give `import_quantity`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Refill {
+    pub export: GoodKind,
+    pub export_quantity: f32,
+    pub import: GoodKind,
+    pub import_quantity: f32,
+}
+
+impl Refill {
+    /// Carries the conversion out at the default rates.
+    pub fn apply(&self, assets: &mut Ledger) {
+        assets.withdraw(self.export, Quantity::from_f32(self.export_quantity)).unwrap();
+        assets.deposit(self.import, Quantity::from_f32(self.import_quantity));
+    }
+}
+
+/// Decides, once a day, which good the market converts into which to keep trading all of them.
+pub trait RefillPolicy {
+    /// Conversion of the day, if any, given the goods of the market and the ones reserved to traders. Only the goods in
+    /// `assets` can be exported. Any random choice is drawn from `rng`.
+    fn plan_refill(&mut self, assets: &Ledger, reservations: &Ledger, rng: &mut dyn RngCore) -> Option<Refill>;
+
+    /// Trackers to save in snapshots, for the policies that follow the goods over the days.
+    fn get_trackers(&self) -> Option<&GoodRefiller> {
+        None
+    }
+}
+
+/// Never converts anything, so that the goods traders drain stay drained.
+pub struct NoRefill;
+
+impl RefillPolicy for NoRefill {
+    fn plan_refill(&mut self, _assets: &Ledger, _reservations: &Ledger, _rng: &mut dyn RngCore) -> Option<Refill> {
+        None
+    }
+}
+
+/// Converts the good furthest above its share of the value of the market into the good furthest below its share, as
+/// soon as one is off by more than `tolerance_percentage` of the value of the market. Values are taken at the default
+/// rates, and the conversion loses the import tax of the [RefillerSettings].
+pub struct TargetWeightRebalancer {
+    pub weights: PerGood<f32>,
+    pub tolerance_percentage: f32,
+    pub import_tax: f32,
+}
+
+impl RefillPolicy for TargetWeightRebalancer {
+    fn plan_refill(&mut self, assets: &Ledger, reservations: &Ledger, _rng: &mut dyn RngCore) -> Option<Refill> {
+        let value_of = |kind: GoodKind| (assets.get_quantity_by_kind(kind) + reservations.get_quantity_by_kind(kind)) / kind.get_default_exchange_rate();
+        let total_value: f32 = self.weights.iter().map(|(kind, _)| value_of(kind)).sum();
+        let total_weight: f32 = self.weights.iter().map(|(_, weight)| weight).sum();
+        if total_value <= 0. || total_weight <= 0. {
+            return None;
+        }
+
+        // value each good is above its share of the market, negative when it is below
+        let excesses: Vec<(GoodKind, f32)> = self.weights.iter()
+            .map(|(kind, weight)| (kind, value_of(kind) - total_value * weight / total_weight))
+            .collect();
+        let (export, surplus) = *excesses.iter().max_by(|(_, a), (_, b)| a.total_cmp(b))?;
+        let (import, deficit) = excesses.iter().map(|(kind, excess)| (*kind, -excess)).max_by(|(_, a), (_, b)| a.total_cmp(b))?;
+        if surplus.max(deficit) <= total_value * self.tolerance_percentage / 100. {
+            return None;
+        }
+
+        // the reserved goods count towards the share, but only the free ones can leave the market
+        let available_value = assets.get_quantity_by_kind(export) / export.get_default_exchange_rate();
+        let export_value = surplus.min(deficit / (1. - self.import_tax)).min(available_value);
+        if export_value <= 0. {
+            return None;
+        }
+
+        Some(Refill {
+            export,
+            export_quantity: export_value * export.get_default_exchange_rate(),
+            import,
+            import_quantity: export_value * (1. - self.import_tax) * import.get_default_exchange_rate(),
+        })
+    }
+}
+
+/// Serializable choice of [RefillPolicy], as set in the market configuration.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RefillPolicyKind {
+    NoRefill,
+    /// The [GoodRefiller], tuned by the [RefillerSettings].
+    #[default]
+    ImporterExporter,
+    /// The [TargetWeightRebalancer], with `weights` relative to each other.
+    TargetWeights { weights: PerGood<f32>, tolerance_percentage: f32 },
+}
+
+impl RefillPolicyKind {
+    pub fn build(&self, settings: &RefillerSettings) -> Box<dyn RefillPolicy> {
+        self.restore(settings, None)
+    }
+
+    /// Like [RefillPolicyKind::build], resuming the importer/exporter policy from `trackers` when there are some.
+    pub fn restore(&self, settings: &RefillerSettings, trackers: Option<GoodRefiller>) -> Box<dyn RefillPolicy> {
+        match *self {
+            RefillPolicyKind::NoRefill => Box::new(NoRefill),
+            RefillPolicyKind::ImporterExporter => Box::new(trackers.unwrap_or_else(|| GoodRefiller::with_settings(settings.clone()))),
+            RefillPolicyKind::TargetWeights { weights, tolerance_percentage } =>
+                Box::new(TargetWeightRebalancer { weights, tolerance_percentage, import_tax: settings.import_tax }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_approx_eq::assert_approx_eq;
+    use unitn_market_2022::good::good::Good;
+    use unitn_market_2022::good::good_kind::GoodKind::{EUR, USD, YEN, YUAN};
+
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use doge_common::account::Account;
+
+    use super::*;
+
+    fn ledger(eur: f32, usd: f32, yen: f32, yuan: f32) -> Ledger {
+        Ledger::of_account(&Account {
+            eur: Good::new(EUR, eur),
+            usd: Good::new(USD, usd * USD.get_default_exchange_rate()),
+            yen: Good::new(YEN, yen * YEN.get_default_exchange_rate()),
+            yuan: Good::new(YUAN, yuan * YUAN.get_default_exchange_rate()),
+        })
+    }
+
+    #[test]
+    fn rebalance_toward_the_weights() {
+        let mut policy = TargetWeightRebalancer { weights: PerGood::new(1., 1., 1., 1.), tolerance_percentage: 5., import_tax: 0.5 };
+
+        // everything is worth 1000 eur, yen is 400 above its share and usd 200 below
+        let mut assets = ledger(240., 50., 650., 60.);
+        let reservations = ledger(0., 0., 0., 0.);
+        let rng = &mut ChaCha8Rng::seed_from_u64(0);
+        let refill = policy.plan_refill(&assets, &reservations, rng).unwrap();
+        assert_eq!((refill.export, refill.import), (YEN, USD));
+        assert_approx_eq!(refill.export_quantity, 400. * YEN.get_default_exchange_rate(), 1.);
+        assert_approx_eq!(refill.import_quantity, 200. * USD.get_default_exchange_rate(), 0.01);
+
+        refill.apply(&mut assets);
+        assert_approx_eq!(assets.get_quantity_by_kind(USD), 250. * USD.get_default_exchange_rate(), 0.01);
+
+        // within the tolerance, or with nothing free to export, there is nothing to do
+        assert_eq!(policy.plan_refill(&ledger(260., 240., 250., 250.), &reservations, rng), None);
+        assert_eq!(policy.plan_refill(&ledger(250., 0., 0., 250.), &ledger(0., 0., 500., 0.), rng), None);
+        assert_eq!(NoRefill.plan_refill(&assets, &reservations, rng), None);
+    }
+}
@@ -1,14 +1,15 @@
 use std::f32;
 
-use rand::Rng;
+use rand::{Rng, RngCore};
+use serde::{Deserialize, Serialize};
 use unitn_market_2022::good::consts::STARTING_CAPITAL;
-use unitn_market_2022::good::good::Good;
 use unitn_market_2022::good::good_kind::GoodKind;
 use unitn_market_2022::good::good_kind::GoodKind::{EUR, USD, YEN, YUAN};
 
-use doge_common::account::Account;
-
+use crate::ledger::Ledger;
+use crate::refill_policy::{Refill, RefillPolicy};
 use crate::refiller::TrackerState::{Exporter, Importer, ImporterExporter, Shortage};
+use crate::serde_defs::GoodKindDef;
 
 const MIN_DAYS_AS_IMPORTER: u32 = 100;
 const MIN_DAYS_AS_EXPORTER: u32 = 100;
@@ -18,11 +19,39 @@ const IMPORT_TAX: f32 = 0.25;
 const SHORTAGE_PROBABILITY_PERCENT: i32 = 5;
 const CAREFUL_FRACTION: f32 = 8.;
 
-fn compute_importer_exporter_threshold(kind: GoodKind) -> f32 {
-    STARTING_CAPITAL / CAREFUL_FRACTION * kind.get_default_exchange_rate()
+/// Tunables of the [GoodRefiller]. The defaults are the values the market has always been run with.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RefillerSettings {
+    pub min_days_as_importer: u32,
+    pub min_days_as_exporter: u32,
+    pub min_days_in_shortage: u32,
+    /// Fraction of the exported quantity lost in each conversion, in `[0, 1)`.
+    pub import_tax: f32,
+    /// Chance, in `[0, 100]`, that the least abundant good goes in shortage instead of being refilled.
+    pub shortage_probability_percent: i32,
+    /// A good is refilled when its quantity drops below `STARTING_CAPITAL / careful_fraction` (in EUR).
+    pub careful_fraction: f32,
+}
+
+impl Default for RefillerSettings {
+    fn default() -> Self {
+        RefillerSettings {
+            min_days_as_importer: MIN_DAYS_AS_IMPORTER,
+            min_days_as_exporter: MIN_DAYS_AS_EXPORTER,
+            min_days_in_shortage: MIN_DAYS_IN_SHORTAGE,
+            import_tax: IMPORT_TAX,
+            shortage_probability_percent: SHORTAGE_PROBABILITY_PERCENT,
+            careful_fraction: CAREFUL_FRACTION,
+        }
+    }
+}
+
+fn compute_importer_exporter_threshold(kind: GoodKind, careful_fraction: f32) -> f32 {
+    STARTING_CAPITAL / careful_fraction * kind.get_default_exchange_rate()
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum TrackerState {
     Importer { days_passed_as_importer: u32 },
     Exporter { days_passed_as_exporter: u32 },
@@ -30,8 +59,9 @@ pub enum TrackerState {
     Shortage { days_passed_in_shortage: u32 },
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GoodTracker {
+    #[serde(with = "GoodKindDef")]
     kind: GoodKind,
     mode: TrackerState,
 }
@@ -45,17 +75,17 @@ impl GoodTracker {
         }
     }
 
-    fn advance_a_day(&mut self) {
+    fn advance_a_day(&mut self, settings: &RefillerSettings) {
         match self.mode {
             Importer { days_passed_as_importer } => {
-                if days_passed_as_importer < MIN_DAYS_AS_IMPORTER {
+                if days_passed_as_importer < settings.min_days_as_importer {
                     self.mode = Importer { days_passed_as_importer: days_passed_as_importer + 1 }
                 } else {
                     self.mode = ImporterExporter
                 }
             }
             Exporter { days_passed_as_exporter } => {
-                if days_passed_as_exporter < MIN_DAYS_AS_EXPORTER {
+                if days_passed_as_exporter < settings.min_days_as_exporter {
                     self.mode = Exporter { days_passed_as_exporter: days_passed_as_exporter + 1 }
                 } else {
                     self.mode = ImporterExporter
@@ -63,7 +93,7 @@ impl GoodTracker {
             }
             ImporterExporter => {} // remain in this state
             Shortage { days_passed_in_shortage } => {
-                if days_passed_in_shortage < MIN_DAYS_IN_SHORTAGE {
+                if days_passed_in_shortage < settings.min_days_in_shortage {
                     self.mode = Shortage { days_passed_in_shortage: days_passed_in_shortage + 1 }
                 } else {
                     self.mode = ImporterExporter
@@ -73,8 +103,9 @@ impl GoodTracker {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GoodRefiller {
+    settings: RefillerSettings,
     eur_tracker: GoodTracker,
     usd_tracker: GoodTracker,
     yen_tracker: GoodTracker,
@@ -82,8 +113,9 @@ pub struct GoodRefiller {
 }
 
 impl GoodRefiller {
-    pub fn new() -> GoodRefiller {
+    pub fn with_settings(settings: RefillerSettings) -> GoodRefiller {
         GoodRefiller {
+            settings,
             eur_tracker: GoodTracker::new(EUR),
             usd_tracker: GoodTracker::new(USD),
             yen_tracker: GoodTracker::new(YEN),
@@ -101,20 +133,21 @@ impl GoodRefiller {
     }
 
     fn increase_days(&mut self) {
-        self.eur_tracker.advance_a_day();
-        self.usd_tracker.advance_a_day();
-        self.yen_tracker.advance_a_day();
-        self.yuan_tracker.advance_a_day();
+        self.eur_tracker.advance_a_day(&self.settings);
+        self.usd_tracker.advance_a_day(&self.settings);
+        self.yen_tracker.advance_a_day(&self.settings);
+        self.yuan_tracker.advance_a_day(&self.settings);
     }
 
-    fn get_total_quantity_of_kind(assets: &Account, reservations: &Account, kind: GoodKind) -> f32 {
+    fn get_total_quantity_of_kind(assets: &Ledger, reservations: &Ledger, kind: GoodKind) -> f32 {
         assets.get_quantity_by_kind(kind) + reservations.get_quantity_by_kind(kind)
     }
 
-    fn search_least_abundant_kind(&mut self, assets: &Account, reservations: &Account) -> Option<GoodKind> {
+    fn search_least_abundant_kind(&mut self, assets: &Ledger, reservations: &Ledger) -> Option<GoodKind> {
+        let careful_fraction = self.settings.careful_fraction;
         [&mut self.eur_tracker, &mut self.usd_tracker, &mut self.yen_tracker, &mut self.yuan_tracker].into_iter()
             .filter(|tracker| matches!(tracker.mode, Importer { .. } | ImporterExporter))
-            .filter(|tracker| GoodRefiller::get_total_quantity_of_kind(assets, reservations, tracker.kind) < compute_importer_exporter_threshold(tracker.kind))
+            .filter(|tracker| GoodRefiller::get_total_quantity_of_kind(assets, reservations, tracker.kind) < compute_importer_exporter_threshold(tracker.kind, careful_fraction))
             .map(|tracker| tracker.kind)
             .reduce(|min_so_far, curr| {
                 if GoodRefiller::get_total_quantity_of_kind(assets, reservations, min_so_far) / min_so_far.get_default_exchange_rate() <=
@@ -126,10 +159,11 @@ impl GoodRefiller {
             })
     }
 
-    fn search_most_abundant_kind(&mut self, assets: &Account, reservations: &Account) -> Option<GoodKind> {
+    fn search_most_abundant_kind(&mut self, assets: &Ledger, reservations: &Ledger) -> Option<GoodKind> {
+        let careful_fraction = self.settings.careful_fraction;
         [&mut self.eur_tracker, &mut self.usd_tracker, &mut self.yen_tracker, &mut self.yuan_tracker].into_iter()
             .filter(|tracker| matches!(tracker.mode, Exporter { .. } | ImporterExporter))
-            .filter(|tracker| GoodRefiller::get_total_quantity_of_kind(assets, reservations, tracker.kind) >= compute_importer_exporter_threshold(tracker.kind) * 2.)
+            .filter(|tracker| GoodRefiller::get_total_quantity_of_kind(assets, reservations, tracker.kind) >= compute_importer_exporter_threshold(tracker.kind, careful_fraction) * 2.)
             .map(|tracker| tracker.kind)
             .reduce(|max_so_far, curr| {
                 if GoodRefiller::get_total_quantity_of_kind(assets, reservations, max_so_far) / max_so_far.get_default_exchange_rate() >=
@@ -141,27 +175,31 @@ impl GoodRefiller {
             })
     }
 
-    pub fn refill_goods(&mut self, assets: &mut Account, reservations: &Account) {
+}
+
+impl RefillPolicy for GoodRefiller {
+    /// Converts the most abundant good into the least abundant one, advancing the trackers by a day.
+    fn plan_refill(&mut self, assets: &Ledger, reservations: &Ledger, rng: &mut dyn RngCore) -> Option<Refill> {
         self.increase_days();
 
-        let least_abundant_good = if let Some(good) = self.search_least_abundant_kind(assets, reservations) { good } else { return; };
-        let most_abundant_good = if let Some(good) = self.search_most_abundant_kind(assets, reservations) { good } else { return; };
+        let least_abundant_good = self.search_least_abundant_kind(assets, reservations)?;
+        let most_abundant_good = self.search_most_abundant_kind(assets, reservations)?;
 
-        if rand::thread_rng().gen_range(0..100) < SHORTAGE_PROBABILITY_PERCENT {
+        if rng.gen_range(0..100) < self.settings.shortage_probability_percent {
             self.get_tracker_from_kind_mut(least_abundant_good).mode = Shortage { days_passed_in_shortage: 0 };
-            return;
+            return None;
         }
 
         // quantity needed by the least abundant good to reach the careful value
-        let least_abundant_good_needed_quantity = compute_importer_exporter_threshold(least_abundant_good) - GoodRefiller::get_total_quantity_of_kind(assets, reservations, least_abundant_good);
+        let least_abundant_good_needed_quantity = compute_importer_exporter_threshold(least_abundant_good, self.settings.careful_fraction) - GoodRefiller::get_total_quantity_of_kind(assets, reservations, least_abundant_good);
         if least_abundant_good_needed_quantity < 0. {
-            return;
+            return None;
         }
 
         // quantity that the most abundant good can cede, remaining above the careful value
-        let most_abundant_good_available_quantity = GoodRefiller::get_total_quantity_of_kind(assets, reservations, most_abundant_good) - compute_importer_exporter_threshold(most_abundant_good);
+        let most_abundant_good_available_quantity = GoodRefiller::get_total_quantity_of_kind(assets, reservations, most_abundant_good) - compute_importer_exporter_threshold(most_abundant_good, self.settings.careful_fraction);
         if most_abundant_good_available_quantity < 0. {
-            return;
+            return None;
         }
 
         if let ImporterExporter = self.get_tracker_from_kind_mut(least_abundant_good).mode {
@@ -185,20 +223,33 @@ impl GoodRefiller {
         // least_abundant_good__needed_quantity_kind_most = 7
         // most_abundant_good__available_quantity = 3
         // most_abundant_good__quantity_to_withdraw => 3
-        let most_abundant_good_quantity_to_withdraw = f32::min(least_abundant_good_needed_quantity_kind_most / (1. - IMPORT_TAX), most_abundant_good_available_quantity);
+        let most_abundant_good_quantity_to_withdraw = f32::min(least_abundant_good_needed_quantity_kind_most / (1. - self.settings.import_tax), most_abundant_good_available_quantity);
         let most_abundant_good_quantity_to_withdraw_eur = most_abundant_good_quantity_to_withdraw / most_abundant_good.get_default_exchange_rate();
 
-        let least_abundant_good_quantity_to_deposit = most_abundant_good_quantity_to_withdraw_eur * least_abundant_good.get_default_exchange_rate() * (1. - IMPORT_TAX);
+        let least_abundant_good_quantity_to_deposit = most_abundant_good_quantity_to_withdraw_eur * least_abundant_good.get_default_exchange_rate() * (1. - self.settings.import_tax);
 
-        assets.withdraw(most_abundant_good, most_abundant_good_quantity_to_withdraw).unwrap();
+        Some(Refill {
+            export: most_abundant_good,
+            export_quantity: most_abundant_good_quantity_to_withdraw,
+            import: least_abundant_good,
+            import_quantity: least_abundant_good_quantity_to_deposit,
+        })
+    }
 
-        assets.deposit(Good::new(least_abundant_good, least_abundant_good_quantity_to_deposit));
+    fn get_trackers(&self) -> Option<&GoodRefiller> {
+        Some(self)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use unitn_market_2022::good::consts::{DEFAULT_EUR_USD_EXCHANGE_RATE, DEFAULT_EUR_YEN_EXCHANGE_RATE, DEFAULT_EUR_YUAN_EXCHANGE_RATE};
+    use unitn_market_2022::good::good::Good;
+
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use doge_common::account::Account;
 
     use super::*;
 
@@ -206,7 +257,7 @@ mod tests {
     fn days_should_pass() {
         let mut tracker = GoodTracker::new(EUR);
         tracker.mode = Importer { days_passed_as_importer: 0 };
-        tracker.advance_a_day();
+        tracker.advance_a_day(&RefillerSettings::default());
         if let Importer { days_passed_as_importer } = tracker.mode {
             assert_eq!(days_passed_as_importer, 1);
         } else {
@@ -216,29 +267,38 @@ mod tests {
 
     #[test]
     pub fn test() {
-        let mut assets = Account {
+        let mut assets = Ledger::of_account(&Account {
             eur: Good::new(EUR, 500_000.),
             usd: Good::new(USD, 25_000. * DEFAULT_EUR_USD_EXCHANGE_RATE),
             yen: Good::new(YEN, 250_000. * DEFAULT_EUR_YEN_EXCHANGE_RATE),
             yuan: Good::new(YUAN, 250_000. * DEFAULT_EUR_YUAN_EXCHANGE_RATE),
-        };
+        });
         println!("Initial assets content: {}", assets);
 
-        let reservations = Account::new_empty();
+        let reservations = Ledger::new_empty();
         println!("Initial reservations content: {}", assets);
 
-        let mut refiller = GoodRefiller::new();
+        let mut refiller = GoodRefiller::with_settings(RefillerSettings::default());
+        let rng = &mut ChaCha8Rng::seed_from_u64(0);
 
-        refiller.refill_goods(&mut assets, &reservations);
+        if let Some(refill) = refiller.plan_refill(&assets, &reservations, rng) {
+            refill.apply(&mut assets);
+        }
         println!("Refill assets #1: {}", assets);
 
-        refiller.refill_goods(&mut assets, &reservations);
+        if let Some(refill) = refiller.plan_refill(&assets, &reservations, rng) {
+            refill.apply(&mut assets);
+        }
         println!("Refill assets #2: {}", assets);
 
-        refiller.refill_goods(&mut assets, &reservations);
+        if let Some(refill) = refiller.plan_refill(&assets, &reservations, rng) {
+            refill.apply(&mut assets);
+        }
         println!("Refill assets #3: {}", assets);
 
-        refiller.refill_goods(&mut assets, &reservations);
+        if let Some(refill) = refiller.plan_refill(&assets, &reservations, rng) {
+            refill.apply(&mut assets);
+        }
         println!("Refill assets #4: {}", assets);
     }
 }
\ No newline at end of file
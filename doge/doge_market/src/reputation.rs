@@ -0,0 +1,128 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+/// Fraction of its goods a partially paid lock has to settle to count as completed rather than expired.
+pub const PARTIAL_FILL_THRESHOLD: f32 = 0.5;
+
+/// How the locks of a trader ended.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TraderRecord {
+    /// Locks that were paid.
+    pub completed: u32,
+    /// Locks that were left to expire or cancelled.
+    pub expired: u32,
+}
+
+impl TraderRecord {
+    /// Fraction of the ended locks that were paid, `1` for a trader with no ended locks.
+    pub fn get_score(&self) -> f32 {
+        let ended = self.completed + self.expired;
+        if ended == 0 {
+            1.
+        } else {
+            self.completed as f32 / ended as f32
+        }
+    }
+}
+
+/// Records of every trader that locked on the market, by trader name. Cancelled locks count as expired, or locking
+/// and cancelling would hold goods back from the other traders for free.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ReputationTable {
+    records: HashMap<String, TraderRecord>,
+}
+
+impl ReputationTable {
+    pub fn new() -> ReputationTable {
+        ReputationTable { records: HashMap::new() }
+    }
+
+    pub fn record_completed(&mut self, trader_name: &str) {
+        self.records.entry(trader_name.to_string()).or_default().completed += 1;
+    }
+
+    pub fn record_expired(&mut self, trader_name: &str) {
+        self.records.entry(trader_name.to_string()).or_default().expired += 1;
+    }
+
+    /// Records a lock settled for `fraction` of its goods, as completed only from [PARTIAL_FILL_THRESHOLD] on.
+    pub fn record_partial(&mut self, trader_name: &str, fraction: f32) {
+        if fraction >= PARTIAL_FILL_THRESHOLD {
+            self.record_completed(trader_name);
+        } else {
+            self.record_expired(trader_name);
+        }
+    }
+
+    pub fn get_record(&self, trader_name: &str) -> TraderRecord {
+        self.records.get(trader_name).copied().unwrap_or_default()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item=(&String, &TraderRecord)> {
+        self.records.iter()
+    }
+}
+
+/// How the market treats traders that abandon their locks. The defaults treat every trader the same.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ReputationSettings {
+    /// Number of ended locks before the score of a trader is taken into account.
+    pub grace_locks: u32,
+    /// Earn percentage added to the spread of a trader with score `0`, scaled linearly down to nothing for score `1`.
+    pub spread_penalty: f32,
+    /// Below this score, in `[0, 1]`, a trader can lock at most its score times the reservable quantity.
+    pub limit_below: f32,
+    /// Below this score, in `[0, 1]`, every lock of the trader is refused.
+    pub refuse_below: f32,
+}
+
+impl Default for ReputationSettings {
+    fn default() -> Self {
+        ReputationSettings {
+            grace_locks: 5,
+            spread_penalty: 0.,
+            limit_below: 0.,
+            refuse_below: 0.,
+        }
+    }
+}
+
+impl ReputationSettings {
+    /// Score the market uses for the trader, `1` while it is in its grace period.
+    pub fn get_effective_score(&self, record: &TraderRecord) -> f32 {
+        if record.completed + record.expired < self.grace_locks {
+            1.
+        } else {
+            record.get_score()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scores() {
+        let mut table = ReputationTable::new();
+        table.record_completed("alice");
+        table.record_expired("alice");
+        table.record_expired("alice");
+        table.record_expired("alice");
+
+        assert_eq!(table.get_record("alice"), TraderRecord { completed: 1, expired: 3 });
+        assert_eq!(table.get_record("alice").get_score(), 0.25);
+        assert_eq!(table.get_record("bob").get_score(), 1.);
+
+        let settings = ReputationSettings { grace_locks: 5, ..ReputationSettings::default() };
+        assert_eq!(settings.get_effective_score(&table.get_record("alice")), 1.);
+        table.record_expired("alice");
+        assert_eq!(settings.get_effective_score(&table.get_record("alice")), 0.2);
+
+        table.record_partial("bob", 0.5);
+        table.record_partial("bob", 0.01);
+        assert_eq!(table.get_record("bob"), TraderRecord { completed: 1, expired: 1 });
+    }
+}
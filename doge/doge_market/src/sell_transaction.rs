@@ -1,25 +1,46 @@
+use serde::{Deserialize, Serialize};
 use unitn_market_2022::good::consts::DEFAULT_GOOD_KIND;
 use unitn_market_2022::good::good::Good;
 use unitn_market_2022::good::good_kind::GoodKind;
 
-use doge_common::account::WithdrawError;
-
 use crate::account_ops::{AccountOps, SellPriceComputationError};
+use crate::pricing_curve::PricingCurve;
+use crate::quantity::Quantity;
+use crate::serde_defs::GoodDef;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SellTx {
+    #[serde(with = "GoodDef")]
     pub sell: Good,
+    #[serde(with = "GoodDef")]
     pub offer: Good,
     pub state: SellTxState,
+    /// Number of times the lock was renewed.
+    #[serde(default)]
+    pub renewals: u32,
+    /// Name the trader gave when locking.
+    #[serde(default)]
+    pub trader_name: String,
+    /// Quantity of the offer good the trader deposited to back the lock.
+    #[serde(default)]
+    pub deposit: f32,
 }
 
-#[derive(Debug, Clone)]
-pub enum SellTxState { Reserved, Paid, Expired }
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum SellTxState {
+    Reserved,
+    Paid,
+    /// Paid only in part, with `sell` of the good in exchange for `offer` of the offer good.
+    PartiallyPaid { sell: f32, offer: f32 },
+    Expired,
+    Cancelled,
+}
 
 #[derive(Debug)]
 pub struct SellTxProposal {
     pub sell: Good,
     pub offer: Good,
+    pub trader_name: String,
 }
 
 #[derive(Debug, Clone)]
@@ -28,6 +49,8 @@ pub enum SellTxReservationError {
     NonPositiveOffer,
     ExceedsReservableQuantity { reservable: f32 },
     OfferTooHigh { highest: f32 },
+    /// The offer is in the good to sell, which cannot pay for itself unless it is the default good.
+    UnsupportedOffer,
 }
 
 #[derive(Debug, Clone)]
@@ -40,35 +63,75 @@ pub enum SellTxPaymentError {
 #[derive(Debug, Clone)]
 pub struct ExpireError;
 
+#[derive(Debug, Clone)]
+pub enum SellTxCancelError {
+    InvalidState { current_state: SellTxState },
+}
+
+#[derive(Debug, Clone)]
+pub enum SellTxRenewalError {
+    InvalidState { current_state: SellTxState },
+    MaxRenewalsReached { max_renewals: u32 },
+    WrongGoodKind { fee_kind: GoodKind },
+    InsufficientGoodQuantity { fee: f32 },
+}
+
 impl SellTx {
-    pub fn reserve(ops: &mut AccountOps, proposal: &SellTxProposal) -> Result<SellTx, SellTxReservationError> {
+    /// Locks the goods of the proposal, holding `deposit` of the offer good on behalf of the trader until the lock ends.
+    /// The offer can be of any good other than the one to sell, priced with [AccountOps::compute_cross_sell_price].
+    pub fn reserve(ops: &mut AccountOps, proposal: &SellTxProposal, curve: &dyn PricingCurve, exchange_rate_earn_percentage: f32, deposit: f32) -> Result<SellTx, SellTxReservationError> {
+        SellTx::check_proposal(ops, proposal)?;
+
+        let price = if proposal.offer.get_kind() == DEFAULT_GOOD_KIND {
+            ops.compute_sell_price(curve, proposal.sell.get_kind(), proposal.sell.get_qty(), exchange_rate_earn_percentage)
+        } else {
+            ops.compute_cross_sell_price(curve, proposal.sell.get_kind(), proposal.sell.get_qty(), proposal.offer.get_kind(), exchange_rate_earn_percentage)
+        };
+        match price {
+            Ok(sell_price) => {
+                if proposal.offer.get_qty() > sell_price {
+                    Err(SellTxReservationError::OfferTooHigh { highest: sell_price })
+                } else {
+                    Ok(SellTx::lock(ops, proposal, deposit))
+                }
+            }
+            Err(err) => match err {
+                SellPriceComputationError::NegativeExchangeEarnRatePercentage => unreachable!(),
+                SellPriceComputationError::NonPositiveQuantity => unreachable!(),
+                SellPriceComputationError::SameKind => Err(SellTxReservationError::UnsupportedOffer),
+            }
+        }
+    }
+
+    /// Like [SellTx::reserve], for an offer the caller already priced, without a deposit.
+    pub fn reserve_at_offer(ops: &mut AccountOps, proposal: &SellTxProposal) -> Result<SellTx, SellTxReservationError> {
+        SellTx::check_proposal(ops, proposal)?;
+        Ok(SellTx::lock(ops, proposal, 0.))
+    }
+
+    fn check_proposal(ops: &AccountOps, proposal: &SellTxProposal) -> Result<(), SellTxReservationError> {
         if proposal.sell.get_qty() <= 0. {
             Err(SellTxReservationError::NonPositiveSell)
         } else if proposal.offer.get_qty() <= 0. {
             Err(SellTxReservationError::NonPositiveOffer)
-        } else if proposal.offer.get_qty() > ops.get_reservable_quantity_by_kind(proposal.offer.get_kind()) {
+        } else if Quantity::from_f32(proposal.offer.get_qty()) > ops.assets.get(proposal.offer.get_kind()) {
             Err(SellTxReservationError::ExceedsReservableQuantity { reservable: ops.get_reservable_quantity_by_kind(proposal.offer.get_kind()) })
         } else {
-            match ops.compute_sell_price(proposal.sell.get_kind(), proposal.sell.get_qty(), if let DEFAULT_GOOD_KIND = proposal.sell.get_kind() { 0. } else { 1. }) {
-                Ok(sell_price) => {
-                    if proposal.offer.get_qty() > sell_price {
-                        Err(SellTxReservationError::OfferTooHigh { highest: sell_price })
-                    } else {
-                        ops.assets.withdraw(proposal.offer.get_kind(), proposal.offer.get_qty()).unwrap();
-                        ops.reservations.deposit(proposal.offer.clone());
-                        ops.futures.deposit(proposal.sell.clone());
-
-                        Ok(SellTx { sell: proposal.sell.clone(), offer: proposal.offer.clone(), state: SellTxState::Reserved })
-                    }
-                }
-                Err(err) => match err {
-                    SellPriceComputationError::NegativeExchangeEarnRatePercentage => unreachable!(),
-                    SellPriceComputationError::NonPositiveQuantity => unreachable!()
-                }
-            }
+            Ok(())
         }
     }
 
+    fn lock(ops: &mut AccountOps, proposal: &SellTxProposal, deposit: f32) -> SellTx {
+        let reservation = Quantity::from_f32(proposal.offer.get_qty());
+        ops.assets.withdraw(proposal.offer.get_kind(), reservation).unwrap();
+        ops.reservations.deposit(proposal.offer.get_kind(), reservation);
+        ops.futures.deposit(proposal.sell.get_kind(), Quantity::from_f32(proposal.sell.get_qty()));
+        ops.deposits.deposit(proposal.offer.get_kind(), Quantity::from_f32(deposit));
+
+        SellTx { sell: proposal.sell.clone(), offer: proposal.offer.clone(), state: SellTxState::Reserved, renewals: 0, trader_name: proposal.trader_name.clone(), deposit }
+    }
+
+    /// Takes the good to sell out of `with`, giving back the offer together with the deposit.
     pub fn sell(&mut self, ops: &mut AccountOps, with: &mut Good) -> Result<Good, SellTxPaymentError> {
         match &self.state {
             SellTxState::Reserved => {
@@ -78,64 +141,101 @@ impl SellTx {
                     Err(SellTxPaymentError::InsufficientGoodQuantity { pre_agreed: self.sell.get_qty() })
                 } else {
                     let from_seller = with.split(self.sell.get_qty()).unwrap();
-                    ops.assets.deposit(from_seller);
-
-                    let from_us = match ops.reservations.withdraw(self.offer.get_kind(), self.offer.get_qty()) {
-                        Ok(good) => good,
-                        Err(err) => match err {
-                            WithdrawError::NonPositiveWithdrawQuantity => unreachable!(),
-                            WithdrawError::WithdrawExcessiveQuantity { withdrawable } => {
-                                // Reached 0 quantity, but went a little below 0 due to approximation errors
-                                if withdrawable > 0. {
-                                    ops.reservations.withdraw(self.offer.get_kind(), withdrawable).unwrap();
-                                }
-                                Good::new(self.offer.get_kind(), self.offer.get_qty())
-                            }
-                        }
-                    };
-
-                    if let Err(WithdrawError::WithdrawExcessiveQuantity { withdrawable }) = ops.futures.withdraw(self.sell.get_kind(), self.sell.get_qty()) {
-                        // Reached 0 quantity, but went a little below 0 due to approximation errors
-                        if withdrawable > 0. {
-                            ops.futures.withdraw(self.sell.get_kind(), withdrawable).unwrap();
-                        }
-                    };
+                    ops.assets.deposit(from_seller.get_kind(), Quantity::from_f32(from_seller.get_qty()));
+
+                    ops.reservations.withdraw(self.offer.get_kind(), Quantity::from_f32(self.offer.get_qty())).unwrap();
+                    ops.futures.withdraw(self.sell.get_kind(), Quantity::from_f32(self.sell.get_qty())).unwrap();
+                    ops.deposits.withdraw(self.offer.get_kind(), Quantity::from_f32(self.deposit)).unwrap();
 
                     self.state = SellTxState::Paid;
 
-                    Ok(from_us)
+                    Ok(Good::new(self.offer.get_kind(), self.offer.get_qty() + self.deposit))
                 }
             }
             other => Err(SellTxPaymentError::InvalidState { current_state: other.clone() })
         }
     }
 
+    /// Like [SellTx::sell], but if `with` falls short of the good to sell it is taken whole in exchange for the same
+    /// fraction of the offer, given back with the whole deposit. The rest of the lock is released and the transaction
+    /// becomes `PartiallyPaid`.
+    pub fn sell_partially(&mut self, ops: &mut AccountOps, with: &mut Good) -> Result<Good, SellTxPaymentError> {
+        if !matches!(self.state, SellTxState::Reserved) || with.get_kind() != self.sell.get_kind() || with.get_qty() >= self.sell.get_qty() {
+            return self.sell(ops, with);
+        }
+
+        if with.get_qty() <= 0. {
+            return Err(SellTxPaymentError::InsufficientGoodQuantity { pre_agreed: self.sell.get_qty() });
+        }
+        let offered = self.offer.get_qty() * (with.get_qty() / self.sell.get_qty());
+
+        let from_seller = with.split(with.get_qty()).unwrap();
+        ops.assets.deposit(from_seller.get_kind(), Quantity::from_f32(from_seller.get_qty()));
+
+        let reservation = Quantity::from_f32(self.offer.get_qty());
+        ops.reservations.withdraw(self.offer.get_kind(), reservation).unwrap();
+        ops.assets.deposit(self.offer.get_kind(), reservation - Quantity::from_f32(offered));
+        ops.futures.withdraw(self.sell.get_kind(), Quantity::from_f32(self.sell.get_qty())).unwrap();
+        ops.deposits.withdraw(self.offer.get_kind(), Quantity::from_f32(self.deposit)).unwrap();
+
+        self.state = SellTxState::PartiallyPaid { sell: from_seller.get_qty(), offer: offered };
+
+        Ok(Good::new(self.offer.get_kind(), offered + self.deposit))
+    }
+
     pub fn expire(&mut self, ops: &mut AccountOps) {
         if let SellTxState::Reserved = self.state {
-            let reservation = match ops.reservations.withdraw(self.offer.get_kind(), self.offer.get_qty()) {
-                Ok(good) => good,
-                Err(err) => match err {
-                    WithdrawError::NonPositiveWithdrawQuantity => unreachable!(),
-                    WithdrawError::WithdrawExcessiveQuantity { withdrawable } => {
-                        // Reached 0 quantity, but went a little below 0 due to approximation errors
-                        if withdrawable > 0. {
-                            ops.reservations.withdraw(self.offer.get_kind(), withdrawable).unwrap();
-                        }
-                        Good::new(self.offer.get_kind(), self.offer.get_qty())
+            self.release(ops);
+            self.state = SellTxState::Expired;
+        }
+    }
+
+    /// Gives up the lock before it expires, releasing it exactly like [SellTx::expire].
+    pub fn cancel(&mut self, ops: &mut AccountOps) -> Result<(), SellTxCancelError> {
+        match &self.state {
+            SellTxState::Reserved => {
+                self.release(ops);
+                self.state = SellTxState::Cancelled;
+                Ok(())
+            }
+            other => Err(SellTxCancelError::InvalidState { current_state: other.clone() })
+        }
+    }
+
+    /// Takes `fee` of `fee_kind` out of `with` to keep the lock alive longer. Postponing its expiration is up to the caller.
+    pub fn renew(&mut self, ops: &mut AccountOps, with: &mut Good, fee_kind: GoodKind, fee: f32, max_renewals: u32) -> Result<(), SellTxRenewalError> {
+        match &self.state {
+            SellTxState::Reserved => {
+                if self.renewals >= max_renewals {
+                    Err(SellTxRenewalError::MaxRenewalsReached { max_renewals })
+                } else if with.get_kind() != fee_kind {
+                    Err(SellTxRenewalError::WrongGoodKind { fee_kind })
+                } else if with.get_qty() < fee {
+                    Err(SellTxRenewalError::InsufficientGoodQuantity { fee })
+                } else {
+                    if fee > 0. {
+                        let from_trader = with.split(fee).unwrap();
+                        ops.assets.deposit(from_trader.get_kind(), Quantity::from_f32(from_trader.get_qty()));
                     }
+
+                    self.renewals += 1;
+                    Ok(())
                 }
-            };
+            }
+            other => Err(SellTxRenewalError::InvalidState { current_state: other.clone() })
+        }
+    }
 
-            ops.assets.deposit(reservation);
+    /// Gives the offer back to the market. The deposit is kept by the market, as the trader did not sell.
+    fn release(&self, ops: &mut AccountOps) {
+        let reservation = Quantity::from_f32(self.offer.get_qty());
+        ops.reservations.withdraw(self.offer.get_kind(), reservation).unwrap();
+        ops.assets.deposit(self.offer.get_kind(), reservation);
 
-            if let Err(WithdrawError::WithdrawExcessiveQuantity { withdrawable }) = ops.futures.withdraw(self.sell.get_kind(), self.sell.get_qty()) {
-                // Reached 0 quantity, but went a little below 0 due to approximation errors
-                if withdrawable > 0. {
-                    ops.futures.withdraw(self.sell.get_kind(), withdrawable).unwrap();
-                }
-            };
+        ops.futures.withdraw(self.sell.get_kind(), Quantity::from_f32(self.sell.get_qty())).unwrap();
 
-            self.state = SellTxState::Expired;
-        }
+        let deposit = Quantity::from_f32(self.deposit);
+        ops.deposits.withdraw(self.offer.get_kind(), deposit).unwrap();
+        ops.assets.deposit(self.offer.get_kind(), deposit);
     }
 }
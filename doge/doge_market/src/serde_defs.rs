@@ -0,0 +1,31 @@
+//! Serde definitions for the types of `unitn_market_2022` that the market snapshots, used via
+//! `#[serde(with = "...")]`.
+
+use serde::{Deserialize, Serialize};
+use unitn_market_2022::good::good::Good;
+use unitn_market_2022::good::good_kind::GoodKind;
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "GoodKind")]
+#[allow(clippy::upper_case_acronyms)]
+pub enum GoodKindDef {
+    EUR,
+    YEN,
+    USD,
+    YUAN,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "Good")]
+pub struct GoodDef {
+    #[serde(getter = "Good::get_kind", with = "GoodKindDef")]
+    kind: GoodKind,
+    #[serde(getter = "Good::get_qty")]
+    quantity: f32,
+}
+
+impl From<GoodDef> for Good {
+    fn from(def: GoodDef) -> Good {
+        Good::new(def.kind, def.quantity)
+    }
+}
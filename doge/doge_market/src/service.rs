@@ -1,19 +1,55 @@
 use std::collections::HashMap;
 
+use rand::{Rng, RngCore};
+use serde::{Deserialize, Serialize};
+use unitn_market_2022::good::consts::DEFAULT_GOOD_KIND;
 use unitn_market_2022::good::good::Good;
 use unitn_market_2022::good::good_kind::GoodKind;
 use uuid::Uuid;
 
 use crate::account_ops::AccountOps;
-use crate::buy_transaction::{BuyTx, BuyTxPaymentError, BuyTxProposal, BuyTxReservationError, BuyTxState};
-use crate::sell_transaction::{SellTx, SellTxPaymentError, SellTxProposal, SellTxReservationError, SellTxState};
+use crate::auditor::{AuditedOperation, AuditedTransactions, AuditReport, Auditor};
+use crate::basket_transaction::{BasketTx, BasketTxPaymentError, BasketTxProposal, BasketTxReservationError};
+use crate::buy_transaction::{BuyTx, BuyTxCancelError, BuyTxPaymentError, BuyTxProposal, BuyTxRenewalError, BuyTxReservationError, BuyTxState};
+use crate::forward_contract::{ForwardTx, ForwardTxAgreementError, ForwardTxClaimError, ForwardTxDeliveryError, ForwardTxProposal, ForwardTxState};
+use crate::ledger::Ledger;
+use crate::market_config::TraderLimits;
+use crate::order_book::OrderSide;
+use crate::per_good::PerGood;
+use crate::pricing_curve::PricingCurve;
+use crate::quantity::Quantity;
+use crate::reputation::ReputationTable;
+use crate::sell_transaction::{SellTx, SellTxCancelError, SellTxPaymentError, SellTxProposal, SellTxRenewalError, SellTxReservationError, SellTxState};
 use crate::tick_deque::TickDeque;
+use crate::trader_history::TraderVolume;
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct TxService {
     ops: AccountOps,
     buys: HashMap<Uuid, BuyTx>,
     sells: HashMap<Uuid, SellTx>,
+    #[serde(default)]
+    baskets: HashMap<Uuid, BasketTx>,
+    #[serde(default)]
+    forwards: HashMap<Uuid, ForwardTx>,
     deque: TickDeque<Uuid>,
+    #[serde(default)]
+    reputation: ReputationTable,
+    /// Not part of snapshots: whoever restores the service decides again whether to audit it.
+    #[serde(skip)]
+    auditor: Option<Auditor>,
+    /// Not part of snapshots either, they come with the configuration of the market.
+    #[serde(skip)]
+    limits: TraderLimits,
+    /// Locks of each trader that may still be waiting to be paid, so that the limits of a trader are checked against
+    /// its own locks only. The ones settled since are dropped the next time the limits of the trader are checked.
+    #[serde(skip)]
+    open_locks: HashMap<String, Vec<Uuid>>,
+}
+
+enum TraderLimitError {
+    TooManyLocks,
+    ExceedsTraderExposure { kind: GoodKind, max_quantity: f32 },
 }
 
 #[derive(Debug)]
@@ -22,6 +58,12 @@ pub enum ServiceBuyReservationError {
     NonPositiveBid,
     ExceedsReservableQuantity { reservable: f32 },
     BidTooLow { lowest: f32 },
+    /// The bid is in the good to buy, which cannot pay for itself unless it is the default good.
+    UnsupportedBid,
+    /// The trader already holds as many locks waiting to be paid as it is allowed to.
+    TooManyLocks,
+    /// The trader can have at most `max_quantity` more of the good reserved.
+    ExceedsTraderExposure { max_quantity: f32 },
 }
 
 #[derive(Debug)]
@@ -30,6 +72,49 @@ pub enum ServiceSellReservationError {
     NonPositiveOffer,
     ExceedsReservableQuantity { reservable: f32 },
     OfferTooHigh { highest: f32 },
+    /// The offer is in the good to sell, which cannot pay for itself unless it is the default good.
+    UnsupportedOffer,
+    /// The trader already holds as many locks waiting to be paid as it is allowed to.
+    TooManyLocks,
+    /// The trader can have at most `max_quantity` more of the default good reserved.
+    ExceedsTraderExposure { max_quantity: f32 },
+}
+
+#[derive(Debug)]
+pub enum ServiceBasketReservationError {
+    EmptyBasket,
+    MixedBidKinds,
+    /// The trader does not have room for one more lock for every leg.
+    TooManyLocks,
+    /// The trader can have at most `max_quantity` more of `kind` reserved.
+    ExceedsTraderExposure { kind: GoodKind, max_quantity: f32 },
+    Leg { index: usize, error: BuyTxReservationError },
+}
+
+#[derive(Debug)]
+pub enum ServiceForwardAgreementError {
+    NonPositiveQuantity,
+    NonPositivePrice,
+    ExceedsReservableQuantity { reservable: f32 },
+    /// The trader already holds as many locks waiting to be paid as it is allowed to.
+    TooManyLocks,
+    /// The trader can have at most `max_quantity` more of the good set aside.
+    ExceedsTraderExposure { max_quantity: f32 },
+}
+
+#[derive(Debug)]
+pub enum ServiceForwardDeliveryError {
+    UnrecognizedUuid,
+    InvalidState { current_state: ForwardTxState },
+    AlreadyDelivered,
+    WrongGoodKind { pre_agreed: GoodKind },
+    InsufficientGoodQuantity { pre_agreed: f32 },
+}
+
+#[derive(Debug)]
+pub enum ServiceForwardClaimError {
+    UnrecognizedUuid,
+    InvalidState { current_state: ForwardTxState },
 }
 
 #[derive(Debug)]
@@ -48,14 +133,156 @@ pub enum ServiceSellError {
     InsufficientGoodQuantity { pre_agreed: f32 },
 }
 
+#[derive(Debug)]
+pub enum ServiceBasketBuyError {
+    UnrecognizedUuid,
+    InvalidState { current_state: BuyTxState },
+    WrongGoodKind { pre_agreed: GoodKind },
+    InsufficientGoodQuantity { pre_agreed: f32 },
+}
+
+#[derive(Debug)]
+pub enum ServiceCancelBuyError {
+    UnrecognizedUuid,
+    InvalidState { current_state: BuyTxState },
+}
+
+#[derive(Debug)]
+pub enum ServiceCancelSellError {
+    UnrecognizedUuid,
+    InvalidState { current_state: SellTxState },
+}
+
+#[derive(Debug)]
+pub enum ServiceRenewBuyError {
+    UnrecognizedUuid,
+    InvalidState { current_state: BuyTxState },
+    MaxRenewalsReached { max_renewals: u32 },
+    WrongGoodKind { fee_kind: GoodKind },
+    InsufficientGoodQuantity { fee: f32 },
+}
+
+#[derive(Debug)]
+pub enum ServiceRenewSellError {
+    UnrecognizedUuid,
+    InvalidState { current_state: SellTxState },
+    MaxRenewalsReached { max_renewals: u32 },
+    WrongGoodKind { fee_kind: GoodKind },
+    InsufficientGoodQuantity { fee: f32 },
+}
+
 impl TxService {
     pub fn new(ops: AccountOps, max_ticks: u32) -> TxService {
         TxService {
             ops,
             buys: HashMap::new(),
             sells: HashMap::new(),
+            baskets: HashMap::new(),
+            forwards: HashMap::new(),
             deque: TickDeque::new(max_ticks),
+            reputation: ReputationTable::new(),
+            auditor: None,
+            limits: TraderLimits::default(),
+            open_locks: HashMap::new(),
+        }
+    }
+
+    /// Enforces `limits` on the reservations made from now on, counting the locks already waiting to be paid.
+    pub fn set_trader_limits(&mut self, limits: TraderLimits) {
+        self.limits = limits;
+
+        // a restored service does not know the locks of each trader yet
+        let mut open_locks: HashMap<String, Vec<Uuid>> = HashMap::new();
+        for uuid in self.deque.iter() {
+            if let Some((trader_name, _)) = self.get_open_lock(uuid) {
+                open_locks.entry(trader_name.to_string()).or_default().push(*uuid);
+            }
         }
+        self.open_locks = open_locks;
+    }
+
+    /// Trader of a lock waiting to be paid and the goods it takes out of the market, every leg of a basket on its own.
+    /// `None` once the lock is settled.
+    fn get_open_lock(&self, uuid: &Uuid) -> Option<(&str, Vec<&Good>)> {
+        if let Some(tx) = self.buys.get(uuid) {
+            matches!(tx.state, BuyTxState::Reserved).then(|| (tx.trader_name.as_str(), vec![&tx.buy]))
+        } else if let Some(tx) = self.sells.get(uuid) {
+            matches!(tx.state, SellTxState::Reserved).then(|| (tx.trader_name.as_str(), vec![&tx.offer]))
+        } else if let Some(tx) = self.baskets.get(uuid) {
+            matches!(tx.get_state(), BuyTxState::Reserved).then(|| (tx.get_trader_name(), tx.legs.iter().map(|leg| &leg.buy).collect()))
+        } else if let Some(tx) = self.forwards.get(uuid) {
+            (tx.state == ForwardTxState::Open).then(|| (tx.trader_name.as_str(), vec![tx.get_outgoing()]))
+        } else {
+            None
+        }
+    }
+
+    /// Checks that new locks of the trader, one for each of the `outgoing` goods, stay within the [TraderLimits]. Only
+    /// the locks waiting to be paid count, every leg of a basket and every open forward as a lock of its own.
+    fn check_trader_limits(&mut self, trader_name: &str, outgoing: &[&Good]) -> Result<(), TraderLimitError> {
+        let mut locks = 0;
+        let mut reserved = Ledger::new_empty();
+
+        let mut open_locks = self.open_locks.remove(trader_name).unwrap_or_default();
+        open_locks.retain(|uuid| match self.get_open_lock(uuid) {
+            Some((_, goods)) => {
+                for good in goods {
+                    locks += 1;
+                    reserved.deposit(good.get_kind(), Quantity::from_f32(good.get_qty()));
+                }
+                true
+            }
+            None => false,
+        });
+        if !open_locks.is_empty() {
+            self.open_locks.insert(trader_name.to_string(), open_locks);
+        }
+
+        if let Some(max_locks) = self.limits.max_locks {
+            if locks + outgoing.len() as u32 > max_locks {
+                return Err(TraderLimitError::TooManyLocks);
+            }
+        }
+
+        let mut requested = Ledger::new_empty();
+        for good in outgoing {
+            requested.deposit(good.get_kind(), Quantity::from_f32(good.get_qty()));
+        }
+        for (kind, fraction) in self.limits.max_reserved_fractions.iter() {
+            if *fraction < 1. && requested.get(kind) > Quantity::ZERO {
+                let owned = self.ops.assets.get(kind) + self.ops.reservations.get(kind);
+                let max_quantity = (owned.to_f32() * fraction - reserved.get_quantity_by_kind(kind)).max(0.);
+                if requested.get_quantity_by_kind(kind) > max_quantity {
+                    return Err(TraderLimitError::ExceedsTraderExposure { kind, max_quantity });
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Audits the ledgers after every reservation, payment and tick from now on.
+    pub fn enable_audit(&mut self) {
+        if self.auditor.is_none() {
+            self.auditor = Some(Auditor::new());
+        }
+    }
+
+    /// Reports of the operations that left the ledgers out of balance. Empty if auditing is disabled.
+    pub fn get_audit_reports(&self) -> &[AuditReport] {
+        self.auditor.as_ref().map_or(&[], |auditor| auditor.get_reports())
+    }
+
+    fn audit(&mut self, operation: AuditedOperation) {
+        if let Some(auditor) = &mut self.auditor {
+            let txs = AuditedTransactions { buys: &self.buys, sells: &self.sells, baskets: &self.baskets, forwards: &self.forwards };
+            auditor.audit(self.deque.get_ticks_passed(), operation, &self.ops, txs, self.deque.iter());
+        }
+    }
+
+    /// How the locks of every trader ended so far.
+    pub fn get_reputation_table(&self) -> &ReputationTable {
+        &self.reputation
     }
 
     pub fn get_account_ops(&self) -> &AccountOps {
@@ -66,29 +293,94 @@ impl TxService {
         &mut self.ops
     }
 
-    pub fn do_buy_reservation(&mut self, proposal: &BuyTxProposal) -> Result<Uuid, ServiceBuyReservationError> {
-        match BuyTx::reserve(&mut self.ops, proposal) {
+    /// Reserves a buy that expires after `lock_ttl` ticks, or after the default number of ticks of the service if `None`,
+    /// backed by `deposit` of the bid good.
+    pub fn do_buy_reservation(&mut self, proposal: &BuyTxProposal, curve: &dyn PricingCurve, exchange_rate_earn_percentage: f32, lock_ttl: Option<u32>, deposit: f32, rng: &mut dyn RngCore) -> Result<Uuid, ServiceBuyReservationError> {
+        let result = match self.check_trader_limits(&proposal.trader_name, &[&proposal.buy]) {
+            Err(TraderLimitError::TooManyLocks) => Err(ServiceBuyReservationError::TooManyLocks),
+            Err(TraderLimitError::ExceedsTraderExposure { max_quantity, .. }) => Err(ServiceBuyReservationError::ExceedsTraderExposure { max_quantity }),
+            Ok(()) => self.reserve_buy(proposal, curve, exchange_rate_earn_percentage, lock_ttl, deposit, rng),
+        };
+
+        self.audit(AuditedOperation::BuyReservation { uuid: result.as_ref().ok().copied() });
+        result
+    }
+
+    /// Reserves a buy under `uuid` at the bid of the proposal, priced by a batch auction instead of the curve.
+    pub fn do_cleared_buy_reservation(&mut self, uuid: Uuid, proposal: &BuyTxProposal, lock_ttl: Option<u32>) -> Result<Uuid, ServiceBuyReservationError> {
+        let result = match self.check_trader_limits(&proposal.trader_name, &[&proposal.buy]) {
+            Err(TraderLimitError::TooManyLocks) => Err(ServiceBuyReservationError::TooManyLocks),
+            Err(TraderLimitError::ExceedsTraderExposure { max_quantity, .. }) => Err(ServiceBuyReservationError::ExceedsTraderExposure { max_quantity }),
+            Ok(()) => {
+                let reservation = BuyTx::reserve_at_bid(&mut self.ops, proposal);
+                self.insert_buy(uuid, reservation, lock_ttl)
+            }
+        };
+
+        self.audit(AuditedOperation::BuyReservation { uuid: result.as_ref().ok().copied() });
+        result
+    }
+
+    fn reserve_buy(&mut self, proposal: &BuyTxProposal, curve: &dyn PricingCurve, exchange_rate_earn_percentage: f32, lock_ttl: Option<u32>, deposit: f32, rng: &mut dyn RngCore) -> Result<Uuid, ServiceBuyReservationError> {
+        let reservation = BuyTx::reserve(&mut self.ops, proposal, curve, exchange_rate_earn_percentage, deposit);
+        self.insert_buy(new_uuid(rng), reservation, lock_ttl)
+    }
+
+    fn insert_buy(&mut self, uuid: Uuid, reservation: Result<BuyTx, BuyTxReservationError>, lock_ttl: Option<u32>) -> Result<Uuid, ServiceBuyReservationError> {
+        match reservation {
             Ok(reservation) => {
-                let uuid = Uuid::new_v4();
                 self.buys.insert(uuid, reservation);
-                self.deque.push_back(uuid);
+                self.schedule(uuid, lock_ttl);
                 Ok(uuid)
             }
             Err(err) => match err {
                 BuyTxReservationError::NonPositiveBuy => Err(ServiceBuyReservationError::NonPositiveBuy),
                 BuyTxReservationError::NonPositiveBid => Err(ServiceBuyReservationError::NonPositiveBid),
                 BuyTxReservationError::ExceedsReservableQuantity { reservable } => Err(ServiceBuyReservationError::ExceedsReservableQuantity { reservable }),
-                BuyTxReservationError::BidTooLow { lowest } => Err(ServiceBuyReservationError::BidTooLow { lowest })
+                BuyTxReservationError::BidTooLow { lowest } => Err(ServiceBuyReservationError::BidTooLow { lowest }),
+                BuyTxReservationError::UnsupportedBid => Err(ServiceBuyReservationError::UnsupportedBid),
             }
         }
     }
 
-    pub fn do_sell_reservation(&mut self, proposal: &SellTxProposal) -> Result<Uuid, ServiceSellReservationError> {
-        match SellTx::reserve(&mut self.ops, proposal) {
+    /// Reserves a sell that expires after `lock_ttl` ticks, or after the default number of ticks of the service if `None`,
+    /// backed by `deposit` of the offer good.
+    pub fn do_sell_reservation(&mut self, proposal: &SellTxProposal, curve: &dyn PricingCurve, exchange_rate_earn_percentage: f32, lock_ttl: Option<u32>, deposit: f32, rng: &mut dyn RngCore) -> Result<Uuid, ServiceSellReservationError> {
+        let result = match self.check_trader_limits(&proposal.trader_name, &[&proposal.offer]) {
+            Err(TraderLimitError::TooManyLocks) => Err(ServiceSellReservationError::TooManyLocks),
+            Err(TraderLimitError::ExceedsTraderExposure { max_quantity, .. }) => Err(ServiceSellReservationError::ExceedsTraderExposure { max_quantity }),
+            Ok(()) => self.reserve_sell(proposal, curve, exchange_rate_earn_percentage, lock_ttl, deposit, rng),
+        };
+
+        self.audit(AuditedOperation::SellReservation { uuid: result.as_ref().ok().copied() });
+        result
+    }
+
+    /// Reserves a sell under `uuid` at the offer of the proposal, priced by a batch auction instead of the curve.
+    pub fn do_cleared_sell_reservation(&mut self, uuid: Uuid, proposal: &SellTxProposal, lock_ttl: Option<u32>) -> Result<Uuid, ServiceSellReservationError> {
+        let result = match self.check_trader_limits(&proposal.trader_name, &[&proposal.offer]) {
+            Err(TraderLimitError::TooManyLocks) => Err(ServiceSellReservationError::TooManyLocks),
+            Err(TraderLimitError::ExceedsTraderExposure { max_quantity, .. }) => Err(ServiceSellReservationError::ExceedsTraderExposure { max_quantity }),
+            Ok(()) => {
+                let reservation = SellTx::reserve_at_offer(&mut self.ops, proposal);
+                self.insert_sell(uuid, reservation, lock_ttl)
+            }
+        };
+
+        self.audit(AuditedOperation::SellReservation { uuid: result.as_ref().ok().copied() });
+        result
+    }
+
+    fn reserve_sell(&mut self, proposal: &SellTxProposal, curve: &dyn PricingCurve, exchange_rate_earn_percentage: f32, lock_ttl: Option<u32>, deposit: f32, rng: &mut dyn RngCore) -> Result<Uuid, ServiceSellReservationError> {
+        let reservation = SellTx::reserve(&mut self.ops, proposal, curve, exchange_rate_earn_percentage, deposit);
+        self.insert_sell(new_uuid(rng), reservation, lock_ttl)
+    }
+
+    fn insert_sell(&mut self, uuid: Uuid, reservation: Result<SellTx, SellTxReservationError>, lock_ttl: Option<u32>) -> Result<Uuid, ServiceSellReservationError> {
+        match reservation {
             Ok(reservation) => {
-                let uuid = Uuid::new_v4();
                 self.sells.insert(uuid, reservation);
-                self.deque.push_back(uuid);
+                self.schedule(uuid, lock_ttl);
                 Ok(uuid)
             }
             Err(err) => match err {
@@ -96,31 +388,293 @@ impl TxService {
                 SellTxReservationError::NonPositiveOffer => Err(ServiceSellReservationError::NonPositiveOffer),
                 SellTxReservationError::ExceedsReservableQuantity { reservable } => Err(ServiceSellReservationError::ExceedsReservableQuantity { reservable }),
                 SellTxReservationError::OfferTooHigh { highest } => Err(ServiceSellReservationError::OfferTooHigh { highest }),
+                SellTxReservationError::UnsupportedOffer => Err(ServiceSellReservationError::UnsupportedOffer),
             }
         }
     }
 
+    /// Reserves the buys of every leg of the basket under a single uuid, or none of them. The basket expires after
+    /// `lock_ttl` ticks, or after the default number of ticks of the service if `None`, and takes no deposit.
+    pub fn do_basket_reservation(&mut self, proposal: &BasketTxProposal, curve: &dyn PricingCurve, exchange_rate_earn_percentages: &PerGood<f32>, lock_ttl: Option<u32>, rng: &mut dyn RngCore) -> Result<Uuid, ServiceBasketReservationError> {
+        let outgoing: Vec<&Good> = proposal.legs.iter().map(|leg| &leg.buy).collect();
+        let result = match self.check_trader_limits(&proposal.trader_name, &outgoing) {
+            Err(TraderLimitError::TooManyLocks) => Err(ServiceBasketReservationError::TooManyLocks),
+            Err(TraderLimitError::ExceedsTraderExposure { kind, max_quantity }) => Err(ServiceBasketReservationError::ExceedsTraderExposure { kind, max_quantity }),
+            Ok(()) => match BasketTx::reserve(&mut self.ops, proposal, curve, exchange_rate_earn_percentages) {
+                Ok(reservation) => {
+                    let uuid = new_uuid(rng);
+                    self.baskets.insert(uuid, reservation);
+                    self.schedule(uuid, lock_ttl);
+                    Ok(uuid)
+                }
+                Err(err) => Err(match err {
+                    BasketTxReservationError::EmptyBasket => ServiceBasketReservationError::EmptyBasket,
+                    BasketTxReservationError::MixedBidKinds => ServiceBasketReservationError::MixedBidKinds,
+                    BasketTxReservationError::Leg { index, error } => ServiceBasketReservationError::Leg { index, error },
+                }),
+            },
+        };
+
+        self.audit(AuditedOperation::BasketReservation { uuid: result.as_ref().ok().copied() });
+        result
+    }
+
+    /// Pays for every leg of the basket at once, giving back the goods in the order of the legs.
+    pub fn do_basket_buy(&mut self, uuid: &Uuid, with: &mut Good) -> Result<Vec<Good>, ServiceBasketBuyError> {
+        let result = if let Some(tx) = self.baskets.get_mut(uuid) {
+            let result = tx.buy(&mut self.ops, with).map_err(|err| match err {
+                BasketTxPaymentError::InvalidState { current_state } => ServiceBasketBuyError::InvalidState { current_state },
+                BasketTxPaymentError::WrongGoodKind { pre_agreed } => ServiceBasketBuyError::WrongGoodKind { pre_agreed },
+                BasketTxPaymentError::InsufficientGoodQuantity { pre_agreed } => ServiceBasketBuyError::InsufficientGoodQuantity { pre_agreed }
+            });
+            if result.is_ok() {
+                self.reputation.record_completed(tx.get_trader_name());
+                self.deque.remove(uuid);
+            }
+            result
+        } else {
+            Err(ServiceBasketBuyError::UnrecognizedUuid)
+        };
+
+        self.audit(AuditedOperation::BasketBuy { uuid: *uuid });
+        result
+    }
+
+    /// Agrees on a forward that matures after `days` ticks, backed by `margin` of the default good.
+    pub fn do_forward_agreement(&mut self, proposal: &ForwardTxProposal, margin: f32, days: u32, rng: &mut dyn RngCore) -> Result<Uuid, ServiceForwardAgreementError> {
+        let outgoing = match proposal.side {
+            OrderSide::Buy => &proposal.good,
+            OrderSide::Sell => &proposal.price,
+        };
+        let result = match self.check_trader_limits(&proposal.trader_name, &[outgoing]) {
+            Err(TraderLimitError::TooManyLocks) => Err(ServiceForwardAgreementError::TooManyLocks),
+            Err(TraderLimitError::ExceedsTraderExposure { max_quantity, .. }) => Err(ServiceForwardAgreementError::ExceedsTraderExposure { max_quantity }),
+            Ok(()) => match ForwardTx::agree(&mut self.ops, proposal, margin) {
+                Ok(forward) => {
+                    let uuid = new_uuid(rng);
+                    self.forwards.insert(uuid, forward);
+                    self.schedule(uuid, Some(days));
+                    Ok(uuid)
+                }
+                Err(err) => Err(match err {
+                    ForwardTxAgreementError::NonPositiveQuantity => ServiceForwardAgreementError::NonPositiveQuantity,
+                    ForwardTxAgreementError::NonPositivePrice => ServiceForwardAgreementError::NonPositivePrice,
+                    ForwardTxAgreementError::ExceedsReservableQuantity { reservable } => ServiceForwardAgreementError::ExceedsReservableQuantity { reservable },
+                }),
+            },
+        };
+
+        self.audit(AuditedOperation::ForwardAgreement { uuid: result.as_ref().ok().copied() });
+        result
+    }
+
+    /// Takes what the trader owes on a forward out of `with` ahead of maturity.
+    pub fn do_forward_delivery(&mut self, uuid: &Uuid, with: &mut Good) -> Result<(), ServiceForwardDeliveryError> {
+        let result = if let Some(tx) = self.forwards.get_mut(uuid) {
+            tx.deliver(with).map_err(|err| match err {
+                ForwardTxDeliveryError::InvalidState { current_state } => ServiceForwardDeliveryError::InvalidState { current_state },
+                ForwardTxDeliveryError::AlreadyDelivered => ServiceForwardDeliveryError::AlreadyDelivered,
+                ForwardTxDeliveryError::WrongGoodKind { pre_agreed } => ServiceForwardDeliveryError::WrongGoodKind { pre_agreed },
+                ForwardTxDeliveryError::InsufficientGoodQuantity { pre_agreed } => ServiceForwardDeliveryError::InsufficientGoodQuantity { pre_agreed },
+            })
+        } else {
+            Err(ServiceForwardDeliveryError::UnrecognizedUuid)
+        };
+
+        self.audit(AuditedOperation::ForwardDelivery { uuid: *uuid });
+        result
+    }
+
+    /// Gives the proceeds of a settled forward to the trader.
+    pub fn do_forward_claim(&mut self, uuid: &Uuid) -> Result<Good, ServiceForwardClaimError> {
+        let result = if let Some(tx) = self.forwards.get_mut(uuid) {
+            tx.claim().map_err(|err| match err {
+                ForwardTxClaimError::InvalidState { current_state } => ServiceForwardClaimError::InvalidState { current_state },
+            })
+        } else {
+            Err(ServiceForwardClaimError::UnrecognizedUuid)
+        };
+
+        self.audit(AuditedOperation::ForwardClaim { uuid: *uuid });
+        result
+    }
+
     pub fn do_buy(&mut self, uuid: &Uuid, with: &mut Good) -> Result<Good, ServiceBuyError> {
-        if let Some(tx) = self.buys.get_mut(uuid) {
-            tx.buy(&mut self.ops, with).map_err(|err| match err {
+        self.pay_buy(uuid, with, false, true)
+    }
+
+    /// Like [TxService::do_buy], but settles for part of the goods if `with` falls short of the bid.
+    pub fn do_partial_buy(&mut self, uuid: &Uuid, with: &mut Good) -> Result<Good, ServiceBuyError> {
+        self.pay_buy(uuid, with, true, true)
+    }
+
+    /// Like [TxService::do_buy], for a lock filling a limit order on the spot. Such a lock could never be left to
+    /// expire, so it does not count towards the reputation of the trader.
+    pub fn do_order_buy(&mut self, uuid: &Uuid, with: &mut Good) -> Result<Good, ServiceBuyError> {
+        self.pay_buy(uuid, with, false, false)
+    }
+
+    pub fn do_sell(&mut self, uuid: &Uuid, with: &mut Good) -> Result<Good, ServiceSellError> {
+        self.pay_sell(uuid, with, false, true)
+    }
+
+    /// Like [TxService::do_sell], but settles for part of the offer if `with` falls short of the good to sell.
+    pub fn do_partial_sell(&mut self, uuid: &Uuid, with: &mut Good) -> Result<Good, ServiceSellError> {
+        self.pay_sell(uuid, with, true, true)
+    }
+
+    /// Like [TxService::do_order_buy], for a sell.
+    pub fn do_order_sell(&mut self, uuid: &Uuid, with: &mut Good) -> Result<Good, ServiceSellError> {
+        self.pay_sell(uuid, with, false, false)
+    }
+
+    fn pay_buy(&mut self, uuid: &Uuid, with: &mut Good, partial: bool, recorded: bool) -> Result<Good, ServiceBuyError> {
+        let result = if let Some(tx) = self.buys.get_mut(uuid) {
+            let result = if partial { tx.buy_partially(&mut self.ops, with) } else { tx.buy(&mut self.ops, with) };
+            let result = result.map_err(|err| match err {
                 BuyTxPaymentError::InvalidState { current_state } => ServiceBuyError::InvalidState { current_state },
                 BuyTxPaymentError::WrongGoodKind { pre_agreed } => ServiceBuyError::WrongGoodKind { pre_agreed },
                 BuyTxPaymentError::InsufficientGoodQuantity { pre_agreed } => ServiceBuyError::InsufficientGoodQuantity { pre_agreed }
-            })
+            });
+            if result.is_ok() {
+                match tx.state {
+                    _ if !recorded => {}
+                    BuyTxState::PartiallyPaid { buy, .. } => self.reputation.record_partial(&tx.trader_name, buy / tx.buy.get_qty()),
+                    _ => self.reputation.record_completed(&tx.trader_name),
+                }
+                // a settled transaction has nothing left to expire
+                self.deque.remove(uuid);
+            }
+            result
         } else {
             Err(ServiceBuyError::UnrecognizedUuid)
-        }
+        };
+
+        self.audit(AuditedOperation::Buy { uuid: *uuid });
+        result
     }
 
-    pub fn do_sell(&mut self, uuid: &Uuid, with: &mut Good) -> Result<Good, ServiceSellError> {
-        if let Some(tx) = self.sells.get_mut(uuid) {
-            tx.sell(&mut self.ops, with).map_err(|err| match err {
+    fn pay_sell(&mut self, uuid: &Uuid, with: &mut Good, partial: bool, recorded: bool) -> Result<Good, ServiceSellError> {
+        let result = if let Some(tx) = self.sells.get_mut(uuid) {
+            let result = if partial { tx.sell_partially(&mut self.ops, with) } else { tx.sell(&mut self.ops, with) };
+            let result = result.map_err(|err| match err {
                 SellTxPaymentError::InvalidState { current_state } => ServiceSellError::InvalidState { current_state },
                 SellTxPaymentError::WrongGoodKind { pre_agreed } => ServiceSellError::WrongGoodKind { pre_agreed },
                 SellTxPaymentError::InsufficientGoodQuantity { pre_agreed } => ServiceSellError::InsufficientGoodQuantity { pre_agreed }
-            })
+            });
+            if result.is_ok() {
+                match tx.state {
+                    _ if !recorded => {}
+                    SellTxState::PartiallyPaid { sell, .. } => self.reputation.record_partial(&tx.trader_name, sell / tx.sell.get_qty()),
+                    _ => self.reputation.record_completed(&tx.trader_name),
+                }
+                // a settled transaction has nothing left to expire
+                self.deque.remove(uuid);
+            }
+            result
         } else {
             Err(ServiceSellError::UnrecognizedUuid)
+        };
+
+        self.audit(AuditedOperation::Sell { uuid: *uuid });
+        result
+    }
+
+    pub fn do_cancel_buy(&mut self, uuid: &Uuid) -> Result<(), ServiceCancelBuyError> {
+        let result = if let Some(tx) = self.buys.get_mut(uuid) {
+            let result = tx.cancel(&mut self.ops).map_err(|err| match err {
+                BuyTxCancelError::InvalidState { current_state } => ServiceCancelBuyError::InvalidState { current_state }
+            });
+            if result.is_ok() {
+                self.reputation.record_expired(&tx.trader_name);
+                self.deque.remove(uuid);
+            }
+            result
+        } else {
+            Err(ServiceCancelBuyError::UnrecognizedUuid)
+        };
+
+        self.audit(AuditedOperation::CancelBuy { uuid: *uuid });
+        result
+    }
+
+    pub fn do_cancel_sell(&mut self, uuid: &Uuid) -> Result<(), ServiceCancelSellError> {
+        let result = if let Some(tx) = self.sells.get_mut(uuid) {
+            let result = tx.cancel(&mut self.ops).map_err(|err| match err {
+                SellTxCancelError::InvalidState { current_state } => ServiceCancelSellError::InvalidState { current_state }
+            });
+            if result.is_ok() {
+                self.reputation.record_expired(&tx.trader_name);
+                self.deque.remove(uuid);
+            }
+            result
+        } else {
+            Err(ServiceCancelSellError::UnrecognizedUuid)
+        };
+
+        self.audit(AuditedOperation::CancelSell { uuid: *uuid });
+        result
+    }
+
+    /// Charges the renewal fee and postpones the expiration of a reserved buy by `ticks`.
+    pub fn do_renew_buy(&mut self, uuid: &Uuid, ticks: u32, with: &mut Good, fee_kind: GoodKind, fee: f32, max_renewals: u32) -> Result<(), ServiceRenewBuyError> {
+        let result = if let Some(tx) = self.buys.get_mut(uuid) {
+            tx.renew(&mut self.ops, with, fee_kind, fee, max_renewals).map_err(|err| match err {
+                BuyTxRenewalError::InvalidState { current_state } => ServiceRenewBuyError::InvalidState { current_state },
+                BuyTxRenewalError::MaxRenewalsReached { max_renewals } => ServiceRenewBuyError::MaxRenewalsReached { max_renewals },
+                BuyTxRenewalError::WrongGoodKind { fee_kind } => ServiceRenewBuyError::WrongGoodKind { fee_kind },
+                BuyTxRenewalError::InsufficientGoodQuantity { fee } => ServiceRenewBuyError::InsufficientGoodQuantity { fee }
+            })
+        } else {
+            Err(ServiceRenewBuyError::UnrecognizedUuid)
+        };
+
+        if result.is_ok() {
+            // a reserved transaction is always waiting to expire
+            self.deque.extend(uuid, ticks).unwrap();
+        }
+
+        self.audit(AuditedOperation::RenewBuy { uuid: *uuid });
+        result
+    }
+
+    /// Charges the renewal fee and postpones the expiration of a reserved sell by `ticks`.
+    pub fn do_renew_sell(&mut self, uuid: &Uuid, ticks: u32, with: &mut Good, fee_kind: GoodKind, fee: f32, max_renewals: u32) -> Result<(), ServiceRenewSellError> {
+        let result = if let Some(tx) = self.sells.get_mut(uuid) {
+            tx.renew(&mut self.ops, with, fee_kind, fee, max_renewals).map_err(|err| match err {
+                SellTxRenewalError::InvalidState { current_state } => ServiceRenewSellError::InvalidState { current_state },
+                SellTxRenewalError::MaxRenewalsReached { max_renewals } => ServiceRenewSellError::MaxRenewalsReached { max_renewals },
+                SellTxRenewalError::WrongGoodKind { fee_kind } => ServiceRenewSellError::WrongGoodKind { fee_kind },
+                SellTxRenewalError::InsufficientGoodQuantity { fee } => ServiceRenewSellError::InsufficientGoodQuantity { fee }
+            })
+        } else {
+            Err(ServiceRenewSellError::UnrecognizedUuid)
+        };
+
+        if result.is_ok() {
+            // a reserved transaction is always waiting to expire
+            self.deque.extend(uuid, ticks).unwrap();
+        }
+
+        self.audit(AuditedOperation::RenewSell { uuid: *uuid });
+        result
+    }
+
+    /// Ticks left before a reserved transaction expires, `None` if it is not waiting to expire, such as once it is paid
+    /// or cancelled.
+    pub fn get_ticks_left(&self, uuid: &Uuid) -> Option<u32> {
+        self.deque.get_deadline(uuid).map(|deadline| deadline - self.deque.get_ticks_passed())
+    }
+
+    fn schedule(&mut self, uuid: Uuid, lock_ttl: Option<u32>) {
+        match lock_ttl {
+            Some(lock_ttl) => self.deque.push_back_with_ttl(uuid, lock_ttl),
+            None => self.deque.push_back(uuid),
+        }
+
+        if let Some((trader_name, _)) = self.get_open_lock(&uuid) {
+            let trader_name = trader_name.to_string();
+            self.open_locks.entry(trader_name).or_default().push(uuid);
         }
     }
 
@@ -132,16 +686,277 @@ impl TxService {
         self.sells.get(uuid)
     }
 
+    pub fn get_basket(&self, uuid: &Uuid) -> Option<&BasketTx> {
+        self.baskets.get(uuid)
+    }
+
+    pub fn get_forward(&self, uuid: &Uuid) -> Option<&ForwardTx> {
+        self.forwards.get(uuid)
+    }
+
+    /// Buys of the trader, followed by every leg of its baskets under the uuid of the basket.
+    pub fn get_buys_by_trader<'a>(&'a self, trader_name: &'a str) -> impl Iterator<Item=(&'a Uuid, &'a BuyTx)> {
+        let buys = self.buys.iter().filter(move |(_, tx)| tx.trader_name == trader_name);
+        let legs = self.baskets.iter()
+            .filter(move |(_, tx)| tx.get_trader_name() == trader_name)
+            .flat_map(|(uuid, tx)| tx.legs.iter().map(move |leg| (uuid, leg)));
+
+        buys.chain(legs)
+    }
+
+    pub fn get_sells_by_trader<'a>(&'a self, trader_name: &'a str) -> impl Iterator<Item=(&'a Uuid, &'a SellTx)> {
+        self.sells.iter().filter(move |(_, tx)| tx.trader_name == trader_name)
+    }
+
+    pub fn get_forwards_by_trader<'a>(&'a self, trader_name: &'a str) -> impl Iterator<Item=(&'a Uuid, &'a ForwardTx)> {
+        self.forwards.iter().filter(move |(_, tx)| tx.trader_name == trader_name)
+    }
+
+    /// Buy, basket and sell locks of the trader that expired without being paid, and its defaulted forwards.
+    pub fn get_expired_by_trader(&self, trader_name: &str) -> Vec<Uuid> {
+        let buys = self.get_buys_by_trader(trader_name)
+            .filter(|(_, tx)| matches!(tx.state, BuyTxState::Expired))
+            .map(|(uuid, _)| *uuid);
+        let sells = self.get_sells_by_trader(trader_name)
+            .filter(|(_, tx)| matches!(tx.state, SellTxState::Expired))
+            .map(|(uuid, _)| *uuid);
+
+        let forwards = self.get_forwards_by_trader(trader_name)
+            .filter(|(_, tx)| tx.state == ForwardTxState::Defaulted)
+            .map(|(uuid, _)| *uuid);
+
+        // the legs of a basket expire together, one after the other
+        let mut expired: Vec<Uuid> = buys.chain(sells).chain(forwards).collect();
+        expired.dedup();
+        expired
+    }
+
+    /// Goods exchanged by the paid transactions of the trader, counting only what was paid of the partially paid ones,
+    /// and by its settled forwards. The margin of a defaulted forward counts as sold to the market.
+    pub fn get_volume_by_trader(&self, trader_name: &str) -> TraderVolume {
+        let mut bought = Ledger::new_empty();
+        let mut sold = Ledger::new_empty();
+
+        for (_, tx) in self.get_buys_by_trader(trader_name) {
+            let (buy, bid) = match tx.state {
+                BuyTxState::Paid => (tx.buy.get_qty(), tx.bid.get_qty()),
+                BuyTxState::PartiallyPaid { buy, bid } => (buy, bid),
+                _ => continue,
+            };
+            bought.deposit(tx.buy.get_kind(), Quantity::from_f32(buy));
+            sold.deposit(tx.bid.get_kind(), Quantity::from_f32(bid));
+        }
+        for (_, tx) in self.get_sells_by_trader(trader_name) {
+            let (sell, offer) = match tx.state {
+                SellTxState::Paid => (tx.sell.get_qty(), tx.offer.get_qty()),
+                SellTxState::PartiallyPaid { sell, offer } => (sell, offer),
+                _ => continue,
+            };
+            sold.deposit(tx.sell.get_kind(), Quantity::from_f32(sell));
+            bought.deposit(tx.offer.get_kind(), Quantity::from_f32(offer));
+        }
+        for (_, tx) in self.get_forwards_by_trader(trader_name) {
+            match tx.state {
+                ForwardTxState::Settled | ForwardTxState::Claimed => {
+                    bought.deposit(tx.get_outgoing().get_kind(), Quantity::from_f32(tx.get_outgoing().get_qty()));
+                    sold.deposit(tx.get_incoming().get_kind(), Quantity::from_f32(tx.get_incoming().get_qty()));
+                }
+                ForwardTxState::Defaulted => sold.deposit(DEFAULT_GOOD_KIND, Quantity::from_f32(tx.margin)),
+                ForwardTxState::Open => {}
+            }
+        }
+
+        let to_per_good = |ledger: &Ledger| PerGood::new(
+            ledger.get_quantity_by_kind(GoodKind::EUR),
+            ledger.get_quantity_by_kind(GoodKind::USD),
+            ledger.get_quantity_by_kind(GoodKind::YEN),
+            ledger.get_quantity_by_kind(GoodKind::YUAN),
+        );
+        TraderVolume { bought: to_per_good(&bought), sold: to_per_good(&sold) }
+    }
+
     pub fn tick_all(&mut self) {
-        self.deque.tick().into_iter()
+        let expired = self.deque.tick();
+        expired.iter()
             .for_each(|uuid| {
-                if let Some(tx) = self.buys.get_mut(&uuid) {
+                if let Some(tx) = self.buys.get_mut(uuid) {
+                    if let BuyTxState::Reserved = tx.state {
+                        self.reputation.record_expired(&tx.trader_name);
+                    }
                     tx.expire(&mut self.ops);
-                } else if let Some(tx) = self.sells.get_mut(&uuid) {
+                } else if let Some(tx) = self.sells.get_mut(uuid) {
+                    if let SellTxState::Reserved = tx.state {
+                        self.reputation.record_expired(&tx.trader_name);
+                    }
                     tx.expire(&mut self.ops);
+                } else if let Some(tx) = self.baskets.get_mut(uuid) {
+                    if let BuyTxState::Reserved = tx.get_state() {
+                        self.reputation.record_expired(tx.get_trader_name());
+                    }
+                    tx.expire(&mut self.ops);
+                } else if let Some(tx) = self.forwards.get_mut(uuid) {
+                    // a forward that matures before the trader delivered counts as an expired lock
+                    if tx.mature(&mut self.ops) {
+                        self.reputation.record_completed(&tx.trader_name);
+                    } else {
+                        self.reputation.record_expired(&tx.trader_name);
+                    }
                 } else {
                     unreachable!()
                 }
             });
+
+        self.audit(AuditedOperation::Tick { expired });
+    }
+}
+
+/// Token drawn from `rng`, so that markets with the same seed hand out the same tokens.
+pub fn new_uuid(rng: &mut dyn RngCore) -> Uuid {
+    uuid::Builder::from_random_bytes(rng.gen()).into_uuid()
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+    use unitn_market_2022::good::good_kind::GoodKind::{EUR, USD, YEN};
+
+    use crate::basket_transaction::BasketLeg;
+    use crate::pricing_curve::FutureInventoryCurve;
+    use crate::reputation::TraderRecord;
+    use crate::test_fixtures::uniform_account;
+
+    use super::*;
+
+    #[test]
+    fn trader_queries() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let assets = uniform_account(100_000.);
+        let mut service = TxService::new(AccountOps::of_assets(assets), 2);
+
+        let paid = service.do_buy_reservation(&BuyTxProposal { buy: Good::new(USD, 10.), bid: Good::new(EUR, 100.), trader_name: "alice".to_string() }, &FutureInventoryCurve, 1., None, 0., &mut rng).unwrap();
+        service.do_buy(&paid, &mut Good::new(EUR, 100.)).unwrap();
+        let sold = service.do_sell_reservation(&SellTxProposal { sell: Good::new(YEN, 20.), offer: Good::new(EUR, 1.), trader_name: "alice".to_string() }, &FutureInventoryCurve, 1., None, 0., &mut rng).unwrap();
+        service.do_sell(&sold, &mut Good::new(YEN, 20.)).unwrap();
+        let expired = service.do_buy_reservation(&BuyTxProposal { buy: Good::new(USD, 10.), bid: Good::new(EUR, 100.), trader_name: "alice".to_string() }, &FutureInventoryCurve, 1., None, 0., &mut rng).unwrap();
+        service.do_sell_reservation(&SellTxProposal { sell: Good::new(YEN, 20.), offer: Good::new(EUR, 1.), trader_name: "bob".to_string() }, &FutureInventoryCurve, 1., None, 0., &mut rng).unwrap();
+        service.tick_all();
+        service.tick_all();
+
+        assert_eq!(service.get_buys_by_trader("alice").count(), 2);
+        assert_eq!(service.get_sells_by_trader("alice").count(), 1);
+        assert_eq!(service.get_sells_by_trader("carol").count(), 0);
+        assert_eq!(service.get_expired_by_trader("alice"), vec![expired]);
+
+        let volume = service.get_volume_by_trader("alice");
+        assert_eq!(volume.bought, PerGood::new(1., 10., 0., 0.));
+        assert_eq!(volume.sold, PerGood::new(100., 0., 20., 0.));
+
+        assert_eq!(service.get_reputation_table().get_record("alice"), TraderRecord { completed: 2, expired: 1 });
+        assert_eq!(service.get_reputation_table().get_record("bob"), TraderRecord { completed: 0, expired: 1 });
+    }
+
+    #[test]
+    fn cancelled_locks_count_as_expired() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let assets = uniform_account(100_000.);
+        let mut service = TxService::new(AccountOps::of_assets(assets), 2);
+
+        let paid = service.do_buy_reservation(&BuyTxProposal { buy: Good::new(USD, 10.), bid: Good::new(EUR, 100.), trader_name: "alice".to_string() }, &FutureInventoryCurve, 1., None, 0., &mut rng).unwrap();
+        service.do_buy(&paid, &mut Good::new(EUR, 100.)).unwrap();
+        let score = service.get_reputation_table().get_record("alice").get_score();
+
+        let buy = service.do_buy_reservation(&BuyTxProposal { buy: Good::new(USD, 10.), bid: Good::new(EUR, 100.), trader_name: "alice".to_string() }, &FutureInventoryCurve, 1., None, 0., &mut rng).unwrap();
+        service.do_cancel_buy(&buy).unwrap();
+        let sell = service.do_sell_reservation(&SellTxProposal { sell: Good::new(YEN, 20.), offer: Good::new(EUR, 1.), trader_name: "alice".to_string() }, &FutureInventoryCurve, 1., None, 0., &mut rng).unwrap();
+        service.do_cancel_sell(&sell).unwrap();
+
+        assert_eq!(service.get_reputation_table().get_record("alice"), TraderRecord { completed: 1, expired: 2 });
+        assert!(service.get_reputation_table().get_record("alice").get_score() < score);
+
+        // a cancelled lock is not waiting to expire anymore, so it does not count twice
+        service.tick_all();
+        service.tick_all();
+        assert_eq!(service.get_reputation_table().get_record("alice").expired, 2);
+    }
+
+    #[test]
+    fn trader_limits() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let assets = uniform_account(100_000.);
+        let mut service = TxService::new(AccountOps::of_assets(assets), 2);
+        service.set_trader_limits(TraderLimits { max_locks: Some(3), max_reserved_fractions: PerGood::new(1., 1., 0.25, 1.) });
+
+        let proposal = |quantity: f32, trader_name: &str| BuyTxProposal { buy: Good::new(YEN, quantity), bid: Good::new(EUR, 100_000.), trader_name: trader_name.to_string() };
+
+        service.do_buy_reservation(&proposal(20_000., "alice"), &FutureInventoryCurve, 1., None, 0., &mut rng).unwrap();
+        assert!(matches!(service.do_buy_reservation(&proposal(10_000., "alice"), &FutureInventoryCurve, 1., None, 0., &mut rng),
+            Err(ServiceBuyReservationError::ExceedsTraderExposure { max_quantity }) if max_quantity == 5_000.));
+        service.do_buy_reservation(&proposal(10_000., "bob"), &FutureInventoryCurve, 1., None, 0., &mut rng).unwrap();
+        service.do_buy_reservation(&proposal(1_000., "alice"), &FutureInventoryCurve, 1., None, 0., &mut rng).unwrap();
+        service.do_buy_reservation(&proposal(1_000., "alice"), &FutureInventoryCurve, 1., None, 0., &mut rng).unwrap();
+        assert!(matches!(service.do_buy_reservation(&proposal(1_000., "alice"), &FutureInventoryCurve, 1., None, 0., &mut rng),
+            Err(ServiceBuyReservationError::TooManyLocks)));
+
+        // a restored service still knows the locks of every trader once the limits are set again
+        let mut restored: TxService = serde_json::from_str(&serde_json::to_string(&service).unwrap()).unwrap();
+        restored.set_trader_limits(service.limits.clone());
+        assert!(matches!(restored.do_buy_reservation(&proposal(1_000., "alice"), &FutureInventoryCurve, 1., None, 0., &mut rng),
+            Err(ServiceBuyReservationError::TooManyLocks)));
+
+        // expired locks free the trader again
+        service.tick_all();
+        service.tick_all();
+        service.do_buy_reservation(&proposal(20_000., "alice"), &FutureInventoryCurve, 1., None, 0., &mut rng).unwrap();
+    }
+
+    #[test]
+    fn baskets() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let assets = uniform_account(100_000.);
+        let mut service = TxService::new(AccountOps::of_assets(assets), 2);
+        service.enable_audit();
+        let initial = service.get_account_ops().clone();
+        let earn_percentages = PerGood::new(0., 1., 1., 1.);
+
+        let basket = |yen: f32| BasketTxProposal {
+            legs: vec![
+                BasketLeg { buy: Good::new(USD, 100.), bid: Good::new(EUR, 1_000.) },
+                BasketLeg { buy: Good::new(YEN, yen), bid: Good::new(EUR, 1_000.) },
+            ],
+            trader_name: "alice".to_string(),
+        };
+
+        // a leg that can not be reserved leaves the other untouched
+        assert!(matches!(service.do_basket_reservation(&basket(200_000.), &FutureInventoryCurve, &earn_percentages, None, &mut rng),
+            Err(ServiceBasketReservationError::Leg { index: 1, error: BuyTxReservationError::ExceedsReservableQuantity { .. } })));
+        assert_eq!(service.get_account_ops().assets, initial.assets);
+        assert!(matches!(service.do_basket_reservation(&BasketTxProposal { legs: vec![], trader_name: "alice".to_string() }, &FutureInventoryCurve, &earn_percentages, None, &mut rng),
+            Err(ServiceBasketReservationError::EmptyBasket)));
+
+        let paid = service.do_basket_reservation(&basket(100.), &FutureInventoryCurve, &earn_percentages, None, &mut rng).unwrap();
+        assert!(matches!(service.do_basket_buy(&paid, &mut Good::new(EUR, 1_999.)),
+            Err(ServiceBasketBuyError::InsufficientGoodQuantity { pre_agreed }) if pre_agreed == 2_000.));
+        let bought = service.do_basket_buy(&paid, &mut Good::new(EUR, 2_000.)).unwrap();
+        assert_eq!(bought.iter().map(|good| (good.get_kind(), good.get_qty())).collect::<Vec<_>>(), vec![(USD, 100.), (YEN, 100.)]);
+
+        let expired = service.do_basket_reservation(&basket(100.), &FutureInventoryCurve, &earn_percentages, None, &mut rng).unwrap();
+        service.tick_all();
+        service.tick_all();
+        assert!(service.get_basket(&expired).unwrap().legs.iter().all(|leg| matches!(leg.state, BuyTxState::Expired)));
+
+        let ops = service.get_account_ops();
+        assert_eq!(ops.assets.get(USD), initial.assets.get(USD) - Quantity::from_f32(100.));
+        assert_eq!(ops.reservations, initial.reservations);
+        assert_eq!(ops.futures, initial.futures);
+        assert_eq!(service.get_reputation_table().get_record("alice"), TraderRecord { completed: 1, expired: 1 });
+        assert_eq!(service.get_audit_reports(), &[]);
+
+        // the legs are buys of the trader, under the uuid of their basket
+        assert_eq!(service.get_buys_by_trader("alice").filter(|(uuid, _)| **uuid == paid).count(), 2);
+        assert_eq!(service.get_expired_by_trader("alice"), vec![expired]);
+        let volume = service.get_volume_by_trader("alice");
+        assert_eq!(volume.bought, PerGood::new(0., 100., 100., 0.));
+        assert_eq!(volume.sold, PerGood::new(2_000., 0., 0., 0.));
     }
 }
@@ -0,0 +1,67 @@
+use std::fs;
+
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::batch_auction::BatchAuction;
+use crate::competitor_rates::CompetitorRates;
+use crate::lending_desk::LendingDesk;
+use crate::market_config::{MarketConfig, MarketConfigError};
+use crate::order_book::OrderBook;
+use crate::refiller::GoodRefiller;
+use crate::service::TxService;
+
+/// Full state of a running market: the ledgers, every transaction with the tick at which it was locked, the refiller
+/// trackers, the limit orders, the locks queued for the batch auction, the loans, the estimate of the rates of the
+/// other markets and the random generator. Restoring it gives back a market whose outstanding tokens are still valid
+/// and expire after the same number of days they had left when the snapshot was taken.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct MarketSnapshot {
+    pub service: TxService,
+    /// `None` unless the market refills with the importer/exporter policy.
+    #[serde(default)]
+    pub refiller: Option<GoodRefiller>,
+    pub config: MarketConfig,
+    #[serde(default)]
+    pub order_book: OrderBook,
+    #[serde(default)]
+    pub auction: BatchAuction,
+    #[serde(default)]
+    pub lending_desk: LendingDesk,
+    #[serde(default)]
+    pub competitor_rates: CompetitorRates,
+    /// State of the random generator, so that a restored market makes the same choices the original would have. `None`
+    /// starts over from the seed of the configuration.
+    #[serde(default)]
+    pub rng: Option<ChaCha8Rng>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SnapshotError {
+    Unwritable { path: String, reason: String },
+    Unreadable { path: String, reason: String },
+    Malformed { reason: String },
+    InvalidConfig { reason: MarketConfigError },
+}
+
+impl MarketSnapshot {
+    pub fn to_json(&self) -> Result<String, SnapshotError> {
+        serde_json::to_string(self).map_err(|err| SnapshotError::Malformed { reason: err.to_string() })
+    }
+
+    pub fn from_json(json: &str) -> Result<MarketSnapshot, SnapshotError> {
+        serde_json::from_str(json).map_err(|err| SnapshotError::Malformed { reason: err.to_string() })
+    }
+
+    pub fn write(&self, path: &str) -> Result<(), SnapshotError> {
+        fs::write(path, self.to_json()?)
+            .map_err(|err| SnapshotError::Unwritable { path: path.to_string(), reason: err.to_string() })
+    }
+
+    pub fn read(path: &str) -> Result<MarketSnapshot, SnapshotError> {
+        let json = fs::read_to_string(path)
+            .map_err(|err| SnapshotError::Unreadable { path: path.to_string(), reason: err.to_string() })?;
+
+        MarketSnapshot::from_json(&json)
+    }
+}
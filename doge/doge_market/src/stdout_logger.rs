@@ -2,6 +2,7 @@ use unitn_market_2022::good::good::Good;
 use uuid::Uuid;
 
 use crate::logger::Logger;
+use crate::refill_backend::RefillTrade;
 
 /// Logger that logs to standard output.
 pub struct StdoutLogger {}
@@ -68,4 +69,67 @@ impl Logger for StdoutLogger {
             }
         }
     }
+
+    fn log_cancel_buy(&mut self, token: &Uuid, success: bool) {
+        match success {
+            true => {
+                println!("SUCCESSFUL CANCEL_BUY WITH TOKEN {token}")
+            }
+            false => {
+                println!("FAILED CANCEL_BUY WITH TOKEN {token}")
+            }
+        }
+    }
+
+    fn log_cancel_sell(&mut self, token: &Uuid, success: bool) {
+        match success {
+            true => {
+                println!("SUCCESSFUL CANCEL_SELL WITH TOKEN {token}")
+            }
+            false => {
+                println!("FAILED CANCEL_SELL WITH TOKEN {token}")
+            }
+        }
+    }
+
+    fn log_renew_buy(&mut self, token: &Uuid, days: u32, success: bool) {
+        match success {
+            true => {
+                println!("SUCCESSFUL RENEW_BUY OF {days} DAYS WITH TOKEN {token}")
+            }
+            false => {
+                println!("FAILED RENEW_BUY OF {days} DAYS WITH TOKEN {token}")
+            }
+        }
+    }
+
+    fn log_renew_sell(&mut self, token: &Uuid, days: u32, success: bool) {
+        match success {
+            true => {
+                println!("SUCCESSFUL RENEW_SELL OF {days} DAYS WITH TOKEN {token}")
+            }
+            false => {
+                println!("FAILED RENEW_SELL OF {days} DAYS WITH TOKEN {token}")
+            }
+        }
+    }
+
+    fn log_seed(&mut self, seed: u64) {
+        println!("SEEDED WITH {seed}")
+    }
+
+    fn log_refill_trade(&mut self, trade: &RefillTrade) {
+        let (supplier, sold, bought) = (trade.supplier, &trade.sold, &trade.bought);
+        match (&trade.token, trade.paid) {
+            (Some(token), true) => {
+                println!("REFILLED {bought} FOR {sold} FROM {supplier} WITH TOKEN {token}")
+            }
+            (Some(token), false) => {
+                println!("FAILED REFILL OF {bought} FOR {sold} FROM {supplier}, LEAVING TOKEN {token} OPEN")
+            }
+            (None, _) => {
+                println!("FAILED REFILL LOCK OF {bought} FOR {sold} FROM {supplier}")
+            }
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,111 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use unitn_market_2022::event::event::{Event, EventKind};
+use unitn_market_2022::event::notifiable::Notifiable;
+use unitn_market_2022::good::good::Good;
+use unitn_market_2022::good::good_kind::GoodKind;
+use unitn_market_2022::good::good_kind::GoodKind::{EUR, USD, YEN, YUAN};
+use unitn_market_2022::market::{BuyError, LockBuyError, LockSellError, Market, MarketGetterError, SellError};
+use unitn_market_2022::market::good_label::GoodLabel;
+
+use doge_common::account::Account;
+
+use crate::dogemarket::DogeMarket;
+use crate::market_config::MarketConfig;
+
+/// Account holding `quantity` of every good.
+pub fn uniform_account(quantity: f32) -> Account {
+    Account {
+        eur: Good::new(EUR, quantity),
+        usd: Good::new(USD, quantity),
+        yen: Good::new(YEN, quantity),
+        yuan: Good::new(YUAN, quantity),
+    }
+}
+
+/// Market holding `quantity` of every good, tuned by `config`.
+pub fn uniform_market(quantity: f32, config: MarketConfig) -> Rc<RefCell<DogeMarket>> {
+    DogeMarket::new_with_config(quantity, quantity, quantity, quantity, config).unwrap()
+}
+
+/// Market with endless goods, exchanged at the default rates widened by `spread` on both sides. Its subscribers are
+/// notified of every lock and trade.
+pub struct StubMarket {
+    pub eur: f32,
+    spread: f32,
+    locks: HashMap<String, (GoodKind, f32, f32)>,
+    subscribers: Vec<Box<dyn Notifiable>>,
+}
+
+pub fn stub_market(spread: f32) -> Rc<RefCell<StubMarket>> {
+    Rc::new(RefCell::new(StubMarket { eur: 0., spread, locks: HashMap::new(), subscribers: vec![] }))
+}
+
+impl StubMarket {
+    fn lock(&mut self, kind: EventKind, good_kind: GoodKind, quantity: f32, price: f32) -> String {
+        let token = self.locks.len().to_string();
+        self.locks.insert(token.clone(), (good_kind, quantity, price));
+        self.notify(kind, good_kind, quantity, price);
+        token
+    }
+
+    fn notify(&mut self, kind: EventKind, good_kind: GoodKind, quantity: f32, price: f32) {
+        let event = Event { kind, good_kind, quantity, price };
+        self.subscribers.iter_mut().for_each(|sub| sub.on_event(event.clone()));
+    }
+}
+
+impl Notifiable for StubMarket {
+    fn add_subscriber(&mut self, subscriber: Box<dyn Notifiable>) {
+        self.subscribers.push(subscriber);
+    }
+
+    fn on_event(&mut self, _event: Event) {}
+}
+
+impl Market for StubMarket {
+    fn new_random() -> Rc<RefCell<dyn Market>> { unimplemented!() }
+
+    fn new_with_quantities(_eur: f32, _yen: f32, _usd: f32, _yuan: f32) -> Rc<RefCell<dyn Market>> { unimplemented!() }
+
+    fn new_file(_path: &str) -> Rc<RefCell<dyn Market>> { unimplemented!() }
+
+    fn get_name(&self) -> &'static str { "Stub" }
+
+    fn get_budget(&self) -> f32 { self.eur }
+
+    fn get_buy_price(&self, kind: GoodKind, quantity: f32) -> Result<f32, MarketGetterError> {
+        Ok(quantity / kind.get_default_exchange_rate() * (1. + self.spread))
+    }
+
+    fn get_sell_price(&self, kind: GoodKind, quantity: f32) -> Result<f32, MarketGetterError> {
+        Ok(quantity / kind.get_default_exchange_rate() * (1. - self.spread))
+    }
+
+    fn get_goods(&self) -> Vec<GoodLabel> { vec![] }
+
+    fn lock_buy(&mut self, kind_to_buy: GoodKind, quantity_to_buy: f32, bid: f32, _trader_name: String) -> Result<String, LockBuyError> {
+        Ok(self.lock(EventKind::LockedBuy, kind_to_buy, quantity_to_buy, bid))
+    }
+
+    fn buy(&mut self, token: String, cash: &mut Good) -> Result<Good, BuyError> {
+        let (kind, quantity, bid) = self.locks[&token];
+        self.eur += cash.split(bid).unwrap().get_qty();
+        self.notify(EventKind::Bought, kind, quantity, bid);
+        Ok(Good::new(kind, quantity))
+    }
+
+    fn lock_sell(&mut self, kind_to_sell: GoodKind, quantity_to_sell: f32, offer: f32, _trader_name: String) -> Result<String, LockSellError> {
+        Ok(self.lock(EventKind::LockedSell, kind_to_sell, quantity_to_sell, offer))
+    }
+
+    fn sell(&mut self, token: String, good: &mut Good) -> Result<Good, SellError> {
+        let (kind, quantity, offer) = self.locks[&token];
+        good.split(quantity).unwrap();
+        self.eur -= offer;
+        self.notify(EventKind::Sold, kind, quantity, offer);
+        Ok(Good::new(EUR, offer))
+    }
+}
@@ -1,30 +1,133 @@
-use std::collections::VecDeque;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+
+use serde::{Deserialize, Serialize};
+
+/// Elements that expire after a number of ticks, each with its own deadline.
+///
+/// Elements are grouped by the tick at which they expire, and numbered in the order they were pushed, so pushing,
+/// extending and expiring them costs lookups in ordered maps instead of a scan of the elements. Elements expiring at the
+/// same tick expire in the order they were pushed. An element must not be pushed again while it is still waiting to
+/// expire.
+///
+/// It is serialized as the elements in the order they expire, each with its deadline, which is the format snapshots
+/// had before the deadlines were indexed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "SerializedTickDeque<T>", into = "SerializedTickDeque<T>")]
+#[serde(bound(serialize = "T: Serialize + Clone", deserialize = "T: Deserialize<'de> + Eq + Hash + Clone"))]
+pub struct TickDeque<T> {
+    max_ticks: u32,
+    ticks_passed: u32,
+    /// Elements by the number of ticks passed at which they expire, then by the order they were pushed.
+    deadlines: BTreeMap<u32, BTreeMap<u64, T>>,
+    /// Deadline and push number of every element still waiting to expire.
+    index: HashMap<T, (u32, u64)>,
+    pushed: u64,
+}
 
-#[derive(Debug)]
+#[derive(Serialize, Deserialize)]
 struct TickNode<T> {
-    creation_tick: u32,
+    /// Number of ticks passed at which the element expires.
+    deadline: u32,
     t: T,
 }
 
-pub struct TickDeque<T> {
+#[derive(Serialize, Deserialize)]
+struct SerializedTickDeque<T> {
     max_ticks: u32,
     ticks_passed: u32,
-    deque: VecDeque<TickNode<T>>,
+    deque: Vec<TickNode<T>>,
+}
+
+impl<T: Eq + Hash + Clone> From<SerializedTickDeque<T>> for TickDeque<T> {
+    fn from(serialized: SerializedTickDeque<T>) -> TickDeque<T> {
+        let mut td = TickDeque::new(serialized.max_ticks);
+        td.ticks_passed = serialized.ticks_passed;
+        for node in serialized.deque {
+            td.insert(node.t, node.deadline);
+        }
+        td
+    }
 }
 
-impl<T> TickDeque<T> {
+impl<T: Clone> From<TickDeque<T>> for SerializedTickDeque<T> {
+    fn from(td: TickDeque<T>) -> SerializedTickDeque<T> {
+        let deque = td.deadlines.into_iter()
+            .flat_map(|(deadline, bucket)| bucket.into_values().map(move |t| TickNode { deadline, t }))
+            .collect();
+
+        SerializedTickDeque { max_ticks: td.max_ticks, ticks_passed: td.ticks_passed, deque }
+    }
+}
+
+impl<T: Eq + Hash + Clone> TickDeque<T> {
+    /// `max_ticks` is the time to live of the elements pushed with [TickDeque::push_back].
     pub fn new(max_ticks: u32) -> TickDeque<T> {
         assert!(max_ticks > 0);
 
         TickDeque {
             max_ticks,
             ticks_passed: 0,
-            deque: VecDeque::new(),
+            deadlines: BTreeMap::new(),
+            index: HashMap::new(),
+            pushed: 0,
         }
     }
 
     pub fn push_back(&mut self, t: T) {
-        self.deque.push_back(TickNode { creation_tick: self.ticks_passed, t });
+        self.push_back_with_ttl(t, self.max_ticks);
+    }
+
+    /// Pushes an element that expires after `ttl` ticks instead of the default `max_ticks`.
+    pub fn push_back_with_ttl(&mut self, t: T, ttl: u32) {
+        assert!(ttl > 0);
+
+        self.insert(t, self.ticks_passed + ttl);
+    }
+
+    fn insert(&mut self, t: T, deadline: u32) {
+        let number = self.pushed;
+        self.pushed += 1;
+
+        self.index.insert(t.clone(), (deadline, number));
+        self.deadlines.entry(deadline).or_default().insert(number, t);
+    }
+
+    pub fn get_ticks_passed(&self) -> u32 {
+        self.ticks_passed
+    }
+
+    /// Number of ticks passed at which `t` expires, or `None` if `t` is not waiting to expire.
+    pub fn get_deadline(&self, t: &T) -> Option<u32> {
+        self.index.get(t).map(|(deadline, _)| *deadline)
+    }
+
+    /// Elements still waiting to expire, the ones expiring first first.
+    pub fn iter(&self) -> impl Iterator<Item=&T> {
+        self.deadlines.values().flat_map(|bucket| bucket.values())
+    }
+
+    /// Postpones the expiration of `t` by `ticks`, returning its new deadline, or `None` if `t` is not waiting to expire.
+    /// It then expires after the elements already expiring at its new deadline.
+    pub fn extend(&mut self, t: &T, ticks: u32) -> Option<u32> {
+        let deadline = self.get_deadline(t)? + ticks;
+        let t = self.remove(t).unwrap();
+        self.insert(t, deadline);
+
+        Some(deadline)
+    }
+
+    /// Stops `t` from expiring, returning it, or `None` if `t` is not waiting to expire.
+    pub fn remove(&mut self, t: &T) -> Option<T> {
+        let (deadline, number) = self.index.remove(t)?;
+
+        let bucket = self.deadlines.get_mut(&deadline).unwrap();
+        let t = bucket.remove(&number).unwrap();
+        if bucket.is_empty() {
+            self.deadlines.remove(&deadline);
+        }
+
+        Some(t)
     }
 
     pub fn tick(&mut self) -> Vec<T> {
@@ -32,14 +135,18 @@ impl<T> TickDeque<T> {
 
         self.ticks_passed += 1;
 
-        while let Some(node) = self.deque.front() {
-            if node.creation_tick + self.max_ticks == self.ticks_passed {
-                expired.push(self.deque.pop_front().unwrap().t);
+        while let Some(entry) = self.deadlines.first_entry() {
+            if *entry.key() <= self.ticks_passed {
+                expired.extend(entry.remove().into_values());
             } else {
                 break;
             }
         }
 
+        for t in expired.iter() {
+            self.index.remove(t);
+        }
+
         expired
     }
 }
@@ -81,4 +188,64 @@ mod tick_dequeue_tests {
         assert_eq!(expired[0], "first");
         assert_eq!(expired[1], "second");
     }
+
+    #[test]
+    fn test_extend() {
+        let mut td: TickDeque<TickDequeType> = TickDeque::new(2);
+
+        td.push_back("first".to_string());
+        td.push_back("second".to_string());
+        assert_eq!(td.extend(&"first".to_string(), 1), Some(3));
+        assert_eq!(td.extend(&"third".to_string(), 1), None);
+
+        assert!(td.tick().is_empty());
+        assert_eq!(td.tick(), vec!["second".to_string()]);
+        assert_eq!(td.tick(), vec!["first".to_string()]);
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut td: TickDeque<TickDequeType> = TickDeque::new(1);
+
+        td.push_back("first".to_string());
+        td.push_back("second".to_string());
+        assert_eq!(td.remove(&"first".to_string()), Some("first".to_string()));
+        assert_eq!(td.remove(&"first".to_string()), None);
+        assert_eq!(td.get_deadline(&"first".to_string()), None);
+
+        assert_eq!(td.tick(), vec!["second".to_string()]);
+    }
+
+    #[test]
+    fn test_per_entry_ttl() {
+        let mut td: TickDeque<TickDequeType> = TickDeque::new(2);
+
+        td.push_back_with_ttl("long".to_string(), 3);
+        td.push_back("default".to_string());
+        td.push_back_with_ttl("short".to_string(), 1);
+        assert_eq!(td.get_deadline(&"long".to_string()), Some(3));
+        assert_eq!(td.iter().collect::<Vec<_>>(), vec!["short", "default", "long"]);
+
+        assert_eq!(td.tick(), vec!["short".to_string()]);
+        assert_eq!(td.tick(), vec!["default".to_string()]);
+        assert_eq!(td.tick(), vec!["long".to_string()]);
+        assert_eq!(td.get_deadline(&"long".to_string()), None);
+    }
+
+    #[test]
+    fn test_serialization() {
+        let mut td: TickDeque<TickDequeType> = TickDeque::new(2);
+        td.push_back("first".to_string());
+        td.push_back_with_ttl("second".to_string(), 1);
+        td.extend(&"first".to_string(), 1);
+
+        let json = serde_json::to_string(&td).unwrap();
+        assert_eq!(json, r#"{"max_ticks":2,"ticks_passed":0,"deque":[{"deadline":1,"t":"second"},{"deadline":3,"t":"first"}]}"#);
+
+        // the format of the snapshots taken before the deadlines were indexed
+        let mut td: TickDeque<TickDequeType> = serde_json::from_str(r#"{"max_ticks":2,"ticks_passed":4,"deque":[{"deadline":5,"t":"a"},{"deadline":5,"t":"b"},{"deadline":6,"t":"c"}]}"#).unwrap();
+        assert_eq!(td.get_deadline(&"c".to_string()), Some(6));
+        assert_eq!(td.tick(), vec!["a".to_string(), "b".to_string()]);
+        assert_eq!(td.tick(), vec!["c".to_string()]);
+    }
 }
@@ -0,0 +1,24 @@
+use crate::per_good::PerGood;
+
+/// Goods a trader exchanged with the market through the transactions it paid and the forwards it settled.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraderVolume {
+    /// Goods the market gave to the trader.
+    pub bought: PerGood<f32>,
+    /// Goods the trader gave to the market.
+    pub sold: PerGood<f32>,
+}
+
+/// Everything a trader did on the market, as recorded by the transactions locked under its name. Tokens are in no
+/// particular order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraderHistory {
+    /// Tokens of the buys and of the baskets.
+    pub buy_tokens: Vec<String>,
+    pub sell_tokens: Vec<String>,
+    pub forward_tokens: Vec<String>,
+    /// Tokens of the locks, buys, baskets and sells alike, that expired without being paid, and of the defaulted
+    /// forwards.
+    pub expired_tokens: Vec<String>,
+    pub volume: TraderVolume,
+}
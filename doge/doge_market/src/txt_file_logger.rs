@@ -6,6 +6,7 @@ use unitn_market_2022::good::good::Good;
 use uuid::Uuid;
 
 use crate::logger::Logger;
+use crate::refill_backend::RefillTrade;
 
 /// Text file logger that conforms to the [Market protocol specifications](https://github.com/WG-AdvancedProgramming/market-protocol-specifications/blob/main/market-protocol-specifications.md).
 pub struct TxtFileLogger {
@@ -116,6 +117,77 @@ impl TxtFileLogger {
             }
         }
     }
+
+    /// ```CANCEL_BUY-TOKEN:<token>-OK``` if the cancellation returns Ok
+    /// ```CANCEL_BUY-TOKEN:<token>-ERROR``` if the cancellation returns Err
+    fn cancel_buy_code(token: &Uuid, success: bool) -> String {
+        match success {
+            true => {
+                format!("CANCEL_BUY-TOKEN:{}-OK", token)
+            }
+            false => {
+                format!("CANCEL_BUY-TOKEN:{}-ERROR", token)
+            }
+        }
+    }
+
+    /// ```CANCEL_SELL-TOKEN:<token>-OK``` if the cancellation returns Ok
+    /// ```CANCEL_SELL-TOKEN:<token>-ERROR``` if the cancellation returns Err
+    fn cancel_sell_code(token: &Uuid, success: bool) -> String {
+        match success {
+            true => {
+                format!("CANCEL_SELL-TOKEN:{}-OK", token)
+            }
+            false => {
+                format!("CANCEL_SELL-TOKEN:{}-ERROR", token)
+            }
+        }
+    }
+
+    /// ```RENEW_BUY-TOKEN:<token>-DAYS:<days>-OK``` if the renewal returns Ok
+    /// ```RENEW_BUY-TOKEN:<token>-DAYS:<days>-ERROR``` if the renewal returns Err
+    fn renew_buy_code(token: &Uuid, days: u32, success: bool) -> String {
+        match success {
+            true => {
+                format!("RENEW_BUY-TOKEN:{}-DAYS:{}-OK", token, days)
+            }
+            false => {
+                format!("RENEW_BUY-TOKEN:{}-DAYS:{}-ERROR", token, days)
+            }
+        }
+    }
+
+    /// ```RENEW_SELL-TOKEN:<token>-DAYS:<days>-OK``` if the renewal returns Ok
+    /// ```RENEW_SELL-TOKEN:<token>-DAYS:<days>-ERROR``` if the renewal returns Err
+    fn renew_sell_code(token: &Uuid, days: u32, success: bool) -> String {
+        match success {
+            true => {
+                format!("RENEW_SELL-TOKEN:{}-DAYS:{}-OK", token, days)
+            }
+            false => {
+                format!("RENEW_SELL-TOKEN:{}-DAYS:{}-ERROR", token, days)
+            }
+        }
+    }
+
+    /// ```SEED:<seed>```, not part of the specifications, so that a run can be replayed
+    fn seed_code(seed: u64) -> String {
+        format!("SEED:{}", seed)
+    }
+
+    /// ```REFILL-<supplier>-SOLD:<good_kind>:<quantity>-BOUGHT:<good_kind>:<quantity>-TOKEN:<token>-OK``` if the lock was paid
+    /// ```REFILL-<supplier>-SOLD:<good_kind>:<quantity>-BOUGHT:<good_kind>:<quantity>-TOKEN:<token>-ERROR``` if the lock was left open
+    /// ```REFILL-<supplier>-SOLD:<good_kind>:<quantity>-BOUGHT:<good_kind>:<quantity>-ERROR``` if the lock was refused,
+    /// not part of the specifications
+    fn refill_trade_code(trade: &RefillTrade) -> String {
+        let code = format!("REFILL-{}-SOLD:{}:{}-BOUGHT:{}:{}",
+                           trade.supplier, trade.sold.get_kind(), trade.sold.get_qty(), trade.bought.get_kind(), trade.bought.get_qty());
+        match (&trade.token, trade.paid) {
+            (Some(token), true) => format!("{}-TOKEN:{}-OK", code, token),
+            (Some(token), false) => format!("{}-TOKEN:{}-ERROR", code, token),
+            (None, _) => format!("{}-ERROR", code),
+        }
+    }
 }
 
 impl Logger for TxtFileLogger {
@@ -148,6 +220,42 @@ impl Logger for TxtFileLogger {
         let sell_code = TxtFileLogger::format_log_code(&self.market_name, Local::now(), &sell_code);
         writeln!(&self.file, "{sell_code}").unwrap();
     }
+
+    fn log_cancel_buy(&mut self, token: &Uuid, success: bool) {
+        let cancel_code = TxtFileLogger::cancel_buy_code(token, success);
+        let cancel_code = TxtFileLogger::format_log_code(&self.market_name, Local::now(), &cancel_code);
+        writeln!(self.file, "{cancel_code}").unwrap();
+    }
+
+    fn log_cancel_sell(&mut self, token: &Uuid, success: bool) {
+        let cancel_code = TxtFileLogger::cancel_sell_code(token, success);
+        let cancel_code = TxtFileLogger::format_log_code(&self.market_name, Local::now(), &cancel_code);
+        writeln!(self.file, "{cancel_code}").unwrap();
+    }
+
+    fn log_renew_buy(&mut self, token: &Uuid, days: u32, success: bool) {
+        let renew_code = TxtFileLogger::renew_buy_code(token, days, success);
+        let renew_code = TxtFileLogger::format_log_code(&self.market_name, Local::now(), &renew_code);
+        writeln!(self.file, "{renew_code}").unwrap();
+    }
+
+    fn log_renew_sell(&mut self, token: &Uuid, days: u32, success: bool) {
+        let renew_code = TxtFileLogger::renew_sell_code(token, days, success);
+        let renew_code = TxtFileLogger::format_log_code(&self.market_name, Local::now(), &renew_code);
+        writeln!(self.file, "{renew_code}").unwrap();
+    }
+
+    fn log_seed(&mut self, seed: u64) {
+        let seed_code = TxtFileLogger::seed_code(seed);
+        let seed_code = TxtFileLogger::format_log_code(&self.market_name, Local::now(), &seed_code);
+        writeln!(self.file, "{seed_code}").unwrap();
+    }
+
+    fn log_refill_trade(&mut self, trade: &RefillTrade) {
+        let refill_code = TxtFileLogger::refill_trade_code(trade);
+        let refill_code = TxtFileLogger::format_log_code(&self.market_name, Local::now(), &refill_code);
+        writeln!(self.file, "{refill_code}").unwrap();
+    }
 }
 
 #[cfg(test)]
@@ -218,4 +326,35 @@ mod tests {
         let output = TxtFileLogger::sell_code(&Uuid::from_str("7ecaab48-62e4-4a31-8e9a-d13f3f2cbc1d").unwrap(), false);
         assert_eq!("SELL-TOKEN:7ecaab48-62e4-4a31-8e9a-d13f3f2cbc1d-ERROR", output);
     }
+
+    #[test]
+    fn test_cancel_log_code() {
+        let output = TxtFileLogger::cancel_buy_code(&Uuid::from_str("465823ac-cccf-407a-971b-49679f32d874").unwrap(), true);
+        assert_eq!("CANCEL_BUY-TOKEN:465823ac-cccf-407a-971b-49679f32d874-OK", output);
+        let output = TxtFileLogger::cancel_sell_code(&Uuid::from_str("7ecaab48-62e4-4a31-8e9a-d13f3f2cbc1d").unwrap(), false);
+        assert_eq!("CANCEL_SELL-TOKEN:7ecaab48-62e4-4a31-8e9a-d13f3f2cbc1d-ERROR", output);
+    }
+
+    #[test]
+    fn test_renew_log_code() {
+        let output = TxtFileLogger::renew_buy_code(&Uuid::from_str("465823ac-cccf-407a-971b-49679f32d874").unwrap(), 3, true);
+        assert_eq!("RENEW_BUY-TOKEN:465823ac-cccf-407a-971b-49679f32d874-DAYS:3-OK", output);
+        let output = TxtFileLogger::renew_sell_code(&Uuid::from_str("7ecaab48-62e4-4a31-8e9a-d13f3f2cbc1d").unwrap(), 1, false);
+        assert_eq!("RENEW_SELL-TOKEN:7ecaab48-62e4-4a31-8e9a-d13f3f2cbc1d-DAYS:1-ERROR", output);
+    }
+
+    #[test]
+    fn test_seed_log_code() {
+        assert_eq!("SEED:42", TxtFileLogger::seed_code(42));
+    }
+
+    #[test]
+    fn test_refill_trade_log_code() {
+        let mut trade = RefillTrade { supplier: "ShibaMarket", sold: Good::new(USD, 150.), bought: Good::new(EUR, 100.), token: Some("42".to_string()), paid: true };
+        assert_eq!("REFILL-ShibaMarket-SOLD:USD:150-BOUGHT:EUR:100-TOKEN:42-OK", TxtFileLogger::refill_trade_code(&trade));
+        trade.paid = false;
+        assert_eq!("REFILL-ShibaMarket-SOLD:USD:150-BOUGHT:EUR:100-TOKEN:42-ERROR", TxtFileLogger::refill_trade_code(&trade));
+        trade.token = None;
+        assert_eq!("REFILL-ShibaMarket-SOLD:USD:150-BOUGHT:EUR:100-ERROR", TxtFileLogger::refill_trade_code(&trade));
+    }
 }